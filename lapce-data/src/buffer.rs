@@ -7,12 +7,16 @@ use druid::{
 use lapce_core::indent::{auto_detect_indent_style, IndentStyle};
 use lapce_core::style::line_styles;
 use lapce_core::syntax::Syntax;
-use lapce_rpc::buffer::{BufferHeadResponse, BufferId, NewBufferResponse};
+use lapce_rpc::buffer::{
+    BufferChunkResponse, BufferHeadResponse, BufferId, NewBufferResponse,
+};
+use lapce_rpc::source_control::{BlameHunk, DiffBase};
 use lapce_rpc::style::{LineStyle, LineStyles, Style};
 use lsp_types::SemanticTokensLegend;
 use lsp_types::SemanticTokensServerCapabilities;
 use lsp_types::{CodeActionResponse, Position};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::cell::RefCell;
 use std::cmp;
 use std::collections::HashMap;
@@ -28,6 +32,7 @@ use xi_rope::{
 };
 use xi_unicode::EmojiExt;
 
+use crate::buffer::conflict::ConflictBlock;
 use crate::buffer::data::{
     BufferData, BufferDataListener, EditableBufferData, DEFAULT_INDENT,
 };
@@ -44,6 +49,7 @@ use crate::{
     state::Mode,
 };
 
+pub mod conflict;
 pub mod data;
 pub mod decoration;
 
@@ -157,6 +163,9 @@ struct Revision {
 #[derive(Clone, PartialEq, Eq, Hash, Serialize, Deserialize, Debug)]
 pub enum LocalBufferKind {
     Search,
+    SearchReplace,
+    SearchInclude,
+    SearchExclude,
     SourceControl,
     Empty,
     FilePicker,
@@ -177,6 +186,9 @@ impl BufferContent {
             BufferContent::File(_) => false,
             BufferContent::Local(local) => match local {
                 LocalBufferKind::Search
+                | LocalBufferKind::SearchReplace
+                | LocalBufferKind::SearchInclude
+                | LocalBufferKind::SearchExclude
                 | LocalBufferKind::SourceControl
                 | LocalBufferKind::FilePicker
                 | LocalBufferKind::Settings
@@ -192,6 +204,9 @@ impl BufferContent {
             BufferContent::File(_) => false,
             BufferContent::Local(local) => match local {
                 LocalBufferKind::Search
+                | LocalBufferKind::SearchReplace
+                | LocalBufferKind::SearchInclude
+                | LocalBufferKind::SearchExclude
                 | LocalBufferKind::FilePicker
                 | LocalBufferKind::Settings
                 | LocalBufferKind::Keymap => true,
@@ -224,6 +239,22 @@ pub struct Buffer {
 
     pub code_actions: im::HashMap<usize, CodeActionResponse>,
 
+    /// Who last changed each line, as of the revision it was computed at.
+    blame: Option<(u64, Arc<Vec<BlameHunk>>)>,
+
+    /// The merge conflicts in the text, as of the revision they were found
+    /// at.
+    conflicts: Rc<RefCell<Option<(u64, Arc<Vec<ConflictBlock>>)>>>,
+
+    /// What the "head" history, and with it the change markers, is the
+    /// file as of.
+    diff_base: DiffBase,
+
+    /// Hash of the content as it was last loaded from or saved to disk.
+    pristine_hash: u64,
+
+    read_only: bool,
+
     decoration: BufferDecoration,
 }
 
@@ -272,6 +303,7 @@ impl Buffer {
                 find_progress: Rc::new(RefCell::new(FindProgress::Ready)),
                 loaded: false,
                 local: false,
+                large_file: false,
                 histories: im::HashMap::new(),
                 tab_id,
                 event_sink,
@@ -285,6 +317,16 @@ impl Buffer {
             scroll_offset: Vec2::ZERO,
 
             code_actions: im::HashMap::new(),
+
+            blame: None,
+
+            conflicts: Rc::new(RefCell::new(None)),
+
+            diff_base: DiffBase::Head,
+
+            pristine_hash: 0,
+
+            read_only: false,
         }
     }
 
@@ -332,6 +374,23 @@ impl Buffer {
         self.data.dirty = dirty;
     }
 
+    pub fn read_only(&self) -> bool {
+        self.read_only
+    }
+
+    pub fn set_read_only(&mut self, read_only: bool) {
+        self.read_only = read_only;
+    }
+
+    pub fn pristine_hash(&self) -> u64 {
+        self.pristine_hash
+    }
+
+    /// Records the current content as the one on disk.
+    pub fn set_pristine(&mut self) {
+        self.pristine_hash = rope_hash(&self.data.rope);
+    }
+
     pub fn set_local(mut self) -> Self {
         self.decoration.local = true;
         self
@@ -353,6 +412,22 @@ impl Buffer {
         self.decoration.local
     }
 
+    pub fn large_file(&self) -> bool {
+        self.decoration.large_file
+    }
+
+    /// Turns off everything that doesn't scale to a large file:
+    /// syntax highlighting, semantic styles and the diff against head.
+    pub fn set_large_file(&mut self) {
+        self.decoration.large_file = true;
+        self.decoration.syntax = None;
+        self.decoration.semantic_styles = None;
+        self.decoration.histories.clear();
+        self.history_styles.clear();
+        self.history_changes.clear();
+        self.history_line_styles.borrow_mut().clear();
+    }
+
     pub fn syntax(&self) -> Option<&Syntax> {
         self.decoration.syntax.as_ref()
     }
@@ -365,6 +440,20 @@ impl Buffer {
         &self.decoration.histories
     }
 
+    pub fn diff_base(&self) -> &DiffBase {
+        &self.diff_base
+    }
+
+    /// Changes what the file is diffed against, dropping the diff against
+    /// the old base until the new one is retrieved.
+    pub fn set_diff_base(&mut self, base: DiffBase) {
+        self.diff_base = base;
+        self.decoration.histories.remove("head");
+        self.history_styles.remove("head");
+        self.history_changes.remove("head");
+        self.history_line_styles.borrow_mut().remove("head");
+    }
+
     pub fn line_styles(&self) -> Rc<RefCell<LineStyles>> {
         self.decoration.line_styles.clone()
     }
@@ -395,6 +484,9 @@ impl Buffer {
     }
 
     pub fn load_history(&mut self, version: &str, content: Rope) {
+        if self.large_file() {
+            return;
+        }
         self.decoration
             .histories
             .insert(version.to_string(), content.clone());
@@ -422,6 +514,7 @@ impl Buffer {
         self.data.max_len_line = max_len_line;
         self.data.num_lines = self.calc_num_lines();
         self.decoration.loaded = true;
+        self.pristine_hash = rope_hash(&self.data.rope);
         self.detect_indent();
         self.notify_update(None);
     }
@@ -469,7 +562,8 @@ impl Buffer {
 
     fn trigger_history_change(&self) {
         if let BufferContent::File(path) = &self.data.content {
-            if let Some(head) = self.histories().get("head") {
+            for (history, head) in self.histories().iter() {
+                let history = history.clone();
                 let id = self.id();
                 let rev = self.rev();
                 let atomic_rev = self.data.atomic_rev.clone();
@@ -498,7 +592,7 @@ impl Buffer {
                             id,
                             path,
                             rev,
-                            history: "head".to_string(),
+                            history,
                             changes: Arc::new(changes),
                         },
                         Target::Widget(tab_id),
@@ -551,13 +645,18 @@ impl Buffer {
         proxy: Arc<LapceProxy>,
         event_sink: ExtEventSink,
     ) {
+        if self.large_file() {
+            return;
+        }
         let id = self.data.id;
         if let BufferContent::File(path) = &self.data.content {
             let path = path.clone();
+            let base = self.diff_base.clone();
             thread::spawn(move || {
                 proxy.get_buffer_head(
                     id,
                     path.clone(),
+                    base,
                     Box::new(move |result| {
                         if let Ok(res) = result {
                             if let Ok(resp) =
@@ -580,12 +679,49 @@ impl Buffer {
         }
     }
 
+    /// Asks the proxy who last changed each line of the buffer as it is now.
+    pub fn retrieve_blame(
+        &self,
+        tab_id: WidgetId,
+        proxy: Arc<LapceProxy>,
+        event_sink: ExtEventSink,
+    ) {
+        if !self.loaded() || self.large_file() {
+            return;
+        }
+        if let BufferContent::File(path) = &self.data.content {
+            let path = path.clone();
+            let rev = self.rev();
+            proxy.git_blame(
+                path.clone(),
+                Box::new(move |result| {
+                    if let Ok(res) = result {
+                        if let Ok(hunks) =
+                            serde_json::from_value::<Vec<BlameHunk>>(res)
+                        {
+                            let _ = event_sink.submit_command(
+                                LAPCE_UI_COMMAND,
+                                LapceUICommand::UpdateBlame {
+                                    path,
+                                    rev,
+                                    hunks: Arc::new(hunks),
+                                },
+                                Target::Widget(tab_id),
+                            );
+                        }
+                    }
+                }),
+            );
+        }
+    }
+
     pub fn retrieve_file(
         &self,
         tab_id: WidgetId,
         proxy: Arc<LapceProxy>,
         event_sink: ExtEventSink,
         locations: Vec<(WidgetId, EditorLocationNew)>,
+        large_file_size: u64,
     ) {
         if self.loaded() || *self.start_to_load.borrow() {
             return;
@@ -594,34 +730,58 @@ impl Buffer {
         let id = self.data.id;
         if let BufferContent::File(path) = &self.data.content {
             let path = path.clone();
-            let proxy = proxy.clone();
-            let event_sink = event_sink.clone();
             thread::spawn(move || {
+                let local_proxy = proxy.clone();
                 proxy.new_buffer(
                     id,
                     path.clone(),
+                    large_file_size,
                     Box::new(move |result| {
                         if let Ok(res) = result {
                             if let Ok(resp) =
                                 serde_json::from_value::<NewBufferResponse>(res)
                             {
-                                let _ = event_sink.submit_command(
-                                    LAPCE_UI_COMMAND,
-                                    LapceUICommand::LoadBuffer {
-                                        path,
-                                        content: resp.content,
-                                        locations,
-                                    },
-                                    Target::Widget(tab_id),
-                                );
+                                let read_only = resp.read_only;
+                                if !resp.large_file {
+                                    let _ = event_sink.submit_command(
+                                        LAPCE_UI_COMMAND,
+                                        LapceUICommand::LoadBuffer {
+                                            path,
+                                            content: resp.content,
+                                            large_file: false,
+                                            read_only,
+                                            locations,
+                                        },
+                                        Target::Widget(tab_id),
+                                    );
+                                    return;
+                                }
+
+                                // The callback runs on the rpc thread, so the
+                                // chunks have to be requested from another one.
+                                thread::spawn(move || {
+                                    if let Some(content) =
+                                        retrieve_large_file(&local_proxy, id)
+                                    {
+                                        let _ = event_sink.submit_command(
+                                            LAPCE_UI_COMMAND,
+                                            LapceUICommand::LoadBuffer {
+                                                path,
+                                                content,
+                                                large_file: true,
+                                                read_only,
+                                                locations,
+                                            },
+                                            Target::Widget(tab_id),
+                                        );
+                                    }
+                                });
                             }
                         };
                     }),
                 )
             });
         }
-
-        self.retrieve_file_head(tab_id, proxy, event_sink);
     }
 
     pub fn reset_find(&self, current_find: &Find) {
@@ -1055,6 +1215,50 @@ impl Buffer {
         self.data.next_grapheme_offset(offset, count, limit)
     }
 
+    /// The change against the `compare` version at `line`, as the lines it
+    /// replaced in that version and the lines it covers in the buffer. A
+    /// deletion covers no lines and is found from the lines around it.
+    pub fn diff_hunk_at_line(
+        &self,
+        compare: &str,
+        line: usize,
+    ) -> Option<(Range<usize>, Range<usize>)> {
+        let changes = self.history_changes.get(compare)?;
+        let contains = |(_, new): &(Range<usize>, Range<usize>)| {
+            new.contains(&line)
+                || (new.is_empty() && (new.start == line || new.start == line + 1))
+        };
+        let mut old_line = 0;
+        let mut new_line = 0;
+        let mut hunk: Option<(Range<usize>, Range<usize>)> = None;
+        for change in changes.iter() {
+            match change {
+                DiffLines::Left(r) => {
+                    hunk.get_or_insert((r.start..r.start, new_line..new_line))
+                        .0
+                        .end = r.end;
+                    old_line = r.end;
+                }
+                DiffLines::Right(r) => {
+                    hunk.get_or_insert((old_line..old_line, r.start..r.start))
+                        .1
+                        .end = r.end;
+                    new_line = r.end;
+                }
+                DiffLines::Both(l, r) | DiffLines::Skip(l, r) => {
+                    if let Some(hunk) = hunk.take() {
+                        if contains(&hunk) {
+                            return Some(hunk);
+                        }
+                    }
+                    old_line = l.end;
+                    new_line = r.end;
+                }
+            }
+        }
+        hunk.filter(contains)
+    }
+
     pub fn diff_visual_line(&self, compare: &str, line: usize) -> usize {
         let mut visual_line = 0;
         if let Some(changes) = self.history_changes.get(compare) {
@@ -1484,6 +1688,74 @@ impl Buffer {
         }
         self.history_changes.insert(history.to_string(), changes);
     }
+
+    pub fn set_blame(&mut self, rev: u64, hunks: Arc<Vec<BlameHunk>>) {
+        self.blame = Some((rev, hunks));
+    }
+
+    /// The blame of the buffer, unless it was edited since.
+    pub fn blame(&self) -> Option<&Arc<Vec<BlameHunk>>> {
+        match &self.blame {
+            Some((rev, hunks)) if *rev == self.rev() => Some(hunks),
+            _ => None,
+        }
+    }
+
+    pub fn blame_at_line(&self, line: usize) -> Option<&BlameHunk> {
+        let hunks = self.blame()?;
+        let i = hunks
+            .partition_point(|hunk| hunk.start_line <= line)
+            .checked_sub(1)?;
+        let hunk = &hunks[i];
+        if line < hunk.start_line + hunk.lines {
+            Some(hunk)
+        } else {
+            None
+        }
+    }
+
+    pub fn blame_same(&self, other: &Buffer) -> bool {
+        match (&self.blame, &other.blame) {
+            (Some((rev, hunks)), Some((other_rev, other_hunks))) => {
+                rev == other_rev && Arc::ptr_eq(hunks, other_hunks)
+            }
+            (None, None) => true,
+            _ => false,
+        }
+    }
+
+    /// The merge conflicts in the text, found again once it was edited.
+    pub fn conflicts(&self) -> Arc<Vec<ConflictBlock>> {
+        let mut cache = self.conflicts.borrow_mut();
+        match cache.as_ref() {
+            Some((rev, conflicts)) if *rev == self.rev() => conflicts.clone(),
+            _ => {
+                let conflicts = Arc::new(conflict::conflicts(self.rope()));
+                *cache = Some((self.rev(), conflicts.clone()));
+                conflicts
+            }
+        }
+    }
+
+    pub fn conflict_at_line(&self, line: usize) -> Option<ConflictBlock> {
+        self.conflicts()
+            .iter()
+            .find(|conflict| conflict.lines().contains(&line))
+            .cloned()
+    }
+}
+
+/// The byte range of the `lines` of `rope`, which may run past its end.
+pub fn rope_line_range(rope: &Rope, lines: &Range<usize>) -> Range<usize> {
+    let last_line = rope.line_of_offset(rope.len());
+    let offset = |line: usize| {
+        if line > last_line {
+            rope.len()
+        } else {
+            rope.offset_of_line(line)
+        }
+    };
+    offset(lines.start)..offset(lines.end)
 }
 
 fn shuffle_tombstones(
@@ -2145,6 +2417,19 @@ pub fn str_col(s: &str, tab_width: usize) -> usize {
     total_width
 }
 
+/// Fetches the content of a large file from the proxy chunk by chunk.
+fn retrieve_large_file(proxy: &LapceProxy, buffer_id: BufferId) -> Option<String> {
+    let mut content = String::new();
+    loop {
+        let resp = proxy.buffer_chunk(buffer_id, content.len()).ok()?;
+        let chunk = serde_json::from_value::<BufferChunkResponse>(resp).ok()?;
+        content.push_str(&chunk.content);
+        if chunk.done {
+            return Some(content);
+        }
+    }
+}
+
 #[allow(dead_code)]
 fn buffer_diff(
     left_rope: Rope,
@@ -2210,6 +2495,19 @@ fn buffer_diff(
     Some(changes)
 }
 
+/// A hash of the content, which is saved with the workspace and so has to
+/// stay the same across Rust releases.
+pub fn rope_hash(rope: &Rope) -> u64 {
+    let mut hasher = Sha256::new();
+    for chunk in rope.iter_chunks(..) {
+        hasher.update(chunk.as_bytes());
+    }
+    let digest = hasher.finalize();
+    let mut bytes = [0; 8];
+    bytes.copy_from_slice(&digest[..8]);
+    u64::from_le_bytes(bytes)
+}
+
 fn rope_diff(
     left_rope: Rope,
     right_rope: Rope,
@@ -2502,3 +2800,18 @@ where
 //     }
 //     UnicodeWidthStr::width(s)
 // }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rope_hash_is_stable() {
+        // The hash is saved with the workspace, so it must never change.
+        assert_eq!(rope_hash(&Rope::from("hello")), 0x0ea3_b05f_ba4d_f22c);
+
+        let mut rope = Rope::from("hel");
+        rope.edit(3..3, "lo");
+        assert_eq!(rope_hash(&rope), rope_hash(&Rope::from("hello")));
+    }
+}
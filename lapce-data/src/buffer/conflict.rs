@@ -0,0 +1,157 @@
+use std::ops::Range;
+
+use xi_rope::Rope;
+
+const CURRENT_MARKER: &str = "<<<<<<<";
+const BASE_MARKER: &str = "|||||||";
+const SEPARATOR_MARKER: &str = "=======";
+const INCOMING_MARKER: &str = ">>>>>>>";
+
+/// A conflict git left in a file while merging, by the 0-based lines of its
+/// markers.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConflictBlock {
+    /// The `<<<<<<<` line, which the current side follows.
+    pub start: usize,
+    /// The `|||||||` line the common ancestor follows, which only the diff3
+    /// conflict style writes.
+    pub base: Option<usize>,
+    /// The `=======` line, which the incoming side follows.
+    pub separator: usize,
+    /// The `>>>>>>>` line.
+    pub end: usize,
+}
+
+/// Which side of a conflict is kept.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ConflictChoice {
+    Current,
+    Incoming,
+    Both,
+}
+
+impl ConflictBlock {
+    /// The lines of the current side, i.e. HEAD's.
+    pub fn current(&self) -> Range<usize> {
+        self.start + 1..self.base.unwrap_or(self.separator)
+    }
+
+    /// The lines of the incoming side, i.e. the merged or rebased commit's.
+    pub fn incoming(&self) -> Range<usize> {
+        self.separator + 1..self.end
+    }
+
+    /// All lines of the conflict, markers included.
+    pub fn lines(&self) -> Range<usize> {
+        self.start..self.end + 1
+    }
+
+    /// The lines the conflict is replaced with when resolved as `choice`.
+    pub fn resolution(&self, choice: ConflictChoice) -> Vec<Range<usize>> {
+        match choice {
+            ConflictChoice::Current => vec![self.current()],
+            ConflictChoice::Incoming => vec![self.incoming()],
+            ConflictChoice::Both => vec![self.current(), self.incoming()],
+        }
+    }
+}
+
+/// Finds the conflicts in the text. Markers that don't make up a whole
+/// conflict are ignored.
+pub fn conflicts(rope: &Rope) -> Vec<ConflictBlock> {
+    let mut conflicts = Vec::new();
+    let mut start = None;
+    let mut base = None;
+    let mut separator = None;
+    for (line, text) in rope.lines_raw(..).enumerate() {
+        if text.starts_with(CURRENT_MARKER) {
+            start = Some(line);
+            base = None;
+            separator = None;
+        } else if text.starts_with(BASE_MARKER) {
+            if start.is_some() && separator.is_none() {
+                base = Some(line);
+            }
+        } else if text.starts_with(SEPARATOR_MARKER) {
+            if start.is_some() && separator.is_none() {
+                separator = Some(line);
+            }
+        } else if text.starts_with(INCOMING_MARKER) {
+            if let (Some(start), Some(separator)) = (start, separator) {
+                conflicts.push(ConflictBlock {
+                    start,
+                    base,
+                    separator,
+                    end: line,
+                });
+            }
+            start = None;
+        }
+    }
+    conflicts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_conflicts() {
+        let rope = Rope::from(
+            "a\n<<<<<<< HEAD\nours\n=======\ntheirs\n>>>>>>> branch\nb\n\
+             <<<<<<< HEAD\nours\n||||||| base\nbase\n=======\n>>>>>>> branch\n",
+        );
+        assert_eq!(
+            conflicts(&rope),
+            vec![
+                ConflictBlock {
+                    start: 1,
+                    base: None,
+                    separator: 3,
+                    end: 5,
+                },
+                ConflictBlock {
+                    start: 7,
+                    base: Some(9),
+                    separator: 11,
+                    end: 12,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_incomplete_conflicts() {
+        // No separator, then a second start before the end.
+        let rope = Rope::from(
+            "<<<<<<< HEAD\nours\n>>>>>>> branch\n<<<<<<< HEAD\n\
+             <<<<<<< HEAD\nours\n=======\ntheirs\n>>>>>>> branch\n=======\n",
+        );
+        assert_eq!(
+            conflicts(&rope),
+            vec![ConflictBlock {
+                start: 4,
+                base: None,
+                separator: 6,
+                end: 8,
+            }]
+        );
+        assert!(conflicts(&Rope::from("=======\n>>>>>>> branch\n")).is_empty());
+    }
+
+    #[test]
+    fn test_resolution() {
+        let block = ConflictBlock {
+            start: 1,
+            base: Some(3),
+            separator: 5,
+            end: 8,
+        };
+        assert_eq!(block.current(), 2..3);
+        assert_eq!(block.incoming(), 6..8);
+        assert_eq!(block.lines(), 1..9);
+        assert_eq!(block.resolution(ConflictChoice::Current), vec![2..3]);
+        assert_eq!(block.resolution(ConflictChoice::Incoming), vec![6..8]);
+        assert_eq!(block.resolution(ConflictChoice::Both), vec![2..3, 6..8]);
+    }
+}
@@ -22,6 +22,7 @@ use crate::{
 pub struct BufferDecoration {
     pub(super) loaded: bool,
     pub(super) local: bool,
+    pub(super) large_file: bool,
 
     pub(super) find: Rc<RefCell<Find>>,
     pub(super) find_progress: Rc<RefCell<FindProgress>>,
@@ -66,6 +67,27 @@ impl BufferDecoration {
                             Target::Widget(self.tab_id),
                         );
                     }
+                    LocalBufferKind::SearchReplace => {
+                        let _ = self.event_sink.submit_command(
+                            LAPCE_UI_COMMAND,
+                            LapceUICommand::UpdateSearchReplace(s),
+                            Target::Widget(self.tab_id),
+                        );
+                    }
+                    LocalBufferKind::SearchInclude => {
+                        let _ = self.event_sink.submit_command(
+                            LAPCE_UI_COMMAND,
+                            LapceUICommand::UpdateSearchInclude(s),
+                            Target::Widget(self.tab_id),
+                        );
+                    }
+                    LocalBufferKind::SearchExclude => {
+                        let _ = self.event_sink.submit_command(
+                            LAPCE_UI_COMMAND,
+                            LapceUICommand::UpdateSearchExclude(s),
+                            Target::Widget(self.tab_id),
+                        );
+                    }
                     LocalBufferKind::SourceControl => {}
                     LocalBufferKind::Empty => {}
                     LocalBufferKind::FilePicker => {
@@ -135,7 +157,8 @@ impl BufferDecoration {
 
     fn trigger_history_change(&self, buffer: &BufferData) {
         if let BufferContent::File(path) = &buffer.content {
-            if let Some(head) = self.histories.get("head") {
+            for (history, head) in self.histories.iter() {
+                let history = history.clone();
                 let id = buffer.id;
                 let rev = buffer.rev;
                 let atomic_rev = buffer.atomic_rev.clone();
@@ -164,7 +187,7 @@ impl BufferDecoration {
                             id,
                             path,
                             rev,
-                            history: "head".to_string(),
+                            history,
                             changes: Arc::new(changes),
                         },
                         Target::Widget(tab_id),
@@ -5,8 +5,13 @@ use druid::{Point, Rect, Selector, Size, WidgetId, WindowId};
 use indexmap::IndexMap;
 use lapce_core::syntax::Syntax;
 use lapce_rpc::{
-    buffer::BufferId, file::FileNodeItem, plugin::PluginDescription,
-    source_control::DiffInfo, style::Style, terminal::TermId,
+    buffer::BufferId,
+    file::FileNodeItem,
+    plugin::PluginDescription,
+    search::SearchReplacement,
+    source_control::{BlameHunk, DiffBase, DiffInfo, FileDiff},
+    style::Style,
+    terminal::TermId,
 };
 use lsp_types::{
     CodeActionResponse, CompletionItem, CompletionResponse, Hover, Location,
@@ -29,6 +34,7 @@ use crate::{
     search::Match,
     split::{SplitDirection, SplitMoveDirection},
     state::LapceWorkspace,
+    task::{TaskRun, TasksConfig},
 };
 
 pub const LAPCE_NEW_COMMAND: Selector<LapceCommandNew> =
@@ -187,9 +193,70 @@ pub enum LapceWorkbenchCommand {
     #[strum(serialize = "palette.workspace")]
     PaletteWorkspace,
 
+    #[strum(message = "Open Local File History")]
+    #[strum(serialize = "palette.local_history")]
+    PaletteLocalHistory,
+
+    #[strum(message = "Checkout Git Branch")]
     #[strum(serialize = "source_control.checkout_branch")]
     CheckoutBranch,
 
+    #[strum(message = "Force Checkout Git Branch")]
+    #[strum(serialize = "source_control.force_checkout_branch")]
+    ForceCheckoutBranch,
+
+    #[strum(message = "Create Git Branch")]
+    #[strum(serialize = "source_control.create_branch")]
+    CreateBranch,
+
+    #[strum(message = "Delete Git Branch")]
+    #[strum(serialize = "source_control.delete_branch")]
+    DeleteBranch,
+
+    #[strum(message = "Mark as Resolved")]
+    #[strum(serialize = "source_control.mark_resolved")]
+    SourceControlMarkResolved,
+
+    #[strum(message = "Git Fetch")]
+    #[strum(serialize = "source_control.fetch")]
+    GitFetch,
+
+    #[strum(message = "Git Pull")]
+    #[strum(serialize = "source_control.pull")]
+    GitPull,
+
+    #[strum(message = "Git Pull (Rebase)")]
+    #[strum(serialize = "source_control.pull_rebase")]
+    GitPullRebase,
+
+    #[strum(message = "Git Push")]
+    #[strum(serialize = "source_control.push")]
+    GitPush,
+
+    #[strum(message = "Stash Changes")]
+    #[strum(serialize = "source_control.stash")]
+    StashChanges,
+
+    #[strum(message = "Stash Changes Including Untracked Files")]
+    #[strum(serialize = "source_control.stash_include_untracked")]
+    StashChangesIncludeUntracked,
+
+    #[strum(message = "Show Git Stashes")]
+    #[strum(serialize = "source_control.stashes")]
+    ShowStashes,
+
+    #[strum(message = "Apply Git Stash")]
+    #[strum(serialize = "source_control.apply_stash")]
+    ApplyStash,
+
+    #[strum(message = "Pop Git Stash")]
+    #[strum(serialize = "source_control.pop_stash")]
+    PopStash,
+
+    #[strum(message = "Drop Git Stash")]
+    #[strum(serialize = "source_control.drop_stash")]
+    DropStash,
+
     #[strum(serialize = "toggle_maximized_panel")]
     ToggleMaximizedPanel,
 
@@ -211,6 +278,14 @@ pub enum LapceWorkbenchCommand {
     #[strum(serialize = "toggle_terminal_focus")]
     ToggleTerminalFocus,
 
+    #[strum(message = "New Terminal")]
+    #[strum(serialize = "new_terminal")]
+    NewTerminal,
+
+    #[strum(message = "Run Task")]
+    #[strum(serialize = "run_task")]
+    RunTask,
+
     #[strum(serialize = "toggle_source_control_focus")]
     ToggleSourceControlFocus,
 
@@ -245,6 +320,22 @@ pub enum LapceWorkbenchCommand {
     #[strum(serialize = "toggle_search_visual")]
     ToggleSearchVisual,
 
+    #[strum(message = "Toggle Search Case Sensitive")]
+    #[strum(serialize = "toggle_search_case_sensitive")]
+    ToggleSearchCaseSensitive,
+
+    #[strum(message = "Toggle Search Whole Word")]
+    #[strum(serialize = "toggle_search_whole_word")]
+    ToggleSearchWholeWord,
+
+    #[strum(message = "Toggle Search Regex")]
+    #[strum(serialize = "toggle_search_regex")]
+    ToggleSearchRegex,
+
+    #[strum(message = "Replace All Search Results")]
+    #[strum(serialize = "search_replace_all")]
+    SearchReplaceAll,
+
     #[strum(serialize = "focus_editor")]
     FocusEditor,
 
@@ -253,6 +344,38 @@ pub enum LapceWorkbenchCommand {
 
     #[strum(serialize = "source_control_commit")]
     SourceControlCommit,
+
+    #[strum(message = "Amend Last Commit")]
+    #[strum(serialize = "source_control.amend")]
+    SourceControlAmend,
+
+    #[strum(message = "Stage File")]
+    #[strum(serialize = "source_control.stage")]
+    SourceControlStage,
+
+    #[strum(message = "Unstage File")]
+    #[strum(serialize = "source_control.unstage")]
+    SourceControlUnstage,
+
+    #[strum(message = "Discard File Changes")]
+    #[strum(serialize = "source_control.discard")]
+    SourceControlDiscard,
+
+    #[strum(message = "Toggle Git Blame Gutter")]
+    #[strum(serialize = "toggle_blame_gutter")]
+    ToggleBlameGutter,
+
+    #[strum(message = "Show Git Log")]
+    #[strum(serialize = "source_control.log")]
+    GitLog,
+
+    #[strum(message = "Show File History")]
+    #[strum(serialize = "source_control.file_history")]
+    ShowFileHistory,
+
+    #[strum(message = "Compare File With Git Revision")]
+    #[strum(serialize = "source_control.compare_with_revision")]
+    CompareWithRevision,
 }
 
 #[derive(Display, EnumString, EnumIter, Clone, PartialEq, Debug, EnumMessage)]
@@ -462,12 +585,33 @@ pub enum LapceCommand {
     #[strum(message = "Go to Previous Difference")]
     #[strum(serialize = "previous_diff")]
     PreviousDiff,
+    #[strum(message = "Stage Change")]
+    #[strum(serialize = "stage_hunk")]
+    StageHunk,
+    #[strum(message = "Revert Change")]
+    #[strum(serialize = "revert_hunk")]
+    RevertHunk,
+    #[strum(message = "Accept Current Change")]
+    #[strum(serialize = "accept_current_change")]
+    AcceptCurrentChange,
+    #[strum(message = "Accept Incoming Change")]
+    #[strum(serialize = "accept_incoming_change")]
+    AcceptIncomingChange,
+    #[strum(message = "Accept Both Changes")]
+    #[strum(serialize = "accept_both_changes")]
+    AcceptBothChanges,
     #[strum(serialize = "format_document")]
     #[strum(message = "Format Document")]
     FormatDocument,
     #[strum(message = "Save")]
     #[strum(serialize = "save")]
     Save,
+    #[strum(message = "Restore Compared Version")]
+    #[strum(serialize = "restore_compared_version")]
+    RestoreComparedVersion,
+    #[strum(message = "Toggle Read-Only")]
+    #[strum(serialize = "toggle_read_only")]
+    ToggleReadOnly,
     #[strum(serialize = "show_code_actions")]
     ShowCodeActions,
     #[strum(serialize = "match_pairs")]
@@ -500,10 +644,62 @@ pub enum LapceCommand {
     ClearSearch,
     #[strum(serialize = "search_in_view")]
     SearchInView,
+    #[strum(message = "Terminal: Go to Previous Prompt")]
+    #[strum(serialize = "terminal.previous_prompt")]
+    PreviousPrompt,
+    #[strum(message = "Terminal: Go to Next Prompt")]
+    #[strum(serialize = "terminal.next_prompt")]
+    NextPrompt,
+    #[strum(message = "Terminal: Copy Last Command Output")]
+    #[strum(serialize = "terminal.copy_last_command_output")]
+    CopyLastCommandOutput,
+    #[strum(message = "Terminal: New Terminal in Current Directory")]
+    #[strum(serialize = "terminal.new_terminal_in_cwd")]
+    NewTerminalInCwd,
     Insert(String),
 }
 
 impl LapceCommand {
+    /// Whether the command changes the content of the buffer.
+    pub fn is_edit_command(&self) -> bool {
+        matches!(
+            self,
+            LapceCommand::MoveLineUp
+                | LapceCommand::MoveLineDown
+                | LapceCommand::DeleteBackward
+                | LapceCommand::DeleteForward
+                | LapceCommand::DeleteForwardAndInsert
+                | LapceCommand::DeleteVisual
+                | LapceCommand::DeleteOperator
+                | LapceCommand::DeleteWordBackward
+                | LapceCommand::DeleteWordForward
+                | LapceCommand::DeleteToBeginningOfLine
+                | LapceCommand::ToggleLineComment
+                | LapceCommand::IndentLine
+                | LapceCommand::OutdentLine
+                | LapceCommand::MotionModeDelete
+                | LapceCommand::MotionModeIndent
+                | LapceCommand::MotionModeOutdent
+                | LapceCommand::NewLineAbove
+                | LapceCommand::NewLineBelow
+                | LapceCommand::InsertNewLine
+                | LapceCommand::InsertTab
+                | LapceCommand::Paste
+                | LapceCommand::ClipboardCut
+                | LapceCommand::ClipboardPaste
+                | LapceCommand::Undo
+                | LapceCommand::Redo
+                | LapceCommand::FormatDocument
+                | LapceCommand::RestoreComparedVersion
+                | LapceCommand::RevertHunk
+                | LapceCommand::AcceptCurrentChange
+                | LapceCommand::AcceptIncomingChange
+                | LapceCommand::AcceptBothChanges
+                | LapceCommand::JoinLines
+                | LapceCommand::Insert(_)
+        )
+    }
+
     pub fn motion_mode_command(&self) -> Option<MotionMode> {
         let mode = match self {
             LapceCommand::MotionModeYank => MotionMode::Yank,
@@ -567,6 +763,8 @@ pub enum LapceUICommand {
     LoadBuffer {
         path: PathBuf,
         content: String,
+        large_file: bool,
+        read_only: bool,
         locations: Vec<(WidgetId, EditorLocationNew)>,
     },
     LoadBufferHead {
@@ -574,6 +772,20 @@ pub enum LapceUICommand {
         id: String,
         content: Rope,
     },
+    OpenLocalHistory(PathBuf, String),
+    /// Opens a file changed by the commit in a diff against its parent.
+    OpenCommitFileDiff(String, FileDiff),
+    /// Opens the file as of a commit in a diff against `parent_content`.
+    OpenGitSnapshot {
+        path: PathBuf,
+        history: String,
+        parent_content: Rope,
+    },
+    /// Changes what the open file is diffed against.
+    SetDiffBase(PathBuf, DiffBase),
+    /// Empties the commit message once the commit went through.
+    ClearCommitMessage,
+    ShowStatusMessage(String),
     LoadBufferAndGoToPosition {
         path: PathBuf,
         content: String,
@@ -583,7 +795,16 @@ pub enum LapceUICommand {
     HideMenu,
     ShowMenu(Point, Arc<Vec<MenuItem>>),
     UpdateSearch(String),
-    GlobalSearchResult(String, Arc<HashMap<PathBuf, Vec<Match>>>),
+    UpdateSearchReplace(String),
+    UpdateSearchInclude(String),
+    UpdateSearchExclude(String),
+    GlobalSearchResult(u64, Arc<HashMap<PathBuf, Vec<Match>>>),
+    /// The search with the id ended, with if it hit the match limit and
+    /// why it couldn't run.
+    GlobalSearchDone(u64, bool, Option<String>),
+    /// "Replace All" was confirmed for the search with the id, with the
+    /// replacements that were confirmed.
+    SearchReplaceAll(u64, HashMap<PathBuf, Vec<SearchReplacement>>),
     CancelFilePicker,
     SetWorkspace(LapceWorkspace),
     SetTheme(String, bool),
@@ -639,7 +860,8 @@ pub enum LapceUICommand {
     ApplyEdits(usize, u64, Vec<TextEdit>),
     ApplyEditsAndSave(usize, u64, Result<Value>),
     DocumentFormat(PathBuf, u64, Result<Value>),
-    DocumentFormatAndSave(PathBuf, u64, Result<Value>),
+    DocumentFormatAndSave(PathBuf, u64, Result<Value>, bool),
+    AutoSave(PathBuf),
     BufferSave(PathBuf, u64),
     UpdateSemanticStyles(BufferId, PathBuf, u64, Arc<Spans<Style>>),
     UpdateTerminalTitle(TermId, String),
@@ -661,11 +883,18 @@ pub enum LapceUICommand {
         history: String,
         changes: Arc<Vec<DiffLines>>,
     },
+    UpdateBlame {
+        path: PathBuf,
+        rev: u64,
+        hunks: Arc<Vec<BlameHunk>>,
+    },
     CenterOfWindow,
     UpdateLineChanges(BufferId),
     PublishDiagnostics(PublishDiagnosticsParams),
     WorkDoneProgress(ProgressParams),
-    UpdateDiffInfo(DiffInfo),
+    UpdateDiffInfo(Vec<DiffInfo>),
+    /// Shows the repository the path is in in the source control panel.
+    SelectRepository(PathBuf),
     ReloadBuffer(BufferId, u64, String),
     EnsureVisible((Rect, (f64, f64), Option<EnsureVisiblePosition>)),
     EnsureRectVisible(Rect),
@@ -678,7 +907,16 @@ pub enum LapceUICommand {
     HomeDir(PathBuf),
     ProxyUpdateStatus(ProxyStatus),
     CloseTerminal(TermId),
+    /// The program of the terminal exited.
+    TerminalExited(TermId),
     SplitTerminal(bool, WidgetId),
+    /// Opens a terminal with the profile, the default one when it's `None`,
+    /// and in the directory if there is one.
+    NewTerminal(Option<String>, Option<PathBuf>),
+    /// Runs the task with the given name, after the ones it depends on.
+    RunTask(Arc<TasksConfig>, String),
+    /// Opens a terminal that runs the task.
+    NewTaskTerminal(TaskRun),
     SplitTerminalClose(TermId, WidgetId),
     SplitEditor(bool, WidgetId),
     SplitEditorMove(SplitMoveDirection, WidgetId),
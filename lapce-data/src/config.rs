@@ -7,6 +7,8 @@ use druid::{
     Color, ExtEventSink, FontFamily, Size, Target,
 };
 use hashbrown::HashMap;
+use lapce_rpc::terminal::TerminalProfile;
+use regex::Regex;
 use serde::{Deserialize, Serialize};
 use structdesc::FieldNames;
 use thiserror::Error;
@@ -43,6 +45,24 @@ impl LapceTheme {
     pub const SOURCE_CONTROL_ADDED: &'static str = "source_control.added";
     pub const SOURCE_CONTROL_REMOVED: &'static str = "source_control.removed";
     pub const SOURCE_CONTROL_MODIFIED: &'static str = "source_control.modified";
+    pub const SOURCE_CONTROL_CONFLICT_CURRENT: &'static str =
+        "source_control.conflict_current";
+    pub const SOURCE_CONTROL_CONFLICT_INCOMING: &'static str =
+        "source_control.conflict_incoming";
+    pub const SOURCE_CONTROL_DECORATION_MODIFIED: &'static str =
+        "source_control.decoration_modified";
+    pub const SOURCE_CONTROL_DECORATION_ADDED: &'static str =
+        "source_control.decoration_added";
+    pub const SOURCE_CONTROL_DECORATION_DELETED: &'static str =
+        "source_control.decoration_deleted";
+    pub const SOURCE_CONTROL_DECORATION_RENAMED: &'static str =
+        "source_control.decoration_renamed";
+    pub const SOURCE_CONTROL_DECORATION_UNTRACKED: &'static str =
+        "source_control.decoration_untracked";
+    pub const SOURCE_CONTROL_DECORATION_IGNORED: &'static str =
+        "source_control.decoration_ignored";
+    pub const SOURCE_CONTROL_DECORATION_CONFLICTED: &'static str =
+        "source_control.decoration_conflicted";
 
     pub const TERMINAL_CURSOR: &'static str = "terminal.cursor";
     pub const TERMINAL_BACKGROUND: &'static str = "terminal.background";
@@ -117,6 +137,20 @@ pub struct LapceConfig {
     pub color_theme: String,
     #[field_names(desc = "Set the terminal Shell")]
     pub terminal_shell: String,
+    #[field_names(
+        desc = "The maximum number of global search matches shown per file"
+    )]
+    pub search_max_file_matches: usize,
+    #[field_names(
+        desc = "The maximum number of global search matches, the search stops when it's reached"
+    )]
+    pub search_max_matches: usize,
+    #[field_names(
+        desc = "The longest the first line of a commit message may be, 0 allows any length"
+    )]
+    pub commit_subject_max_length: usize,
+    #[field_names(desc = "Add a Signed-off-by trailer to commit messages")]
+    pub commit_sign_off: bool,
 }
 
 #[derive(FieldNames, Debug, Clone, Deserialize, Serialize, Default)]
@@ -138,12 +172,142 @@ pub struct EditorConfig {
         desc = "How long (in ms) it should take before the hover information appears"
     )]
     pub hover_delay: u64,
+    #[field_names(
+        desc = "Files larger than this size (in MB) are opened without syntax highlighting, language server and git diff"
+    )]
+    pub large_file_size: u64,
+    #[field_names(
+        desc = "When to save modified files automatically: off, after-delay, on-focus-change or on-window-change"
+    )]
+    pub auto_save: AutoSave,
+    #[field_names(
+        desc = "How long (in ms) after the last edit files are saved when auto-save is after-delay"
+    )]
+    pub auto_save_delay: u64,
+    #[field_names(desc = "If auto-save formats the document before saving it")]
+    pub format_on_auto_save: bool,
+    #[field_names(
+        desc = "Show who last changed the current line at the end of the line"
+    )]
+    pub inline_blame: bool,
+    #[field_names(
+        desc = "Show who last changed each line in a column of the gutter"
+    )]
+    pub blame_gutter: bool,
 }
 
 impl EditorConfig {
     pub fn font_family(&self) -> FontFamily {
         FontFamily::new_unchecked(self.font_family.clone())
     }
+
+    /// The large file threshold in bytes.
+    pub fn large_file_threshold(&self) -> u64 {
+        self.large_file_size * 1024 * 1024
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, Default)]
+#[serde(rename_all = "kebab-case")]
+pub struct TerminalConfig {
+    /// The profile of new terminals. When it's empty, they run the
+    /// `terminal-shell`.
+    pub default_profile: String,
+    pub profiles: Vec<TerminalProfileConfig>,
+    /// Regexes of the links in the terminal output. A `url` group makes a
+    /// URL, otherwise the `path`, `line` and `column` groups make a file
+    /// location, where only the path is required.
+    pub link_patterns: Vec<String>,
+    /// The compiled `link_patterns`, without the invalid ones.
+    #[serde(skip)]
+    pub link_regexes: Vec<Regex>,
+    /// How many lines each terminal keeps above the screen.
+    pub scrollback_lines: usize,
+    /// How many lines of each terminal are saved with the workspace, to
+    /// show above the prompt when it's reopened. 0 saves none.
+    pub saved_scrollback_lines: usize,
+}
+
+/// A `[[terminal.profiles]]` entry of the settings.
+#[derive(Debug, Clone, Deserialize, Serialize, Default)]
+#[serde(default)]
+pub struct TerminalProfileConfig {
+    pub name: String,
+    pub program: String,
+    pub args: Vec<String>,
+    /// Environment variables as `NAME=value`, since the keys of a table
+    /// would be lowercased when the settings are loaded.
+    pub env: Vec<String>,
+    pub cwd: Option<PathBuf>,
+    /// The title of the terminal, instead of the one the shell sets.
+    pub title: Option<String>,
+}
+
+impl TerminalProfileConfig {
+    pub fn profile(&self) -> TerminalProfile {
+        TerminalProfile {
+            program: self.program.clone(),
+            args: self.args.clone(),
+            env: self
+                .env
+                .iter()
+                .filter_map(|var| {
+                    let (name, value) = var.split_once('=')?;
+                    Some((name.to_string(), value.to_string()))
+                })
+                .collect(),
+            cwd: self.cwd.clone(),
+            report_exit_code: false,
+        }
+    }
+}
+
+/// Splits a command line at whitespace. Quotes group words with spaces and
+/// a backslash escapes a quote, any other backslash is kept as it is so that
+/// Windows paths stay intact.
+fn split_command_line(line: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut quote = None;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if matches!(chars.peek(), Some(&'"') | Some(&'\'')) => {
+                let c = chars.next().unwrap();
+                word.get_or_insert_with(String::new).push(c);
+            }
+            '"' | '\'' if quote.is_none() => {
+                quote = Some(c);
+                word.get_or_insert_with(String::new);
+            }
+            c if quote == Some(c) => quote = None,
+            c if c.is_whitespace() && quote.is_none() => {
+                words.extend(word.take());
+            }
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    words.extend(word);
+    words
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AutoSave {
+    Off,
+    /// Save once no edit was made for `auto-save-delay` ms.
+    AfterDelay,
+    /// Save the active editor's file when another editor or a panel gets
+    /// the focus.
+    OnFocusChange,
+    /// Save all files when the window loses the focus.
+    OnWindowChange,
+}
+
+impl Default for AutoSave {
+    fn default() -> Self {
+        AutoSave::Off
+    }
 }
 
 #[derive(Debug, Clone, Default)]
@@ -330,6 +494,8 @@ impl Themes {
 pub struct Config {
     pub lapce: LapceConfig,
     pub editor: EditorConfig,
+    #[serde(default)]
+    pub terminal: TerminalConfig,
     #[serde(skip)]
     pub themes: Themes,
 }
@@ -387,6 +553,18 @@ impl Config {
         }
 
         let mut config: Config = settings.try_into()?;
+        config.terminal.link_regexes = config
+            .terminal
+            .link_patterns
+            .iter()
+            .filter_map(|pattern| match Regex::new(pattern) {
+                Ok(regex) => Some(regex),
+                Err(e) => {
+                    log::error!("invalid terminal link pattern {pattern}: {e}");
+                    None
+                }
+            })
+            .collect();
 
         config.themes = Themes::default();
 
@@ -395,6 +573,27 @@ impl Config {
         Ok(config)
     }
 
+    /// The terminal profile called `name`, or the default profile when it's
+    /// `None`. Falls back to a profile running the `terminal-shell`.
+    pub fn terminal_profile(&self, name: Option<&str>) -> TerminalProfileConfig {
+        let name = name.unwrap_or(&self.terminal.default_profile);
+        if let Some(profile) = self
+            .terminal
+            .profiles
+            .iter()
+            .find(|profile| profile.name == name)
+        {
+            return profile.clone();
+        }
+
+        let mut words = split_command_line(&self.lapce.terminal_shell).into_iter();
+        TerminalProfileConfig {
+            program: words.next().unwrap_or_default(),
+            args: words.collect(),
+            ..Default::default()
+        }
+    }
+
     pub fn dir() -> Option<PathBuf> {
         ProjectDirs::from("", "", "Lapce").map(|d| PathBuf::from(d.config_dir()))
     }
@@ -717,3 +916,51 @@ impl Config {
         Some(path)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_command_line() {
+        assert_eq!(
+            split_command_line("  cargo run   --release "),
+            vec!["cargo", "run", "--release"]
+        );
+        assert_eq!(
+            split_command_line(r#"git commit -m "first commit" 'it"s'"#),
+            vec!["git", "commit", "-m", "first commit", "it\"s"]
+        );
+        assert_eq!(
+            split_command_line(r#"--name="a b"c ''"#),
+            vec!["--name=a bc", ""]
+        );
+        assert_eq!(
+            split_command_line(r#"echo \"quoted\" "\'""#),
+            vec!["echo", "\"quoted\"", "'"]
+        );
+        assert_eq!(
+            split_command_line(r"C:\Tools\app.exe C:\Users\me"),
+            vec![r"C:\Tools\app.exe", r"C:\Users\me"]
+        );
+        assert!(split_command_line("   ").is_empty());
+    }
+
+    #[test]
+    fn test_auto_save() {
+        #[derive(Deserialize)]
+        #[serde(rename_all = "kebab-case")]
+        struct Settings {
+            auto_save: AutoSave,
+        }
+        let parse = |value: &str| {
+            toml::from_str::<Settings>(&format!("auto-save = \"{value}\""))
+                .map(|settings| settings.auto_save)
+        };
+        assert_eq!(parse("off").unwrap(), AutoSave::Off);
+        assert_eq!(parse("after-delay").unwrap(), AutoSave::AfterDelay);
+        assert_eq!(parse("on-focus-change").unwrap(), AutoSave::OnFocusChange);
+        assert_eq!(parse("on-window-change").unwrap(), AutoSave::OnWindowChange);
+        assert!(parse("on-focus").is_err());
+    }
+}
@@ -1,15 +1,16 @@
 use std::{
     cell::RefCell,
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     path::{Path, PathBuf},
     rc::Rc,
     str::FromStr,
     sync::Arc,
     thread,
+    time::Duration,
 };
 
-use anyhow::Result;
-use crossbeam_channel::{unbounded, Receiver, Sender};
+use anyhow::{anyhow, Result};
+use crossbeam_channel::{bounded, unbounded, Receiver, Sender};
 use druid::{
     piet::{PietText, PietTextLayout, Text, TextLayout, TextLayoutBuilder},
     theme, Command, Data, Env, EventCtx, ExtEventSink, FontFamily, Lens,
@@ -17,16 +18,22 @@ use druid::{
 };
 
 use lapce_rpc::{
-    file::FileNodeItem, plugin::PluginDescription, source_control::FileDiff,
+    buffer::BufferHeadResponse,
+    file::FileNodeItem,
+    plugin::PluginDescription,
+    search::SearchReplacement,
+    source_control::{BlameHunk, FileDiff, GitCommitFileResponse},
     terminal::TermId,
+    Callback,
 };
 use lsp_types::{
-    CodeActionOrCommand, Diagnostic, Position, ProgressToken, TextEdit,
+    CodeActionOrCommand, Diagnostic, DiagnosticSeverity, Position, ProgressToken,
+    TextEdit,
 };
 use notify::Watcher;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use xi_rope::{RopeDelta, Transformer};
+use xi_rope::{Rope, RopeDelta, Transformer};
 
 use crate::{
     buffer::{
@@ -41,7 +48,7 @@ use crate::{
     config::{Config, ConfigWatcher, GetConfig, LapceTheme},
     db::{
         EditorInfo, EditorTabChildInfo, EditorTabInfo, LapceDb, SplitContentInfo,
-        SplitInfo, TabsInfo, WindowInfo, WorkspaceInfo,
+        SplitInfo, TabsInfo, UnsavedBufferInfo, WindowInfo, WorkspaceInfo,
     },
     editor::{EditorLocationNew, LapceEditorBufferData, TabRect},
     explorer::FileExplorerData,
@@ -50,18 +57,21 @@ use crate::{
     keypress::KeyPressData,
     menu::MenuData,
     movement::{Cursor, CursorMode, Movement, Selection},
-    palette::{PaletteData, PaletteType, PaletteViewData},
+    palette::{
+        GitBranchAction, GitStashAction, PaletteData, PaletteType, PaletteViewData,
+    },
     panel::PanelPosition,
     picker::FilePickerData,
     plugin::PluginData,
     problem::ProblemData,
     proxy::{LapceProxy, ProxyStatus, TermEvent},
-    search::SearchData,
+    search::{Match, SearchData},
     settings::LapceSettingsPanelData,
     source_control::SourceControlData,
     split::{SplitDirection, SplitMoveDirection},
     state::{LapceWorkspace, LapceWorkspaceType, VisualMode},
-    terminal::TerminalSplitData,
+    task::{read_tasks, TaskRun, TasksConfig},
+    terminal::{LapceTerminalData, TerminalSplitData},
 };
 
 #[derive(Clone, Data)]
@@ -410,6 +420,8 @@ pub struct LapceTabData {
     pub db: Arc<LapceDb>,
     pub progresses: im::Vector<WorkProgress>,
     pub drag: Arc<Option<(Vec2, DragContent)>>,
+    /// A short message shown in the status bar for a few seconds.
+    pub status_message: Option<String>,
 }
 
 impl Data for LapceTabData {
@@ -440,6 +452,7 @@ impl Data for LapceTabData {
             && self.drag.same(&other.drag)
             && self.keypress.same(&other.keypress)
             && self.settings.same(&other.settings)
+            && self.status_message == other.status_message
     }
 }
 
@@ -526,6 +539,27 @@ impl LapceTabData {
             &config,
             event_sink.clone(),
         );
+        main_split.add_editor(
+            search.replace_view_id,
+            None,
+            LocalBufferKind::SearchReplace,
+            &config,
+            event_sink.clone(),
+        );
+        main_split.add_editor(
+            search.include_view_id,
+            None,
+            LocalBufferKind::SearchInclude,
+            &config,
+            event_sink.clone(),
+        );
+        main_split.add_editor(
+            search.exclude_view_id,
+            None,
+            LocalBufferKind::SearchExclude,
+            &config,
+            event_sink.clone(),
+        );
         main_split.add_editor(
             file_picker.editor_view_id,
             None,
@@ -534,7 +568,12 @@ impl LapceTabData {
             event_sink.clone(),
         );
 
-        let terminal = Arc::new(TerminalSplitData::new(proxy.clone()));
+        let mut terminal = TerminalSplitData::new(proxy.clone());
+        terminal.restore = workspace_info
+            .as_ref()
+            .map(|info| info.terminal.clone())
+            .filter(|info| !info.terminals.is_empty());
+        let terminal = Arc::new(terminal);
         let problem = Arc::new(ProblemData::new());
 
         let mut panels = im::HashMap::new();
@@ -606,6 +645,7 @@ impl LapceTabData {
             db,
             progresses: im::Vector::new(),
             drag: Arc::new(None),
+            status_message: None,
         };
         tab.start_update_process(event_sink);
         tab
@@ -619,6 +659,7 @@ impl LapceTabData {
             .unwrap();
         WorkspaceInfo {
             split: main_split_data.split_info(self, self.config.editor.tab_width),
+            terminal: self.terminal.split_info(&self.config),
         }
     }
 
@@ -922,6 +963,7 @@ impl LapceTabData {
             find: self.find.clone(),
             focus_area: self.focus_area.clone(),
             terminal: self.terminal.clone(),
+            source_control: self.source_control.clone(),
         }
     }
 
@@ -1099,6 +1141,14 @@ impl LapceTabData {
                 config.lapce.modal = false;
                 Config::update_file("lapce.modal", toml::Value::Boolean(false));
             }
+            LapceWorkbenchCommand::ToggleBlameGutter => {
+                let config = Arc::make_mut(&mut self.config);
+                config.editor.blame_gutter = !config.editor.blame_gutter;
+                Config::update_file(
+                    "editor.blame-gutter",
+                    toml::Value::Boolean(config.editor.blame_gutter),
+                );
+            }
             LapceWorkbenchCommand::ChangeTheme => {
                 ctx.submit_command(Command::new(
                     LAPCE_UI_COMMAND,
@@ -1207,6 +1257,42 @@ impl LapceTabData {
                     Target::Widget(self.palette.widget_id),
                 ));
             }
+            LapceWorkbenchCommand::PaletteLocalHistory => {
+                ctx.submit_command(Command::new(
+                    LAPCE_UI_COMMAND,
+                    LapceUICommand::RunPalette(Some(PaletteType::LocalHistory)),
+                    Target::Widget(self.palette.widget_id),
+                ));
+            }
+            LapceWorkbenchCommand::GitLog => {
+                ctx.submit_command(Command::new(
+                    LAPCE_UI_COMMAND,
+                    LapceUICommand::RunPalette(Some(PaletteType::GitLog(None))),
+                    Target::Widget(self.palette.widget_id),
+                ));
+            }
+            LapceWorkbenchCommand::ShowFileHistory => {
+                if let Some(path) = self.source_control_path(data) {
+                    ctx.submit_command(Command::new(
+                        LAPCE_UI_COMMAND,
+                        LapceUICommand::RunPalette(Some(PaletteType::GitLog(Some(
+                            path,
+                        )))),
+                        Target::Widget(self.palette.widget_id),
+                    ));
+                }
+            }
+            LapceWorkbenchCommand::CompareWithRevision => {
+                if let Some(path) = self.source_control_path(data) {
+                    ctx.submit_command(Command::new(
+                        LAPCE_UI_COMMAND,
+                        LapceUICommand::RunPalette(Some(PaletteType::GitDiffBase(
+                            path,
+                        ))),
+                        Target::Widget(self.palette.widget_id),
+                    ));
+                }
+            }
             LapceWorkbenchCommand::NewTab => {
                 ctx.submit_command(Command::new(
                     LAPCE_UI_COMMAND,
@@ -1297,6 +1383,24 @@ impl LapceTabData {
             LapceWorkbenchCommand::ToggleSearchVisual => {
                 self.toggle_panel_visual(ctx, PanelKind::Search);
             }
+            LapceWorkbenchCommand::ToggleSearchCaseSensitive => {
+                let options = &mut Arc::make_mut(&mut self.search).options;
+                options.case_sensitive = !options.case_sensitive;
+                self.refresh_search(ctx);
+            }
+            LapceWorkbenchCommand::ToggleSearchWholeWord => {
+                let options = &mut Arc::make_mut(&mut self.search).options;
+                options.whole_word = !options.whole_word;
+                self.refresh_search(ctx);
+            }
+            LapceWorkbenchCommand::ToggleSearchRegex => {
+                let options = &mut Arc::make_mut(&mut self.search).options;
+                options.is_regex = !options.is_regex;
+                self.refresh_search(ctx);
+            }
+            LapceWorkbenchCommand::SearchReplaceAll => {
+                self.confirm_search_replace_all(ctx);
+            }
             LapceWorkbenchCommand::ToggleProblemVisual => {
                 self.toggle_panel_visual(ctx, PanelKind::Problem);
             }
@@ -1329,6 +1433,59 @@ impl LapceTabData {
             LapceWorkbenchCommand::ToggleTerminalFocus => {
                 self.toggle_panel_focus(ctx, PanelKind::Terminal);
             }
+            LapceWorkbenchCommand::NewTerminal => {
+                let profile = data
+                    .and_then(|data| serde_json::from_value::<String>(data).ok());
+                if profile.is_none() && !self.config.terminal.profiles.is_empty() {
+                    ctx.submit_command(Command::new(
+                        LAPCE_UI_COMMAND,
+                        LapceUICommand::RunPalette(Some(
+                            PaletteType::TerminalProfile,
+                        )),
+                        Target::Widget(self.palette.widget_id),
+                    ));
+                } else {
+                    self.show_panel(ctx, PanelKind::Terminal);
+                    ctx.submit_command(Command::new(
+                        LAPCE_UI_COMMAND,
+                        LapceUICommand::NewTerminal(profile, None),
+                        Target::Widget(self.terminal.split_id),
+                    ));
+                }
+            }
+            LapceWorkbenchCommand::RunTask => {
+                let name = data
+                    .and_then(|data| serde_json::from_value::<String>(data).ok());
+                if let Some(workspace) = self.workspace.path.as_ref() {
+                    match name {
+                        Some(name) => {
+                            let event_sink = ctx.get_external_handle();
+                            let tab_id = self.id;
+                            read_tasks(&self.proxy, workspace, move |tasks| {
+                                let command = match tasks {
+                                    Ok(tasks) => LapceUICommand::RunTask(
+                                        Arc::new(tasks),
+                                        name,
+                                    ),
+                                    Err(e) => LapceUICommand::ShowStatusMessage(
+                                        e.to_string(),
+                                    ),
+                                };
+                                let _ = event_sink.submit_command(
+                                    LAPCE_UI_COMMAND,
+                                    command,
+                                    Target::Widget(tab_id),
+                                );
+                            });
+                        }
+                        None => ctx.submit_command(Command::new(
+                            LAPCE_UI_COMMAND,
+                            LapceUICommand::RunPalette(Some(PaletteType::Task)),
+                            Target::Widget(self.palette.widget_id),
+                        )),
+                    }
+                }
+            }
             LapceWorkbenchCommand::TogglePanelFocus => {
                 if let Some(data) = data {
                     if let Ok(kind) = serde_json::from_value::<PanelKind>(data) {
@@ -1352,47 +1509,304 @@ impl LapceTabData {
                 }
             }
             LapceWorkbenchCommand::SourceControlCommit => {
-                let diffs: Vec<FileDiff> = self
-                    .source_control
-                    .file_diffs
-                    .iter()
-                    .filter_map(
-                        |(diff, checked)| {
-                            if *checked {
-                                Some(diff.clone())
-                            } else {
-                                None
+                self.commit(ctx, false);
+            }
+            LapceWorkbenchCommand::SourceControlAmend => {
+                self.commit(ctx, true);
+            }
+            LapceWorkbenchCommand::SourceControlStage => {
+                if let Some(path) = self.source_control_path(data) {
+                    let callback =
+                        self.git_callback(ctx, None, "Can't stage".to_string());
+                    self.proxy.git_stage(vec![path], callback);
+                }
+            }
+            LapceWorkbenchCommand::SourceControlUnstage => {
+                if let Some(path) = self.source_control_path(data) {
+                    let callback =
+                        self.git_callback(ctx, None, "Can't unstage".to_string());
+                    self.proxy.git_unstage(vec![path], callback);
+                }
+            }
+            LapceWorkbenchCommand::SourceControlMarkResolved => {
+                if let Some(path) = self.source_control_path(data) {
+                    // Unsaved edits may still have markers the file doesn't.
+                    let unresolved = self
+                        .main_split
+                        .open_files
+                        .get(&path)
+                        .map(|buffer| !buffer.conflicts().is_empty())
+                        .unwrap_or(false);
+                    if unresolved {
+                        ctx.submit_command(Command::new(
+                            LAPCE_UI_COMMAND,
+                            LapceUICommand::ShowStatusMessage(
+                                "Can't mark as resolved: the file still has conflict markers"
+                                    .to_string(),
+                            ),
+                            Target::Widget(self.id),
+                        ));
+                    } else {
+                        let callback = self.git_callback(
+                            ctx,
+                            None,
+                            "Can't mark as resolved".to_string(),
+                        );
+                        self.proxy.git_resolve(&path, callback);
+                    }
+                }
+            }
+            LapceWorkbenchCommand::SourceControlDiscard => {
+                if let Some(path) = self.source_control_path(data) {
+                    let proxy = self.proxy.clone();
+                    let callback = self.git_callback(
+                        ctx,
+                        None,
+                        "Can't discard changes".to_string(),
+                    );
+                    let file_name = path
+                        .file_name()
+                        .and_then(|f| f.to_str())
+                        .unwrap_or("")
+                        .to_string();
+                    // The dialog blocks, so it gets a thread of its own.
+                    thread::spawn(move || {
+                        let answer = tinyfiledialogs::message_box_yes_no(
+                            "Discard Changes",
+                            &format!(
+                                "Discard the changes to {file_name}? This can't be undone."
+                            ),
+                            tinyfiledialogs::MessageBoxIcon::Warning,
+                            tinyfiledialogs::YesNo::No,
+                        );
+                        if answer == tinyfiledialogs::YesNo::Yes {
+                            proxy.git_discard(vec![path], callback);
+                        }
+                    });
+                }
+            }
+            LapceWorkbenchCommand::CheckoutBranch
+            | LapceWorkbenchCommand::ForceCheckoutBranch => {
+                let force = command == &LapceWorkbenchCommand::ForceCheckoutBranch;
+                match data.and_then(|d| serde_json::from_value::<String>(d).ok()) {
+                    Some(branch) => {
+                        self.proxy.git_checkout(
+                            &self.source_control.repository,
+                            &branch,
+                            force,
+                            self.git_callback(
+                                ctx,
+                                Some(format!("Switched to branch {branch}")),
+                                format!("Can't checkout {branch}"),
+                            ),
+                        );
+                    }
+                    None => {
+                        let action = if force {
+                            GitBranchAction::ForceCheckout
+                        } else {
+                            GitBranchAction::Checkout
+                        };
+                        ctx.submit_command(Command::new(
+                            LAPCE_UI_COMMAND,
+                            LapceUICommand::RunPalette(Some(
+                                PaletteType::GitBranch(action),
+                            )),
+                            Target::Widget(self.palette.widget_id),
+                        ));
+                    }
+                }
+            }
+            LapceWorkbenchCommand::CreateBranch => {
+                let name = data
+                    .as_ref()
+                    .and_then(|d| d.get("name"))
+                    .and_then(|n| n.as_str());
+                match name {
+                    Some(name) => {
+                        let start_point = data
+                            .as_ref()
+                            .and_then(|d| d.get("start_point"))
+                            .and_then(|s| s.as_str());
+                        self.proxy.git_create_branch(
+                            &self.source_control.repository,
+                            name,
+                            start_point,
+                            self.git_callback(
+                                ctx,
+                                Some(format!("Created branch {name}")),
+                                format!("Can't create branch {name}"),
+                            ),
+                        );
+                    }
+                    None => {
+                        ctx.submit_command(Command::new(
+                            LAPCE_UI_COMMAND,
+                            LapceUICommand::RunPalette(Some(
+                                PaletteType::GitBranch(GitBranchAction::Create),
+                            )),
+                            Target::Widget(self.palette.widget_id),
+                        ));
+                    }
+                }
+            }
+            LapceWorkbenchCommand::DeleteBranch => {
+                match data.and_then(|d| serde_json::from_value::<String>(d).ok()) {
+                    Some(branch) => {
+                        self.proxy.git_delete_branch(
+                            &self.source_control.repository,
+                            &branch,
+                            self.git_callback(
+                                ctx,
+                                Some(format!("Deleted branch {branch}")),
+                                format!("Can't delete branch {branch}"),
+                            ),
+                        );
+                    }
+                    None => {
+                        ctx.submit_command(Command::new(
+                            LAPCE_UI_COMMAND,
+                            LapceUICommand::RunPalette(Some(
+                                PaletteType::GitBranch(GitBranchAction::Delete),
+                            )),
+                            Target::Widget(self.palette.widget_id),
+                        ));
+                    }
+                }
+            }
+            LapceWorkbenchCommand::GitFetch => {
+                self.proxy.git_fetch(
+                    &self.source_control.repository,
+                    self.git_callback(
+                        ctx,
+                        Some("Fetched the remote".to_string()),
+                        "Can't fetch".to_string(),
+                    ),
+                );
+            }
+            LapceWorkbenchCommand::GitPull
+            | LapceWorkbenchCommand::GitPullRebase => {
+                self.proxy.git_pull(
+                    &self.source_control.repository,
+                    command == &LapceWorkbenchCommand::GitPullRebase,
+                    self.git_callback(
+                        ctx,
+                        Some(format!("Pulled {}", self.source_control.branch)),
+                        "Can't pull".to_string(),
+                    ),
+                );
+            }
+            LapceWorkbenchCommand::GitPush => {
+                self.proxy.git_push(
+                    &self.source_control.repository,
+                    self.git_callback(
+                        ctx,
+                        Some(format!("Pushed {}", self.source_control.branch)),
+                        "Can't push".to_string(),
+                    ),
+                );
+            }
+            LapceWorkbenchCommand::StashChanges
+            | LapceWorkbenchCommand::StashChangesIncludeUntracked => {
+                let include_untracked =
+                    command == &LapceWorkbenchCommand::StashChangesIncludeUntracked;
+                let message = data
+                    .as_ref()
+                    .and_then(|d| d.get("message"))
+                    .and_then(|m| m.as_str());
+                match message {
+                    Some(message) => {
+                        self.proxy.git_stash_save(
+                            &self.source_control.repository,
+                            message,
+                            include_untracked,
+                            self.git_callback(
+                                ctx,
+                                Some("Stashed the changes".to_string()),
+                                "Can't stash the changes".to_string(),
+                            ),
+                        );
+                    }
+                    None => {
+                        ctx.submit_command(Command::new(
+                            LAPCE_UI_COMMAND,
+                            LapceUICommand::RunPalette(Some(
+                                PaletteType::GitStashMessage(include_untracked),
+                            )),
+                            Target::Widget(self.palette.widget_id),
+                        ));
+                    }
+                }
+            }
+            LapceWorkbenchCommand::ShowStashes => {
+                ctx.submit_command(Command::new(
+                    LAPCE_UI_COMMAND,
+                    LapceUICommand::RunPalette(Some(PaletteType::GitStash(
+                        GitStashAction::Show,
+                    ))),
+                    Target::Widget(self.palette.widget_id),
+                ));
+            }
+            LapceWorkbenchCommand::ApplyStash
+            | LapceWorkbenchCommand::PopStash
+            | LapceWorkbenchCommand::DropStash => {
+                match data.and_then(|d| serde_json::from_value::<usize>(d).ok()) {
+                    Some(index) => {
+                        let name = format!("stash@{{{index}}}");
+                        match command {
+                            LapceWorkbenchCommand::ApplyStash => {
+                                self.proxy.git_stash_apply(
+                                    &self.source_control.repository,
+                                    index,
+                                    self.git_callback(
+                                        ctx,
+                                        Some(format!("Applied {name}")),
+                                        format!("Can't apply {name}"),
+                                    ),
+                                );
                             }
-                        },
-                    )
-                    .collect();
-                if diffs.is_empty() {
-                    return;
-                }
-                let buffer = self
-                    .main_split
-                    .local_buffers
-                    .get_mut(&LocalBufferKind::SourceControl)
-                    .unwrap();
-                let message = buffer.rope().to_string();
-                let message = message.trim();
-                if message.is_empty() {
-                    return;
-                }
-                self.proxy.git_commit(message, diffs);
-                Arc::make_mut(buffer).load_content("");
-                let editor = self
-                    .main_split
-                    .editors
-                    .get_mut(&self.source_control.editor_view_id)
-                    .unwrap();
-                Arc::make_mut(editor).cursor = if self.config.lapce.modal {
-                    Cursor::new(CursorMode::Normal(0), None)
-                } else {
-                    Cursor::new(CursorMode::Insert(Selection::caret(0)), None)
-                };
+                            LapceWorkbenchCommand::PopStash => {
+                                self.proxy.git_stash_pop(
+                                    &self.source_control.repository,
+                                    index,
+                                    self.git_callback(
+                                        ctx,
+                                        Some(format!("Popped {name}")),
+                                        format!("Can't pop {name}"),
+                                    ),
+                                );
+                            }
+                            _ => {
+                                self.proxy.git_stash_drop(
+                                    &self.source_control.repository,
+                                    index,
+                                    self.git_callback(
+                                        ctx,
+                                        Some(format!("Dropped {name}")),
+                                        format!("Can't drop {name}"),
+                                    ),
+                                );
+                            }
+                        }
+                    }
+                    None => {
+                        let action = match command {
+                            LapceWorkbenchCommand::ApplyStash => {
+                                GitStashAction::Apply
+                            }
+                            LapceWorkbenchCommand::PopStash => GitStashAction::Pop,
+                            _ => GitStashAction::Drop,
+                        };
+                        ctx.submit_command(Command::new(
+                            LAPCE_UI_COMMAND,
+                            LapceUICommand::RunPalette(Some(PaletteType::GitStash(
+                                action,
+                            ))),
+                            Target::Widget(self.palette.widget_id),
+                        ));
+                    }
+                }
             }
-            LapceWorkbenchCommand::CheckoutBranch => {}
             LapceWorkbenchCommand::ConnectSshHost => {
                 ctx.submit_command(Command::new(
                     LAPCE_UI_COMMAND,
@@ -1475,7 +1889,13 @@ impl LapceTabData {
             };
             match event {
                 TermEvent::CloseTerminal => {
+                    // After the last content of the terminal was applied.
                     terminals.remove(&term_id);
+                    let _ = event_sink.submit_command(
+                        LAPCE_UI_COMMAND,
+                        LapceUICommand::TerminalExited(term_id),
+                        Target::Widget(tab_id),
+                    );
                 }
                 TermEvent::NewTerminal(raw) => {
                     terminals.insert(term_id, raw);
@@ -1564,60 +1984,516 @@ impl LapceTabData {
         }
     }
 
-    fn toggle_panel_visual(&mut self, ctx: &mut EventCtx, kind: PanelKind) {
-        if self.is_panel_visible(kind) {
-            self.hide_panel(ctx, kind);
-        } else {
-            self.show_panel(ctx, kind);
+    /// Runs the task called `name` in a terminal, once the tasks it depends
+    /// on ran successfully.
+    pub fn run_task(
+        &mut self,
+        ctx: &mut EventCtx,
+        tasks: Arc<TasksConfig>,
+        name: &str,
+    ) {
+        let mut plan = match tasks.plan(name) {
+            Ok(plan) => plan.into_iter(),
+            Err(e) => {
+                ctx.submit_command(Command::new(
+                    LAPCE_UI_COMMAND,
+                    LapceUICommand::ShowStatusMessage(format!(
+                        "Can't run task: {e}"
+                    )),
+                    Target::Widget(self.id),
+                ));
+                return;
+            }
+        };
+        if let Some(task) = plan.next() {
+            self.start_task(
+                ctx,
+                TaskRun {
+                    task,
+                    pending: plan.collect(),
+                    tasks,
+                    finished: false,
+                    exit_code: None,
+                },
+            );
         }
     }
 
-    fn toggle_panel_focus(&mut self, ctx: &mut EventCtx, kind: PanelKind) {
-        let should_hide = match kind {
-            PanelKind::FileExplorer | PanelKind::Plugin | PanelKind::Problem => {
-                // Some panels don't accept focus (yet). Fall back to visibility check
-                // in those cases.
-                self.is_panel_visible(kind)
+    fn start_task(&mut self, ctx: &mut EventCtx, run: TaskRun) {
+        self.show_panel(ctx, PanelKind::Terminal);
+
+        // The terminal of the last run of the task is replaced, but only
+        // once the new one is there so that the panel stays open.
+        let finished: Vec<_> = self
+            .terminal
+            .terminals
+            .values()
+            .filter(|terminal| {
+                terminal.task.as_ref().map_or(false, |task| {
+                    task.finished && task.task.name == run.task.name
+                })
+            })
+            .map(|terminal| {
+                (terminal.term_id, terminal.widget_id, terminal.split_id)
+            })
+            .collect();
+        ctx.submit_command(Command::new(
+            LAPCE_UI_COMMAND,
+            LapceUICommand::NewTaskTerminal(run),
+            Target::Widget(self.terminal.split_id),
+        ));
+        for (term_id, widget_id, split_id) in finished {
+            ctx.submit_command(Command::new(
+                LAPCE_UI_COMMAND,
+                LapceUICommand::SplitTerminalClose(term_id, widget_id),
+                Target::Widget(split_id),
+            ));
+        }
+    }
+
+    /// Called when the task of the terminal exited. The problems in its
+    /// output replace the ones it found before, and the next task starts if
+    /// it succeeded.
+    pub fn task_finished(&mut self, ctx: &mut EventCtx, term_id: &TermId) {
+        let terminal =
+            match Arc::make_mut(&mut self.terminal).terminals.get_mut(term_id) {
+                Some(terminal) => Arc::make_mut(terminal),
+                None => return,
+            };
+        let (exit_code, output) = {
+            let raw = terminal.raw.lock();
+            let exit_code = raw
+                .shell
+                .commands
+                .last()
+                .and_then(|command| command.exit_code);
+            (exit_code, raw.shell.last_output(&raw.term))
+        };
+        let task = match terminal.task.as_mut() {
+            Some(task) if !task.finished => task,
+            _ => return,
+        };
+        task.finished = true;
+        task.exit_code = exit_code;
+        terminal.title = match exit_code {
+            Some(0) => format!("{} (done)", terminal.title),
+            Some(code) => format!("{} (exit code {code})", terminal.title),
+            None => format!("{} (exited)", terminal.title),
+        };
+        let run = task.clone();
+
+        if let Some(name) = run.task.problem_matcher.as_ref() {
+            match run.tasks.problem_matcher(name) {
+                Ok(matcher) => {
+                    let cwd =
+                        run.task.working_directory(self.workspace.path.as_deref());
+                    let source = run.task.diagnostic_source();
+                    let problems = matcher.problems(
+                        output.as_deref().unwrap_or_default(),
+                        &cwd,
+                        &source,
+                    );
+                    self.main_split.set_task_diagnostics(&source, problems);
+                }
+                Err(e) => {
+                    ctx.submit_command(Command::new(
+                        LAPCE_UI_COMMAND,
+                        LapceUICommand::ShowStatusMessage(format!(
+                            "Can't match the problems of task {}: {e}",
+                            run.task.name
+                        )),
+                        Target::Widget(self.id),
+                    ));
+                }
             }
-            PanelKind::Terminal | PanelKind::SourceControl | PanelKind::Search => {
-                self.is_panel_focused(kind)
+        }
+
+        if exit_code != Some(0) {
+            if !run.pending.is_empty() {
+                ctx.submit_command(Command::new(
+                    LAPCE_UI_COMMAND,
+                    LapceUICommand::ShowStatusMessage(format!(
+                        "Task {} failed, the tasks that depend on it didn't run",
+                        run.task.name
+                    )),
+                    Target::Widget(self.id),
+                ));
             }
-        };
-        if should_hide {
-            self.hide_panel(ctx, kind);
-        } else {
-            self.show_panel(ctx, kind);
+            return;
+        }
+        let mut pending = run.pending.into_iter();
+        if let Some(task) = pending.next() {
+            self.start_task(
+                ctx,
+                TaskRun {
+                    task,
+                    pending: pending.collect(),
+                    tasks: run.tasks,
+                    finished: false,
+                    exit_code: None,
+                },
+            );
         }
     }
 
-    pub fn read_picker_pwd(&mut self, ctx: &mut EventCtx) {
-        let path = self.picker.pwd.clone();
+    /// Asks before "Replace All" goes ahead, since the files that aren't
+    /// open are written directly and can't be undone.
+    fn confirm_search_replace_all(&self, ctx: &mut EventCtx) {
+        let replacements = self.search.replacements();
+        if replacements.is_empty() {
+            return;
+        }
+        let matches: usize = replacements.values().map(|r| r.len()).sum();
+        let message = if self.search.replace.is_empty() {
+            format!(
+                "Delete {matches} matches in {} files? The replacement is empty.",
+                replacements.len()
+            )
+        } else {
+            format!(
+                "Replace {matches} matches in {} files with \"{}\"?",
+                replacements.len(),
+                self.search.replace
+            )
+        };
+        let search_id = self.search.search_id;
         let event_sink = ctx.get_external_handle();
         let tab_id = self.id;
-        self.proxy.read_dir(
-            &path.clone(),
-            Box::new(move |result| {
-                if let Ok(res) = result {
-                    let resp: Result<Vec<FileNodeItem>, serde_json::Error> =
-                        serde_json::from_value(res);
-                    if let Ok(items) = resp {
-                        let _ = event_sink.submit_command(
-                            LAPCE_UI_COMMAND,
-                            LapceUICommand::UpdatePickerItems(
-                                path,
-                                items
-                                    .iter()
-                                    .map(|item| {
-                                        (item.path_buf.clone(), item.clone())
-                                    })
-                                    .collect(),
-                            ),
-                            Target::Widget(tab_id),
-                        );
-                    }
-                }
-            }),
-        );
+        // The dialog blocks, so it gets a thread of its own.
+        thread::spawn(move || {
+            let answer = tinyfiledialogs::message_box_yes_no(
+                "Replace All",
+                &message,
+                tinyfiledialogs::MessageBoxIcon::Warning,
+                tinyfiledialogs::YesNo::No,
+            );
+            if answer == tinyfiledialogs::YesNo::Yes {
+                let _ = event_sink.submit_command(
+                    LAPCE_UI_COMMAND,
+                    LapceUICommand::SearchReplaceAll(search_id, replacements),
+                    Target::Widget(tab_id),
+                );
+            }
+        });
+    }
+
+    /// Applies the confirmed replacements of the search with `search_id`.
+    /// Open files are edited like any other edit, so it can be undone, and
+    /// the proxy writes the rest directly.
+    pub fn search_replace_all(
+        &mut self,
+        ctx: &mut EventCtx,
+        search_id: u64,
+        replacements: HashMap<PathBuf, Vec<SearchReplacement>>,
+    ) {
+        if search_id != self.search.search_id {
+            return;
+        }
+
+        let mut edited_files = 0;
+        let mut unopened = HashMap::new();
+        for (path, file_replacements) in replacements {
+            match self.main_split.open_files.get(&path) {
+                None => {
+                    unopened.insert(path, file_replacements);
+                }
+                Some(buffer) if !buffer.loaded() => {
+                    // Applied once the file is loaded.
+                    buffer.retrieve_file(
+                        self.id,
+                        self.proxy.clone(),
+                        ctx.get_external_handle(),
+                        vec![],
+                        self.config.editor.large_file_threshold(),
+                    );
+                    self.main_split
+                        .pending_replacements
+                        .insert(path, Arc::new(file_replacements));
+                    edited_files += 1;
+                }
+                Some(_) => {
+                    if self.main_split.replace_in_buffer(
+                        &path,
+                        &file_replacements,
+                        &self.config,
+                    ) {
+                        edited_files += 1;
+                    }
+                }
+            }
+        }
+
+        // Only the excluded matches are left to show.
+        let search = Arc::make_mut(&mut self.search);
+        let matches: HashMap<PathBuf, Vec<Match>> = search
+            .matches
+            .iter()
+            .filter_map(|(path, matches)| {
+                let matches: Vec<Match> = matches
+                    .iter()
+                    .filter(|m| search.is_excluded(path, Some(m)))
+                    .cloned()
+                    .collect();
+                if matches.is_empty() {
+                    None
+                } else {
+                    Some((path.clone(), matches))
+                }
+            })
+            .collect();
+        search.matches = Arc::new(matches);
+
+        if unopened.is_empty() {
+            ctx.submit_command(Command::new(
+                LAPCE_UI_COMMAND,
+                LapceUICommand::ShowStatusMessage(format!(
+                    "Replaced in {edited_files} files"
+                )),
+                Target::Widget(self.id),
+            ));
+            return;
+        }
+        let event_sink = ctx.get_external_handle();
+        let tab_id = self.id;
+        self.proxy.replace_in_files(
+            unopened,
+            Box::new(move |result| {
+                let changed = result
+                    .ok()
+                    .and_then(|value| serde_json::from_value::<usize>(value).ok())
+                    .unwrap_or(0);
+                let _ = event_sink.submit_command(
+                    LAPCE_UI_COMMAND,
+                    LapceUICommand::ShowStatusMessage(format!(
+                        "Replaced in {} files",
+                        edited_files + changed
+                    )),
+                    Target::Widget(tab_id),
+                );
+            }),
+        );
+    }
+
+    /// A callback for a git request that shows `success`, if any, in the
+    /// status bar, or `failure` followed by the error from the proxy.
+    pub fn git_callback(
+        &self,
+        ctx: &mut EventCtx,
+        success: Option<String>,
+        failure: String,
+    ) -> Box<dyn Callback> {
+        let event_sink = ctx.get_external_handle();
+        let tab_id = self.id;
+        Box::new(move |result| {
+            let message = match result {
+                Ok(_) => match success {
+                    Some(success) => success,
+                    None => return,
+                },
+                Err(err) => {
+                    let err = err
+                        .get("message")
+                        .and_then(|m| m.as_str())
+                        .unwrap_or("unknown error");
+                    format!("{failure}: {err}")
+                }
+            };
+            let _ = event_sink.submit_command(
+                LAPCE_UI_COMMAND,
+                LapceUICommand::ShowStatusMessage(message),
+                Target::Widget(tab_id),
+            );
+        })
+    }
+
+    /// Commits the staged changes, or all changes when nothing is staged,
+    /// with the message in the source control panel. Amending may leave the
+    /// message empty to keep the last commit's.
+    fn commit(&mut self, ctx: &mut EventCtx, amend: bool) {
+        let source_control = &self.source_control;
+        // Amending with nothing staged only rewords HEAD's commit, instead of
+        // pulling every change into it.
+        let diffs = if source_control.staged_diffs.is_empty() && !amend {
+            source_control.file_diffs.clone()
+        } else {
+            Vec::new()
+        };
+        if !amend && diffs.is_empty() && source_control.staged_diffs.is_empty() {
+            return;
+        }
+        let message = self
+            .main_split
+            .local_buffers
+            .get(&LocalBufferKind::SourceControl)
+            .unwrap()
+            .rope()
+            .to_string();
+        let message = message.trim();
+        if message.is_empty() && !amend {
+            return;
+        }
+        let max_length = self.config.lapce.commit_subject_max_length;
+        let subject_length = message.lines().next().unwrap_or("").chars().count();
+        if max_length > 0 && subject_length > max_length {
+            ctx.submit_command(Command::new(
+                LAPCE_UI_COMMAND,
+                LapceUICommand::ShowStatusMessage(format!(
+                    "The commit subject is {subject_length} characters long, the limit is {max_length}"
+                )),
+                Target::Widget(self.id),
+            ));
+            return;
+        }
+
+        let event_sink = ctx.get_external_handle();
+        let tab_id = self.id;
+        self.proxy.git_commit(
+            &self.source_control.repository,
+            message,
+            diffs,
+            amend,
+            self.config.lapce.commit_sign_off,
+            Box::new(move |result| match result {
+                Ok(_) => {
+                    let _ = event_sink.submit_command(
+                        LAPCE_UI_COMMAND,
+                        LapceUICommand::ClearCommitMessage,
+                        Target::Widget(tab_id),
+                    );
+                }
+                Err(err) => {
+                    let err = err
+                        .get("message")
+                        .and_then(|m| m.as_str())
+                        .unwrap_or("unknown error")
+                        .to_string();
+                    let _ = event_sink.submit_command(
+                        LAPCE_UI_COMMAND,
+                        LapceUICommand::ShowStatusMessage(format!(
+                            "Can't commit: {}",
+                            err.lines().next().unwrap_or("")
+                        )),
+                        Target::Widget(tab_id),
+                    );
+                    // Hook output spans lines, which the status bar can't
+                    // show, so it goes to a dialog.
+                    if err.contains('\n') {
+                        thread::spawn(move || {
+                            tinyfiledialogs::message_box_ok(
+                                "Commit Failed",
+                                &err,
+                                tinyfiledialogs::MessageBoxIcon::Error,
+                            );
+                        });
+                    }
+                }
+            }),
+        );
+    }
+
+    /// Empties the commit message, moving the cursor back to the start.
+    pub fn clear_commit_message(&mut self) {
+        let buffer = self
+            .main_split
+            .local_buffers
+            .get_mut(&LocalBufferKind::SourceControl)
+            .unwrap();
+        Arc::make_mut(buffer).load_content("");
+        let editor = self
+            .main_split
+            .editors
+            .get_mut(&self.source_control.editor_view_id)
+            .unwrap();
+        Arc::make_mut(editor).cursor = if self.config.lapce.modal {
+            Cursor::new(CursorMode::Normal(0), None)
+        } else {
+            Cursor::new(CursorMode::Insert(Selection::caret(0)), None)
+        };
+    }
+
+    /// The file a source control command acts on: the path passed along with
+    /// it, or else the file of the active editor.
+    fn source_control_path(
+        &self,
+        data: Option<serde_json::Value>,
+    ) -> Option<PathBuf> {
+        data.and_then(|data| serde_json::from_value::<PathBuf>(data).ok())
+            .or_else(|| match self.main_split.active_editor()?.content {
+                BufferContent::File(ref path) => Some(path.clone()),
+                _ => None,
+            })
+    }
+
+    /// Runs the current search pattern again, e.g. after the options changed.
+    pub fn refresh_search(&self, ctx: &mut EventCtx) {
+        let pattern = self
+            .main_split
+            .local_buffers
+            .get(&LocalBufferKind::Search)
+            .unwrap()
+            .rope()
+            .to_string();
+        ctx.submit_command(Command::new(
+            LAPCE_UI_COMMAND,
+            LapceUICommand::UpdateSearch(pattern),
+            Target::Widget(self.id),
+        ));
+    }
+
+    fn toggle_panel_visual(&mut self, ctx: &mut EventCtx, kind: PanelKind) {
+        if self.is_panel_visible(kind) {
+            self.hide_panel(ctx, kind);
+        } else {
+            self.show_panel(ctx, kind);
+        }
+    }
+
+    fn toggle_panel_focus(&mut self, ctx: &mut EventCtx, kind: PanelKind) {
+        let should_hide = match kind {
+            PanelKind::FileExplorer | PanelKind::Plugin | PanelKind::Problem => {
+                // Some panels don't accept focus (yet). Fall back to visibility check
+                // in those cases.
+                self.is_panel_visible(kind)
+            }
+            PanelKind::Terminal | PanelKind::SourceControl | PanelKind::Search => {
+                self.is_panel_focused(kind)
+            }
+        };
+        if should_hide {
+            self.hide_panel(ctx, kind);
+        } else {
+            self.show_panel(ctx, kind);
+        }
+    }
+
+    pub fn read_picker_pwd(&mut self, ctx: &mut EventCtx) {
+        let path = self.picker.pwd.clone();
+        let event_sink = ctx.get_external_handle();
+        let tab_id = self.id;
+        self.proxy.read_dir(
+            &path.clone(),
+            Box::new(move |result| {
+                if let Ok(res) = result {
+                    let resp: Result<Vec<FileNodeItem>, serde_json::Error> =
+                        serde_json::from_value(res);
+                    if let Ok(items) = resp {
+                        let _ = event_sink.submit_command(
+                            LAPCE_UI_COMMAND,
+                            LapceUICommand::UpdatePickerItems(
+                                path,
+                                items
+                                    .iter()
+                                    .map(|item| {
+                                        (item.path_buf.clone(), item.clone())
+                                    })
+                                    .collect(),
+                            ),
+                            Target::Widget(tab_id),
+                        );
+                    }
+                }
+            }),
+        );
     }
 
     pub fn set_picker_pwd(&mut self, pwd: PathBuf) {
@@ -1875,14 +2751,117 @@ pub struct LapceMainSplitData {
     pub warning_count: usize,
     pub workspace: Arc<LapceWorkspace>,
     pub db: Arc<LapceDb>,
+    /// Unsaved changes from the last session, waiting for their file to load.
+    pub unsaved_buffers: im::HashMap<PathBuf, Arc<UnsavedBufferInfo>>,
+    /// "Replace All" replacements for open files, waiting for them to load.
+    pub pending_replacements: im::HashMap<PathBuf, Arc<Vec<SearchReplacement>>>,
+    /// The open files that are a file as a commit left it, which only exist
+    /// in memory.
+    pub git_snapshots: im::HashSet<PathBuf>,
 }
 
 impl LapceMainSplitData {
+    /// Replaces the diagnostics a task found before, which have `source`.
+    pub fn set_task_diagnostics(
+        &mut self,
+        source: &str,
+        mut problems: HashMap<PathBuf, Vec<Diagnostic>>,
+    ) {
+        let paths: HashSet<PathBuf> = self
+            .diagnostics
+            .keys()
+            .chain(problems.keys())
+            .cloned()
+            .collect();
+        for path in paths {
+            let mut diagnostics: Vec<EditorDiagnostic> = self
+                .diagnostics
+                .get(&path)
+                .map(|diagnostics| {
+                    diagnostics
+                        .iter()
+                        .filter(|d| d.diagnositc.source.as_deref() != Some(source))
+                        .cloned()
+                        .collect()
+                })
+                .unwrap_or_default();
+            diagnostics.extend(problems.remove(&path).into_iter().flatten().map(
+                |diagnositc| EditorDiagnostic {
+                    range: None,
+                    diagnositc,
+                },
+            ));
+            self.diagnostics.insert(path, Arc::new(diagnostics));
+        }
+        self.update_diagnostic_counts();
+    }
+
+    pub fn update_diagnostic_counts(&mut self) {
+        let mut errors = 0;
+        let mut warnings = 0;
+        for (_, diagnositics) in self.diagnostics.iter() {
+            for diagnositic in diagnositics.iter() {
+                match diagnositic.diagnositc.severity {
+                    Some(DiagnosticSeverity::Error) => errors += 1,
+                    Some(DiagnosticSeverity::Warning) => warnings += 1,
+                    _ => (),
+                }
+            }
+        }
+        self.error_count = errors;
+        self.warning_count = warnings;
+    }
+
     pub fn active_editor(&self) -> Option<&LapceEditorData> {
         let id = (*self.active)?;
         Some(self.editors.get(&id)?.as_ref())
     }
 
+    /// The buffer of the file shown in the active editor.
+    pub fn active_file_buffer(&self) -> Option<&Arc<Buffer>> {
+        match self.active_editor().map(|e| &e.content) {
+            Some(BufferContent::File(path)) => self.open_files.get(path),
+            _ => None,
+        }
+    }
+
+    /// Blames the files shown in editors again. Unless `all` is set, that's
+    /// only done for the ones edited since they were last blamed.
+    pub fn retrieve_blame(&self, ctx: &mut EventCtx, all: bool) {
+        let paths: HashSet<&PathBuf> = self
+            .editors
+            .values()
+            .filter_map(|editor| match &editor.content {
+                BufferContent::File(path) => Some(path),
+                _ => None,
+            })
+            .collect();
+        for path in paths {
+            if let Some(buffer) = self.open_files.get(path) {
+                if all || buffer.blame().is_none() {
+                    buffer.retrieve_blame(
+                        *self.tab_id,
+                        self.proxy.clone(),
+                        ctx.get_external_handle(),
+                    );
+                }
+            }
+        }
+    }
+
+    /// Whether the active editor shows a file opened in large file mode.
+    pub fn active_editor_large_file(&self) -> bool {
+        self.active_file_buffer()
+            .map(|b| b.large_file())
+            .unwrap_or(false)
+    }
+
+    pub fn active_editor_read_only(&self) -> bool {
+        self.active_file_buffer()
+            .map(|b| b.read_only())
+            .unwrap_or(false)
+    }
+
     pub fn editor_buffer(&self, editor_view_id: WidgetId) -> Arc<Buffer> {
         let editor = self.editors.get(&editor_view_id).unwrap();
         let buffer = match &editor.content {
@@ -1954,10 +2933,16 @@ impl LapceMainSplitData {
         path: &Path,
         rev: u64,
         result: &Result<Value>,
+        auto_save: bool,
         config: &Config,
     ) {
         self.document_format(path, rev, result, config);
+        self.document_save(ctx, path, auto_save);
+    }
 
+    /// Saves the buffer at its current rev. Auto-saves don't recreate a file
+    /// that was deleted on disk.
+    pub fn document_save(&self, ctx: &mut EventCtx, path: &Path, auto_save: bool) {
         let buffer = self.open_files.get(path).unwrap();
         let rev = buffer.rev();
         let buffer_id = buffer.id();
@@ -1966,6 +2951,7 @@ impl LapceMainSplitData {
         self.proxy.save(
             rev,
             buffer_id,
+            auto_save,
             Box::new(move |result| {
                 if let Ok(_r) = result {
                     let _ = event_sink.submit_command(
@@ -1978,6 +2964,218 @@ impl LapceMainSplitData {
         );
     }
 
+    /// Fetches a version of `path` from the local file history and opens it
+    /// in a diff against the current buffer.
+    pub fn open_local_history(
+        &self,
+        ctx: &mut EventCtx,
+        path: &Path,
+        version: &str,
+    ) {
+        let tab_id = *self.tab_id;
+        let event_sink = ctx.get_external_handle();
+        let path = path.to_path_buf();
+        self.proxy.get_file_history_version(
+            path.clone(),
+            version.to_string(),
+            Box::new(move |result| {
+                if let Ok(res) = result {
+                    if let Ok(resp) =
+                        serde_json::from_value::<BufferHeadResponse>(res)
+                    {
+                        let _ = event_sink.submit_command(
+                            LAPCE_UI_COMMAND,
+                            LapceUICommand::LoadBufferHead {
+                                path: path.clone(),
+                                id: resp.id.clone(),
+                                content: Rope::from(resp.content),
+                            },
+                            Target::Widget(tab_id),
+                        );
+                        let _ = event_sink.submit_command(
+                            LAPCE_UI_COMMAND,
+                            LapceUICommand::OpenFileDiff(path, resp.id),
+                            Target::Widget(tab_id),
+                        );
+                    }
+                }
+            }),
+        );
+    }
+
+    /// Opens a file the commit changed in a diff against the commit's parent.
+    /// The file as of the commit is a read-only snapshot the proxy keeps in
+    /// memory.
+    pub fn open_commit_file_diff(
+        &self,
+        ctx: &mut EventCtx,
+        commit: &str,
+        diff: &FileDiff,
+    ) {
+        let (path, parent_path) = match diff {
+            FileDiff::Renamed(new, old) => (new.clone(), old.clone()),
+            FileDiff::Modified(path)
+            | FileDiff::Added(path)
+            | FileDiff::Deleted(path) => (path.clone(), path.clone()),
+        };
+        let tab_id = *self.tab_id;
+        let history = format!("{}^", &commit[..7]);
+        let event_sink = ctx.get_external_handle();
+        self.proxy.git_commit_file(
+            commit.to_string(),
+            path,
+            parent_path,
+            Box::new(move |result| match result {
+                Ok(res) => {
+                    if let Ok(resp) =
+                        serde_json::from_value::<GitCommitFileResponse>(res)
+                    {
+                        let _ = event_sink.submit_command(
+                            LAPCE_UI_COMMAND,
+                            LapceUICommand::OpenGitSnapshot {
+                                path: resp.snapshot,
+                                history,
+                                parent_content: Rope::from(resp.parent_content),
+                            },
+                            Target::Widget(tab_id),
+                        );
+                    }
+                }
+                Err(err) => {
+                    let message = err
+                        .get("message")
+                        .and_then(|m| m.as_str())
+                        .unwrap_or("unknown error");
+                    let _ = event_sink.submit_command(
+                        LAPCE_UI_COMMAND,
+                        LapceUICommand::ShowStatusMessage(format!(
+                            "Failed to open the commit: {message}"
+                        )),
+                        Target::Widget(tab_id),
+                    );
+                }
+            }),
+        );
+    }
+
+    /// Opens a snapshot from [`Self::open_commit_file_diff`] in a diff
+    /// against `parent_content`, the file before the commit.
+    pub fn open_git_snapshot(
+        &mut self,
+        ctx: &mut EventCtx,
+        path: &Path,
+        history: &str,
+        parent_content: &Rope,
+        config: &Config,
+    ) -> WidgetId {
+        self.git_snapshots.insert(path.to_path_buf());
+        // Opening the diff creates the buffer the parent's content is loaded
+        // into.
+        let editor_view_id = self.active.clone();
+        let editor_view_id = self.jump_to_location(
+            ctx,
+            *editor_view_id,
+            EditorLocationNew {
+                path: path.to_path_buf(),
+                position: None,
+                scroll_offset: None,
+                history: Some(history.to_string()),
+            },
+            config,
+        );
+        if let Some(buffer) = self.open_files.get_mut(path) {
+            Arc::make_mut(buffer).load_history(history, parent_content.clone());
+        }
+        editor_view_id
+    }
+
+    /// Drops the buffer of a git snapshot once the last editor showing it was
+    /// closed, and has the proxy drop it too.
+    pub fn close_git_snapshot(&mut self, path: &Path) {
+        if !self.git_snapshots.contains(path) {
+            return;
+        }
+        let content = BufferContent::File(path.to_path_buf());
+        if self.editors.values().any(|e| e.content == content) {
+            return;
+        }
+        self.git_snapshots.remove(path);
+        self.open_files.remove(path);
+        self.proxy.git_close_snapshot(path.to_path_buf());
+    }
+
+    /// Opens `path` in a diff against its version from before the commit of
+    /// the blame hunk, or against HEAD when the hunk isn't committed.
+    pub fn open_blame_diff(
+        &self,
+        ctx: &mut EventCtx,
+        path: &Path,
+        hunk: &BlameHunk,
+    ) {
+        let tab_id = *self.tab_id;
+        let path = path.to_path_buf();
+        if hunk.uncommitted {
+            ctx.submit_command(Command::new(
+                LAPCE_UI_COMMAND,
+                LapceUICommand::OpenFileDiff(path, "head".to_string()),
+                Target::Widget(tab_id),
+            ));
+            return;
+        }
+
+        self.open_commit_file_diff(ctx, &hunk.commit_id, &FileDiff::Modified(path));
+    }
+
+    /// Auto-saves every file buffer with unsaved changes.
+    pub fn auto_save_all(&self, ctx: &mut EventCtx, config: &Config) {
+        for path in self.open_files.keys() {
+            self.auto_save(ctx, path, config);
+        }
+    }
+
+    /// Saves the buffer like the save command does, except that it's only
+    /// formatted if `format-on-auto-save` is set.
+    pub fn auto_save(&self, ctx: &mut EventCtx, path: &Path, config: &Config) {
+        let buffer = match self.open_files.get(path) {
+            Some(buffer) => buffer,
+            None => return,
+        };
+        if !buffer.loaded() || !buffer.dirty() {
+            return;
+        }
+
+        if !config.editor.format_on_auto_save {
+            self.document_save(ctx, path, true);
+            return;
+        }
+
+        let path = path.to_path_buf();
+        let proxy = self.proxy.clone();
+        let buffer_id = buffer.id();
+        let rev = buffer.rev();
+        let event_sink = ctx.get_external_handle();
+        thread::spawn(move || {
+            let (sender, receiver) = bounded(1);
+            proxy.get_document_formatting(
+                buffer_id,
+                Box::new(move |result| {
+                    let _ = sender.send(result);
+                }),
+            );
+
+            let result = receiver.recv_timeout(Duration::from_secs(1)).map_or_else(
+                |e| Err(anyhow!("{}", e)),
+                |v| v.map_err(|e| anyhow!("{:?}", e)),
+            );
+
+            let _ = event_sink.submit_command(
+                LAPCE_UI_COMMAND,
+                LapceUICommand::DocumentFormatAndSave(path, rev, result, true),
+                Target::Auto,
+            );
+        });
+    }
+
     fn initiate_diagnositcs_offset(&mut self, path: &Path, config: &Config) {
         if let Some(diagnostics) = self.diagnostics.get_mut(path) {
             if let Some(buffer) = self.open_files.get(path) {
@@ -2046,6 +3244,11 @@ impl LapceMainSplitData {
         edit_type: EditType,
         config: &Config,
     ) -> Option<RopeDelta> {
+        // Code actions and formatting land here, keep them off read-only
+        // buffers like the editor does for typing.
+        if self.open_files.get(path)?.read_only() {
+            return None;
+        }
         self.initiate_diagnositcs_offset(path, config);
         let proxy = self.proxy.clone();
         let buffer = self.open_files.get_mut(path)?;
@@ -2368,6 +3571,7 @@ impl LapceMainSplitData {
                 self.proxy.clone(),
                 ctx.get_external_handle(),
                 vec![(editor_view_id, location)],
+                config.editor.large_file_threshold(),
             );
         } else {
             let buffer = self.open_files.get_mut(&path).unwrap().clone();
@@ -2494,6 +3698,13 @@ impl LapceMainSplitData {
             )),
         );
 
+        let unsaved_buffers: im::HashMap<PathBuf, Arc<UnsavedBufferInfo>> = db
+            .get_unsaved_buffers(&workspace)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|info| (info.path.clone(), Arc::new(info)))
+            .collect();
+
         let mut main_split_data = Self {
             tab_id: Arc::new(tab_id),
             split_id,
@@ -2515,6 +3726,9 @@ impl LapceMainSplitData {
             warning_count: 0,
             workspace,
             db,
+            unsaved_buffers,
+            pending_replacements: im::HashMap::new(),
+            git_snapshots: im::HashSet::new(),
         };
 
         if let Some(info) = workspace_info {
@@ -2538,6 +3752,7 @@ impl LapceMainSplitData {
                         proxy.clone(),
                         event_sink.clone(),
                         locations.clone(),
+                        config.editor.large_file_threshold(),
                     );
             }
         } else {
@@ -2552,9 +3767,125 @@ impl LapceMainSplitData {
                 }),
             );
         }
+        for path in main_split_data.unsaved_buffers.keys() {
+            if !main_split_data.open_files.contains_key(path) {
+                let _ = event_sink.submit_command(
+                    LAPCE_UI_COMMAND,
+                    LapceUICommand::OpenFile(path.clone()),
+                    Target::Widget(tab_id),
+                );
+            }
+        }
         main_split_data
     }
 
+    /// Snapshots of the buffers with unsaved changes, including the ones
+    /// from the last session that haven't been restored yet.
+    pub fn unsaved_buffers_info(&self) -> Vec<UnsavedBufferInfo> {
+        let mut infos: Vec<UnsavedBufferInfo> = self
+            .open_files
+            .iter()
+            .filter(|(_, buffer)| {
+                buffer.loaded() && buffer.dirty() && !buffer.large_file()
+            })
+            .map(|(path, buffer)| UnsavedBufferInfo {
+                path: path.clone(),
+                content: buffer.rope().to_string(),
+                pristine_hash: buffer.pristine_hash(),
+            })
+            .collect();
+        infos.extend(self.unsaved_buffers.values().map(|info| (**info).clone()));
+        infos
+    }
+
+    /// Applies search replacements to the open file at `path` as a single
+    /// edit, skipping the ones whose text changed since the search. Returns
+    /// whether anything was replaced.
+    pub fn replace_in_buffer(
+        &mut self,
+        path: &Path,
+        replacements: &[SearchReplacement],
+        config: &Config,
+    ) -> bool {
+        let buffer = match self.open_files.get(path) {
+            Some(buffer) => buffer,
+            None => return false,
+        };
+        let edits: Vec<(Selection, &str)> = replacements
+            .iter()
+            .filter_map(|r| {
+                let line = r.line.checked_sub(1)?;
+                if line >= buffer.num_lines() {
+                    return None;
+                }
+                let start = buffer.offset_of_line(line) + r.start;
+                let end = start + (r.end - r.start);
+                if end > buffer.len() || buffer.slice_to_cow(start..end) != r.old {
+                    return None;
+                }
+                Some((Selection::region(start, end), r.new.as_str()))
+            })
+            .collect();
+        if edits.is_empty() {
+            return false;
+        }
+        let edits: Vec<(&Selection, &str)> =
+            edits.iter().map(|(s, new)| (s, *new)).collect();
+        self.edit(path, &edits, EditType::Other, config).is_some()
+    }
+
+    /// Applies the "Replace All" replacements that waited for the file at
+    /// `path` to load.
+    pub fn apply_pending_replacements(&mut self, path: &Path, config: &Config) {
+        if let Some(replacements) = self.pending_replacements.remove(path) {
+            self.replace_in_buffer(path, &replacements, config);
+        }
+    }
+
+    /// Puts the unsaved changes from the last session back on top of the
+    /// file that was just loaded. If the file changed on disk since, the
+    /// disk version is opened in a diff against the restored content.
+    pub fn restore_unsaved_buffer(&mut self, ctx: &mut EventCtx, path: &Path) {
+        let info = match self.unsaved_buffers.remove(path) {
+            Some(info) => info,
+            None => return,
+        };
+        let buffer = match self.open_files.get_mut(path) {
+            Some(buffer) => Arc::make_mut(buffer),
+            None => return,
+        };
+        if buffer.large_file() || buffer.rope().to_string() == info.content {
+            return;
+        }
+        if buffer.read_only() {
+            ctx.submit_command(Command::new(
+                LAPCE_UI_COMMAND,
+                LapceUICommand::ShowStatusMessage(format!(
+                    "{} is read-only, its unsaved changes weren't restored",
+                    path.display()
+                )),
+                Target::Widget(*self.tab_id),
+            ));
+            return;
+        }
+
+        let disk_changed = buffer.pristine_hash() != info.pristine_hash;
+        let disk_content = buffer.rope().clone();
+        let selection = Selection::region(0, buffer.len());
+        buffer
+            .editable(&self.proxy)
+            .edit_multiple(&[(&selection, info.content.as_str())], EditType::Other);
+
+        if disk_changed {
+            buffer.load_history("disk", disk_content);
+            ctx.submit_command(Command::new(
+                LAPCE_UI_COMMAND,
+                LapceUICommand::OpenFileDiff(path.to_path_buf(), "disk".to_string()),
+                Target::Widget(*self.tab_id),
+            ));
+        }
+    }
+
     pub fn insert_editor(&mut self, editor: Arc<LapceEditorData>, config: &Config) {
         if let Some(find_view_id) = editor.find_view_id {
             let mut find_editor = LapceEditorData::new(
@@ -2570,6 +3901,24 @@ impl LapceMainSplitData {
         self.editors.insert(editor.view_id, editor);
     }
 
+    /// Adds the editor of the find bar of a terminal, whose searches go to
+    /// the terminal.
+    pub fn insert_terminal_find_editor(
+        &mut self,
+        terminal: &LapceTerminalData,
+        config: &Config,
+    ) {
+        let mut find_editor = LapceEditorData::new(
+            Some(terminal.find_view_id),
+            None,
+            BufferContent::Local(LocalBufferKind::Search),
+            config,
+        );
+        find_editor.parent_view_id = Some(terminal.widget_id);
+        self.editors
+            .insert(find_editor.view_id, Arc::new(find_editor));
+    }
+
     pub fn add_editor(
         &mut self,
         view_id: WidgetId,
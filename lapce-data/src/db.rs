@@ -29,6 +29,7 @@ pub enum SaveEvent {
     Workspace(LapceWorkspace, WorkspaceInfo),
     Tabs(TabsInfo),
     Buffer(BufferInfo),
+    UnsavedBuffers(LapceWorkspace, Vec<UnsavedBufferInfo>),
 }
 
 #[derive(Clone)]
@@ -207,6 +208,26 @@ impl SplitInfo {
 #[derive(Clone, Serialize, Deserialize)]
 pub struct WorkspaceInfo {
     pub split: SplitInfo,
+    #[serde(default)]
+    pub terminal: TerminalSplitInfo,
+}
+
+/// The terminals of a workspace, in the order of the split.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct TerminalSplitInfo {
+    pub terminals: Vec<TerminalInfo>,
+    pub active: usize,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TerminalInfo {
+    /// The name of the profile, the default one when it's `None`.
+    pub profile: Option<String>,
+    /// Where the shell was last, the directory of the profile when it's
+    /// `None`.
+    pub cwd: Option<PathBuf>,
+    /// The text of the scrollback, up to the prompt.
+    pub scrollback: Option<String>,
 }
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -230,6 +251,16 @@ pub struct BufferInfo {
     pub cursor_offset: usize,
 }
 
+/// Snapshot of a buffer with unsaved changes, restored on the next start.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct UnsavedBufferInfo {
+    pub path: PathBuf,
+    pub content: String,
+    /// Hash of the file content the edits were made on top of, used to
+    /// tell if the file changed on disk in the meantime.
+    pub pristine_hash: u64,
+}
+
 #[derive(Clone, Serialize, Deserialize)]
 pub struct EditorInfo {
     pub content: BufferContent,
@@ -322,6 +353,9 @@ impl LapceDb {
                     SaveEvent::Buffer(info) => {
                         let _ = local_db.insert_buffer(&info);
                     }
+                    SaveEvent::UnsavedBuffers(workspace, infos) => {
+                        let _ = local_db.insert_unsaved_buffers(&workspace, &infos);
+                    }
                 }
             }
         });
@@ -424,6 +458,45 @@ impl LapceDb {
         Ok(())
     }
 
+    pub fn get_unsaved_buffers(
+        &self,
+        workspace: &LapceWorkspace,
+    ) -> Result<Vec<UnsavedBufferInfo>> {
+        let key = format!("unsaved:{}", workspace);
+        let sled_db = self.get_db()?;
+        let infos = sled_db
+            .get(key.as_str())?
+            .ok_or_else(|| anyhow!("can't find unsaved buffers"))?;
+        let infos = std::str::from_utf8(&infos)?;
+        let infos: Vec<UnsavedBufferInfo> = serde_json::from_str(infos)?;
+        Ok(infos)
+    }
+
+    fn insert_unsaved_buffers(
+        &self,
+        workspace: &LapceWorkspace,
+        infos: &[UnsavedBufferInfo],
+    ) -> Result<()> {
+        let key = format!("unsaved:{}", workspace);
+        let sled_db = self.get_db()?;
+        if infos.is_empty() {
+            sled_db.remove(key.as_str())?;
+        } else {
+            let infos = serde_json::to_string(infos)?;
+            sled_db.insert(key.as_str(), infos.as_str())?;
+        }
+        sled_db.flush()?;
+        Ok(())
+    }
+
+    pub fn save_unsaved_buffers_async(&self, data: &LapceTabData) -> Result<()> {
+        let workspace = (*data.workspace).clone();
+        let infos = data.main_split.unsaved_buffers_info();
+        self.save_tx
+            .send(SaveEvent::UnsavedBuffers(workspace, infos))?;
+        Ok(())
+    }
+
     fn insert_tabs(&self, info: &TabsInfo) -> Result<()> {
         let tabs_info = serde_json::to_string(info)?;
         let sled_db = self.get_db()?;
@@ -473,6 +546,10 @@ impl LapceDb {
         let workspace_info = data.workspace_info();
 
         self.insert_workspace(&workspace, &workspace_info)?;
+        self.insert_unsaved_buffers(
+            &workspace,
+            &data.main_split.unsaved_buffers_info(),
+        )?;
         Ok(())
     }
 
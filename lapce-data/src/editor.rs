@@ -1,7 +1,9 @@
+use crate::buffer::conflict::{ConflictBlock, ConflictChoice};
 use crate::buffer::get_word_property;
 use crate::buffer::matching_char;
 use crate::buffer::{
-    has_unmatched_pair, BufferContent, DiffLines, EditType, LocalBufferKind,
+    has_unmatched_pair, rope_line_range, BufferContent, DiffLines, EditType,
+    LocalBufferKind,
 };
 use crate::buffer::{matching_pair_direction, Buffer};
 use crate::command::CommandExecuted;
@@ -45,6 +47,8 @@ use druid::{
 use druid::{Application, ExtEventSink, MouseEvent};
 pub use lapce_core::syntax::Syntax;
 use lapce_rpc::buffer::BufferId;
+use lapce_rpc::search::SearchOptions;
+use lapce_rpc::source_control::{DiffBase, GitHunk};
 use lsp_types::CompletionTextEdit;
 use lsp_types::{
     CodeActionResponse, CompletionItem, DiagnosticSeverity, GotoDefinitionResponse,
@@ -54,8 +58,8 @@ use serde_json::Value;
 use std::cmp::Ordering;
 use std::collections::HashSet;
 use std::path::Path;
+use std::sync::Arc;
 use std::thread;
-use std::{collections::HashMap, sync::Arc};
 use std::{iter::Iterator, path::PathBuf};
 use std::{str::FromStr, time::Duration};
 use xi_rope::{RopeDelta, Transformer};
@@ -501,6 +505,54 @@ impl LapceEditorBufferData {
         Arc::make_mut(&mut self.editor).cursor.apply_delta(&delta);
     }
 
+    /// Whether the file has merge conflicts git knows of.
+    pub fn is_conflicted(&self) -> bool {
+        match self.buffer.content() {
+            BufferContent::File(path) => self
+                .source_control
+                .conflicts
+                .iter()
+                .any(|diff| diff.path() == path),
+            _ => false,
+        }
+    }
+
+    /// Replaces the conflict, markers and all, with the side or sides picked.
+    pub fn resolve_conflict(
+        &mut self,
+        ctx: &mut EventCtx,
+        conflict: &ConflictBlock,
+        choice: ConflictChoice,
+    ) {
+        if self.reject_read_only(ctx) {
+            return;
+        }
+        let rope = self.buffer.rope();
+        let content: String = conflict
+            .resolution(choice)
+            .iter()
+            .map(|lines| rope.slice_to_cow(rope_line_range(rope, lines)))
+            .collect();
+        let range = rope_line_range(rope, &conflict.lines());
+        let selection = Selection::region(range.start, range.end);
+        let delta = self.edit(&[(&selection, &content)], true, EditType::Other);
+        Arc::make_mut(&mut self.editor).cursor.apply_delta(&delta);
+    }
+
+    /// Tells the user the buffer can't be edited if it's read-only, and
+    /// returns whether it is.
+    fn reject_read_only(&self, ctx: &mut EventCtx) -> bool {
+        if !self.buffer.read_only() {
+            return false;
+        }
+        ctx.submit_command(Command::new(
+            LAPCE_UI_COMMAND,
+            LapceUICommand::ShowStatusMessage("The file is read-only".to_string()),
+            Target::Widget(*self.main_split.tab_id),
+        ));
+        true
+    }
+
     fn inactive_apply_delta(&mut self, delta: &RopeDelta) {
         for (view_id, editor) in self.main_split.editors.iter_mut() {
             if view_id != &self.editor.view_id
@@ -522,6 +574,9 @@ impl LapceEditorBufferData {
     }
 
     pub fn apply_completion_item(&mut self, item: &CompletionItem) -> Result<()> {
+        if self.buffer.read_only() {
+            return Err(anyhow!("The file is read-only"));
+        }
         let additional_edit: Option<Vec<_>> =
             item.additional_text_edits.as_ref().map(|edits| {
                 edits
@@ -812,45 +867,15 @@ impl LapceEditorBufferData {
         );
     }
 
+    /// The search itself is run by the tab when handling `UpdateSearch`, so
+    /// that it picks up the search options of the panel.
     pub fn update_global_search(&self, ctx: &mut EventCtx, pattern: String) {
         let tab_id = *self.main_split.tab_id;
         ctx.submit_command(Command::new(
             LAPCE_UI_COMMAND,
-            LapceUICommand::UpdateSearch(pattern.to_string()),
+            LapceUICommand::UpdateSearch(pattern),
             Target::Widget(tab_id),
         ));
-        if pattern.is_empty() {
-            ctx.submit_command(Command::new(
-                LAPCE_UI_COMMAND,
-                LapceUICommand::GlobalSearchResult(
-                    pattern,
-                    Arc::new(HashMap::new()),
-                ),
-                Target::Widget(tab_id),
-            ));
-        } else {
-            let event_sink = ctx.get_external_handle();
-            self.proxy.global_search(
-                pattern.clone(),
-                Box::new(move |result| {
-                    if let Ok(matches) = result {
-                        if let Ok(matches) = serde_json::from_value::<
-                            HashMap<PathBuf, Vec<(usize, (usize, usize), String)>>,
-                        >(matches)
-                        {
-                            let _ = event_sink.submit_command(
-                                LAPCE_UI_COMMAND,
-                                LapceUICommand::GlobalSearchResult(
-                                    pattern,
-                                    Arc::new(matches),
-                                ),
-                                Target::Widget(tab_id),
-                            );
-                        }
-                    }
-                }),
-            )
-        }
     }
 
     fn insert_new_line(&mut self, ctx: &mut EventCtx, selection: Selection) {
@@ -909,7 +934,11 @@ impl LapceEditorBufferData {
                     ));
                     return;
                 }
-                LocalBufferKind::SourceControl | LocalBufferKind::Empty => {}
+                LocalBufferKind::SearchReplace
+                | LocalBufferKind::SearchInclude
+                | LocalBufferKind::SearchExclude
+                | LocalBufferKind::SourceControl
+                | LocalBufferKind::Empty => {}
             },
         }
 
@@ -1293,54 +1322,19 @@ impl LapceEditorBufferData {
         None
     }
 
-    fn next_diff(&mut self, ctx: &mut EventCtx, _env: &Env) {
+    /// Jumps to the next or previous change of the changed files, going on
+    /// to the next or previous file past the current file's last or first
+    /// change.
+    fn jump_to_diff(&mut self, ctx: &mut EventCtx, forward: bool) {
         if let BufferContent::File(buffer_path) = self.buffer.content() {
-            if self.source_control.file_diffs.is_empty() {
-                return;
-            }
             let mut diff_files: Vec<(PathBuf, Vec<Position>)> = self
                 .source_control
                 .file_diffs
                 .iter()
-                .map(|(diff, _)| {
+                .chain(self.source_control.staged_diffs.iter())
+                .map(|diff| {
                     let path = diff.path();
-                    let mut positions = Vec::new();
-                    if let Some(buffer) = self.main_split.open_files.get(path) {
-                        if let Some(changes) = buffer.history_changes.get("head") {
-                            for (i, change) in changes.iter().enumerate() {
-                                match change {
-                                    DiffLines::Left(_) => {
-                                        if let Some(next) = changes.get(i + 1) {
-                                            match next {
-                                                DiffLines::Right(_) => {}
-                                                DiffLines::Left(_) => {}
-                                                DiffLines::Both(_, r) => {
-                                                    positions.push(Position {
-                                                        line: r.start as u32,
-                                                        character: 0,
-                                                    });
-                                                }
-                                                DiffLines::Skip(_, r) => {
-                                                    positions.push(Position {
-                                                        line: r.start as u32,
-                                                        character: 0,
-                                                    });
-                                                }
-                                            }
-                                        }
-                                    }
-                                    DiffLines::Both(_, _) => {}
-                                    DiffLines::Skip(_, _) => {}
-                                    DiffLines::Right(r) => {
-                                        positions.push(Position {
-                                            line: r.start as u32,
-                                            character: 0,
-                                        });
-                                    }
-                                }
-                            }
-                        }
-                    }
+                    let mut positions = self.diff_positions(path);
                     if positions.is_empty() {
                         positions.push(Position {
                             line: 0,
@@ -1350,14 +1344,30 @@ impl LapceEditorBufferData {
                     (path.clone(), positions)
                 })
                 .collect();
+            // Against anything but HEAD the file's changes needn't show up in
+            // source control.
+            if self.buffer.diff_base() != &DiffBase::Head {
+                let positions = self.diff_positions(buffer_path);
+                if !positions.is_empty() {
+                    diff_files.push((buffer_path.clone(), positions));
+                }
+            }
+            if diff_files.is_empty() {
+                return;
+            }
             diff_files.sort();
+            // A file can have both staged and unstaged changes.
+            diff_files.dedup_by(|a, b| a.0 == b.0);
 
             let offset = self.editor.cursor.offset();
             let position = self
                 .buffer
                 .offset_to_position(offset, self.config.editor.tab_width);
-            let (path, position) =
-                next_in_file_diff_offset(position, buffer_path, &diff_files);
+            let (path, position) = if forward {
+                next_in_file_diff_offset(position, buffer_path, &diff_files)
+            } else {
+                previous_in_file_diff_offset(position, buffer_path, &diff_files)
+            };
             let location = EditorLocationNew {
                 path,
                 position: Some(position),
@@ -1372,6 +1382,48 @@ impl LapceEditorBufferData {
         }
     }
 
+    /// Where each change of the open file starts, in order.
+    fn diff_positions(&self, path: &Path) -> Vec<Position> {
+        let mut positions = Vec::new();
+        if let Some(buffer) = self.main_split.open_files.get(path) {
+            if let Some(changes) = buffer.history_changes.get("head") {
+                for (i, change) in changes.iter().enumerate() {
+                    match change {
+                        DiffLines::Left(_) => {
+                            if let Some(next) = changes.get(i + 1) {
+                                match next {
+                                    DiffLines::Right(_) => {}
+                                    DiffLines::Left(_) => {}
+                                    DiffLines::Both(_, r) => {
+                                        positions.push(Position {
+                                            line: r.start as u32,
+                                            character: 0,
+                                        });
+                                    }
+                                    DiffLines::Skip(_, r) => {
+                                        positions.push(Position {
+                                            line: r.start as u32,
+                                            character: 0,
+                                        });
+                                    }
+                                }
+                            }
+                        }
+                        DiffLines::Both(_, _) => {}
+                        DiffLines::Skip(_, _) => {}
+                        DiffLines::Right(r) => {
+                            positions.push(Position {
+                                line: r.start as u32,
+                                character: 0,
+                            });
+                        }
+                    }
+                }
+            }
+        }
+        positions
+    }
+
     fn next_error(&mut self, ctx: &mut EventCtx, _env: &Env) {
         if let BufferContent::File(buffer_path) = self.buffer.content() {
             let mut file_diagnostics = self
@@ -1781,6 +1833,9 @@ impl KeyPressFocus for LapceEditorBufferData {
         mods: Modifiers,
         env: &Env,
     ) -> CommandExecuted {
+        if cmd.is_edit_command() && self.reject_read_only(ctx) {
+            return CommandExecuted::Yes;
+        }
         if let Some(movement) = cmd.move_command(count) {
             self.do_move(&movement, count.unwrap_or(1), mods);
             if let Some(snippet) = self.editor.snippet.as_ref() {
@@ -2614,7 +2669,7 @@ impl KeyPressFocus for LapceEditorBufferData {
                         };
                         let search_str = self.buffer.slice_to_cow(start..end);
                         let mut find = Find::new(0);
-                        find.set_find(&search_str, false, false, false);
+                        find.set_find(&search_str, &SearchOptions::default());
                         let mut offset = 0;
                         while let Some((start, end)) =
                             find.next(&self.buffer.rope(), offset, false, false)
@@ -2650,7 +2705,7 @@ impl KeyPressFocus for LapceEditorBufferData {
                             let search_str =
                                 self.buffer.slice_to_cow(r.min()..r.max());
                             let mut find = Find::new(0);
-                            find.set_find(&search_str, false, false, false);
+                            find.set_find(&search_str, &SearchOptions::default());
                             let mut offset = r.max();
                             let mut seen = HashSet::new();
                             while let Some((start, end)) =
@@ -2695,7 +2750,7 @@ impl KeyPressFocus for LapceEditorBufferData {
                             let search_str =
                                 self.buffer.slice_to_cow(r.min()..r.max());
                             let mut find = Find::new(0);
-                            find.set_find(&search_str, false, false, false);
+                            find.set_find(&search_str, &SearchOptions::default());
                             let mut offset = r.max();
                             let mut seen = HashSet::new();
                             while let Some((start, end)) =
@@ -2743,9 +2798,89 @@ impl KeyPressFocus for LapceEditorBufferData {
             }
             LapceCommand::PreviousError => {}
             LapceCommand::NextDiff => {
-                self.next_diff(ctx, env);
+                self.jump_to_diff(ctx, true);
+            }
+            LapceCommand::PreviousDiff => {
+                self.jump_to_diff(ctx, false);
+            }
+            LapceCommand::StageHunk => {
+                let path = match self.buffer.content() {
+                    BufferContent::File(path) => path.clone(),
+                    _ => return CommandExecuted::Yes,
+                };
+                // The change's lines are those of the diff base, while
+                // staging goes by HEAD's.
+                if self.buffer.diff_base() != &DiffBase::Head {
+                    ctx.submit_command(Command::new(
+                        LAPCE_UI_COMMAND,
+                        LapceUICommand::ShowStatusMessage(
+                            "Only changes against HEAD can be staged".to_string(),
+                        ),
+                        Target::Widget(*self.main_split.tab_id),
+                    ));
+                    return CommandExecuted::Yes;
+                }
+                let line = self.buffer.line_of_offset(self.editor.cursor.offset());
+                if let Some((old, new)) = self.buffer.diff_hunk_at_line("head", line)
+                {
+                    let rope = self.buffer.rope();
+                    let hunk = GitHunk {
+                        old_start: old.start,
+                        old_lines: old.len(),
+                        new_content: rope
+                            .slice_to_cow(rope_line_range(rope, &new))
+                            .to_string(),
+                    };
+                    let event_sink = ctx.get_external_handle();
+                    let tab_id = *self.main_split.tab_id;
+                    self.proxy.git_stage_hunk(
+                        path,
+                        hunk,
+                        Box::new(move |result| {
+                            if let Err(err) = result {
+                                let err = err
+                                    .get("message")
+                                    .and_then(|m| m.as_str())
+                                    .unwrap_or("unknown error");
+                                let _ = event_sink.submit_command(
+                                    LAPCE_UI_COMMAND,
+                                    LapceUICommand::ShowStatusMessage(format!(
+                                        "Can't stage the change: {err}"
+                                    )),
+                                    Target::Widget(tab_id),
+                                );
+                            }
+                        }),
+                    );
+                }
+            }
+            LapceCommand::RevertHunk => {
+                let line = self.buffer.line_of_offset(self.editor.cursor.offset());
+                let hunk = self.buffer.diff_hunk_at_line("head", line);
+                let head = self.buffer.histories().get("head");
+                if let (Some((old, new)), Some(head)) = (hunk, head) {
+                    let content =
+                        head.slice_to_cow(rope_line_range(head, &old)).to_string();
+                    let range = rope_line_range(self.buffer.rope(), &new);
+                    let selection = Selection::region(range.start, range.end);
+                    let delta =
+                        self.edit(&[(&selection, &content)], true, EditType::Other);
+                    Arc::make_mut(&mut self.editor).cursor.apply_delta(&delta);
+                }
+            }
+            LapceCommand::AcceptCurrentChange
+            | LapceCommand::AcceptIncomingChange
+            | LapceCommand::AcceptBothChanges => {
+                let choice = match cmd {
+                    LapceCommand::AcceptCurrentChange => ConflictChoice::Current,
+                    LapceCommand::AcceptIncomingChange => ConflictChoice::Incoming,
+                    _ => ConflictChoice::Both,
+                };
+                let line = self.buffer.line_of_offset(self.editor.cursor.offset());
+                if let Some(conflict) = self.buffer.conflict_at_line(line) {
+                    self.resolve_conflict(ctx, &conflict, choice);
+                }
             }
-            LapceCommand::PreviousDiff => {}
             LapceCommand::ListNext => {
                 let completion = Arc::make_mut(&mut self.completion);
                 completion.next();
@@ -3034,6 +3169,12 @@ impl KeyPressFocus for LapceEditorBufferData {
                 let editor = Arc::make_mut(&mut self.editor);
                 editor.code_lens = !editor.code_lens;
             }
+            LapceCommand::ToggleReadOnly => {
+                if let BufferContent::File(_) = self.buffer.content() {
+                    let read_only = !self.buffer.read_only();
+                    self.buffer_mut().set_read_only(read_only);
+                }
+            }
             LapceCommand::GotoDefinition => {
                 let offset = self.editor.cursor.offset();
                 let start_offset = self.buffer.prev_code_boundary(offset);
@@ -3170,7 +3311,7 @@ impl KeyPressFocus for LapceEditorBufferData {
                 };
                 if !pattern.contains('\n') {
                     Arc::make_mut(&mut self.find)
-                        .set_find(&pattern, false, false, false);
+                        .set_find(&pattern, &SearchOptions::default());
                     ctx.submit_command(Command::new(
                         LAPCE_UI_COMMAND,
                         LapceUICommand::UpdateSearch(pattern),
@@ -3205,7 +3346,13 @@ impl KeyPressFocus for LapceEditorBufferData {
                     LapceUICommand::UpdateSearch(word.clone()),
                     Target::Widget(*self.main_split.tab_id),
                 ));
-                Arc::make_mut(&mut self.find).set_find(&word, false, false, true);
+                Arc::make_mut(&mut self.find).set_find(
+                    &word,
+                    &SearchOptions {
+                        whole_word: true,
+                        ..Default::default()
+                    },
+                );
                 let next = self.find.next(&self.buffer.rope(), offset, false, true);
                 if let Some((start, _end)) = next {
                     self.do_move(&Movement::Offset(start), 1, mods);
@@ -3359,6 +3506,20 @@ impl KeyPressFocus for LapceEditorBufferData {
                     });
                 }
             }
+            LapceCommand::RestoreComparedVersion => {
+                let content = self
+                    .editor
+                    .compare
+                    .as_ref()
+                    .and_then(|compare| self.buffer.histories().get(compare))
+                    .map(|rope| rope.to_string());
+                if let Some(content) = content {
+                    let selection = Selection::region(0, self.buffer.len());
+                    let delta =
+                        self.edit(&[(&selection, &content)], true, EditType::Other);
+                    Arc::make_mut(&mut self.editor).cursor.apply_delta(&delta);
+                }
+            }
             LapceCommand::Save => {
                 if !self.buffer.dirty() {
                     return CommandExecuted::Yes;
@@ -3388,7 +3549,9 @@ impl KeyPressFocus for LapceEditorBufferData {
 
                         let _ = event_sink.submit_command(
                             LAPCE_UI_COMMAND,
-                            LapceUICommand::DocumentFormatAndSave(path, rev, result),
+                            LapceUICommand::DocumentFormatAndSave(
+                                path, rev, result, false,
+                            ),
                             Target::Auto,
                         );
                     });
@@ -3400,6 +3563,9 @@ impl KeyPressFocus for LapceEditorBufferData {
     }
 
     fn receive_char(&mut self, ctx: &mut EventCtx, c: &str) {
+        if self.get_mode() == Mode::Insert && self.reject_read_only(ctx) {
+            return;
+        }
         if self.get_mode() == Mode::Insert {
             let mut selection = self
                 .editor
@@ -3526,6 +3692,27 @@ fn next_in_file_diff_offset(
     ((file_diffs[0].0).clone(), file_diffs[0].1[0])
 }
 
+fn previous_in_file_diff_offset(
+    position: Position,
+    path: &Path,
+    file_diffs: &[(PathBuf, Vec<Position>)],
+) -> (PathBuf, Position) {
+    for (current_path, positions) in file_diffs.iter().rev() {
+        if path == current_path {
+            for diff_position in positions.iter().rev() {
+                if diff_position.line < position.line {
+                    return ((*current_path).clone(), *diff_position);
+                }
+            }
+        }
+        if current_path.as_path() < path {
+            return ((*current_path).clone(), *positions.last().unwrap());
+        }
+    }
+    let (path, positions) = file_diffs.last().unwrap();
+    (path.clone(), *positions.last().unwrap())
+}
+
 fn next_in_file_errors_offset(
     position: Position,
     path: &Path,
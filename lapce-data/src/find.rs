@@ -1,3 +1,4 @@
+use lapce_rpc::search::SearchOptions;
 use regex::{Regex, RegexBuilder};
 use serde::{Deserialize, Serialize};
 use std::cmp::{max, min};
@@ -125,15 +126,15 @@ impl Find {
     pub fn set_find(
         &mut self,
         search_string: &str,
-        case_sensitive: bool,
-        is_regex: bool,
-        whole_words: bool,
+        options: &SearchOptions,
     ) -> bool {
         if search_string.is_empty() {
             self.unset();
         }
 
-        let case_matching = if case_sensitive {
+        let is_regex = options.is_regex;
+        let whole_words = options.whole_word;
+        let case_matching = if options.case_sensitive {
             CaseMatching::Exact
         } else {
             CaseMatching::CaseInsensitive
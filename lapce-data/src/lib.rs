@@ -23,8 +23,10 @@ pub mod proxy;
 pub mod scroll;
 pub mod search;
 pub mod settings;
+pub mod shell_integration;
 pub mod signature;
 pub mod source_control;
 pub mod split;
 pub mod state;
+pub mod task;
 pub mod terminal;
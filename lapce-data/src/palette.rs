@@ -1,5 +1,6 @@
 use alacritty_terminal::{grid::Dimensions, term::cell::Flags};
 use anyhow::Result;
+use chrono::{Local, TimeZone};
 use crossbeam_channel::{unbounded, Receiver, Sender, TryRecvError};
 use druid::{
     Command, ExtEventSink, Lens, Modifiers, Target,
@@ -11,6 +12,11 @@ use druid::{
 use fuzzy_matcher::skim::SkimMatcherV2;
 use fuzzy_matcher::FuzzyMatcher;
 use itertools::Itertools;
+use lapce_rpc::buffer::FileHistoryVersion;
+use lapce_rpc::search::SearchOptions;
+use lapce_rpc::source_control::{
+    DiffBase, FileDiff, GitBranch, GitCommit, GitRevision, GitRevisionKind, GitStash,
+};
 use lsp_types::{DocumentSymbolResponse, Range, SymbolKind};
 use serde_json;
 use std::collections::HashSet;
@@ -23,7 +29,9 @@ use crate::{
     buffer::BufferContent,
     command::LAPCE_UI_COMMAND,
     command::{CommandExecuted, LapceCommand, LAPCE_NEW_COMMAND},
-    command::{LapceCommandNew, LapceUICommand},
+    command::{
+        CommandTarget, LapceCommandNew, LapceUICommand, LapceWorkbenchCommand,
+    },
     config::Config,
     data::{FocusArea, LapceMainSplitData, LapceTabData, PanelKind},
     editor::EditorLocationNew,
@@ -31,12 +39,17 @@ use crate::{
     keypress::{KeyPressData, KeyPressFocus},
     movement::Movement,
     proxy::LapceProxy,
+    source_control::{commit_hint, SourceControlData},
     state::LapceWorkspace,
     state::LapceWorkspaceType,
     state::Mode,
+    task::read_tasks,
     terminal::TerminalSplitData,
 };
 
+/// How many commits the git log palette loads at a time.
+const GIT_LOG_PAGE_SIZE: usize = 100;
+
 #[derive(Clone, Debug, PartialEq)]
 pub enum PaletteType {
     File,
@@ -48,6 +61,68 @@ pub enum PaletteType {
     Reference,
     Theme,
     SshHost,
+    LocalHistory,
+    GitBranch(GitBranchAction),
+    /// Asks for the name of a new branch starting at the given revision.
+    GitBranchName(String),
+    /// The commits of the repository, or only those touching the path.
+    GitLog(Option<PathBuf>),
+    /// The files changed by the given commit.
+    GitCommitFiles(String),
+    GitStash(GitStashAction),
+    /// Asks for the message of a new stash, which includes untracked files
+    /// if true.
+    GitStashMessage(bool),
+    /// What the file is diffed against.
+    GitDiffBase(PathBuf),
+    /// The profile of a new terminal.
+    TerminalProfile,
+    /// The tasks of the workspace.
+    Task,
+}
+
+/// What happens to the branch picked in the branch palette.
+#[derive(Clone, Debug, PartialEq)]
+pub enum GitBranchAction {
+    Checkout,
+    ForceCheckout,
+    /// Pick the revision the new branch starts at.
+    Create,
+    Delete,
+}
+
+/// What happens to the stash picked in the stash palette.
+#[derive(Clone, Debug, PartialEq)]
+pub enum GitStashAction {
+    /// List the files the stash changes.
+    Show,
+    Apply,
+    Pop,
+    Drop,
+}
+
+impl GitStashAction {
+    fn command(&self) -> Option<LapceWorkbenchCommand> {
+        match self {
+            GitStashAction::Show => None,
+            GitStashAction::Apply => Some(LapceWorkbenchCommand::ApplyStash),
+            GitStashAction::Pop => Some(LapceWorkbenchCommand::PopStash),
+            GitStashAction::Drop => Some(LapceWorkbenchCommand::DropStash),
+        }
+    }
+}
+
+impl GitBranchAction {
+    fn command(&self) -> LapceWorkbenchCommand {
+        match self {
+            GitBranchAction::Checkout => LapceWorkbenchCommand::CheckoutBranch,
+            GitBranchAction::ForceCheckout => {
+                LapceWorkbenchCommand::ForceCheckoutBranch
+            }
+            GitBranchAction::Create => LapceWorkbenchCommand::CreateBranch,
+            GitBranchAction::Delete => LapceWorkbenchCommand::DeleteBranch,
+        }
+    }
 }
 
 impl PaletteType {
@@ -62,6 +137,16 @@ impl PaletteType {
             PaletteType::Reference => "".to_string(),
             PaletteType::Theme => "".to_string(),
             PaletteType::SshHost => "".to_string(),
+            PaletteType::LocalHistory => "".to_string(),
+            PaletteType::GitBranch(_) => "".to_string(),
+            PaletteType::GitBranchName(_) => "".to_string(),
+            PaletteType::GitLog(_) => "".to_string(),
+            PaletteType::GitCommitFiles(_) => "".to_string(),
+            PaletteType::GitStash(_) => "".to_string(),
+            PaletteType::GitStashMessage(_) => "".to_string(),
+            PaletteType::GitDiffBase(_) => "".to_string(),
+            PaletteType::TerminalProfile => "".to_string(),
+            PaletteType::Task => "".to_string(),
         }
     }
 
@@ -106,6 +191,38 @@ pub enum PaletteItemContent {
     SshHost(String, String),
     Command(LapceCommandNew),
     Theme(String),
+    LocalHistory {
+        path: PathBuf,
+        version: String,
+        time: String,
+    },
+    GitBranch(GitBranchAction, String),
+    GitCommit(GitCommit),
+    /// Loads the next page of the git log.
+    GitLogMore {
+        path: Option<PathBuf>,
+        skip: usize,
+    },
+    GitCommitFile {
+        commit: String,
+        path: PathBuf,
+        diff: FileDiff,
+    },
+    GitStash(GitStashAction, GitStash),
+    GitDiffBase {
+        path: PathBuf,
+        base: DiffBase,
+        name: String,
+        hint: String,
+    },
+    TerminalProfile {
+        name: String,
+        hint: String,
+    },
+    Task {
+        name: String,
+        hint: String,
+    },
 }
 
 impl PaletteItemContent {
@@ -217,6 +334,114 @@ impl PaletteItemContent {
                     ));
                 }
             }
+            PaletteItemContent::LocalHistory { path, version, .. } => {
+                if !preview {
+                    ctx.submit_command(Command::new(
+                        LAPCE_UI_COMMAND,
+                        LapceUICommand::OpenLocalHistory(
+                            path.clone(),
+                            version.clone(),
+                        ),
+                        Target::Auto,
+                    ));
+                }
+            }
+            PaletteItemContent::GitBranch(action, branch) => {
+                if !preview {
+                    if action == &GitBranchAction::Create {
+                        return Some(PaletteType::GitBranchName(branch.clone()));
+                    }
+                    let command = action.command();
+                    ctx.submit_command(Command::new(
+                        LAPCE_NEW_COMMAND,
+                        LapceCommandNew {
+                            cmd: command.to_string(),
+                            data: Some(serde_json::json!(branch)),
+                            palette_desc: None,
+                            target: CommandTarget::Workbench,
+                        },
+                        Target::Auto,
+                    ));
+                }
+            }
+            PaletteItemContent::TerminalProfile { name, .. } => {
+                if !preview {
+                    ctx.submit_command(Command::new(
+                        LAPCE_NEW_COMMAND,
+                        LapceCommandNew {
+                            cmd: LapceWorkbenchCommand::NewTerminal.to_string(),
+                            data: Some(serde_json::json!(name)),
+                            palette_desc: None,
+                            target: CommandTarget::Workbench,
+                        },
+                        Target::Auto,
+                    ));
+                }
+            }
+            PaletteItemContent::Task { name, .. } => {
+                if !preview {
+                    ctx.submit_command(Command::new(
+                        LAPCE_NEW_COMMAND,
+                        LapceCommandNew {
+                            cmd: LapceWorkbenchCommand::RunTask.to_string(),
+                            data: Some(serde_json::json!(name)),
+                            palette_desc: None,
+                            target: CommandTarget::Workbench,
+                        },
+                        Target::Auto,
+                    ));
+                }
+            }
+            PaletteItemContent::GitCommit(commit) => {
+                if !preview {
+                    return Some(PaletteType::GitCommitFiles(commit.id.clone()));
+                }
+            }
+            PaletteItemContent::GitLogMore { .. } => {}
+            PaletteItemContent::GitStash(action, stash) => {
+                if !preview {
+                    match action.command() {
+                        Some(command) => {
+                            ctx.submit_command(Command::new(
+                                LAPCE_NEW_COMMAND,
+                                LapceCommandNew {
+                                    cmd: command.to_string(),
+                                    data: Some(serde_json::json!(stash.index)),
+                                    palette_desc: None,
+                                    target: CommandTarget::Workbench,
+                                },
+                                Target::Auto,
+                            ));
+                        }
+                        None => {
+                            return Some(PaletteType::GitCommitFiles(
+                                stash.id.clone(),
+                            ));
+                        }
+                    }
+                }
+            }
+            PaletteItemContent::GitDiffBase { path, base, .. } => {
+                if !preview {
+                    ctx.submit_command(Command::new(
+                        LAPCE_UI_COMMAND,
+                        LapceUICommand::SetDiffBase(path.clone(), base.clone()),
+                        Target::Auto,
+                    ));
+                }
+            }
+            PaletteItemContent::GitCommitFile { commit, diff, .. } => {
+                if !preview {
+                    ctx.submit_command(Command::new(
+                        LAPCE_UI_COMMAND,
+                        LapceUICommand::OpenCommitFileDiff(
+                            commit.clone(),
+                            diff.clone(),
+                        ),
+                        Target::Auto,
+                    ));
+                }
+            }
         }
         None
     }
@@ -242,6 +467,7 @@ pub struct PaletteViewData {
     pub config: Arc<Config>,
     pub focus_area: FocusArea,
     pub terminal: Arc<TerminalSplitData>,
+    pub source_control: Arc<SourceControlData>,
 }
 
 impl Lens<LapceTabData, PaletteViewData> for PaletteViewLens {
@@ -397,6 +623,16 @@ impl PaletteData {
             PaletteType::Reference => &self.input,
             PaletteType::Theme => &self.input,
             PaletteType::SshHost => &self.input,
+            PaletteType::LocalHistory => &self.input,
+            PaletteType::GitBranch(_) => &self.input,
+            PaletteType::GitBranchName(_) => &self.input,
+            PaletteType::GitLog(_) => &self.input,
+            PaletteType::GitCommitFiles(_) => &self.input,
+            PaletteType::GitStash(_) => &self.input,
+            PaletteType::GitStashMessage(_) => &self.input,
+            PaletteType::GitDiffBase(_) => &self.input,
+            PaletteType::TerminalProfile => &self.input,
+            PaletteType::Task => &self.input,
             PaletteType::Line => &self.input[1..],
             PaletteType::DocumentSymbol => &self.input[1..],
             PaletteType::Workspace => &self.input[1..],
@@ -499,10 +735,42 @@ impl PaletteViewData {
             PaletteType::Command => {
                 self.get_commands(ctx);
             }
+            PaletteType::LocalHistory => {
+                self.get_local_history(ctx);
+            }
+            PaletteType::GitBranch(ref action) => {
+                let action = action.clone();
+                self.get_git_branches(ctx, action);
+            }
+            PaletteType::GitBranchName(_) => {}
+            PaletteType::GitLog(ref path) => {
+                let path = path.clone();
+                self.get_git_log(ctx, path, 0);
+            }
+            PaletteType::GitCommitFiles(ref commit) => {
+                let commit = commit.clone();
+                self.get_git_commit_files(ctx, commit);
+            }
+            PaletteType::GitStash(ref action) => {
+                let action = action.clone();
+                self.get_git_stashes(ctx, action);
+            }
+            PaletteType::GitStashMessage(_) => {}
+            PaletteType::GitDiffBase(ref path) => {
+                let path = path.clone();
+                self.get_git_diff_bases(ctx, path);
+            }
             PaletteType::Theme => {
                 let config = self.config.clone();
                 self.get_themes(ctx, &config);
             }
+            PaletteType::TerminalProfile => {
+                let config = self.config.clone();
+                self.get_terminal_profiles(ctx, &config);
+            }
+            PaletteType::Task => {
+                self.get_tasks(ctx);
+            }
         }
     }
 
@@ -528,6 +796,16 @@ impl PaletteViewData {
             PaletteType::Reference => 0,
             PaletteType::Theme => 0,
             PaletteType::SshHost => 0,
+            PaletteType::LocalHistory => 0,
+            PaletteType::GitBranch(_) => 0,
+            PaletteType::GitBranchName(_) => 0,
+            PaletteType::GitLog(_) => 0,
+            PaletteType::GitCommitFiles(_) => 0,
+            PaletteType::GitStash(_) => 0,
+            PaletteType::GitStashMessage(_) => 0,
+            PaletteType::GitDiffBase(_) => 0,
+            PaletteType::TerminalProfile => 0,
+            PaletteType::Task => 0,
             PaletteType::Line => 1,
             PaletteType::DocumentSymbol => 1,
             PaletteType::Workspace => 1,
@@ -564,13 +842,20 @@ impl PaletteViewData {
             let pattern = self.palette.get_input().to_string();
             let find = Arc::make_mut(&mut self.find);
             find.visual = true;
-            find.set_find(&pattern, false, false, false);
+            find.set_find(&pattern, &SearchOptions::default());
             ctx.submit_command(Command::new(
                 LAPCE_UI_COMMAND,
                 LapceUICommand::UpdateSearch(pattern),
                 Target::Widget(*self.main_split.tab_id),
             ));
         }
+        if let Some(PaletteItemContent::GitLogMore { path, skip }) =
+            self.palette.get_item().map(|item| &item.content)
+        {
+            let (path, skip) = (path.clone(), *skip);
+            self.get_git_log(ctx, path, skip);
+            return;
+        }
         let palette = Arc::make_mut(&mut self.palette);
         if let Some(item) = palette.get_item() {
             if let Some(palette_type) =
@@ -601,6 +886,47 @@ impl PaletteViewData {
                 ));
                 return;
             }
+            if let PaletteType::GitBranchName(start_point) =
+                &self.palette.palette_type
+            {
+                let name = self.palette.get_input().trim().to_string();
+                if !name.is_empty() {
+                    ctx.submit_command(Command::new(
+                        LAPCE_NEW_COMMAND,
+                        LapceCommandNew {
+                            cmd: LapceWorkbenchCommand::CreateBranch.to_string(),
+                            data: Some(serde_json::json!({
+                                "name": name,
+                                "start_point": start_point,
+                            })),
+                            palette_desc: None,
+                            target: CommandTarget::Workbench,
+                        },
+                        Target::Auto,
+                    ));
+                }
+            }
+            if let PaletteType::GitStashMessage(include_untracked) =
+                self.palette.palette_type
+            {
+                let command = if include_untracked {
+                    LapceWorkbenchCommand::StashChangesIncludeUntracked
+                } else {
+                    LapceWorkbenchCommand::StashChanges
+                };
+                ctx.submit_command(Command::new(
+                    LAPCE_NEW_COMMAND,
+                    LapceCommandNew {
+                        cmd: command.to_string(),
+                        data: Some(serde_json::json!({
+                            "message": self.palette.get_input().trim(),
+                        })),
+                        palette_desc: None,
+                        target: CommandTarget::Workbench,
+                    },
+                    Target::Auto,
+                ));
+            }
             self.cancel(ctx);
         }
     }
@@ -626,7 +952,18 @@ impl PaletteViewData {
 
     fn get_palette_type(&self) -> PaletteType {
         match self.palette.palette_type {
-            PaletteType::Reference | PaletteType::SshHost => {
+            PaletteType::Reference
+            | PaletteType::SshHost
+            | PaletteType::LocalHistory
+            | PaletteType::GitBranch(_)
+            | PaletteType::GitBranchName(_)
+            | PaletteType::GitLog(_)
+            | PaletteType::GitCommitFiles(_)
+            | PaletteType::GitStash(_)
+            | PaletteType::GitStashMessage(_)
+            | PaletteType::GitDiffBase(_)
+            | PaletteType::TerminalProfile
+            | PaletteType::Task => {
                 return self.palette.palette_type.clone();
             }
             _ => (),
@@ -686,6 +1023,321 @@ impl PaletteViewData {
         }));
     }
 
+    fn get_local_history(&self, ctx: &mut EventCtx) {
+        let path = match self.main_split.active_editor().map(|e| &e.content) {
+            Some(BufferContent::File(path)) => path.clone(),
+            _ => return,
+        };
+        let run_id = self.palette.run_id.clone();
+        let widget_id = self.palette.widget_id;
+        let event_sink = ctx.get_external_handle();
+        self.palette.proxy.get_file_history(
+            path.clone(),
+            Box::new(move |result| {
+                if let Ok(res) = result {
+                    let resp: Result<Vec<FileHistoryVersion>, serde_json::Error> =
+                        serde_json::from_value(res);
+                    if let Ok(resp) = resp {
+                        let items: Vec<NewPaletteItem> = resp
+                            .into_iter()
+                            .map(|version| {
+                                let time = Local
+                                    .timestamp_millis(version.time as i64)
+                                    .format("%Y-%m-%d %H:%M:%S")
+                                    .to_string();
+                                NewPaletteItem {
+                                    content: PaletteItemContent::LocalHistory {
+                                        path: path.clone(),
+                                        version: version.id,
+                                        time: time.clone(),
+                                    },
+                                    filter_text: time,
+                                    score: 0,
+                                    indices: Vec::new(),
+                                }
+                            })
+                            .collect();
+
+                        let _ = event_sink.submit_command(
+                            LAPCE_UI_COMMAND,
+                            LapceUICommand::UpdatePaletteItems(run_id, items),
+                            Target::Widget(widget_id),
+                        );
+                    }
+                }
+            }),
+        );
+    }
+
+    fn get_git_branches(&self, ctx: &mut EventCtx, action: GitBranchAction) {
+        let run_id = self.palette.run_id.clone();
+        let widget_id = self.palette.widget_id;
+        let event_sink = ctx.get_external_handle();
+        self.palette.proxy.git_branches(
+            &self.source_control.repository,
+            Box::new(move |result| {
+                if let Ok(res) = result {
+                    let resp: Result<Vec<GitBranch>, serde_json::Error> =
+                        serde_json::from_value(res);
+                    if let Ok(resp) = resp {
+                        let mut names = Vec::new();
+                        match action {
+                            GitBranchAction::Checkout
+                            | GitBranchAction::ForceCheckout => {
+                                names.extend(
+                                    resp.into_iter()
+                                        .filter(|b| !b.head)
+                                        .map(|b| b.name),
+                                );
+                            }
+                            GitBranchAction::Create => {
+                                names.push("HEAD".to_string());
+                                names.extend(resp.into_iter().map(|b| b.name));
+                            }
+                            GitBranchAction::Delete => {
+                                names.extend(
+                                    resp.into_iter()
+                                        .filter(|b| !b.head && !b.remote)
+                                        .map(|b| b.name),
+                                );
+                            }
+                        }
+                        let items: Vec<NewPaletteItem> = names
+                            .into_iter()
+                            .map(|name| NewPaletteItem {
+                                content: PaletteItemContent::GitBranch(
+                                    action.clone(),
+                                    name.clone(),
+                                ),
+                                filter_text: name,
+                                score: 0,
+                                indices: Vec::new(),
+                            })
+                            .collect();
+
+                        let _ = event_sink.submit_command(
+                            LAPCE_UI_COMMAND,
+                            LapceUICommand::UpdatePaletteItems(run_id, items),
+                            Target::Widget(widget_id),
+                        );
+                    }
+                }
+            }),
+        );
+    }
+
+    /// Lists HEAD, the index and the revisions of the repository to diff the
+    /// file against.
+    fn get_git_diff_bases(&self, ctx: &mut EventCtx, path: PathBuf) {
+        let run_id = self.palette.run_id.clone();
+        let widget_id = self.palette.widget_id;
+        let event_sink = ctx.get_external_handle();
+        self.palette.proxy.git_revisions(
+            path.clone(),
+            Box::new(move |result| {
+                if let Ok(res) = result {
+                    let resp: Result<Vec<GitRevision>, serde_json::Error> =
+                        serde_json::from_value(res);
+                    if let Ok(resp) = resp {
+                        let mut bases = vec![
+                            (DiffBase::Head, "HEAD".to_string(), "".to_string()),
+                            (
+                                DiffBase::Index,
+                                "Index".to_string(),
+                                "Staged Changes".to_string(),
+                            ),
+                        ];
+                        bases.extend(resp.into_iter().map(|revision| {
+                            let name = match revision.kind {
+                                GitRevisionKind::Commit => {
+                                    revision.name[..7].to_string()
+                                }
+                                _ => revision.name.clone(),
+                            };
+                            let kind = match revision.kind {
+                                GitRevisionKind::Branch => "Branch",
+                                GitRevisionKind::RemoteBranch => "Remote Branch",
+                                GitRevisionKind::Tag => "Tag",
+                                GitRevisionKind::Commit => "Commit",
+                            };
+                            (
+                                DiffBase::Revision(revision.name),
+                                name,
+                                format!("{kind} • {}", revision.summary),
+                            )
+                        }));
+                        let items: Vec<NewPaletteItem> = bases
+                            .into_iter()
+                            .map(|(base, name, hint)| NewPaletteItem {
+                                filter_text: format!("{name} {hint}"),
+                                content: PaletteItemContent::GitDiffBase {
+                                    path: path.clone(),
+                                    base,
+                                    name,
+                                    hint,
+                                },
+                                score: 0,
+                                indices: Vec::new(),
+                            })
+                            .collect();
+
+                        let _ = event_sink.submit_command(
+                            LAPCE_UI_COMMAND,
+                            LapceUICommand::UpdatePaletteItems(run_id, items),
+                            Target::Widget(widget_id),
+                        );
+                    }
+                }
+            }),
+        );
+    }
+
+    /// Loads a page of commits after the `skip` already listed, which are
+    /// kept, and ends the list with an item loading the next page.
+    fn get_git_log(&self, ctx: &mut EventCtx, path: Option<PathBuf>, skip: usize) {
+        let mut items: Vec<NewPaletteItem> = self
+            .palette
+            .items
+            .iter()
+            .filter(|item| matches!(item.content, PaletteItemContent::GitCommit(_)))
+            .cloned()
+            .collect();
+        let run_id = self.palette.run_id.clone();
+        let widget_id = self.palette.widget_id;
+        let event_sink = ctx.get_external_handle();
+        self.palette.proxy.git_log(
+            &self.source_control.repository,
+            path.clone(),
+            skip,
+            GIT_LOG_PAGE_SIZE,
+            Box::new(move |result| {
+                if let Ok(res) = result {
+                    let resp: Result<Vec<GitCommit>, serde_json::Error> =
+                        serde_json::from_value(res);
+                    if let Ok(resp) = resp {
+                        let more = resp.len() == GIT_LOG_PAGE_SIZE;
+                        items.extend(resp.into_iter().map(|commit| {
+                            let filter_text = format!(
+                                "{}{}",
+                                commit.summary,
+                                commit_hint(&commit)
+                            );
+                            NewPaletteItem {
+                                content: PaletteItemContent::GitCommit(commit),
+                                filter_text,
+                                score: 0,
+                                indices: Vec::new(),
+                            }
+                        }));
+                        if more {
+                            items.push(NewPaletteItem {
+                                content: PaletteItemContent::GitLogMore {
+                                    path,
+                                    skip: skip + GIT_LOG_PAGE_SIZE,
+                                },
+                                filter_text: "".to_string(),
+                                score: 0,
+                                indices: Vec::new(),
+                            });
+                        }
+
+                        let _ = event_sink.submit_command(
+                            LAPCE_UI_COMMAND,
+                            LapceUICommand::UpdatePaletteItems(run_id, items),
+                            Target::Widget(widget_id),
+                        );
+                    }
+                }
+            }),
+        );
+    }
+
+    fn get_git_commit_files(&self, ctx: &mut EventCtx, commit: String) {
+        let run_id = self.palette.run_id.clone();
+        let widget_id = self.palette.widget_id;
+        let workspace = self.workspace.clone();
+        let event_sink = ctx.get_external_handle();
+        self.palette.proxy.git_commit_files(
+            &self.source_control.repository,
+            commit.clone(),
+            Box::new(move |result| {
+                if let Ok(res) = result {
+                    let resp: Result<Vec<FileDiff>, serde_json::Error> =
+                        serde_json::from_value(res);
+                    if let Ok(resp) = resp {
+                        let items: Vec<NewPaletteItem> = resp
+                            .into_iter()
+                            .map(|diff| {
+                                let full_path = diff.path().clone();
+                                let mut path = full_path.clone();
+                                if let Some(workspace_path) = workspace.path.as_ref()
+                                {
+                                    path = path
+                                        .strip_prefix(workspace_path)
+                                        .unwrap_or(&full_path)
+                                        .to_path_buf();
+                                }
+                                let filter_text =
+                                    path.to_str().unwrap_or("").to_string();
+                                NewPaletteItem {
+                                    content: PaletteItemContent::GitCommitFile {
+                                        commit: commit.clone(),
+                                        path,
+                                        diff,
+                                    },
+                                    filter_text,
+                                    score: 0,
+                                    indices: Vec::new(),
+                                }
+                            })
+                            .collect();
+
+                        let _ = event_sink.submit_command(
+                            LAPCE_UI_COMMAND,
+                            LapceUICommand::UpdatePaletteItems(run_id, items),
+                            Target::Widget(widget_id),
+                        );
+                    }
+                }
+            }),
+        );
+    }
+
+    fn get_git_stashes(&self, ctx: &mut EventCtx, action: GitStashAction) {
+        let run_id = self.palette.run_id.clone();
+        let widget_id = self.palette.widget_id;
+        let event_sink = ctx.get_external_handle();
+        self.palette.proxy.git_stashes(
+            &self.source_control.repository,
+            Box::new(move |result| {
+                if let Ok(res) = result {
+                    let resp: Result<Vec<GitStash>, serde_json::Error> =
+                        serde_json::from_value(res);
+                    if let Ok(resp) = resp {
+                        let items: Vec<NewPaletteItem> = resp
+                            .into_iter()
+                            .map(|stash| NewPaletteItem {
+                                filter_text: stash.message.clone(),
+                                content: PaletteItemContent::GitStash(
+                                    action.clone(),
+                                    stash,
+                                ),
+                                score: 0,
+                                indices: Vec::new(),
+                            })
+                            .collect();
+
+                        let _ = event_sink.submit_command(
+                            LAPCE_UI_COMMAND,
+                            LapceUICommand::UpdatePaletteItems(run_id, items),
+                            Target::Widget(widget_id),
+                        );
+                    }
+                }
+            }),
+        );
+    }
+
     #[allow(unused_variables)]
     fn get_ssh_hosts(&mut self, ctx: &mut EventCtx) {
         let workspaces = Config::recent_workspaces().unwrap_or_default();
@@ -759,6 +1411,82 @@ impl PaletteViewData {
             .collect();
     }
 
+    #[allow(unused_variables)]
+    fn get_terminal_profiles(&mut self, ctx: &mut EventCtx, config: &Config) {
+        let palette = Arc::make_mut(&mut self.palette);
+        palette.items = config
+            .terminal
+            .profiles
+            .iter()
+            .map(|profile| {
+                let mut hint = profile.program.clone();
+                for arg in &profile.args {
+                    hint.push(' ');
+                    hint.push_str(arg);
+                }
+                NewPaletteItem {
+                    content: PaletteItemContent::TerminalProfile {
+                        name: profile.name.clone(),
+                        hint,
+                    },
+                    filter_text: profile.name.clone(),
+                    score: 0,
+                    indices: vec![],
+                }
+            })
+            .collect();
+    }
+
+    fn get_tasks(&self, ctx: &mut EventCtx) {
+        let workspace = match self.workspace.path.as_ref() {
+            Some(workspace) => workspace,
+            None => return,
+        };
+        let run_id = self.palette.run_id.clone();
+        let widget_id = self.palette.widget_id;
+        let tab_id = *self.main_split.tab_id;
+        let event_sink = ctx.get_external_handle();
+        read_tasks(&self.palette.proxy, workspace, move |tasks| {
+            let tasks = match tasks {
+                Ok(tasks) => tasks,
+                Err(e) => {
+                    let _ = event_sink.submit_command(
+                        LAPCE_UI_COMMAND,
+                        LapceUICommand::ShowStatusMessage(e.to_string()),
+                        Target::Widget(tab_id),
+                    );
+                    return;
+                }
+            };
+            let items: Vec<NewPaletteItem> = tasks
+                .tasks
+                .into_iter()
+                .map(|task| {
+                    let mut hint = task.command;
+                    for arg in &task.args {
+                        hint.push(' ');
+                        hint.push_str(arg);
+                    }
+                    NewPaletteItem {
+                        filter_text: task.name.clone(),
+                        content: PaletteItemContent::Task {
+                            name: task.name,
+                            hint,
+                        },
+                        score: 0,
+                        indices: Vec::new(),
+                    }
+                })
+                .collect();
+
+            let _ = event_sink.submit_command(
+                LAPCE_UI_COMMAND,
+                LapceUICommand::UpdatePaletteItems(run_id, items),
+                Target::Widget(widget_id),
+            );
+        });
+    }
+
     #[allow(unused_variables)]
     fn get_commands(&mut self, ctx: &mut EventCtx) {
         let palette = Arc::make_mut(&mut self.palette);
@@ -997,6 +1725,11 @@ impl PaletteViewData {
                     item.score = score;
                     item.indices = indices;
                     Some(item)
+                } else if let PaletteItemContent::GitLogMore { .. } = i.content {
+                    // Paging stays reachable whatever the filter.
+                    let mut item = i.clone();
+                    item.score = i64::MIN;
+                    Some(item)
                 } else {
                     None
                 }
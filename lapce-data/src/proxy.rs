@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::io::BufReader;
 #[cfg(target_os = "windows")]
 use std::os::windows::process::CommandExt;
@@ -16,8 +17,9 @@ use lapce_proxy::dispatch::Dispatcher;
 use lapce_rpc::buffer::BufferId;
 use lapce_rpc::core::{CoreNotification, CoreRequest};
 use lapce_rpc::plugin::PluginDescription;
-use lapce_rpc::source_control::FileDiff;
-use lapce_rpc::terminal::TermId;
+use lapce_rpc::search::{SearchOptions, SearchReplacement};
+use lapce_rpc::source_control::{DiffBase, FileDiff, GitCredentials, GitHunk};
+use lapce_rpc::terminal::{TermId, TerminalProfile};
 use lapce_rpc::RpcHandler;
 use lapce_rpc::{stdio_transport, Callback};
 use lapce_rpc::{ControlFlow, Handler};
@@ -134,10 +136,10 @@ impl Handler for LapceProxy {
             ListDir { items } => {}
             #[allow(unused_variables)]
             DiffFiles { files } => {}
-            DiffInfo { diff } => {
+            DiffInfo { repositories } => {
                 let _ = self.event_sink.submit_command(
                     LAPCE_UI_COMMAND,
-                    LapceUICommand::UpdateDiffInfo(diff),
+                    LapceUICommand::UpdateDiffInfo(repositories),
                     Target::Widget(self.tab_id),
                 );
             }
@@ -148,11 +150,6 @@ impl Handler for LapceProxy {
             }
             CloseTerminal { term_id } => {
                 let _ = self.term_tx.send((term_id, TermEvent::CloseTerminal));
-                let _ = self.event_sink.submit_command(
-                    LAPCE_UI_COMMAND,
-                    LapceUICommand::CloseTerminal(term_id),
-                    Target::Widget(self.tab_id),
-                );
             }
             ProxyConnected {} => {
                 let _ = self.event_sink.submit_command(
@@ -168,12 +165,80 @@ impl Handler for LapceProxy {
                     Target::Widget(self.tab_id),
                 );
             }
+            GlobalSearchResult { search_id, matches } => {
+                let _ = self.event_sink.submit_command(
+                    LAPCE_UI_COMMAND,
+                    LapceUICommand::GlobalSearchResult(search_id, Arc::new(matches)),
+                    Target::Widget(self.tab_id),
+                );
+            }
+            GlobalSearchDone {
+                search_id,
+                limit_reached,
+                error,
+            } => {
+                let _ = self.event_sink.submit_command(
+                    LAPCE_UI_COMMAND,
+                    LapceUICommand::GlobalSearchDone(
+                        search_id,
+                        limit_reached,
+                        error,
+                    ),
+                    Target::Widget(self.tab_id),
+                );
+            }
         }
         ControlFlow::Continue
     }
 
-    fn handle_request(&mut self, _rpc: Self::Request) -> Result<Value, Value> {
-        Err(json!("unimplemented"))
+    fn handle_request(&mut self, id: u64, rpc: Self::Request) {
+        match rpc {
+            CoreRequest::GitCredentials {
+                url,
+                username,
+                ssh_key,
+            } => {
+                // The dialogs block, so they get a thread of their own and
+                // the messages from the proxy keep being handled meanwhile.
+                let rpc = self.rpc.clone();
+                thread::spawn(move || {
+                    let credentials =
+                        ask_git_credentials(&url, username, ssh_key.as_deref());
+                    rpc.respond(id, Ok(json!(credentials)));
+                });
+            }
+        }
+    }
+}
+
+/// Asks for the credentials to the git remote at `url`, or only for the
+/// passphrase of `ssh_key`. None when the user cancelled.
+fn ask_git_credentials(
+    url: &str,
+    username: Option<String>,
+    ssh_key: Option<&Path>,
+) -> Option<GitCredentials> {
+    let title = "Git Credentials";
+    match ssh_key {
+        Some(ssh_key) => tinyfiledialogs::password_box(
+            title,
+            &format!("Passphrase for {}", ssh_key.display()),
+        )
+        .map(|password| GitCredentials {
+            username: username.unwrap_or_else(|| "git".to_string()),
+            password,
+        }),
+        None => username
+            .or_else(|| {
+                tinyfiledialogs::input_box(title, &format!("Username for {url}"), "")
+            })
+            .and_then(|username| {
+                let password = tinyfiledialogs::password_box(
+                    title,
+                    &format!("Password for {username} at {url}"),
+                )?;
+                Some(GitCredentials { username, password })
+            }),
     }
 }
 
@@ -332,6 +397,15 @@ impl LapceProxy {
         )
     }
 
+    pub fn git_close_snapshot(&self, path: PathBuf) {
+        self.rpc.send_rpc_notification(
+            "git_close_snapshot",
+            &json!({
+                "path": path,
+            }),
+        )
+    }
+
     pub fn terminal_resize(&self, term_id: TermId, width: usize, height: usize) {
         self.rpc.send_rpc_notification(
             "terminal_resize",
@@ -357,7 +431,7 @@ impl LapceProxy {
         &self,
         term_id: TermId,
         cwd: Option<PathBuf>,
-        shell: String,
+        profile: TerminalProfile,
         raw: Arc<Mutex<RawTerminal>>,
     ) {
         let _ = self.term_tx.send((term_id, TermEvent::NewTerminal(raw)));
@@ -366,19 +440,31 @@ impl LapceProxy {
             &json!({
                 "term_id": term_id,
                 "cwd": cwd,
-                "shell": shell,
+                "profile": profile,
             }),
         )
     }
 
-    pub fn git_commit(&self, message: &str, diffs: Vec<FileDiff>) {
-        self.rpc.send_rpc_notification(
+    pub fn git_commit(
+        &self,
+        repository: &Path,
+        message: &str,
+        diffs: Vec<FileDiff>,
+        amend: bool,
+        sign_off: bool,
+        f: Box<dyn Callback>,
+    ) {
+        self.rpc.send_rpc_request_async(
             "git_commit",
             &json!({
+                "repository": repository,
                 "message": message,
                 "diffs": diffs,
+                "amend": amend,
+                "sign_off": sign_off,
             }),
-        )
+            f,
+        );
     }
 
     pub fn install_plugin(&self, plugin: &PluginDescription) {
@@ -390,36 +476,334 @@ impl LapceProxy {
         &self,
         buffer_id: BufferId,
         path: PathBuf,
+        base: DiffBase,
         f: Box<dyn Callback>,
     ) {
         self.rpc.send_rpc_request_async(
             "buffer_head",
-            &json!({ "buffer_id": buffer_id, "path": path, }),
+            &json!({ "buffer_id": buffer_id, "path": path, "base": base }),
             f,
         );
     }
 
-    pub fn global_search(&self, pattern: String, f: Box<dyn Callback>) {
-        self.rpc.send_rpc_request_async(
+    pub fn global_search(
+        &self,
+        search_id: u64,
+        pattern: &str,
+        options: &SearchOptions,
+        max_file_matches: usize,
+        max_matches: usize,
+    ) {
+        self.rpc.send_rpc_notification(
             "global_search",
-            &json!({ "pattern": pattern }),
+            &json!({
+                "search_id": search_id,
+                "pattern": pattern,
+                "options": options,
+                "max_file_matches": max_file_matches,
+                "max_matches": max_matches,
+            }),
+        );
+    }
+
+    pub fn replace_in_files(
+        &self,
+        files: HashMap<PathBuf, Vec<SearchReplacement>>,
+        f: Box<dyn Callback>,
+    ) {
+        self.rpc.send_rpc_request_async(
+            "replace_in_files",
+            &json!({ "files": files }),
+            f,
+        );
+    }
+
+    pub fn git_branches(&self, repository: &Path, f: Box<dyn Callback>) {
+        self.rpc.send_rpc_request_async(
+            "git_branches",
+            &json!({ "repository": repository }),
+            f,
+        );
+    }
+
+    pub fn git_checkout(
+        &self,
+        repository: &Path,
+        branch: &str,
+        force: bool,
+        f: Box<dyn Callback>,
+    ) {
+        self.rpc.send_rpc_request_async(
+            "git_checkout",
+            &json!({
+                "repository": repository,
+                "branch": branch,
+                "force": force,
+            }),
+            f,
+        );
+    }
+
+    pub fn git_create_branch(
+        &self,
+        repository: &Path,
+        name: &str,
+        start_point: Option<&str>,
+        f: Box<dyn Callback>,
+    ) {
+        self.rpc.send_rpc_request_async(
+            "git_create_branch",
+            &json!({
+                "repository": repository,
+                "name": name,
+                "start_point": start_point,
+            }),
+            f,
+        );
+    }
+
+    pub fn git_delete_branch(
+        &self,
+        repository: &Path,
+        name: &str,
+        f: Box<dyn Callback>,
+    ) {
+        self.rpc.send_rpc_request_async(
+            "git_delete_branch",
+            &json!({ "repository": repository, "name": name }),
+            f,
+        );
+    }
+
+    pub fn git_fetch(&self, repository: &Path, f: Box<dyn Callback>) {
+        self.rpc.send_rpc_request_async(
+            "git_fetch",
+            &json!({ "repository": repository }),
+            f,
+        );
+    }
+
+    pub fn git_pull(&self, repository: &Path, rebase: bool, f: Box<dyn Callback>) {
+        self.rpc.send_rpc_request_async(
+            "git_pull",
+            &json!({ "repository": repository, "rebase": rebase }),
+            f,
+        );
+    }
+
+    pub fn git_push(&self, repository: &Path, f: Box<dyn Callback>) {
+        self.rpc.send_rpc_request_async(
+            "git_push",
+            &json!({ "repository": repository }),
+            f,
+        );
+    }
+
+    pub fn git_resolve(&self, path: &Path, f: Box<dyn Callback>) {
+        self.rpc
+            .send_rpc_request_async("git_resolve", &json!({ "path": path }), f);
+    }
+
+    pub fn git_stash_save(
+        &self,
+        repository: &Path,
+        message: &str,
+        include_untracked: bool,
+        f: Box<dyn Callback>,
+    ) {
+        self.rpc.send_rpc_request_async(
+            "git_stash_save",
+            &json!({
+                "repository": repository,
+                "message": message,
+                "include_untracked": include_untracked,
+            }),
+            f,
+        );
+    }
+
+    pub fn git_stashes(&self, repository: &Path, f: Box<dyn Callback>) {
+        self.rpc.send_rpc_request_async(
+            "git_stashes",
+            &json!({ "repository": repository }),
+            f,
+        );
+    }
+
+    pub fn git_stash_apply(
+        &self,
+        repository: &Path,
+        index: usize,
+        f: Box<dyn Callback>,
+    ) {
+        self.rpc.send_rpc_request_async(
+            "git_stash_apply",
+            &json!({ "repository": repository, "index": index }),
+            f,
+        );
+    }
+
+    pub fn git_stash_pop(
+        &self,
+        repository: &Path,
+        index: usize,
+        f: Box<dyn Callback>,
+    ) {
+        self.rpc.send_rpc_request_async(
+            "git_stash_pop",
+            &json!({ "repository": repository, "index": index }),
+            f,
+        );
+    }
+
+    pub fn git_stash_drop(
+        &self,
+        repository: &Path,
+        index: usize,
+        f: Box<dyn Callback>,
+    ) {
+        self.rpc.send_rpc_request_async(
+            "git_stash_drop",
+            &json!({ "repository": repository, "index": index }),
+            f,
+        );
+    }
+
+    pub fn git_stage(&self, paths: Vec<PathBuf>, f: Box<dyn Callback>) {
+        self.rpc
+            .send_rpc_request_async("git_stage", &json!({ "paths": paths }), f);
+    }
+
+    pub fn git_unstage(&self, paths: Vec<PathBuf>, f: Box<dyn Callback>) {
+        self.rpc.send_rpc_request_async(
+            "git_unstage",
+            &json!({ "paths": paths }),
+            f,
+        );
+    }
+
+    pub fn git_discard(&self, paths: Vec<PathBuf>, f: Box<dyn Callback>) {
+        self.rpc.send_rpc_request_async(
+            "git_discard",
+            &json!({ "paths": paths }),
+            f,
+        );
+    }
+
+    pub fn git_stage_hunk(
+        &self,
+        path: PathBuf,
+        hunk: GitHunk,
+        f: Box<dyn Callback>,
+    ) {
+        self.rpc.send_rpc_request_async(
+            "git_stage_hunk",
+            &json!({
+                "path": path,
+                "hunk": hunk,
+            }),
+            f,
+        );
+    }
+
+    pub fn git_blame(&self, path: PathBuf, f: Box<dyn Callback>) {
+        self.rpc
+            .send_rpc_request_async("git_blame", &json!({ "path": path }), f);
+    }
+
+    pub fn git_log(
+        &self,
+        repository: &Path,
+        path: Option<PathBuf>,
+        skip: usize,
+        limit: usize,
+        f: Box<dyn Callback>,
+    ) {
+        self.rpc.send_rpc_request_async(
+            "git_log",
+            &json!({
+                "repository": repository,
+                "path": path,
+                "skip": skip,
+                "limit": limit,
+            }),
             f,
         );
     }
 
+    pub fn git_commit_files(
+        &self,
+        repository: &Path,
+        commit: String,
+        f: Box<dyn Callback>,
+    ) {
+        self.rpc.send_rpc_request_async(
+            "git_commit_files",
+            &json!({ "repository": repository, "commit": commit }),
+            f,
+        );
+    }
+
+    pub fn git_commit_file(
+        &self,
+        commit: String,
+        path: PathBuf,
+        parent_path: PathBuf,
+        f: Box<dyn Callback>,
+    ) {
+        self.rpc.send_rpc_request_async(
+            "git_commit_file",
+            &json!({
+                "commit": commit,
+                "path": path,
+                "parent_path": parent_path,
+            }),
+            f,
+        );
+    }
+
+    pub fn git_revisions(&self, path: PathBuf, f: Box<dyn Callback>) {
+        self.rpc.send_rpc_request_async(
+            "git_revisions",
+            &json!({ "path": path }),
+            f,
+        );
+    }
+
+    pub fn cancel_global_search(&self) {
+        self.rpc
+            .send_rpc_notification("cancel_global_search", &json!({}));
+    }
+
     pub fn new_buffer(
         &self,
         buffer_id: BufferId,
         path: PathBuf,
+        large_file_size: u64,
         f: Box<dyn Callback>,
     ) {
         self.rpc.send_rpc_request_async(
             "new_buffer",
-            &json!({ "buffer_id": buffer_id, "path": path }),
+            &json!({
+                "buffer_id": buffer_id,
+                "path": path,
+                "large_file_size": large_file_size,
+            }),
             f,
         );
     }
 
+    pub fn buffer_chunk(
+        &self,
+        buffer_id: BufferId,
+        offset: usize,
+    ) -> Result<Value, Value> {
+        self.rpc.send_rpc_request(
+            "buffer_chunk",
+            &json!({ "buffer_id": buffer_id, "offset": offset }),
+        )
+    }
+
     pub fn update(&self, buffer_id: BufferId, delta: &RopeDelta, rev: u64) {
         self.rpc.send_rpc_notification(
             "update",
@@ -431,17 +815,42 @@ impl LapceProxy {
         )
     }
 
-    pub fn save(&self, rev: u64, buffer_id: BufferId, f: Box<dyn Callback>) {
+    pub fn save(
+        &self,
+        rev: u64,
+        buffer_id: BufferId,
+        only_if_exists: bool,
+        f: Box<dyn Callback>,
+    ) {
         self.rpc.send_rpc_request_async(
             "save",
             &json!({
                 "rev": rev,
                 "buffer_id": buffer_id,
+                "only_if_exists": only_if_exists,
             }),
             f,
         );
     }
 
+    pub fn get_file_history(&self, path: PathBuf, f: Box<dyn Callback>) {
+        self.rpc
+            .send_rpc_request_async("file_history", &json!({ "path": path }), f);
+    }
+
+    pub fn get_file_history_version(
+        &self,
+        path: PathBuf,
+        version: String,
+        f: Box<dyn Callback>,
+    ) {
+        self.rpc.send_rpc_request_async(
+            "file_history_version",
+            &json!({ "path": path, "version": version }),
+            f,
+        );
+    }
+
     pub fn get_completion(
         &self,
         request_id: usize,
@@ -546,6 +955,16 @@ impl LapceProxy {
         );
     }
 
+    pub fn read_file(&self, path: &Path, f: Box<dyn Callback>) {
+        self.rpc.send_rpc_request_async(
+            "read_file",
+            &json!({
+                "path": path,
+            }),
+            f,
+        );
+    }
+
     pub fn get_definition(
         &self,
         request_id: usize,
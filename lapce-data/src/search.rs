@@ -1,15 +1,40 @@
-use std::{collections::HashMap, path::PathBuf, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
 use druid::WidgetId;
+use lapce_rpc::search::{SearchMatch, SearchOptions, SearchReplacement};
+use regex::{NoExpand, Regex, RegexBuilder};
 
-pub type Match = (usize, (usize, usize), String);
+pub type Match = SearchMatch;
 #[derive(Clone)]
 pub struct SearchData {
     pub active: WidgetId,
     pub widget_id: WidgetId,
     pub split_id: WidgetId,
     pub editor_view_id: WidgetId,
+    pub replace_view_id: WidgetId,
+    pub include_view_id: WidgetId,
+    pub exclude_view_id: WidgetId,
     pub matches: Arc<HashMap<PathBuf, Vec<Match>>>,
+    pub options: SearchOptions,
+    /// Identifies the running search; results of older searches are dropped.
+    pub search_id: u64,
+    /// If the search stopped early because it hit the configured match limit.
+    pub limit_reached: bool,
+    /// Why the search couldn't run, like an invalid regex or glob.
+    pub error: Option<String>,
+    /// The pattern of the current search.
+    pub pattern: String,
+    /// The replacement text; `$1` style capture groups work in regex mode.
+    pub replace: String,
+    /// Files left alone by "Replace All".
+    pub excluded_files: HashSet<PathBuf>,
+    /// Matches, by file, line number and start column, left alone by
+    /// "Replace All".
+    pub excluded_matches: HashSet<(PathBuf, usize, usize)>,
 }
 
 impl SearchData {
@@ -20,9 +45,136 @@ impl SearchData {
             widget_id: WidgetId::next(),
             split_id: WidgetId::next(),
             editor_view_id,
+            replace_view_id: WidgetId::next(),
+            include_view_id: WidgetId::next(),
+            exclude_view_id: WidgetId::next(),
             matches: Arc::new(HashMap::new()),
+            options: SearchOptions::default(),
+            search_id: 0,
+            limit_reached: false,
+            error: None,
+            pattern: "".to_string(),
+            replace: "".to_string(),
+            excluded_files: HashSet::new(),
+            excluded_matches: HashSet::new(),
         }
     }
+
+    /// Starts over for a new search, dropping the results and exclusions of
+    /// the previous one.
+    pub fn reset(&mut self, pattern: &str) {
+        self.search_id += 1;
+        self.pattern = pattern.to_string();
+        self.matches = Arc::new(HashMap::new());
+        self.limit_reached = false;
+        self.error = None;
+        self.excluded_files.clear();
+        self.excluded_matches.clear();
+    }
+
+    /// If the file, or the match `m` in it, is left alone by "Replace All".
+    pub fn is_excluded(&self, path: &Path, m: Option<&Match>) -> bool {
+        self.excluded_files.contains(path)
+            || m.map(|(line, (start, _), _)| {
+                self.excluded_matches
+                    .contains(&(path.to_path_buf(), *line, *start))
+            })
+            .unwrap_or(false)
+    }
+
+    pub fn toggle_excluded(&mut self, path: &Path, m: Option<&Match>) {
+        match m {
+            Some((line, (start, _), _)) => {
+                let key = (path.to_path_buf(), *line, *start);
+                if !self.excluded_matches.remove(&key) {
+                    self.excluded_matches.insert(key);
+                }
+            }
+            None => {
+                if !self.excluded_files.remove(path) {
+                    self.excluded_files.insert(path.to_path_buf());
+                }
+            }
+        }
+    }
+
+    /// The regex the matches are replaced with, built the same way as the
+    /// proxy builds its matcher.
+    pub fn replace_regex(&self) -> Option<Regex> {
+        let mut pattern = if self.options.is_regex {
+            self.pattern.clone()
+        } else {
+            regex::escape(&self.pattern)
+        };
+        if self.options.whole_word {
+            pattern = format!(r"\b(?:{pattern})\b");
+        }
+        RegexBuilder::new(&pattern)
+            .case_insensitive(!self.options.case_sensitive)
+            .build()
+            .ok()
+    }
+
+    /// The text `m` is replaced with. The regex runs on the whole line, so
+    /// anchors, word boundaries and lookarounds see the match in context.
+    pub fn replacement(&self, regex: &Regex, m: &Match) -> String {
+        let (_, (start, end), line) = m;
+        let caps = regex
+            .captures_iter(line)
+            .find(|caps| caps.get(0).map(|m| m.start()) == Some(*start));
+        match caps {
+            Some(caps) if self.options.is_regex => {
+                let mut new = String::new();
+                caps.expand(&self.replace, &mut new);
+                new
+            }
+            Some(_) => self.replace.clone(),
+            // The regex doesn't find the match the proxy found, fall back to
+            // replacing within the matched text.
+            None => {
+                let old = line.get(*start..*end).unwrap_or("");
+                if self.options.is_regex {
+                    regex.replace(old, self.replace.as_str()).to_string()
+                } else {
+                    regex.replace(old, NoExpand(&self.replace)).to_string()
+                }
+            }
+        }
+    }
+
+    /// All replacements "Replace All" applies, skipping excluded files and
+    /// matches.
+    pub fn replacements(&self) -> HashMap<PathBuf, Vec<SearchReplacement>> {
+        let regex = match self.replace_regex() {
+            Some(regex) => regex,
+            None => return HashMap::new(),
+        };
+        self.matches
+            .iter()
+            .filter(|(path, _)| !self.excluded_files.contains(*path))
+            .filter_map(|(path, matches)| {
+                let replacements: Vec<SearchReplacement> = matches
+                    .iter()
+                    .filter(|m| !self.is_excluded(path, Some(m)))
+                    .map(|m| {
+                        let (line, (start, end), text) = m;
+                        SearchReplacement {
+                            line: *line,
+                            start: *start,
+                            end: *end,
+                            old: text.get(*start..*end).unwrap_or("").to_string(),
+                            new: self.replacement(&regex, m),
+                        }
+                    })
+                    .collect();
+                if replacements.is_empty() {
+                    None
+                } else {
+                    Some((path.clone(), replacements))
+                }
+            })
+            .collect()
+    }
 }
 
 impl Default for SearchData {
@@ -30,3 +182,94 @@ impl Default for SearchData {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn search(pattern: &str, replace: &str, options: SearchOptions) -> SearchData {
+        let mut search = SearchData::new();
+        search.reset(pattern);
+        search.replace = replace.to_string();
+        search.options = options;
+        search
+    }
+
+    fn line_match(line: &str, start: usize, end: usize) -> Match {
+        (1, (start, end), line.to_string())
+    }
+
+    #[test]
+    fn test_replacement_expands_captures() {
+        let search = search(
+            r"(\w+)=(\w+)",
+            "$2=$1",
+            SearchOptions {
+                is_regex: true,
+                ..Default::default()
+            },
+        );
+        let regex = search.replace_regex().unwrap();
+        let m = line_match("a=b, c=d\n", 5, 8);
+        assert_eq!(search.replacement(&regex, &m), "d=c");
+    }
+
+    #[test]
+    fn test_replacement_sees_the_whole_line() {
+        // On its own the matched `a` is followed by a word boundary, so the
+        // regex wouldn't match it.
+        let search = search(
+            r"a\B",
+            "x",
+            SearchOptions {
+                is_regex: true,
+                ..Default::default()
+            },
+        );
+        let regex = search.replace_regex().unwrap();
+        let m = line_match("ab a", 0, 1);
+        assert_eq!(search.replacement(&regex, &m), "x");
+    }
+
+    #[test]
+    fn test_replacement_literal() {
+        let search = search("a.b", "$1", SearchOptions::default());
+        let regex = search.replace_regex().unwrap();
+        let m = line_match("xa.b", 1, 4);
+        assert_eq!(search.replacement(&regex, &m), "$1");
+    }
+
+    #[test]
+    fn test_excluded_matches_on_one_line() {
+        let mut search = search("foo", "bar", SearchOptions::default());
+        let path = PathBuf::from("/file");
+        let first = line_match("foo foo\n", 0, 3);
+        let second = line_match("foo foo\n", 4, 7);
+        search.matches = Arc::new(
+            vec![(path.clone(), vec![first.clone(), second.clone()])]
+                .into_iter()
+                .collect(),
+        );
+
+        search.toggle_excluded(&path, Some(&first));
+        assert!(search.is_excluded(&path, Some(&first)));
+        assert!(!search.is_excluded(&path, Some(&second)));
+        let replacements = search.replacements();
+        assert_eq!(
+            replacements.get(&path).unwrap(),
+            &vec![SearchReplacement {
+                line: 1,
+                start: 4,
+                end: 7,
+                old: "foo".to_string(),
+                new: "bar".to_string(),
+            }]
+        );
+
+        search.toggle_excluded(&path, None);
+        assert!(search.replacements().is_empty());
+        search.toggle_excluded(&path, None);
+        search.toggle_excluded(&path, Some(&first));
+        assert_eq!(search.replacements().get(&path).unwrap().len(), 2);
+    }
+}
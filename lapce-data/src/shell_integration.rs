@@ -0,0 +1,448 @@
+use std::path::PathBuf;
+
+use alacritty_terminal::{
+    event::EventListener,
+    grid::{Dimensions, Scroll},
+    index::{Column, Line, Point},
+    term::TermMode,
+    Term,
+};
+
+/// The longest OSC sequence that is kept, longer ones are ignored.
+const MAX_OSC_LEN: usize = 4096;
+
+/// A command run in the shell, marked by the OSC 133 sequences of shell
+/// integration. Lines are absolute, counted from the first line the
+/// terminal ever had, so that they stay put while the output scrolls.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ShellCommand {
+    /// Where the prompt starts.
+    pub prompt: usize,
+    /// Where the output starts, once the command runs.
+    pub output: Option<usize>,
+    /// The line and column where the output ends, once the command finished.
+    pub end: Option<(usize, usize)>,
+    pub exit_code: Option<i32>,
+}
+
+impl ShellCommand {
+    pub fn failed(&self) -> bool {
+        matches!(self.exit_code, Some(code) if code != 0)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum OscState {
+    Ground,
+    Escape,
+    Osc,
+    OscEscape,
+}
+
+/// Follows the shell integration sequences in the output of a terminal,
+/// which alacritty ignores: OSC 133 for the commands and OSC 7 for the
+/// working directory.
+pub struct ShellIntegration {
+    state: OscState,
+    osc: Vec<u8>,
+    /// The number of lines the output scrolled up so far.
+    scrolled: usize,
+    pub commands: Vec<ShellCommand>,
+    /// The working directory the shell reported last.
+    pub cwd: Option<PathBuf>,
+}
+
+impl ShellIntegration {
+    pub fn new() -> Self {
+        Self {
+            state: OscState::Ground,
+            osc: Vec::new(),
+            scrolled: 0,
+            commands: Vec::new(),
+            cwd: None,
+        }
+    }
+
+    /// The number of lines the output scrolled up so far.
+    pub fn scrolled(&self) -> usize {
+        self.scrolled
+    }
+
+    /// Feeds a byte of the output to `term` and follows it.
+    pub fn advance<T: EventListener>(
+        &mut self,
+        parser: &mut alacritty_terminal::ansi::Processor,
+        term: &mut Term<T>,
+        byte: u8,
+    ) {
+        // The scrollback grows with the lines scrolled out of the screen,
+        // until it's full and only the line feeds at the bottom tell.
+        let bottom = Line(term.screen_lines() as i32 - 1);
+        let at_bottom = term.grid().cursor.point.line == bottom;
+        let history = term.grid().history_size();
+        parser.advance(term, byte);
+        let grown = term.grid().history_size().saturating_sub(history);
+        if grown > 0 {
+            self.scrolled += grown;
+        } else if byte == b'\n'
+            && at_bottom
+            && term.grid().cursor.point.line == bottom
+            && !term.mode().contains(TermMode::ALT_SCREEN)
+        {
+            self.scrolled += 1;
+        }
+
+        self.state = match (self.state, byte) {
+            (_, 0x1b) if self.state != OscState::Osc => OscState::Escape,
+            (OscState::Escape, b']') => {
+                self.osc.clear();
+                OscState::Osc
+            }
+            (OscState::Osc, 0x07) | (OscState::OscEscape, b'\\') => {
+                let osc = std::mem::take(&mut self.osc);
+                self.osc_dispatch(term, &osc);
+                OscState::Ground
+            }
+            (OscState::Osc, 0x1b) => OscState::OscEscape,
+            (OscState::Osc, byte) => {
+                if self.osc.len() < MAX_OSC_LEN {
+                    self.osc.push(byte);
+                }
+                OscState::Osc
+            }
+            _ => OscState::Ground,
+        };
+    }
+
+    fn osc_dispatch<T: EventListener>(&mut self, term: &Term<T>, osc: &[u8]) {
+        let osc = String::from_utf8_lossy(osc);
+        let point = term.grid().cursor.point;
+        let line = self.absolute_line(point.line);
+        let (kind, params) = match osc.split_once(';') {
+            Some(osc) => osc,
+            None => return,
+        };
+        if kind == "7" {
+            if let Some(path) = file_url_path(params) {
+                self.cwd = Some(path);
+            }
+            return;
+        }
+        if kind != "133" {
+            return;
+        }
+
+        let mut params = params.split(';');
+        match params.next() {
+            Some("A") => {
+                let history = term.grid().history_size();
+                let scrolled = self.scrolled;
+                self.commands
+                    .retain(|command| command.prompt + history >= scrolled);
+                self.commands.push(ShellCommand {
+                    prompt: line,
+                    output: None,
+                    end: None,
+                    exit_code: None,
+                });
+            }
+            Some("C") => {
+                if let Some(command) = self.commands.last_mut() {
+                    command.output.get_or_insert(line);
+                }
+            }
+            Some("D") => {
+                // A command line that was left empty has no exit code.
+                if let Some(command) = self
+                    .commands
+                    .last_mut()
+                    .filter(|command| command.output.is_some())
+                {
+                    if command.end.is_none() {
+                        command.end = Some((line, point.column.0));
+                        command.exit_code =
+                            params.next().and_then(|code| code.parse().ok());
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn absolute_line(&self, line: Line) -> usize {
+        (self.scrolled as i32 + line.0).max(0) as usize
+    }
+
+    /// The grid line of an absolute line, negative in the scrollback.
+    pub fn grid_line(&self, line: usize) -> Line {
+        Line(line as i32 - self.scrolled as i32)
+    }
+
+    /// Scrolls the view so that the next or previous prompt after the top
+    /// of the view is at the top.
+    pub fn scroll_to_prompt<T: EventListener>(
+        &self,
+        term: &mut Term<T>,
+        forward: bool,
+    ) {
+        let display_offset = term.grid().display_offset() as i32;
+        let top = -display_offset;
+        let prompts = self
+            .commands
+            .iter()
+            .map(|command| self.grid_line(command.prompt).0);
+        let prompt = if forward {
+            prompts.filter(|line| *line > top).min()
+        } else {
+            prompts.filter(|line| *line < top).max()
+        };
+        if let Some(line) = prompt {
+            let offset = (-line).clamp(0, term.grid().history_size() as i32);
+            term.scroll_display(Scroll::Delta(offset - display_offset));
+        }
+    }
+
+    /// The output of the last command that finished, as long as it's still
+    /// in the scrollback.
+    pub fn last_output<T: EventListener>(&self, term: &Term<T>) -> Option<String> {
+        let command = self.commands.iter().rev().find(|c| c.end.is_some())?;
+        let start = self.grid_line(command.output?);
+        let (end_line, end_column) = command.end?;
+        let end_line = self.grid_line(end_line);
+        let end = if end_column > 0 {
+            Point::new(end_line, Column(end_column - 1))
+        } else {
+            Point::new(Line(end_line.0 - 1), term.last_column())
+        };
+        let start = Point::new(start.max(term.topmost_line()), Column(0));
+        if end < start {
+            return None;
+        }
+        Some(term.bounds_to_string(start, end))
+    }
+}
+
+impl Default for ShellIntegration {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The path of a `file://host/path` URL, as sent by OSC 7.
+fn file_url_path(url: &str) -> Option<PathBuf> {
+    let url = url.strip_prefix("file://")?;
+    let path = &url[url.find('/')?..];
+
+    let mut bytes = Vec::with_capacity(path.len());
+    let mut rest = path.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        rest = tail;
+        if byte == b'%' && rest.len() >= 2 {
+            if let Some(decoded) = std::str::from_utf8(&rest[..2])
+                .ok()
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            {
+                bytes.push(decoded);
+                rest = &rest[2..];
+                continue;
+            }
+        }
+        bytes.push(byte);
+    }
+    let path = String::from_utf8(bytes).ok()?;
+
+    // `/C:/Users` on Windows.
+    let path = match path.as_bytes() {
+        [b'/', drive, b':', ..] if drive.is_ascii_alphabetic() => &path[1..],
+        _ => &path,
+    };
+    Some(PathBuf::from(path))
+}
+
+#[cfg(test)]
+mod tests {
+    use alacritty_terminal::{ansi, config::Config, term::SizeInfo};
+
+    use super::*;
+
+    struct Listener;
+
+    impl EventListener for Listener {}
+
+    /// Feeds `output` to a terminal of 20 columns and 5 lines with a
+    /// scrollback of `history` lines.
+    fn run(output: &str, history: u32) -> (ShellIntegration, Term<Listener>) {
+        let mut config = Config::default();
+        config.scrolling.set_history(history);
+        let size = SizeInfo::new(20.0, 5.0, 1.0, 1.0, 0.0, 0.0, false);
+        let mut term = Term::new(&config, size, Listener);
+        let mut parser = ansi::Processor::new();
+        let mut shell = ShellIntegration::new();
+        for byte in output.bytes() {
+            shell.advance(&mut parser, &mut term, byte);
+        }
+        (shell, term)
+    }
+
+    #[test]
+    fn test_commands() {
+        let (shell, _) = run(
+            "\x1b]133;A\x07$ \x1b]133;B\x07ls\r\n\x1b]133;C\x07a\r\nb\r\n\
+             \x1b]133;D;0\x07\x1b]133;A\x1b\\$ false\r\n\x1b]133;C\x1b\\\
+             \x1b]133;D;1\x1b\\\x1b]133;A\x07$ ",
+            100,
+        );
+        assert_eq!(
+            shell.commands,
+            vec![
+                ShellCommand {
+                    prompt: 0,
+                    output: Some(1),
+                    end: Some((3, 0)),
+                    exit_code: Some(0),
+                },
+                ShellCommand {
+                    prompt: 3,
+                    output: Some(4),
+                    end: Some((4, 0)),
+                    exit_code: Some(1),
+                },
+                ShellCommand {
+                    prompt: 4,
+                    output: None,
+                    end: None,
+                    exit_code: None,
+                },
+            ]
+        );
+        assert!(!shell.commands[0].failed());
+        assert!(shell.commands[1].failed());
+        assert!(!shell.commands[2].failed());
+    }
+
+    #[test]
+    fn test_empty_command_line() {
+        // The prompt is left without running anything.
+        let (shell, _) = run("\x1b]133;A\x07$ \r\n\x1b]133;D\x07", 100);
+        assert_eq!(shell.commands.len(), 1);
+        assert_eq!(shell.commands[0].end, None);
+        assert_eq!(shell.commands[0].exit_code, None);
+    }
+
+    #[test]
+    fn test_other_osc() {
+        let (shell, _) = run(
+            "\x1b]0;title\x07\x1b]7;file://host/tmp\x1b\\\x1b]133;X\x07",
+            100,
+        );
+        assert!(shell.commands.is_empty());
+        assert_eq!(shell.cwd, Some(PathBuf::from("/tmp")));
+    }
+
+    #[test]
+    fn test_scrolled() {
+        let lines: Vec<String> = (0..10).map(|i| i.to_string()).collect();
+        let output = lines.join("\r\n");
+        // The scrollback grows with the first lines scrolled out.
+        let (shell, term) = run(&output, 100);
+        assert_eq!(term.grid().history_size(), 5);
+        assert_eq!(shell.scrolled(), 5);
+        assert_eq!(shell.grid_line(0), Line(-5));
+        assert_eq!(shell.grid_line(9), Line(4));
+        // Once it's full, the line feeds at the bottom are counted.
+        let (shell, term) = run(&output, 2);
+        assert_eq!(term.grid().history_size(), 2);
+        assert_eq!(shell.scrolled(), 5);
+        assert_eq!(shell.grid_line(9), Line(4));
+    }
+
+    #[test]
+    fn test_scrolled_alt_screen() {
+        let (shell, _) = run("\x1b[?1049h\x1b[5;1H\n\n\n", 2);
+        assert_eq!(shell.scrolled(), 0);
+    }
+
+    #[test]
+    fn test_commands_leave_scrollback() {
+        let prompt = "\x1b]133;A\x07$ ";
+        // The first prompt is still in the 2 lines of scrollback.
+        let (shell, _) = run(&format!("{prompt}{}{prompt}", "\r\n".repeat(6)), 2);
+        assert_eq!(shell.commands.len(), 2);
+        // It scrolled out of them.
+        let (shell, _) = run(&format!("{prompt}{}{prompt}", "\r\n".repeat(7)), 2);
+        assert_eq!(shell.commands.len(), 1);
+        assert_eq!(shell.commands[0].prompt, 7);
+    }
+
+    #[test]
+    fn test_scroll_to_prompt() {
+        let output = "\x1b]133;A\x07$ cmd\r\no\r\no\r\no\r\no\r\n".repeat(3);
+        let (shell, mut term) = run(&output, 100);
+        assert_eq!(term.grid().history_size(), 11);
+        let prompts: Vec<Line> = shell
+            .commands
+            .iter()
+            .map(|command| shell.grid_line(command.prompt))
+            .collect();
+        assert_eq!(prompts, vec![Line(-11), Line(-6), Line(-1)]);
+
+        let mut scroll = |forward| {
+            shell.scroll_to_prompt(&mut term, forward);
+            term.grid().display_offset()
+        };
+        assert_eq!(scroll(false), 1);
+        assert_eq!(scroll(false), 6);
+        assert_eq!(scroll(false), 11);
+        assert_eq!(scroll(false), 11);
+        assert_eq!(scroll(true), 6);
+        assert_eq!(scroll(true), 1);
+        // The last prompt is at the top already.
+        assert_eq!(scroll(true), 1);
+    }
+
+    #[test]
+    fn test_last_output() {
+        let (shell, term) = run("\x1b]133;A\x07$ ", 100);
+        assert_eq!(shell.last_output(&term), None);
+
+        let (shell, term) = run(
+            "\x1b]133;A\x07$ ls\r\n\x1b]133;C\x07a\r\nb\r\n\x1b]133;D;0\x07\
+             \x1b]133;A\x07$ ",
+            100,
+        );
+        assert_eq!(
+            shell.last_output(&term).as_deref().map(str::trim_end),
+            Some("a\nb")
+        );
+
+        // The output doesn't end with a line feed, and the next command runs.
+        let (shell, term) = run(
+            "\x1b]133;A\x07$ ls\r\n\x1b]133;C\x07a\r\nb\x1b]133;D;1\x07\r\n\
+             \x1b]133;A\x07$ ls\r\n\x1b]133;C\x07c",
+            100,
+        );
+        assert_eq!(shell.last_output(&term).as_deref(), Some("a\nb"));
+    }
+
+    #[test]
+    fn test_file_url_path() {
+        assert_eq!(
+            file_url_path("file://host/home/me/my%20project"),
+            Some(PathBuf::from("/home/me/my project"))
+        );
+        assert_eq!(
+            file_url_path("file:///tmp/caf%C3%A9"),
+            Some(PathBuf::from("/tmp/café"))
+        );
+        assert_eq!(
+            file_url_path("file://host/tmp/100%/%zz"),
+            Some(PathBuf::from("/tmp/100%/%zz"))
+        );
+        assert_eq!(
+            file_url_path("file://host/C:/Users/me"),
+            Some(PathBuf::from("C:/Users/me"))
+        );
+        assert_eq!(file_url_path("file://host"), None);
+        assert_eq!(file_url_path("https://host/tmp"), None);
+    }
+}
@@ -1,8 +1,20 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use chrono::Utc;
 use druid::{Command, Env, EventCtx, Modifiers, Target, WidgetId};
-use lapce_rpc::source_control::FileDiff;
+use lapce_rpc::source_control::{BlameHunk, DiffInfo, FileDiff, GitCommit};
+use serde_json::json;
 
 use crate::{
-    command::{CommandExecuted, LapceCommand, LapceUICommand, LAPCE_UI_COMMAND},
+    command::{
+        CommandExecuted, CommandTarget, LapceCommand, LapceCommandNew,
+        LapceUICommand, LapceWorkbenchCommand, LAPCE_NEW_COMMAND, LAPCE_UI_COMMAND,
+    },
+    config::LapceTheme,
     keypress::KeyPressFocus,
     movement::Movement,
     split::{SplitDirection, SplitMoveDirection},
@@ -12,18 +24,166 @@ use crate::{
 pub const SOURCE_CONTROL_BUFFER: &str = "[Source Control Buffer]";
 pub const SEARCH_BUFFER: &str = "[Search Buffer]";
 
+/// The git status a file is decorated with, ordered by how much it matters
+/// when a folder sums up the files it contains.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum GitFileStatus {
+    Ignored,
+    Untracked,
+    Renamed,
+    Deleted,
+    Added,
+    Modified,
+    Conflicted,
+}
+
+impl GitFileStatus {
+    /// The letter shown next to the file name.
+    pub fn badge(&self) -> &'static str {
+        match self {
+            GitFileStatus::Ignored => "I",
+            GitFileStatus::Untracked => "U",
+            GitFileStatus::Renamed => "R",
+            GitFileStatus::Deleted => "D",
+            GitFileStatus::Added => "A",
+            GitFileStatus::Modified => "M",
+            GitFileStatus::Conflicted => "!",
+        }
+    }
+
+    pub fn theme_key(&self) -> &'static str {
+        match self {
+            GitFileStatus::Ignored => LapceTheme::SOURCE_CONTROL_DECORATION_IGNORED,
+            GitFileStatus::Untracked => {
+                LapceTheme::SOURCE_CONTROL_DECORATION_UNTRACKED
+            }
+            GitFileStatus::Renamed => LapceTheme::SOURCE_CONTROL_DECORATION_RENAMED,
+            GitFileStatus::Deleted => LapceTheme::SOURCE_CONTROL_DECORATION_DELETED,
+            GitFileStatus::Added => LapceTheme::SOURCE_CONTROL_DECORATION_ADDED,
+            GitFileStatus::Modified => {
+                LapceTheme::SOURCE_CONTROL_DECORATION_MODIFIED
+            }
+            GitFileStatus::Conflicted => {
+                LapceTheme::SOURCE_CONTROL_DECORATION_CONFLICTED
+            }
+        }
+    }
+}
+
+/// The git status of the files and folders of the workspace, as the file
+/// explorer decorates them.
+#[derive(Clone, Default, Debug, PartialEq)]
+pub struct GitDecorations {
+    files: HashMap<PathBuf, GitFileStatus>,
+    /// The status that matters most among the changed files of each folder.
+    folders: HashMap<PathBuf, GitFileStatus>,
+    /// Ignored files and folders, everything inside them is ignored too.
+    ignored: HashSet<PathBuf>,
+}
+
+impl GitDecorations {
+    pub fn new(workspace: &Path, repositories: &[DiffInfo]) -> Self {
+        let mut files = HashMap::new();
+        let mut ignored = HashSet::new();
+        for diff in repositories {
+            // Staged changes come first so that changing the file again in
+            // the worktree shows it as modified.
+            for file_diff in diff.staged.iter().chain(diff.diffs.iter()) {
+                let (path, status) = match file_diff {
+                    FileDiff::Modified(path) => (path, GitFileStatus::Modified),
+                    FileDiff::Added(path) => (path, GitFileStatus::Added),
+                    FileDiff::Deleted(path) => (path, GitFileStatus::Deleted),
+                    FileDiff::Renamed(path, _) => (path, GitFileStatus::Renamed),
+                };
+                files.insert(path.clone(), status);
+            }
+            for path in diff.untracked.iter() {
+                files.insert(path.clone(), GitFileStatus::Untracked);
+            }
+            for conflict in diff.conflicts.iter() {
+                files.insert(conflict.path().clone(), GitFileStatus::Conflicted);
+            }
+            ignored.extend(diff.ignored.iter().cloned());
+        }
+
+        let mut folders: HashMap<PathBuf, GitFileStatus> = HashMap::new();
+        for (path, status) in files.iter() {
+            for folder in path.ancestors().skip(1) {
+                if !folder.starts_with(workspace) {
+                    break;
+                }
+                let folder_status =
+                    folders.entry(folder.to_path_buf()).or_insert(*status);
+                if *folder_status < *status {
+                    *folder_status = *status;
+                }
+            }
+        }
+
+        Self {
+            files,
+            folders,
+            ignored,
+        }
+    }
+
+    pub fn file_status(&self, path: &Path) -> Option<GitFileStatus> {
+        self.files
+            .get(path)
+            .copied()
+            .or_else(|| self.ignored_status(path))
+    }
+
+    /// The status of a folder, summing up the changes of the files in it.
+    pub fn folder_status(&self, path: &Path) -> Option<GitFileStatus> {
+        self.folders
+            .get(path)
+            .copied()
+            .or_else(|| self.ignored_status(path))
+    }
+
+    fn ignored_status(&self, path: &Path) -> Option<GitFileStatus> {
+        path.ancestors()
+            .any(|ancestor| self.ignored.contains(ancestor))
+            .then(|| GitFileStatus::Ignored)
+    }
+}
+
 #[derive(Clone)]
 pub struct SourceControlData {
     pub active: WidgetId,
     pub widget_id: WidgetId,
     pub split_id: WidgetId,
     pub split_direction: SplitDirection,
+    pub repository_list_id: WidgetId,
     pub file_list_id: WidgetId,
     pub file_list_index: usize,
+    pub merge_list_id: WidgetId,
+    pub merge_list_index: usize,
+    pub staged_list_id: WidgetId,
+    pub staged_list_index: usize,
     pub editor_view_id: WidgetId,
-    pub file_diffs: Vec<(FileDiff, bool)>,
+    /// The diff info of every repository in the workspace.
+    pub repositories: Vec<DiffInfo>,
+    /// The worktree of the repository the panel shows, which commits,
+    /// branches and stashes are for. The fields below are its diff info.
+    pub repository: PathBuf,
+    /// Changes in the worktree that aren't staged.
+    pub file_diffs: Vec<FileDiff>,
+    /// Changes staged for the next commit.
+    pub staged_diffs: Vec<FileDiff>,
+    /// Files with unresolved merge conflicts.
+    pub conflicts: Vec<FileDiff>,
     pub branch: String,
     pub branches: Vec<String>,
+    /// How many stashes the repository has.
+    pub stashes: usize,
+    /// Commits the branch has that its upstream doesn't.
+    pub ahead: usize,
+    /// Commits the upstream has that the branch doesn't.
+    pub behind: usize,
+    /// How the file explorer decorates files by their git status.
+    pub decorations: Arc<GitDecorations>,
 }
 
 impl SourceControlData {
@@ -34,13 +194,103 @@ impl SourceControlData {
             active: editor_view_id,
             widget_id: WidgetId::next(),
             editor_view_id,
+            repository_list_id: WidgetId::next(),
             file_list_id,
             file_list_index: 0,
+            merge_list_id: WidgetId::next(),
+            merge_list_index: 0,
+            staged_list_id: WidgetId::next(),
+            staged_list_index: 0,
             split_id: WidgetId::next(),
             split_direction: SplitDirection::Horizontal,
+            repositories: Vec::new(),
+            repository: PathBuf::new(),
             file_diffs: Vec::new(),
+            staged_diffs: Vec::new(),
+            conflicts: Vec::new(),
             branch: "".to_string(),
             branches: Vec::new(),
+            stashes: 0,
+            ahead: 0,
+            behind: 0,
+            decorations: Arc::new(GitDecorations::default()),
+        }
+    }
+
+    /// The innermost repository `path` is in.
+    pub fn repository_of(&self, path: &Path) -> Option<&DiffInfo> {
+        self.repositories
+            .iter()
+            .filter(|diff| path.starts_with(&diff.path))
+            .max_by_key(|diff| diff.path.components().count())
+    }
+
+    /// Shows the repository `path` is in, leaving the one shown when the
+    /// path isn't in any.
+    pub fn select_repository(&mut self, path: &Path) {
+        if let Some(diff) = self.repository_of(path) {
+            if diff.path != self.repository {
+                self.repository = diff.path.clone();
+                self.load_repository();
+            }
+        }
+    }
+
+    /// Takes new diff info for the repositories, still showing the same one
+    /// when it's there.
+    pub fn update_repositories(
+        &mut self,
+        workspace: Option<&Path>,
+        repositories: Vec<DiffInfo>,
+    ) {
+        if !repositories.iter().any(|diff| diff.path == self.repository) {
+            self.repository = repositories
+                .first()
+                .map(|diff| diff.path.clone())
+                .unwrap_or_default();
+        }
+        if let Some(workspace) = workspace {
+            self.decorations =
+                Arc::new(GitDecorations::new(workspace, &repositories));
+        }
+        self.repositories = repositories;
+        self.load_repository();
+    }
+
+    fn load_repository(&mut self) {
+        let diff = self
+            .repositories
+            .iter()
+            .find(|diff| diff.path == self.repository)
+            .cloned()
+            .unwrap_or_default();
+        self.branch = diff.head;
+        self.branches = diff.branches;
+        self.stashes = diff.stashes;
+        self.ahead = diff.ahead;
+        self.behind = diff.behind;
+        self.file_diffs = diff.diffs;
+        self.staged_diffs = diff.staged;
+        self.conflicts = diff.conflicts;
+        self.file_list_index = self
+            .file_list_index
+            .min(self.file_diffs.len().saturating_sub(1));
+        self.staged_list_index = self
+            .staged_list_index
+            .min(self.staged_diffs.len().saturating_sub(1));
+        self.merge_list_index = self
+            .merge_list_index
+            .min(self.conflicts.len().saturating_sub(1));
+    }
+
+    /// The diffs of the focused list and the index of its current item.
+    fn active_list(&mut self) -> (&[FileDiff], &mut usize) {
+        if self.active == self.staged_list_id {
+            (&self.staged_diffs, &mut self.staged_list_index)
+        } else if self.active == self.merge_list_id {
+            (&self.conflicts, &mut self.merge_list_index)
+        } else {
+            (&self.file_diffs, &mut self.file_list_index)
         }
     }
 }
@@ -59,7 +309,11 @@ impl KeyPressFocus for SourceControlData {
     fn check_condition(&self, condition: &str) -> bool {
         match condition {
             "source_control_focus" => true,
-            "list_focus" => self.active == self.file_list_id,
+            "list_focus" => {
+                self.active == self.file_list_id
+                    || self.active == self.staged_list_id
+                    || self.active == self.merge_list_id
+            }
             _ => false,
         }
     }
@@ -91,35 +345,53 @@ impl KeyPressFocus for SourceControlData {
                 ));
             }
             LapceCommand::Up | LapceCommand::ListPrevious => {
-                self.file_list_index = Movement::Up.update_index(
-                    self.file_list_index,
-                    self.file_diffs.len(),
-                    1,
-                    true,
-                );
+                let (diffs, index) = self.active_list();
+                *index = Movement::Up.update_index(*index, diffs.len(), 1, true);
             }
             LapceCommand::Down | LapceCommand::ListNext => {
-                self.file_list_index = Movement::Down.update_index(
-                    self.file_list_index,
-                    self.file_diffs.len(),
-                    1,
-                    true,
-                );
+                let (diffs, index) = self.active_list();
+                *index = Movement::Down.update_index(*index, diffs.len(), 1, true);
             }
             LapceCommand::ListExpand => {
-                if !self.file_diffs.is_empty() {
-                    self.file_diffs[self.file_list_index].1 =
-                        !self.file_diffs[self.file_list_index].1;
+                let staged = self.active == self.staged_list_id;
+                let merge = self.active == self.merge_list_id;
+                let (diffs, index) = self.active_list();
+                if let Some(diff) = diffs.get(*index) {
+                    let command = if staged {
+                        LapceWorkbenchCommand::SourceControlUnstage
+                    } else if merge {
+                        LapceWorkbenchCommand::SourceControlMarkResolved
+                    } else {
+                        LapceWorkbenchCommand::SourceControlStage
+                    };
+                    ctx.submit_command(Command::new(
+                        LAPCE_NEW_COMMAND,
+                        LapceCommandNew {
+                            cmd: command.to_string(),
+                            data: Some(json!(diff.path())),
+                            palette_desc: None,
+                            target: CommandTarget::Workbench,
+                        },
+                        Target::Auto,
+                    ));
                 }
             }
             LapceCommand::ListSelect => {
-                if !self.file_diffs.is_empty() {
-                    ctx.submit_command(Command::new(
-                        LAPCE_UI_COMMAND,
+                let merge = self.active == self.merge_list_id;
+                let (diffs, index) = self.active_list();
+                if let Some(diff) = diffs.get(*index) {
+                    // Conflicts are resolved in the file itself.
+                    let command = if merge {
+                        LapceUICommand::OpenFile(diff.path().clone())
+                    } else {
                         LapceUICommand::OpenFileDiff(
-                            self.file_diffs[self.file_list_index].0.path().clone(),
+                            diff.path().clone(),
                             "head".to_string(),
-                        ),
+                        )
+                    };
+                    ctx.submit_command(Command::new(
+                        LAPCE_UI_COMMAND,
+                        command,
                         Target::Auto,
                     ));
                 }
@@ -131,3 +403,122 @@ impl KeyPressFocus for SourceControlData {
 
     fn receive_char(&mut self, _ctx: &mut EventCtx, _c: &str) {}
 }
+
+/// The text shown after the end of a line for who last changed it.
+pub fn blame_annotation(hunk: &BlameHunk) -> String {
+    if hunk.uncommitted {
+        return "You, Uncommitted changes".to_string();
+    }
+    format!(
+        "{}, {} • {}",
+        hunk.author,
+        relative_time(hunk.time),
+        hunk.summary
+    )
+}
+
+/// The text shown next to a commit's summary in the git log palette.
+pub fn commit_hint(commit: &GitCommit) -> String {
+    format!(
+        "  {}, {} • {}",
+        commit.author,
+        relative_time(commit.time),
+        &commit.id[..7.min(commit.id.len())]
+    )
+}
+
+/// How long ago the unix `time` in seconds was, such as "3 days ago".
+pub fn relative_time(time: i64) -> String {
+    let seconds = (Utc::now().timestamp() - time).max(0);
+    let (n, unit) = match seconds {
+        s if s < 60 => return "just now".to_string(),
+        s if s < 60 * 60 => (s / 60, "minute"),
+        s if s < 60 * 60 * 24 => (s / (60 * 60), "hour"),
+        s if s < 60 * 60 * 24 * 30 => (s / (60 * 60 * 24), "day"),
+        s if s < 60 * 60 * 24 * 365 => (s / (60 * 60 * 24 * 30), "month"),
+        s => (s / (60 * 60 * 24 * 365), "year"),
+    };
+    format!("{n} {unit}{} ago", if n == 1 { "" } else { "s" })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_status() {
+        let ws = Path::new("/ws");
+        let diff = DiffInfo {
+            path: ws.to_path_buf(),
+            staged: vec![
+                FileDiff::Added(ws.join("a.rs")),
+                FileDiff::Modified(ws.join("b.rs")),
+            ],
+            diffs: vec![
+                FileDiff::Modified(ws.join("a.rs")),
+                FileDiff::Deleted(ws.join("c.rs")),
+                FileDiff::Renamed(ws.join("new.rs"), ws.join("old.rs")),
+                FileDiff::Added(ws.join("u.rs")),
+            ],
+            untracked: vec![ws.join("u.rs")],
+            conflicts: vec![FileDiff::Modified(ws.join("x.rs"))],
+            ignored: vec![ws.join("target")],
+            ..Default::default()
+        };
+        let decorations = GitDecorations::new(ws, &[diff]);
+        let status = |name: &str| decorations.file_status(&ws.join(name));
+
+        // Changing a staged file again shows it as modified.
+        assert_eq!(status("a.rs"), Some(GitFileStatus::Modified));
+        assert_eq!(status("b.rs"), Some(GitFileStatus::Modified));
+        assert_eq!(status("c.rs"), Some(GitFileStatus::Deleted));
+        assert_eq!(status("new.rs"), Some(GitFileStatus::Renamed));
+        assert_eq!(status("old.rs"), None);
+        assert_eq!(status("u.rs"), Some(GitFileStatus::Untracked));
+        assert_eq!(status("x.rs"), Some(GitFileStatus::Conflicted));
+        assert_eq!(status("other.rs"), None);
+
+        // Everything in an ignored folder is ignored.
+        assert_eq!(status("target/debug/app"), Some(GitFileStatus::Ignored));
+        assert_eq!(
+            decorations.folder_status(&ws.join("target/debug")),
+            Some(GitFileStatus::Ignored)
+        );
+        assert_eq!(GitFileStatus::Conflicted.badge(), "!");
+    }
+
+    #[test]
+    fn test_folder_status() {
+        let ws = Path::new("/ws");
+        let diff = DiffInfo {
+            path: ws.to_path_buf(),
+            diffs: vec![
+                FileDiff::Added(ws.join("src/a/x.rs")),
+                FileDiff::Added(ws.join("src/a/y.rs")),
+                FileDiff::Modified(ws.join("src/b.rs")),
+            ],
+            untracked: vec![ws.join("src/a/x.rs")],
+            ..Default::default()
+        };
+        // A repository nested in the workspace.
+        let nested = DiffInfo {
+            path: ws.join("sub"),
+            conflicts: vec![FileDiff::Modified(ws.join("sub/z.rs"))],
+            ..Default::default()
+        };
+        let decorations = GitDecorations::new(ws, &[diff, nested]);
+        let status = |name: &str| decorations.folder_status(&ws.join(name));
+
+        // A folder shows the status of its files that matters most.
+        assert_eq!(status("src/a"), Some(GitFileStatus::Added));
+        assert_eq!(status("src"), Some(GitFileStatus::Modified));
+        assert_eq!(status("sub"), Some(GitFileStatus::Conflicted));
+        assert_eq!(
+            decorations.folder_status(ws),
+            Some(GitFileStatus::Conflicted)
+        );
+        assert_eq!(status("docs"), None);
+        // Nothing outside of the workspace is decorated.
+        assert_eq!(decorations.folder_status(Path::new("/")), None);
+    }
+}
@@ -0,0 +1,399 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::{anyhow, Result};
+use lsp_types::{Diagnostic, DiagnosticSeverity, NumberOrString, Position, Range};
+use regex::Regex;
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::{config::TerminalProfileConfig, proxy::LapceProxy};
+
+/// Where the tasks of a workspace are defined.
+pub const TASKS_FILE: &str = ".lapce/tasks.toml";
+
+/// The diagnostics a task found are those with this source, followed by the
+/// name of the task.
+pub const TASK_SOURCE_PREFIX: &str = "task: ";
+
+/// The content of `.lapce/tasks.toml`.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct TasksConfig {
+    pub tasks: Vec<TaskConfig>,
+    pub problem_matchers: Vec<ProblemMatcherConfig>,
+}
+
+/// A `[[tasks]]` entry.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct TaskConfig {
+    pub name: String,
+    pub command: String,
+    pub args: Vec<String>,
+    /// Relative to the workspace.
+    pub cwd: Option<PathBuf>,
+    /// Environment variables as `NAME=value`, like in terminal profiles.
+    pub env: Vec<String>,
+    /// Tasks that run before this one, which only runs when they succeeded.
+    pub depends_on: Vec<String>,
+    /// The name of the problem matcher for the output, `rustc` or one of
+    /// the `[[problem-matchers]]`.
+    pub problem_matcher: Option<String>,
+}
+
+/// A `[[problem-matchers]]` entry, whose patterns match consecutive lines
+/// of the output. The named groups of all of them make a problem: `file`,
+/// `line`, `column`, `severity`, `code` and `message`, where only `file` is
+/// required.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct ProblemMatcherConfig {
+    pub name: String,
+    pub patterns: Vec<String>,
+}
+
+impl TasksConfig {
+    pub fn parse(content: &str) -> Result<Self> {
+        Ok(toml::from_str(content)?)
+    }
+
+    pub fn task(&self, name: &str) -> Option<&TaskConfig> {
+        self.tasks.iter().find(|task| task.name == name)
+    }
+
+    /// The tasks to run for the one called `name`, its dependencies first.
+    pub fn plan(&self, name: &str) -> Result<Vec<TaskConfig>> {
+        let mut plan = Vec::new();
+        self.add_to_plan(name, &mut Vec::new(), &mut plan)?;
+        Ok(plan)
+    }
+
+    fn add_to_plan(
+        &self,
+        name: &str,
+        path: &mut Vec<String>,
+        plan: &mut Vec<TaskConfig>,
+    ) -> Result<()> {
+        if plan.iter().any(|task| task.name == name) {
+            return Ok(());
+        }
+        if path.iter().any(|n| n == name) {
+            return Err(anyhow!(
+                "task {name} depends on itself through {}",
+                path.join(", ")
+            ));
+        }
+        let task = self
+            .task(name)
+            .ok_or_else(|| anyhow!("there's no task {name}"))?;
+
+        path.push(name.to_string());
+        for dependency in &task.depends_on {
+            self.add_to_plan(dependency, path, plan)?;
+        }
+        path.pop();
+        plan.push(task.clone());
+        Ok(())
+    }
+
+    /// The problem matcher called `name`, where the matchers of the file
+    /// take precedence over the built-in ones.
+    pub fn problem_matcher(&self, name: &str) -> Result<ProblemMatcher> {
+        if let Some(matcher) = self.problem_matchers.iter().find(|m| m.name == name)
+        {
+            let patterns = matcher
+                .patterns
+                .iter()
+                .map(|pattern| Regex::new(pattern))
+                .collect::<Result<Vec<_>, _>>()?;
+            return Ok(ProblemMatcher {
+                name: name.to_string(),
+                patterns,
+            });
+        }
+        match name {
+            "rustc" => Ok(ProblemMatcher::rustc()),
+            _ => Err(anyhow!("there's no problem matcher {name}")),
+        }
+    }
+}
+
+impl TaskConfig {
+    /// The terminal profile that runs the task.
+    pub fn profile(&self) -> TerminalProfileConfig {
+        TerminalProfileConfig {
+            name: self.name.clone(),
+            program: self.command.clone(),
+            args: self.args.clone(),
+            env: self.env.clone(),
+            cwd: self.cwd.clone(),
+            title: Some(format!("Task: {}", self.name)),
+        }
+    }
+
+    pub fn diagnostic_source(&self) -> String {
+        format!("{TASK_SOURCE_PREFIX}{}", self.name)
+    }
+
+    /// The directory the task runs in, where the paths in its output are
+    /// resolved.
+    pub fn working_directory(&self, workspace: Option<&Path>) -> PathBuf {
+        let workspace = workspace.map(Path::to_path_buf).unwrap_or_default();
+        match self.cwd.as_ref() {
+            Some(cwd) => workspace.join(cwd),
+            None => workspace,
+        }
+    }
+}
+
+/// Reads the tasks file of the workspace through the proxy.
+pub fn read_tasks(
+    proxy: &LapceProxy,
+    workspace: &Path,
+    f: impl FnOnce(Result<TasksConfig>) + Send + 'static,
+) {
+    proxy.read_file(
+        &workspace.join(TASKS_FILE),
+        Box::new(move |result: Result<Value, Value>| {
+            let tasks = result
+                .map_err(|e| {
+                    anyhow!(
+                        "Can't read {TASKS_FILE}: {}",
+                        e["message"].as_str().unwrap_or_default()
+                    )
+                })
+                .and_then(|content| Ok(serde_json::from_value::<String>(content)?))
+                .and_then(|content| {
+                    TasksConfig::parse(&content)
+                        .map_err(|e| anyhow!("Invalid {TASKS_FILE}: {e}"))
+                });
+            f(tasks);
+        }),
+    );
+}
+
+/// A task running in a terminal.
+#[derive(Clone, Debug)]
+pub struct TaskRun {
+    pub task: TaskConfig,
+    /// The tasks that run next, once this one succeeded.
+    pub pending: Vec<TaskConfig>,
+    pub tasks: Arc<TasksConfig>,
+    pub finished: bool,
+    /// The exit code of the finished task, when it could be told.
+    pub exit_code: Option<i32>,
+}
+
+pub struct ProblemMatcher {
+    pub name: String,
+    patterns: Vec<Regex>,
+}
+
+impl ProblemMatcher {
+    /// The errors and warnings of rustc, which cargo passes on.
+    pub fn rustc() -> Self {
+        let patterns = [
+            r"^(?P<severity>error|warning)(?:\[(?P<code>[^\]]+)\])?: (?P<message>.+)$",
+            r"^\s*--> (?P<file>.+?):(?P<line>\d+):(?P<column>\d+)$",
+        ];
+        Self {
+            name: "rustc".to_string(),
+            patterns: patterns.iter().map(|p| Regex::new(p).unwrap()).collect(),
+        }
+    }
+
+    /// The problems in the output of a task, whose relative paths are
+    /// resolved in `cwd`.
+    pub fn problems(
+        &self,
+        output: &str,
+        cwd: &Path,
+        source: &str,
+    ) -> HashMap<PathBuf, Vec<Diagnostic>> {
+        let mut problems: HashMap<PathBuf, Vec<Diagnostic>> = HashMap::new();
+        if self.patterns.is_empty() {
+            return problems;
+        }
+
+        let lines: Vec<&str> = output.lines().collect();
+        let mut i = 0;
+        while i + self.patterns.len() <= lines.len() {
+            let captures: Option<Vec<_>> = self
+                .patterns
+                .iter()
+                .zip(&lines[i..])
+                .map(|(pattern, line)| pattern.captures(line))
+                .collect();
+            let captures = match captures {
+                Some(captures) => captures,
+                None => {
+                    i += 1;
+                    continue;
+                }
+            };
+            i += self.patterns.len();
+
+            let group = |name: &str| {
+                captures
+                    .iter()
+                    .find_map(|c| c.name(name))
+                    .map(|m| m.as_str().trim())
+            };
+            let file = match group("file") {
+                Some(file) => cwd.join(file),
+                None => continue,
+            };
+            let number = |name: &str| {
+                group(name)
+                    .and_then(|n| n.parse::<u32>().ok())
+                    .unwrap_or(1)
+                    .saturating_sub(1)
+            };
+            let start = Position {
+                line: number("line"),
+                character: number("column"),
+            };
+            let severity = match group("severity").map(|s| s.to_lowercase()) {
+                Some(s) if s.contains("warn") => DiagnosticSeverity::Warning,
+                Some(s) if s == "note" || s.starts_with("info") => {
+                    DiagnosticSeverity::Information
+                }
+                Some(s) if s == "help" || s == "hint" => DiagnosticSeverity::Hint,
+                _ => DiagnosticSeverity::Error,
+            };
+            problems.entry(file).or_default().push(Diagnostic {
+                range: Range {
+                    start,
+                    end: Position {
+                        line: start.line,
+                        character: start.character + 1,
+                    },
+                },
+                severity: Some(severity),
+                code: group("code").map(|c| NumberOrString::String(c.to_string())),
+                source: Some(source.to_string()),
+                message: group("message").unwrap_or_default().to_string(),
+                ..Default::default()
+            });
+        }
+        problems
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn task(name: &str, depends_on: &[&str]) -> TaskConfig {
+        TaskConfig {
+            name: name.to_string(),
+            depends_on: depends_on.iter().map(|d| d.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    fn plan(tasks: &TasksConfig, name: &str) -> Result<Vec<String>> {
+        Ok(tasks
+            .plan(name)?
+            .into_iter()
+            .map(|task| task.name)
+            .collect())
+    }
+
+    #[test]
+    fn test_plan() {
+        let tasks = TasksConfig {
+            tasks: vec![
+                task("test", &["build", "fmt"]),
+                task("build", &["fmt"]),
+                task("fmt", &[]),
+                task("lint", &["check"]),
+                task("check", &["lint"]),
+                task("deploy", &["package"]),
+            ],
+            ..Default::default()
+        };
+        assert_eq!(plan(&tasks, "fmt").unwrap(), vec!["fmt"]);
+        assert_eq!(plan(&tasks, "test").unwrap(), vec!["fmt", "build", "test"]);
+        assert!(plan(&tasks, "lint").is_err());
+        assert!(plan(&tasks, "deploy").is_err());
+        assert!(plan(&tasks, "missing").is_err());
+    }
+
+    #[test]
+    fn test_rustc_problems() {
+        let output = "   Compiling app v0.1.0
+warning: unused variable: `a`
+ --> src/main.rs:2:9
+  |
+error[E0308]: mismatched types
+  --> src/lib.rs:10:5
+error: could not compile `app`";
+        let problems = ProblemMatcher::rustc().problems(
+            output,
+            Path::new("/ws"),
+            "task: build",
+        );
+        assert_eq!(problems.len(), 2);
+
+        let warnings = &problems[Path::new("/ws/src/main.rs")];
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].severity, Some(DiagnosticSeverity::Warning));
+        assert_eq!(warnings[0].range.start, Position::new(1, 8));
+        assert_eq!(warnings[0].message, "unused variable: `a`");
+        assert_eq!(warnings[0].code, None);
+        assert_eq!(warnings[0].source.as_deref(), Some("task: build"));
+
+        let errors = &problems[Path::new("/ws/src/lib.rs")];
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].severity, Some(DiagnosticSeverity::Error));
+        assert_eq!(errors[0].range.start, Position::new(9, 4));
+        assert_eq!(
+            errors[0].code,
+            Some(NumberOrString::String("E0308".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_custom_problems() {
+        let tasks = TasksConfig {
+            problem_matchers: vec![ProblemMatcherConfig {
+                name: "gcc".to_string(),
+                patterns: vec![
+                    r"^(?P<file>[^:]+):(?P<line>\d+): (?P<severity>\w+): (?P<message>.+)$"
+                        .to_string(),
+                ],
+            }],
+            ..Default::default()
+        };
+        assert!(tasks.problem_matcher("missing").is_err());
+        assert!(tasks.problem_matcher("rustc").is_ok());
+
+        let output = "main.c:3: note: declared here
+main.c:7: warning: unused
+/src/util.c:1: error: expected ';'
+make: *** [all] Error 1";
+        let problems = tasks.problem_matcher("gcc").unwrap().problems(
+            output,
+            Path::new("/ws"),
+            "task: make",
+        );
+        let main = &problems[Path::new("/ws/main.c")];
+        let severities: Vec<_> = main.iter().map(|d| d.severity).collect();
+        assert_eq!(
+            severities,
+            vec![
+                Some(DiagnosticSeverity::Information),
+                Some(DiagnosticSeverity::Warning)
+            ]
+        );
+        assert_eq!(main[1].range.start, Position::new(6, 0));
+        let util = &problems[Path::new("/src/util.c")];
+        assert_eq!(util[0].severity, Some(DiagnosticSeverity::Error));
+        assert_eq!(util[0].message, "expected ';'");
+    }
+}
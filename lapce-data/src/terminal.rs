@@ -1,12 +1,16 @@
-use std::sync::Arc;
+use std::{path::PathBuf, sync::Arc};
 
 use alacritty_terminal::{
     ansi,
     event::EventListener,
     grid::{Dimensions, Scroll},
-    index::{Direction, Side},
+    index::{Column, Direction, Line, Point, Side},
     selection::{Selection, SelectionType},
-    term::{search::RegexSearch, SizeInfo, TermMode},
+    term::{
+        cell::Flags,
+        search::{Match, RegexSearch},
+        SizeInfo, TermMode,
+    },
     vi_mode::ViMotion,
     Term,
 };
@@ -17,20 +21,34 @@ use druid::{
 use hashbrown::HashMap;
 use lapce_rpc::terminal::TermId;
 use parking_lot::Mutex;
+use regex::Regex;
+use xi_rope::find::CaseMatching;
 
 use crate::{
-    command::{CommandExecuted, LapceCommand, LapceUICommand, LAPCE_UI_COMMAND},
-    config::{Config, LapceTheme},
+    command::{
+        CommandExecuted, CommandTarget, LapceCommand, LapceCommandNew,
+        LapceUICommand, LAPCE_NEW_COMMAND, LAPCE_UI_COMMAND,
+    },
+    config::{Config, LapceTheme, TerminalProfileConfig},
+    db::{TerminalInfo, TerminalSplitInfo},
     find::Find,
     keypress::KeyPressFocus,
     movement::{LinePosition, Movement},
     proxy::LapceProxy,
+    shell_integration::ShellIntegration,
     split::SplitMoveDirection,
     state::{LapceWorkspace, Mode, VisualMode},
+    task::TaskRun,
 };
 
 pub type TermConfig = alacritty_terminal::config::Config;
 
+/// The most matches of a search that are counted in the scrollback.
+pub const MAX_SEARCH_MATCHES: usize = 1000;
+
+/// The longest scrollback alacritty allows.
+const MAX_SCROLLBACK_LINES: usize = 100_000;
+
 #[derive(Clone)]
 pub struct TerminalSplitData {
     pub active: WidgetId,
@@ -38,6 +56,11 @@ pub struct TerminalSplitData {
     pub widget_id: WidgetId,
     pub split_id: WidgetId,
     pub terminals: im::HashMap<TermId, Arc<LapceTerminalData>>,
+    /// The widget ids of the terminals, in the order of the split.
+    pub order: Vec<WidgetId>,
+    /// The terminals of the last session, which the panel opens instead of
+    /// a new one.
+    pub restore: Option<TerminalSplitInfo>,
     pub indexed_colors: Arc<HashMap<u8, Color>>,
 }
 
@@ -53,10 +76,41 @@ impl TerminalSplitData {
             widget_id: WidgetId::next(),
             split_id,
             terminals,
+            order: Vec::new(),
+            restore: None,
             indexed_colors: Arc::new(Self::get_indexed_colors()),
         }
     }
 
+    /// The terminals to restore when the workspace opens again. Those
+    /// running tasks aren't kept.
+    pub fn split_info(&self, config: &Config) -> TerminalSplitInfo {
+        let terminals: Vec<&Arc<LapceTerminalData>> = self
+            .order
+            .iter()
+            .filter_map(|widget_id| {
+                self.terminals
+                    .values()
+                    .find(|terminal| &terminal.widget_id == widget_id)
+            })
+            .filter(|terminal| terminal.task.is_none())
+            .collect();
+        let active = terminals
+            .iter()
+            .position(|terminal| terminal.term_id == self.active_term_id)
+            .unwrap_or(0);
+        let info = TerminalSplitInfo {
+            terminals: terminals
+                .iter()
+                .map(|terminal| {
+                    terminal.terminal_info(config.terminal.saved_scrollback_lines)
+                })
+                .collect(),
+            active,
+        };
+        with_restored(info, self.restore.as_ref())
+    }
+
     pub fn get_indexed_colors() -> HashMap<u8, Color> {
         let mut indexed_colors = HashMap::new();
         // Build colors.
@@ -403,22 +457,83 @@ impl KeyPressFocus for LapceTerminalViewData {
                     self.receive_char(ctx, &s);
                 }
             }
-            LapceCommand::SearchForward => {
-                if let Some(search_string) = self.find.search_string.as_ref() {
-                    let mut raw = self.terminal.raw.lock();
-                    let term = &mut raw.term;
-                    self.terminal
-                        .search_next(term, search_string, Direction::Right);
+            LapceCommand::Search => {
+                Arc::make_mut(&mut self.find).visual = true;
+                self.terminal_mut().find_visual = true;
+                let pattern =
+                    self.terminal.raw.lock().term.selection_to_string().filter(
+                        |pattern| !pattern.is_empty() && !pattern.contains('\n'),
+                    );
+                if let Some(pattern) = pattern {
+                    ctx.submit_command(Command::new(
+                        LAPCE_UI_COMMAND,
+                        LapceUICommand::UpdateSearch(pattern),
+                        Target::Widget(self.terminal.proxy.tab_id),
+                    ));
                 }
+                ctx.submit_command(Command::new(
+                    LAPCE_NEW_COMMAND,
+                    LapceCommandNew {
+                        cmd: LapceCommand::SelectAll.to_string(),
+                        data: None,
+                        palette_desc: None,
+                        target: CommandTarget::Focus,
+                    },
+                    Target::Widget(self.terminal.find_view_id),
+                ));
+                ctx.submit_command(Command::new(
+                    LAPCE_UI_COMMAND,
+                    LapceUICommand::Focus,
+                    Target::Widget(self.terminal.find_view_id),
+                ));
+            }
+            LapceCommand::ClearSearch => {
+                Arc::make_mut(&mut self.find).visual = false;
+                self.terminal_mut().find_visual = false;
+                self.terminal.raw.lock().search_match = None;
+                ctx.submit_command(Command::new(
+                    LAPCE_UI_COMMAND,
+                    LapceUICommand::Focus,
+                    Target::Widget(self.terminal.widget_id),
+                ));
+            }
+            LapceCommand::SearchForward => {
+                Arc::make_mut(&mut self.find).visual = true;
+                self.terminal.search_next(&self.find, Direction::Right);
             }
             LapceCommand::SearchBackward => {
-                if let Some(search_string) = self.find.search_string.as_ref() {
-                    let mut raw = self.terminal.raw.lock();
-                    let term = &mut raw.term;
-                    self.terminal
-                        .search_next(term, search_string, Direction::Left);
+                Arc::make_mut(&mut self.find).visual = true;
+                self.terminal.search_next(&self.find, Direction::Left);
+            }
+            LapceCommand::PreviousPrompt | LapceCommand::NextPrompt => {
+                let mut raw = self.terminal.raw.lock();
+                let raw = &mut *raw;
+                raw.shell.scroll_to_prompt(
+                    &mut raw.term,
+                    command == &LapceCommand::NextPrompt,
+                );
+            }
+            LapceCommand::CopyLastCommandOutput => {
+                let raw = self.terminal.raw.lock();
+                if let Some(output) = raw.shell.last_output(&raw.term) {
+                    Application::global().clipboard().put_string(output);
                 }
             }
+            LapceCommand::NewTerminalInCwd => {
+                let cwd = self
+                    .terminal
+                    .raw
+                    .lock()
+                    .shell
+                    .cwd
+                    .clone()
+                    .or_else(|| self.terminal.cwd.clone());
+                ctx.submit_command(Command::new(
+                    LAPCE_UI_COMMAND,
+                    LapceUICommand::NewTerminal(None, cwd),
+                    Target::Widget(self.terminal.split_id),
+                ));
+            }
             _ => return CommandExecuted::No,
         }
         CommandExecuted::Yes
@@ -435,25 +550,132 @@ pub struct RawTerminal {
     pub parser: ansi::Processor,
     pub term: Term<EventProxy>,
     pub scroll_delta: f64,
+    pub shell: ShellIntegration,
+    /// The match the search moved to last.
+    pub search_match: Option<Match>,
+    /// Changes whenever the grid does, so that what was found in it can be
+    /// kept until then.
+    pub grid_version: u64,
 }
 
 impl RawTerminal {
     pub fn update_content(&mut self, content: &str) {
         if let Ok(content) = base64::decode(content) {
+            if content.is_empty() {
+                return;
+            }
+            let scrolled = self.shell.scrolled();
             for byte in content {
-                self.parser.advance(&mut self.term, byte);
+                self.shell.advance(&mut self.parser, &mut self.term, byte);
             }
+            self.grid_version += 1;
+
+            // Keep the match on the text it was found in as the output
+            // scrolls, until that text leaves the scrollback.
+            let delta = (self.shell.scrolled() - scrolled) as i32;
+            let topmost = self.term.topmost_line();
+            self.search_match = self.search_match.take().and_then(|m| {
+                let start =
+                    Point::new(Line(m.start().line.0 - delta), m.start().column);
+                let end = Point::new(Line(m.end().line.0 - delta), m.end().column);
+                (start.line >= topmost).then(|| start..=end)
+            });
+        }
+    }
+
+    pub fn resize(&mut self, size: SizeInfo) {
+        self.term.resize(size);
+        self.grid_changed();
+    }
+
+    /// The lines of the grid may have reflowed, so a match no longer points
+    /// at the text it was found in.
+    fn grid_changed(&mut self) {
+        self.grid_version += 1;
+        self.search_match = None;
+    }
+
+    /// Writes the scrollback of the last session dimmed, so that the output
+    /// of the new shell follows it.
+    pub fn restore_scrollback(&mut self, scrollback: &str) {
+        for byte in restored_scrollback(scrollback).bytes() {
+            self.shell.advance(&mut self.parser, &mut self.term, byte);
         }
+        self.grid_changed();
+    }
+
+    /// The last `lines` lines before the one of the cursor, which is the
+    /// prompt of the shell.
+    pub fn scrollback(&self, lines: usize) -> Option<String> {
+        scrollback(&self.term, lines)
     }
 }
 
+/// Puts the terminals of the last session before those in `info`. The panel
+/// wasn't opened when `restore` is still there, so it comes first like once
+/// it's restored.
+fn with_restored(
+    mut info: TerminalSplitInfo,
+    restore: Option<&TerminalSplitInfo>,
+) -> TerminalSplitInfo {
+    if let Some(restore) = restore {
+        info.active = if info.terminals.is_empty() {
+            restore.active
+        } else {
+            info.active + restore.terminals.len()
+        };
+        info.terminals
+            .splice(0..0, restore.terminals.iter().cloned());
+    }
+    info
+}
+
+/// The output that writes a saved scrollback dimmed, on lines of its own.
+fn restored_scrollback(scrollback: &str) -> String {
+    let mut content = String::from("\x1b[2m");
+    for line in scrollback.lines() {
+        content.push_str(line);
+        content.push_str("\r\n");
+    }
+    content.push_str("\x1b[0m");
+    content
+}
+
+/// The last `lines` lines before the one of the cursor, none on the
+/// alternate screen.
+fn scrollback<T: EventListener>(term: &Term<T>, lines: usize) -> Option<String> {
+    if lines == 0 || term.mode().contains(TermMode::ALT_SCREEN) {
+        return None;
+    }
+    let lines = lines.min(term.grid().history_size() + term.screen_lines());
+    let cursor = term.grid().cursor.point.line;
+    let end = Line(cursor.0 - 1);
+    let start = Line(cursor.0 - lines as i32).max(term.topmost_line());
+    if end < start {
+        return None;
+    }
+    let scrollback = term.bounds_to_string(
+        Point::new(start, Column(0)),
+        Point::new(end, term.last_column()),
+    );
+    let scrollback = scrollback.trim_end();
+    if scrollback.is_empty() {
+        return None;
+    }
+    Some(scrollback.to_string())
+}
+
 impl RawTerminal {
     pub fn new(
         term_id: TermId,
         proxy: Arc<LapceProxy>,
         event_sink: ExtEventSink,
+        scrollback_lines: usize,
     ) -> Self {
-        let config = TermConfig::default();
+        let mut config = TermConfig::default();
+        config
+            .scrolling
+            .set_history(scrollback_lines.min(MAX_SCROLLBACK_LINES) as u32);
         let size = SizeInfo::new(50.0, 30.0, 1.0, 1.0, 0.0, 0.0, true);
         let event_proxy = EventProxy {
             proxy,
@@ -468,46 +690,117 @@ impl RawTerminal {
             parser,
             term,
             scroll_delta: 0.0,
+            shell: ShellIntegration::new(),
+            search_match: None,
+            grid_version: 0,
         }
     }
 }
 
+/// What a link in the terminal output points to.
+#[derive(Clone, Debug, PartialEq)]
+pub enum TerminalLinkTarget {
+    Url(String),
+    File {
+        path: PathBuf,
+        /// 1-based, like in compiler messages.
+        line: u32,
+        /// 1-based.
+        column: u32,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TerminalLink {
+    /// The grid line, which is negative in the scrollback.
+    pub line: i32,
+    pub start_column: usize,
+    /// Exclusive.
+    pub end_column: usize,
+    pub target: TerminalLinkTarget,
+}
+
 #[derive(Clone)]
 pub struct LapceTerminalData {
     pub term_id: TermId,
     pub view_id: WidgetId,
     pub widget_id: WidgetId,
     pub split_id: WidgetId,
+    /// The editor of the find bar.
+    pub find_view_id: WidgetId,
+    /// The find bar is open, while the find is shown.
+    pub find_visual: bool,
     pub title: String,
+    /// The title comes from the profile, and the shell can't change it.
+    pub fixed_title: bool,
+    /// Where relative paths in the output are resolved, until the shell
+    /// reports its working directory.
+    pub cwd: Option<PathBuf>,
     pub mode: Mode,
     pub visual_mode: VisualMode,
     pub raw: Arc<Mutex<RawTerminal>>,
     pub proxy: Arc<LapceProxy>,
+    /// The task the terminal runs, which keeps it open once it finished.
+    pub task: Option<TaskRun>,
+    /// The name of the profile, when it isn't the built-in one.
+    pub profile: Option<String>,
+    /// The directory the terminal was opened in, instead of that of the
+    /// profile.
+    pub opened_in: Option<PathBuf>,
 }
 
 impl LapceTerminalData {
+    /// Starts a terminal with the profile, in `cwd` when it's given instead
+    /// of the workspace and the cwd of the profile. The scrollback of a
+    /// restored terminal is shown before what the shell writes.
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         workspace: Arc<LapceWorkspace>,
         split_id: WidgetId,
         event_sink: ExtEventSink,
         proxy: Arc<LapceProxy>,
         config: &Config,
+        mut profile: TerminalProfileConfig,
+        cwd: Option<PathBuf>,
+        task: Option<TaskRun>,
+        scrollback: Option<&str>,
     ) -> Self {
-        let cwd = workspace.path.as_ref().cloned();
         let widget_id = WidgetId::next();
         let view_id = WidgetId::next();
         let term_id = TermId::next();
-        let raw = Arc::new(Mutex::new(RawTerminal::new(
+        let mut raw = RawTerminal::new(
             term_id,
             proxy.clone(),
             event_sink,
-        )));
+            config.terminal.scrollback_lines,
+        );
+        if let Some(scrollback) = scrollback {
+            raw.restore_scrollback(scrollback);
+        }
+        let raw = Arc::new(Mutex::new(raw));
 
         let local_proxy = proxy.clone();
         let local_raw = raw.clone();
-        let shell = config.lapce.terminal_shell.clone();
+        let title = profile.title.clone();
+        let profile_name =
+            Some(profile.name.clone()).filter(|name| !name.is_empty());
+        let opened_in = cwd.clone();
+        if cwd.is_some() {
+            profile.cwd = None;
+        }
+        let cwd = cwd.or_else(|| workspace.path.clone());
+        // The home directory is only known to the proxy, so a cwd there
+        // leaves links relative to the workspace.
+        let link_cwd = match (&cwd, &profile.cwd) {
+            (Some(cwd), Some(profile_cwd)) if !profile_cwd.starts_with("~") => {
+                Some(cwd.join(profile_cwd))
+            }
+            (cwd, _) => cwd.clone(),
+        };
+        let mut profile = profile.profile();
+        profile.report_exit_code = task.is_some();
         std::thread::spawn(move || {
-            local_proxy.new_terminal(term_id, cwd, shell, local_raw);
+            local_proxy.new_terminal(term_id, cwd, profile, local_raw);
         });
 
         Self {
@@ -515,11 +808,27 @@ impl LapceTerminalData {
             widget_id,
             view_id,
             split_id,
-            title: "".to_string(),
+            find_view_id: WidgetId::next(),
+            find_visual: false,
+            fixed_title: title.is_some(),
+            title: title.unwrap_or_default(),
+            cwd: link_cwd,
             mode: Mode::Terminal,
             visual_mode: VisualMode::Normal,
             raw,
             proxy,
+            task,
+            profile: profile_name,
+            opened_in,
+        }
+    }
+
+    pub fn terminal_info(&self, scrollback_lines: usize) -> TerminalInfo {
+        let raw = self.raw.lock();
+        TerminalInfo {
+            profile: self.profile.clone(),
+            cwd: raw.shell.cwd.clone().or_else(|| self.opened_in.clone()),
+            scrollback: raw.scrollback(scrollback_lines),
         }
     }
 
@@ -531,7 +840,7 @@ impl LapceTerminalData {
         let proxy = self.proxy.clone();
         let term_id = self.term_id;
         std::thread::spawn(move || {
-            raw.lock().term.resize(size);
+            raw.lock().resize(size);
             proxy.terminal_resize(term_id, width, height);
         });
     }
@@ -548,35 +857,45 @@ impl LapceTerminalData {
         }
     }
 
-    pub fn search_next(
-        &self,
-        term: &mut Term<EventProxy>,
-        search_string: &str,
-        direction: Direction,
-    ) {
-        if let Ok(dfas) = RegexSearch::new(search_string) {
-            let mut point = term.renderable_content().cursor.point;
-            if direction == Direction::Right {
-                if point.column.0 < term.last_column() {
-                    point.column.0 += 1;
-                } else if point.line.0 < term.bottommost_line() {
-                    point.column.0 = 0;
-                    point.line.0 += 1;
-                }
-            } else if point.column.0 > 0 {
-                point.column.0 -= 1;
-            } else if point.line.0 > term.topmost_line() {
-                point.column.0 = term.last_column().0;
-                point.line.0 -= 1;
-            }
-            if let Some(m) =
-                term.search_next(&dfas, point, direction, Side::Left, None)
-            {
-                term.vi_goto_point(*m.start());
-            }
+    /// Moves to the next match of the find in the direction, from the
+    /// last match or the cursor, and wraps around the scrollback.
+    pub fn search_next(&self, find: &Find, direction: Direction) {
+        let dfas = match terminal_search(find) {
+            Some(dfas) => dfas,
+            None => return,
+        };
+        let mut raw = self.raw.lock();
+        let raw = &mut *raw;
+        let term = &mut raw.term;
+        let point = raw
+            .search_match
+            .as_ref()
+            .map(|m| *m.start())
+            .unwrap_or_else(|| term.renderable_content().cursor.point);
+        let point = if direction == Direction::Right {
+            next_point(term, point)
+        } else {
+            previous_point(term, point)
+        };
+        if let Some(m) = term.search_next(&dfas, point, direction, Side::Left, None)
+        {
+            term.vi_goto_point(*m.start());
+            raw.search_match = Some(m);
         }
     }
 
+    /// The link under the point, found with the link patterns of the config.
+    /// Links don't continue on wrapped lines.
+    pub fn link_at(
+        &self,
+        raw: &RawTerminal,
+        point: Point,
+        config: &Config,
+    ) -> Option<TerminalLink> {
+        let cwd = raw.shell.cwd.as_ref().or(self.cwd.as_ref());
+        find_link(&raw.term, point, &config.terminal.link_regexes, cwd)
+    }
+
     pub fn clear_selection(&self, term: &mut Term<EventProxy>) {
         term.selection = None;
     }
@@ -610,6 +929,164 @@ impl LapceTerminalData {
     }
 }
 
+/// The regex search of the find in the terminal, which follows the regex
+/// and case options of the find instead of the smart case of alacritty.
+pub fn terminal_search(find: &Find) -> Option<RegexSearch> {
+    let search_string = find.search_string.as_ref().filter(|s| !s.is_empty())?;
+    let pattern = if find.regex.is_some() {
+        search_string.clone()
+    } else {
+        regex::escape(search_string)
+    };
+    let pattern = match find.case_matching {
+        CaseMatching::Exact => format!("(?-i){pattern}"),
+        CaseMatching::CaseInsensitive => format!("(?i){pattern}"),
+    };
+    RegexSearch::new(&pattern).ok()
+}
+
+/// The link under the point, found with `regexes`. Relative paths are
+/// resolved against `cwd`.
+fn find_link<T: EventListener>(
+    term: &Term<T>,
+    point: Point,
+    regexes: &[Regex],
+    cwd: Option<&PathBuf>,
+) -> Option<TerminalLink> {
+    if point.line < term.topmost_line()
+        || point.line > term.bottommost_line()
+        || point.column.0 >= term.columns()
+    {
+        return None;
+    }
+
+    // The text of the line, with the byte offset and column of each char.
+    let mut text = String::new();
+    let mut columns = Vec::new();
+    let row = &term.grid()[point.line];
+    for column in 0..term.columns() {
+        let cell = &row[Column(column)];
+        if cell
+            .flags
+            .intersects(Flags::WIDE_CHAR_SPACER | Flags::LEADING_WIDE_CHAR_SPACER)
+        {
+            continue;
+        }
+        columns.push((text.len(), column));
+        text.push(cell.c);
+    }
+    let column_of = |offset: usize| {
+        columns
+            .iter()
+            .find(|(o, _)| *o >= offset)
+            .map(|(_, column)| *column)
+            .unwrap_or_else(|| term.columns())
+    };
+    let offset = columns
+        .iter()
+        .rev()
+        .find(|(_, column)| *column <= point.column.0)?
+        .0;
+
+    for regex in regexes {
+        for captures in regex.captures_iter(&text) {
+            let m = captures.get(0).unwrap();
+            if offset < m.start() || offset >= m.end() {
+                continue;
+            }
+            let target = if let Some(url) = captures.name("url") {
+                TerminalLinkTarget::Url(url.as_str().to_string())
+            } else if let Some(path) = captures.name("path") {
+                let number = |name: &str| {
+                    captures
+                        .name(name)
+                        .and_then(|m| m.as_str().parse().ok())
+                        .unwrap_or(1)
+                };
+                let path = PathBuf::from(path.as_str());
+                let path = match cwd {
+                    Some(cwd) if path.is_relative() => cwd.join(path),
+                    _ => path,
+                };
+                TerminalLinkTarget::File {
+                    path,
+                    line: number("line"),
+                    column: number("column"),
+                }
+            } else {
+                continue;
+            };
+            return Some(TerminalLink {
+                line: point.line.0,
+                start_column: column_of(m.start()),
+                end_column: column_of(m.end()),
+                target,
+            });
+        }
+    }
+    None
+}
+
+/// The matches that start between the lines, in order, up to `max` of them.
+pub fn search_matches<T: EventListener>(
+    term: &Term<T>,
+    dfas: &RegexSearch,
+    start_line: Line,
+    end_line: Line,
+    max: usize,
+) -> Vec<Match> {
+    let mut matches = Vec::new();
+    let mut point = Point::new(start_line.max(term.topmost_line()), Column(0));
+    let end_line = end_line.min(term.bottommost_line());
+    while matches.len() < max && point.line <= end_line {
+        let max_lines = (end_line.0 - point.line.0) as usize;
+        let m = match term.search_next(
+            dfas,
+            point,
+            Direction::Right,
+            Side::Left,
+            Some(max_lines),
+        ) {
+            Some(m) => m,
+            None => break,
+        };
+        // The search wraps around at the end.
+        if *m.start() < point || m.start().line > end_line {
+            break;
+        }
+        let next = next_point(term, *m.end());
+        let last = next == *m.end();
+        matches.push(m);
+        if last {
+            break;
+        }
+        point = next;
+    }
+    matches
+}
+
+/// The point after this one, which stays at the end of the grid.
+fn next_point<T: EventListener>(term: &Term<T>, mut point: Point) -> Point {
+    if point.column < term.last_column() {
+        point.column.0 += 1;
+    } else if point.line < term.bottommost_line() {
+        point.column.0 = 0;
+        point.line.0 += 1;
+    }
+    point
+}
+
+/// The point before this one, which stays at the start of the grid.
+fn previous_point<T: EventListener>(term: &Term<T>, mut point: Point) -> Point {
+    if point.column.0 > 0 {
+        point.column.0 -= 1;
+    } else if point.line > term.topmost_line() {
+        point.column = term.last_column();
+        point.line.0 -= 1;
+    }
+    point
+}
+
 #[derive(Clone)]
 pub struct EventProxy {
     term_id: TermId,
@@ -636,3 +1113,264 @@ impl EventListener for EventProxy {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use lapce_rpc::search::SearchOptions;
+
+    use super::*;
+
+    struct Listener;
+
+    impl EventListener for Listener {}
+
+    /// A terminal of 20 columns and 5 lines that shows the lines, those
+    /// above the last 5 are in the scrollback.
+    fn term(lines: &[&str]) -> Term<Listener> {
+        let size = SizeInfo::new(20.0, 5.0, 1.0, 1.0, 0.0, 0.0, false);
+        let mut term = Term::new(&TermConfig::default(), size, Listener);
+        let mut parser = ansi::Processor::new();
+        for byte in lines.join("\r\n").bytes() {
+            parser.advance(&mut term, byte);
+        }
+        term
+    }
+
+    fn find(pattern: &str, case_sensitive: bool, is_regex: bool) -> Find {
+        let mut find = Find::new(0);
+        find.set_find(
+            pattern,
+            &SearchOptions {
+                case_sensitive,
+                is_regex,
+                ..Default::default()
+            },
+        );
+        find
+    }
+
+    /// The line and column where the matches between the lines start.
+    fn starts(
+        term: &Term<Listener>,
+        find: &Find,
+        start_line: i32,
+        end_line: i32,
+        max: usize,
+    ) -> Vec<(i32, usize)> {
+        let dfas = terminal_search(find).unwrap();
+        search_matches(term, &dfas, Line(start_line), Line(end_line), max)
+            .iter()
+            .map(|m| (m.start().line.0, m.start().column.0))
+            .collect()
+    }
+
+    #[test]
+    fn test_terminal_search() {
+        assert!(terminal_search(&Find::new(0)).is_none());
+        assert!(terminal_search(&find("", false, false)).is_none());
+
+        let term = term(&["foo bar Foo", "barfoo", "1", "2", "3", "4", "b.r"]);
+        assert_eq!(term.topmost_line(), Line(-2));
+        assert_eq!(
+            starts(&term, &find("foo", false, false), -10, 10, usize::MAX),
+            vec![(-2, 0), (-2, 8), (-1, 3)]
+        );
+        assert_eq!(
+            starts(&term, &find("Foo", true, false), -10, 10, usize::MAX),
+            vec![(-2, 8)]
+        );
+        assert_eq!(
+            starts(&term, &find("b.r", false, false), -10, 10, usize::MAX),
+            vec![(4, 0)]
+        );
+        assert_eq!(
+            starts(&term, &find("b.r", false, true), -10, 10, usize::MAX),
+            vec![(-2, 4), (-1, 0), (4, 0)]
+        );
+    }
+
+    #[test]
+    fn test_search_matches_between_lines() {
+        let term = term(&["foo bar Foo", "barfoo", "1", "2", "3", "4", "foo"]);
+        let foo = find("foo", false, false);
+        assert_eq!(
+            starts(&term, &foo, -1, 4, usize::MAX),
+            vec![(-1, 3), (4, 0)]
+        );
+        assert!(starts(&term, &foo, 0, 3, usize::MAX).is_empty());
+        assert_eq!(starts(&term, &foo, -2, 4, 2), vec![(-2, 0), (-2, 8)]);
+        assert_eq!(starts(&term, &foo, 4, 4, usize::MAX), vec![(4, 0)]);
+    }
+
+    /// The default link patterns of the settings.
+    fn link_regexes() -> Vec<Regex> {
+        let settings: toml::Value =
+            toml::from_str(include_str!("../../defaults/settings.toml")).unwrap();
+        settings["terminal"]["link-patterns"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|pattern| Regex::new(pattern.as_str().unwrap()).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_find_link() {
+        let term = term(&[
+            "see https://a.io/x.",
+            "at src/main.rs:12:5",
+            "/tmp/a.rs:3 x",
+            "File \"x.py\", line 4",
+        ]);
+        let regexes = link_regexes();
+        let cwd = PathBuf::from("/ws");
+        let link = |line, column| {
+            find_link(
+                &term,
+                Point::new(Line(line), Column(column)),
+                &regexes,
+                Some(&cwd),
+            )
+        };
+
+        assert_eq!(
+            link(0, 10),
+            Some(TerminalLink {
+                line: 0,
+                start_column: 4,
+                end_column: 18,
+                target: TerminalLinkTarget::Url("https://a.io/x".to_string()),
+            })
+        );
+        assert_eq!(link(0, 2), None);
+        // The trailing period isn't part of the URL.
+        assert_eq!(link(0, 18), None);
+
+        assert_eq!(
+            link(1, 3),
+            Some(TerminalLink {
+                line: 1,
+                start_column: 3,
+                end_column: 19,
+                target: TerminalLinkTarget::File {
+                    path: PathBuf::from("/ws/src/main.rs"),
+                    line: 12,
+                    column: 5,
+                },
+            })
+        );
+        assert_eq!(
+            link(2, 0).map(|link| (link.end_column, link.target)),
+            Some((
+                11,
+                TerminalLinkTarget::File {
+                    path: PathBuf::from("/tmp/a.rs"),
+                    line: 3,
+                    column: 1,
+                }
+            ))
+        );
+        assert_eq!(link(2, 12), None);
+        assert_eq!(
+            link(3, 10).map(|link| link.target),
+            Some(TerminalLinkTarget::File {
+                path: PathBuf::from("/ws/x.py"),
+                line: 4,
+                column: 1,
+            })
+        );
+        // Outside of the grid.
+        assert_eq!(link(5, 0), None);
+        assert_eq!(link(0, 20), None);
+    }
+
+    #[test]
+    fn test_find_link_without_cwd() {
+        let term = term(&["src/main.rs:12"]);
+        let link =
+            find_link(&term, Point::new(Line(0), Column(0)), &link_regexes(), None);
+        assert_eq!(
+            link.map(|link| link.target),
+            Some(TerminalLinkTarget::File {
+                path: PathBuf::from("src/main.rs"),
+                line: 12,
+                column: 1,
+            })
+        );
+    }
+
+    /// Terminals named by their profile.
+    fn split_info(names: &[&str], active: usize) -> TerminalSplitInfo {
+        TerminalSplitInfo {
+            terminals: names
+                .iter()
+                .map(|name| TerminalInfo {
+                    profile: Some(name.to_string()),
+                    cwd: None,
+                    scrollback: None,
+                })
+                .collect(),
+            active,
+        }
+    }
+
+    fn names(info: &TerminalSplitInfo) -> Vec<&str> {
+        info.terminals
+            .iter()
+            .filter_map(|terminal| terminal.profile.as_deref())
+            .collect()
+    }
+
+    #[test]
+    fn test_with_restored() {
+        let info = with_restored(split_info(&["c"], 0), None);
+        assert_eq!(names(&info), vec!["c"]);
+        assert_eq!(info.active, 0);
+
+        let restore = split_info(&["a", "b"], 1);
+        let info = with_restored(split_info(&[], 0), Some(&restore));
+        assert_eq!(names(&info), vec!["a", "b"]);
+        assert_eq!(info.active, 1);
+
+        let info = with_restored(split_info(&["c", "d"], 1), Some(&restore));
+        assert_eq!(names(&info), vec!["a", "b", "c", "d"]);
+        assert_eq!(info.active, 3);
+    }
+
+    #[test]
+    fn test_scrollback() {
+        // Nothing before the prompt.
+        assert_eq!(scrollback(&term(&["$ "]), 10), None);
+        assert_eq!(scrollback(&term(&["", "", "$ "]), 10), None);
+
+        let term = term(&["a", "b", "c", "d", "e", "f", "$ "]);
+        assert_eq!(term.topmost_line(), Line(-2));
+        assert_eq!(scrollback(&term, 2).as_deref(), Some("e\nf"));
+        assert_eq!(scrollback(&term, 6).as_deref(), Some("a\nb\nc\nd\ne\nf"));
+        // More lines than the terminal has don't wrap around.
+        assert_eq!(
+            scrollback(&term, usize::MAX).as_deref(),
+            Some("a\nb\nc\nd\ne\nf")
+        );
+        assert_eq!(scrollback(&term, 0), None);
+    }
+
+    #[test]
+    fn test_scrollback_alt_screen() {
+        let term = term(&["a", "b", "\x1b[?1049hvim"]);
+        assert!(term.mode().contains(TermMode::ALT_SCREEN));
+        assert_eq!(scrollback(&term, 10), None);
+    }
+
+    #[test]
+    fn test_restore_scrollback() {
+        let term = term(&[&restored_scrollback("a\nb\n\nc")]);
+        assert_eq!(term.grid().cursor.point, Point::new(Line(4), Column(0)));
+        assert!(term.grid()[Point::new(Line(0), Column(0))]
+            .flags
+            .contains(Flags::DIM));
+        // What the shell writes next isn't dimmed.
+        assert!(!term.grid().cursor.template.flags.contains(Flags::DIM));
+        assert_eq!(scrollback(&term, 10).as_deref(), Some("a\nb\n\nc"));
+    }
+}
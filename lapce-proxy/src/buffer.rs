@@ -12,6 +12,9 @@ use std::{borrow::Cow, path::Path, time::SystemTime};
 use lsp_types::*;
 use xi_rope::{interval::IntervalBounds, rope::Rope, RopeDelta};
 
+/// The size of each piece of a large file sent to the editor.
+const CHUNK_SIZE: usize = 1024 * 1024;
+
 pub struct Buffer {
     pub language_id: String,
     pub id: BufferId,
@@ -19,14 +22,19 @@ pub struct Buffer {
     pub path: PathBuf,
     pub rev: u64,
     pub dirty: bool,
+    pub large_file: bool,
     sender: Sender<(BufferId, u64)>,
     pub mod_time: Option<SystemTime>,
+    /// The file as a commit left it, which only exists in memory and can't
+    /// be saved.
+    pub snapshot: bool,
 }
 
 impl Buffer {
     pub fn new(
         id: BufferId,
         path: PathBuf,
+        large_file_size: u64,
         sender: Sender<(BufferId, u64)>,
     ) -> Buffer {
         let rope = if let Ok(rope) = load_file(&path) {
@@ -34,7 +42,15 @@ impl Buffer {
         } else {
             Rope::from("")
         };
-        let language_id = language_id_from_path(&path).unwrap_or("").to_string();
+        let large_file = rope.len() as u64 > large_file_size;
+        // Large files are never handed to a language server, so they don't
+        // get a language id.
+        let language_id = if large_file {
+            ""
+        } else {
+            language_id_from_path(&path).unwrap_or("")
+        }
+        .to_string();
         let mod_time = get_mod_time(&path);
         Buffer {
             id,
@@ -44,14 +60,61 @@ impl Buffer {
             rev: 0,
             sender,
             dirty: false,
+            large_file,
             mod_time,
+            snapshot: false,
+        }
+    }
+
+    /// A read-only buffer of `content`, the file at `path` as of a commit.
+    pub fn snapshot(
+        id: BufferId,
+        path: PathBuf,
+        content: &str,
+        sender: Sender<(BufferId, u64)>,
+    ) -> Buffer {
+        let language_id = language_id_from_path(&path).unwrap_or("").to_string();
+        Buffer {
+            id,
+            rope: Rope::from(content),
+            path,
+            language_id,
+            rev: 0,
+            sender,
+            dirty: false,
+            large_file: false,
+            mod_time: None,
+            snapshot: true,
         }
     }
 
+    /// Returns the text starting at `offset`, at most `CHUNK_SIZE` bytes long
+    /// and ending on a codepoint boundary, and whether it's the last chunk.
+    pub fn chunk(&self, offset: usize) -> (String, bool) {
+        let len = self.rope.len();
+        let start = offset.min(len);
+        let end = self
+            .rope
+            .at_or_next_codepoint_boundary((start + CHUNK_SIZE).min(len))
+            .unwrap_or(len);
+        (self.rope.slice_to_cow(start..end).to_string(), end >= len)
+    }
+
+    /// Whether the buffer is a snapshot, or the file exists and can't be
+    /// opened for writing.
+    pub fn is_read_only(&self) -> bool {
+        self.snapshot
+            || (self.path.exists()
+                && fs::OpenOptions::new().write(true).open(&self.path).is_err())
+    }
+
     pub fn save(&mut self, rev: u64) -> Result<()> {
         if self.rev != rev {
             return Err(anyhow!("not the right rev"));
         }
+        if self.snapshot {
+            return Err(anyhow!("a snapshot can't be saved"));
+        }
         self.dirty = false;
         let tmp_extension = self.path.extension().map_or_else(
             || OsString::from("swp"),
@@ -73,6 +136,9 @@ impl Buffer {
     }
 
     pub fn reload(&mut self) {
+        if self.snapshot {
+            return;
+        }
         let rope = if let Ok(rope) = load_file(&self.path) {
             rope
         } else {
@@ -211,3 +277,67 @@ pub fn get_mod_time<P: AsRef<Path>>(path: P) -> Option<SystemTime> {
         .and_then(|meta| meta.modified())
         .ok()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn buffer(content: &str) -> Buffer {
+        let (sender, _) = crossbeam_channel::unbounded();
+        Buffer {
+            language_id: String::new(),
+            id: BufferId(0),
+            rope: Rope::from(content),
+            path: PathBuf::new(),
+            rev: 0,
+            dirty: false,
+            large_file: true,
+            sender,
+            mod_time: None,
+            snapshot: false,
+        }
+    }
+
+    #[test]
+    fn test_snapshot() {
+        let (sender, _) = crossbeam_channel::unbounded();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("file.rs");
+        let mut buffer =
+            Buffer::snapshot(BufferId(0), path.clone(), "old\n", sender);
+        assert!(buffer.is_read_only());
+        assert_eq!(buffer.language_id, "rust");
+        assert!(buffer.save(0).is_err());
+        assert!(!path.exists());
+        buffer.reload();
+        assert_eq!(buffer.get_document(), "old\n");
+    }
+
+    #[test]
+    fn test_chunk_empty() {
+        let buffer = buffer("");
+        assert_eq!(buffer.chunk(0), (String::new(), true));
+    }
+
+    #[test]
+    fn test_chunk_codepoint_boundary() {
+        // The odd prefix puts the chunk size in the middle of a "é".
+        let content = format!("a{}", "é".repeat(CHUNK_SIZE));
+        let buffer = buffer(&content);
+
+        let (first, done) = buffer.chunk(0);
+        assert!(!done);
+        assert_eq!(first.len(), CHUNK_SIZE + 1);
+
+        let mut result = first;
+        loop {
+            let (chunk, done) = buffer.chunk(result.len());
+            result.push_str(&chunk);
+            if done {
+                break;
+            }
+        }
+        assert_eq!(result, content);
+        assert_eq!(buffer.chunk(content.len()), (String::new(), true));
+    }
+}
@@ -1,35 +1,46 @@
 use crate::buffer::{get_mod_time, Buffer};
+use crate::git;
+use crate::history;
 use crate::lsp::LspCatalog;
 use crate::plugin::PluginCatalog;
+use crate::search;
 use crate::terminal::Terminal;
 use alacritty_terminal::event_loop::Msg;
 use alacritty_terminal::term::SizeInfo;
 use anyhow::{anyhow, Context, Result};
 use crossbeam_channel::{unbounded, Receiver, Sender};
 use directories::BaseDirs;
-use git2::{DiffOptions, Repository};
-use grep_matcher::Matcher;
-use grep_regex::RegexMatcherBuilder;
-use grep_searcher::sinks::UTF8;
-use grep_searcher::SearcherBuilder;
-use lapce_rpc::buffer::{BufferHeadResponse, BufferId, NewBufferResponse};
+use git2::{DiffOptions, ErrorCode, Repository};
+use lapce_rpc::buffer::{
+    BufferChunkResponse, BufferHeadResponse, BufferId, NewBufferResponse,
+};
 use lapce_rpc::file::FileNodeItem;
 use lapce_rpc::proxy::{ProxyNotification, ProxyRequest};
-use lapce_rpc::source_control::{DiffInfo, FileDiff};
+use lapce_rpc::source_control::{DiffBase, DiffInfo, FileDiff, GitCredentials};
 use lapce_rpc::terminal::TermId;
 use lapce_rpc::{self, Call, RequestId, RpcObject};
-use lsp_types::TextDocumentContentChangeEvent;
+use lsp_types::{
+    NumberOrString, ProgressParams, ProgressParamsValue,
+    TextDocumentContentChangeEvent, WorkDoneProgress, WorkDoneProgressBegin,
+    WorkDoneProgressEnd, WorkDoneProgressReport,
+};
 use notify::Watcher;
 use parking_lot::Mutex;
 use serde_json::json;
 use serde_json::Value;
 use std::collections::HashMap;
+use std::ffi::OsStr;
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
 use std::thread;
 use std::{collections::HashSet, io::BufRead};
 
+/// How many of the latest commits a file can be compared with.
+const RECENT_COMMITS: usize = 50;
+
 #[derive(Clone)]
 pub struct Dispatcher {
     pub sender: Arc<Sender<Value>>,
@@ -41,10 +52,20 @@ pub struct Dispatcher {
     pub terminals: Arc<Mutex<HashMap<TermId, mio::channel::Sender<Msg>>>>,
 
     open_files: Arc<Mutex<HashMap<String, BufferId>>>,
+    /// The content of the files as of a commit, by the snapshot path the
+    /// core opens them under.
+    snapshots: Arc<Mutex<HashMap<PathBuf, String>>>,
     plugins: Arc<Mutex<PluginCatalog>>,
     pub lsp: Arc<Mutex<LspCatalog>>,
     pub watcher: Arc<Mutex<Option<notify::RecommendedWatcher>>>,
-    last_diff: Arc<Mutex<DiffInfo>>,
+    /// The worktrees of the repositories in the workspace.
+    repositories: Arc<Mutex<Vec<PathBuf>>>,
+    last_diff: Arc<Mutex<Vec<DiffInfo>>>,
+    /// The id of the running global search, 0 when there is none.
+    search_id: Arc<AtomicU64>,
+    request_id: Arc<AtomicU64>,
+    /// Requests sent to the core, waiting for their response.
+    pending: Arc<Mutex<HashMap<RequestId, Sender<Result<Value, Value>>>>>,
 }
 
 impl notify::EventHandler for Dispatcher {
@@ -63,26 +84,7 @@ impl notify::EventHandler for Dispatcher {
                                     {
                                         return;
                                     }
-                                    if !buffer.dirty {
-                                        buffer.reload();
-                                        self.lsp.lock().update(
-                                            buffer,
-                                            &TextDocumentContentChangeEvent {
-                                                range: None,
-                                                range_length: None,
-                                                text: buffer.get_document(),
-                                            },
-                                            buffer.rev,
-                                        );
-                                        let _ = self.sender.send(json!({
-                                            "method": "reload_buffer",
-                                            "params": {
-                                                "buffer_id": buffer_id,
-                                                "rev": buffer.rev,
-                                                "new_content": buffer.get_document(),
-                                            },
-                                        }));
-                                    }
+                                    self.reload_buffer(buffer);
                                 }
                             }
                             _ => (),
@@ -94,19 +96,15 @@ impl notify::EventHandler for Dispatcher {
                 notify::EventKind::Create(_)
                 | notify::EventKind::Modify(_)
                 | notify::EventKind::Remove(_) => {
-                    if let Some(workspace) = self.workspace.lock().clone() {
-                        if let Some(diff) = git_diff_new(&workspace) {
-                            if diff != *self.last_diff.lock() {
-                                self.send_notification(
-                                    "diff_info",
-                                    json!({
-                                        "diff": diff,
-                                    }),
-                                );
-                                *self.last_diff.lock() = diff;
-                            }
-                        }
+                    // A repository was created or removed.
+                    if event
+                        .paths
+                        .iter()
+                        .any(|path| path.file_name() == Some(OsStr::new(".git")))
+                    {
+                        self.find_repositories();
                     }
+                    self.refresh_diff_info();
                 }
                 _ => (),
             }
@@ -124,11 +122,16 @@ impl Dispatcher {
             workspace: Arc::new(Mutex::new(None)),
             buffers: Arc::new(Mutex::new(HashMap::new())),
             open_files: Arc::new(Mutex::new(HashMap::new())),
+            snapshots: Arc::new(Mutex::new(HashMap::new())),
             terminals: Arc::new(Mutex::new(HashMap::new())),
             plugins: Arc::new(Mutex::new(plugins)),
             lsp: Arc::new(Mutex::new(LspCatalog::new())),
             watcher: Arc::new(Mutex::new(None)),
-            last_diff: Arc::new(Mutex::new(DiffInfo::default())),
+            repositories: Arc::new(Mutex::new(Vec::new())),
+            last_diff: Arc::new(Mutex::new(Vec::new())),
+            search_id: Arc::new(AtomicU64::new(0)),
+            request_id: Arc::new(AtomicU64::new(0)),
+            pending: Arc::new(Mutex::new(HashMap::new())),
         };
         *dispatcher.watcher.lock() =
             Some(notify::recommended_watcher(dispatcher.clone()).unwrap());
@@ -173,6 +176,12 @@ impl Dispatcher {
         for msg in receiver {
             let rpc: RpcObject = msg.into();
             if rpc.is_response() {
+                let sender =
+                    rpc.get_id().and_then(|id| self.pending.lock().remove(&id));
+                if let Some(sender) = sender {
+                    let _ = sender
+                        .send(rpc.into_response().unwrap_or_else(|e| Err(json!(e))));
+                }
             } else {
                 match rpc.into_rpc::<ProxyNotification, ProxyRequest>() {
                     Ok(Call::Request(id, request)) => {
@@ -263,6 +272,145 @@ impl Dispatcher {
         let _ = self.sender.send(resp);
     }
 
+    /// Reloads a buffer that isn't dirty from disk and tells the core and the
+    /// language servers about the new content.
+    fn reload_buffer(&self, buffer: &mut Buffer) {
+        if buffer.dirty {
+            return;
+        }
+        buffer.reload();
+        self.lsp.lock().update(
+            buffer,
+            &TextDocumentContentChangeEvent {
+                range: None,
+                range_length: None,
+                text: buffer.get_document(),
+            },
+            buffer.rev,
+        );
+        let _ = self.sender.send(json!({
+            "method": "reload_buffer",
+            "params": {
+                "buffer_id": buffer.id,
+                "rev": buffer.rev,
+                "new_content": buffer.get_document(),
+            },
+        }));
+    }
+
+    /// Looks for the repositories in the workspace again.
+    fn find_repositories(&self) {
+        if let Some(workspace) = self.workspace.lock().clone() {
+            *self.repositories.lock() = git::repositories(&workspace);
+        }
+    }
+
+    /// The worktree of the innermost repository `path` is in.
+    fn repository(&self, path: &Path) -> Result<PathBuf> {
+        self.repositories
+            .lock()
+            .iter()
+            .filter(|repository| path.starts_with(repository))
+            .max_by_key(|repository| repository.components().count())
+            .cloned()
+            .ok_or_else(|| anyhow!("{} isn't in a git repository", path.display()))
+    }
+
+    /// Splits `paths` by the repository they are in.
+    fn paths_by_repository(
+        &self,
+        paths: Vec<PathBuf>,
+    ) -> Result<HashMap<PathBuf, Vec<PathBuf>>> {
+        let mut repositories: HashMap<PathBuf, Vec<PathBuf>> = HashMap::new();
+        for path in paths {
+            repositories
+                .entry(self.repository(&path)?)
+                .or_default()
+                .push(path);
+        }
+        Ok(repositories)
+    }
+
+    /// Sends the diff info of the repositories again if it changed since it
+    /// was last sent.
+    fn refresh_diff_info(&self) {
+        let repositories = self.repositories.lock().clone();
+        // stdout is the rpc channel, so failures are reported on stderr.
+        let diffs: Vec<DiffInfo> = repositories
+            .iter()
+            .filter_map(|repository| {
+                let diff = git_diff_new(repository);
+                if diff.is_none() {
+                    eprintln!(
+                        "failed to read the git status of {}",
+                        repository.display()
+                    );
+                }
+                diff
+            })
+            .collect();
+        if diffs != *self.last_diff.lock() {
+            self.send_notification(
+                "diff_info",
+                json!({
+                    "repositories": diffs,
+                }),
+            );
+            *self.last_diff.lock() = diffs;
+        }
+    }
+
+    /// Brings the buffers and diff info up to date after a checkout or
+    /// discard changed the worktree, without waiting for the file watcher.
+    fn refresh_worktree(&self) {
+        for buffer in self.buffers.lock().values_mut() {
+            if get_mod_time(&buffer.path) != buffer.mod_time {
+                self.reload_buffer(buffer);
+            }
+        }
+        self.refresh_diff_info();
+    }
+
+    /// Sends a request to the core and waits for the response, so it mustn't
+    /// be called from the main loop.
+    pub fn send_request(&self, method: &str, params: Value) -> Result<Value> {
+        let id = self.request_id.fetch_add(1, Ordering::Relaxed);
+        let (sender, receiver) = crossbeam_channel::bounded(1);
+        self.pending.lock().insert(id, sender);
+        let _ = self.sender.send(json!({
+            "id": id,
+            "method": method,
+            "params": params,
+        }));
+        receiver.recv()?.map_err(|e| anyhow!("{e}"))
+    }
+
+    /// Runs a fetch, pull or push on its own thread, with its transfer
+    /// progress shown as work done progress titled `title`.
+    fn git_remote<F>(&self, id: RequestId, repository: PathBuf, title: &str, f: F)
+    where
+        F: FnOnce(&Path, &dyn git::RemoteHandler) -> Result<()> + Send + 'static,
+    {
+        let handler = GitRemoteHandler {
+            dispatcher: self.clone(),
+            token: NumberOrString::String(format!("git-{id}")),
+        };
+        let title = title.to_string();
+        thread::spawn(move || {
+            handler.send(WorkDoneProgress::Begin(WorkDoneProgressBegin {
+                title,
+                cancellable: None,
+                message: None,
+                percentage: None,
+            }));
+            let resp = f(&repository, &handler);
+            handler
+                .send(WorkDoneProgress::End(WorkDoneProgressEnd { message: None }));
+            handler.dispatcher.refresh_worktree();
+            handler.dispatcher.respond(id, resp.map(|_| json!({})));
+        });
+    }
+
     pub fn send_notification(&self, method: &str, params: Value) {
         let _ = self.sender.send(json!({
             "method": method,
@@ -281,17 +429,37 @@ impl Dispatcher {
                     .as_mut()
                     .unwrap()
                     .watch(&workspace, notify::RecursiveMode::Recursive);
-                if let Some(diff) = git_diff_new(&workspace) {
-                    self.send_notification(
-                        "diff_info",
-                        json!({
-                            "diff": diff,
-                        }),
-                    );
-                    *self.last_diff.lock() = diff;
-                }
+                self.find_repositories();
+                self.refresh_diff_info();
             }
             Shutdown {} => {}
+            GlobalSearch {
+                search_id,
+                pattern,
+                options,
+                max_file_matches,
+                max_matches,
+            } => {
+                self.search_id.store(search_id, Ordering::SeqCst);
+                if let Some(workspace) = self.workspace.lock().clone() {
+                    let dispatcher = self.clone();
+                    thread::spawn(move || {
+                        search::global_search(
+                            &dispatcher,
+                            dispatcher.search_id.clone(),
+                            search_id,
+                            &workspace,
+                            &pattern,
+                            &options,
+                            max_file_matches,
+                            max_matches,
+                        );
+                    });
+                }
+            }
+            CancelGlobalSearch {} => {
+                self.search_id.store(0, Ordering::SeqCst);
+            }
             Update {
                 buffer_id,
                 delta,
@@ -323,9 +491,9 @@ impl Dispatcher {
             NewTerminal {
                 term_id,
                 cwd,
-                shell,
+                profile,
             } => {
-                let mut terminal = Terminal::new(term_id, cwd, shell, 50, 10);
+                let mut terminal = Terminal::new(term_id, cwd, profile, 50, 10);
                 let tx = terminal.tx.clone();
                 self.terminals.lock().insert(term_id, tx);
                 let dispatcher = self.clone();
@@ -340,6 +508,10 @@ impl Dispatcher {
                     let _ = tx.send(Msg::Shutdown);
                 }
             }
+            GitCloseSnapshot { path } => {
+                self.snapshots.lock().remove(&path);
+                self.buffers.lock().retain(|_, buffer| buffer.path != path);
+            }
             TerminalWrite { term_id, content } => {
                 let terminals = self.terminals.lock();
                 if let Some(tx) = terminals.get(&term_id) {
@@ -368,18 +540,36 @@ impl Dispatcher {
                     let _ = tx.send(Msg::Resize(size));
                 }
             }
-            GitCommit { message, diffs } => {
-                if let Some(workspace) = self.workspace.lock().clone() {
-                    if let Err(_e) = git_commit(&workspace, &message, diffs) {}
-                }
-            }
         }
     }
 
     fn handle_request(&self, id: RequestId, rpc: ProxyRequest) {
         use ProxyRequest::*;
         match rpc {
-            NewBuffer { buffer_id, path } => {
+            NewBuffer {
+                buffer_id,
+                path,
+                large_file_size,
+            } => {
+                if let Some(content) = self.snapshots.lock().get(&path) {
+                    let buffer = Buffer::snapshot(
+                        buffer_id,
+                        path,
+                        content,
+                        self.git_sender.clone(),
+                    );
+                    let resp = NewBufferResponse {
+                        content: buffer.rope.to_string(),
+                        large_file: false,
+                        read_only: true,
+                    };
+                    self.buffers.lock().insert(buffer_id, buffer);
+                    let _ = self.sender.send(json!({
+                        "id": id,
+                        "result": resp,
+                    }));
+                    return;
+                }
                 let _ = self
                     .watcher
                     .lock()
@@ -389,31 +579,300 @@ impl Dispatcher {
                 self.open_files
                     .lock()
                     .insert(path.to_str().unwrap().to_string(), buffer_id);
-                let buffer = Buffer::new(buffer_id, path, self.git_sender.clone());
-                let content = buffer.rope.to_string();
+                let buffer = Buffer::new(
+                    buffer_id,
+                    path,
+                    large_file_size,
+                    self.git_sender.clone(),
+                );
+                let large_file = buffer.large_file;
+                let read_only = buffer.is_read_only();
+                let content = if large_file {
+                    String::new()
+                } else {
+                    buffer.rope.to_string()
+                };
                 self.buffers.lock().insert(buffer_id, buffer);
-                let _ = self.git_sender.send((buffer_id, 0));
-                let resp = NewBufferResponse { content };
+                if !large_file {
+                    let _ = self.git_sender.send((buffer_id, 0));
+                }
+                let resp = NewBufferResponse {
+                    content,
+                    large_file,
+                    read_only,
+                };
                 let _ = self.sender.send(json!({
                     "id": id,
                     "result": resp,
                 }));
             }
+            BufferChunk { buffer_id, offset } => {
+                let buffers = self.buffers.lock();
+                let resp = buffers
+                    .get(&buffer_id)
+                    .ok_or_else(|| anyhow!("buffer not found"))
+                    .map(|buffer| {
+                        let (content, done) = buffer.chunk(offset);
+                        serde_json::to_value(BufferChunkResponse { content, done })
+                            .unwrap()
+                    });
+                self.respond(id, resp);
+            }
             #[allow(unused_variables)]
-            BufferHead { buffer_id, path } => {
-                if let Some(workspace) = self.workspace.lock().clone() {
-                    let result = file_get_head(&workspace, &path);
-                    if let Ok((_blob_id, content)) = result {
-                        let resp = BufferHeadResponse {
+            BufferHead {
+                buffer_id,
+                path,
+                base,
+            } => {
+                let resp = self
+                    .repository(&path)
+                    .and_then(|repository| match base {
+                        DiffBase::Head => file_get_head(&repository, &path)
+                            .map(|(_blob_id, content)| content),
+                        DiffBase::Index => git::index_version(&repository, &path),
+                        DiffBase::Revision(revision) => {
+                            git::file_version(&repository, &path, &revision)
+                        }
+                    })
+                    .map(|content| {
+                        serde_json::to_value(BufferHeadResponse {
                             id: "head".to_string(),
                             content,
-                        };
-                        let _ = self.sender.send(json!({
-                            "id": id,
-                            "result": resp,
-                        }));
-                    }
+                        })
+                        .unwrap()
+                    });
+                self.respond(id, resp);
+            }
+            FileHistory { path } => {
+                let resp = match self.workspace.lock().as_ref() {
+                    Some(workspace) => history::list_versions(workspace, &path)
+                        .map(|versions| serde_json::to_value(versions).unwrap()),
+                    None => Err(anyhow!("no workspace")),
+                };
+                self.respond(id, resp);
+            }
+            ReplaceInFiles { files } => {
+                let changed = search::replace_in_files(files);
+                self.respond(id, Ok(json!(changed)));
+            }
+            GitBranches { repository } => {
+                let resp = git::branches(&repository)
+                    .map(|branches| serde_json::to_value(branches).unwrap());
+                self.respond(id, resp);
+            }
+            GitCheckout {
+                repository,
+                branch,
+                force,
+            } => {
+                let resp = git::checkout(&repository, &branch, force);
+                if resp.is_ok() {
+                    self.refresh_worktree();
+                }
+                self.respond(id, resp.map(|_| json!({})));
+            }
+            GitCreateBranch {
+                repository,
+                name,
+                start_point,
+            } => {
+                let resp =
+                    git::create_branch(&repository, &name, start_point.as_deref());
+                if resp.is_ok() {
+                    self.refresh_diff_info();
+                }
+                self.respond(id, resp.map(|_| json!({})));
+            }
+            GitDeleteBranch { repository, name } => {
+                let resp = git::delete_branch(&repository, &name);
+                if resp.is_ok() {
+                    self.refresh_diff_info();
                 }
+                self.respond(id, resp.map(|_| json!({})));
+            }
+            GitStage { paths } => {
+                let resp =
+                    self.paths_by_repository(paths).and_then(|repositories| {
+                        repositories.iter().try_for_each(|(repository, paths)| {
+                            git::stage(repository, paths)
+                        })
+                    });
+                self.refresh_diff_info();
+                self.respond(id, resp.map(|_| json!({})));
+            }
+            GitUnstage { paths } => {
+                let resp =
+                    self.paths_by_repository(paths).and_then(|repositories| {
+                        repositories.iter().try_for_each(|(repository, paths)| {
+                            git::unstage(repository, paths)
+                        })
+                    });
+                self.refresh_diff_info();
+                self.respond(id, resp.map(|_| json!({})));
+            }
+            GitDiscard { paths } => {
+                let resp =
+                    self.paths_by_repository(paths).and_then(|repositories| {
+                        repositories.iter().try_for_each(|(repository, paths)| {
+                            git::discard(repository, paths)
+                        })
+                    });
+                self.refresh_worktree();
+                self.respond(id, resp.map(|_| json!({})));
+            }
+            GitResolve { path } => {
+                let resp = self
+                    .repository(&path)
+                    .and_then(|repository| git::resolve(&repository, &path));
+                self.refresh_diff_info();
+                self.respond(id, resp.map(|_| json!({})));
+            }
+            GitStageHunk { path, hunk } => {
+                let resp = self.repository(&path).and_then(|repository| {
+                    git::stage_hunk(&repository, &path, &hunk)
+                });
+                self.refresh_diff_info();
+                self.respond(id, resp.map(|_| json!({})));
+            }
+            GitBlame { path } => {
+                let repository = self.repository(&path);
+                let content = path.to_str().and_then(|p| {
+                    let buffer_id = *self.open_files.lock().get(p)?;
+                    let buffers = self.buffers.lock();
+                    Some(buffers.get(&buffer_id)?.rope.to_string())
+                });
+                let dispatcher = self.clone();
+                thread::spawn(move || {
+                    let resp = repository
+                        .and_then(|repository| {
+                            git::blame(&repository, &path, content.as_deref())
+                        })
+                        .map(|hunks| serde_json::to_value(hunks).unwrap());
+                    dispatcher.respond(id, resp);
+                });
+            }
+            GitRevisions { path } => {
+                let resp = self
+                    .repository(&path)
+                    .and_then(|repository| {
+                        git::revisions(&repository, RECENT_COMMITS)
+                    })
+                    .map(|revisions| serde_json::to_value(revisions).unwrap());
+                self.respond(id, resp);
+            }
+            GitLog {
+                repository,
+                path,
+                skip,
+                limit,
+            } => {
+                // A file's history is the one of the repository it's in.
+                let repository = match path.as_ref() {
+                    Some(path) => self.repository(path),
+                    None => Ok(repository),
+                };
+                let dispatcher = self.clone();
+                thread::spawn(move || {
+                    let resp = repository
+                        .and_then(|repository| {
+                            git::log(&repository, path.as_deref(), skip, limit)
+                        })
+                        .map(|commits| serde_json::to_value(commits).unwrap());
+                    dispatcher.respond(id, resp);
+                });
+            }
+            GitCommit {
+                repository,
+                message,
+                diffs,
+                amend,
+                sign_off,
+            } => {
+                let dispatcher = self.clone();
+                // Hooks can take a while, e.g. running a test suite.
+                thread::spawn(move || {
+                    let resp =
+                        git::commit(&repository, &message, diffs, amend, sign_off);
+                    dispatcher.refresh_diff_info();
+                    dispatcher.respond(id, resp.map(|_| json!({})));
+                });
+            }
+            GitStashSave {
+                repository,
+                message,
+                include_untracked,
+            } => {
+                let resp = git::stash_save(&repository, &message, include_untracked);
+                self.refresh_diff_info();
+                self.respond(id, resp.map(|_| json!({})));
+            }
+            GitStashes { repository } => {
+                let resp = git::stashes(&repository)
+                    .map(|stashes| serde_json::to_value(stashes).unwrap());
+                self.respond(id, resp);
+            }
+            GitStashApply { repository, index } => {
+                let resp = git::stash_apply(&repository, index);
+                self.refresh_diff_info();
+                self.respond(id, resp.map(|_| json!({})));
+            }
+            GitStashPop { repository, index } => {
+                let resp = git::stash_pop(&repository, index);
+                self.refresh_diff_info();
+                self.respond(id, resp.map(|_| json!({})));
+            }
+            GitStashDrop { repository, index } => {
+                let resp = git::stash_drop(&repository, index);
+                self.refresh_diff_info();
+                self.respond(id, resp.map(|_| json!({})));
+            }
+            GitFetch { repository } => {
+                self.git_remote(id, repository, "Git Fetch", git::fetch);
+            }
+            GitPull { repository, rebase } => {
+                self.git_remote(id, repository, "Git Pull", move |repo, handler| {
+                    git::pull(repo, rebase, handler)
+                });
+            }
+            GitPush { repository } => {
+                self.git_remote(id, repository, "Git Push", git::push);
+            }
+            GitCommitFiles { repository, commit } => {
+                let resp = git::commit_files(&repository, &commit)
+                    .map(|files| serde_json::to_value(files).unwrap());
+                self.respond(id, resp);
+            }
+            GitCommitFile {
+                commit,
+                path,
+                parent_path,
+            } => {
+                let resp = self
+                    .repository(&path)
+                    .and_then(|repository| {
+                        git::commit_file(&repository, &commit, &path, &parent_path)
+                    })
+                    .map(|(resp, content)| {
+                        self.snapshots.lock().insert(resp.snapshot.clone(), content);
+                        serde_json::to_value(resp).unwrap()
+                    });
+                self.respond(id, resp);
+            }
+            FileHistoryVersion { path, version } => {
+                let resp = match self.workspace.lock().as_ref() {
+                    Some(workspace) => history::get_version(
+                        workspace, &path, &version,
+                    )
+                    .map(|content| {
+                        serde_json::to_value(BufferHeadResponse {
+                            id: format!("local:{}", version),
+                            content,
+                        })
+                        .unwrap()
+                    }),
+                    None => Err(anyhow!("no workspace")),
+                };
+                self.respond(id, resp);
             }
             GetCompletion {
                 buffer_id,
@@ -516,6 +975,12 @@ impl Dispatcher {
                     local_dispatcher.respond(id, result);
                 });
             }
+            ReadFile { path } => {
+                let result = fs::read_to_string(path)
+                    .map(|content| json!(content))
+                    .map_err(|e| anyhow!(e));
+                self.respond(id, result);
+            }
             #[allow(unused_variables)]
             GetFiles { path } => {
                 if let Some(workspace) = self.workspace.lock().clone() {
@@ -534,55 +999,28 @@ impl Dispatcher {
                     });
                 }
             }
-            Save { rev, buffer_id } => {
+            Save {
+                rev,
+                buffer_id,
+                only_if_exists,
+            } => {
                 let mut buffers = self.buffers.lock();
                 let buffer = buffers.get_mut(&buffer_id).unwrap();
-                let resp = buffer.save(rev).map(|_r| json!({}));
-                self.lsp.lock().save_buffer(buffer);
-                self.respond(id, resp);
-            }
-            GlobalSearch { pattern } => {
-                if let Some(workspace) = self.workspace.lock().clone() {
-                    let local_dispatcher = self.clone();
-                    thread::spawn(move || {
-                        let mut matches = HashMap::new();
-                        let pattern = regex::escape(&pattern);
-                        if let Ok(matcher) = RegexMatcherBuilder::new()
-                            .case_insensitive(true)
-                            .build_literals(&[&pattern])
-                        {
-                            let mut searcher = SearcherBuilder::new().build();
-                            for path in ignore::Walk::new(workspace).flatten() {
-                                if let Some(file_type) = path.file_type() {
-                                    if file_type.is_file() {
-                                        let path = path.into_path();
-                                        let mut line_matches = Vec::new();
-                                        let _ = searcher.search_path(
-                                            &matcher,
-                                            path.clone(),
-                                            UTF8(|lnum, line| {
-                                                let mymatch = matcher
-                                                    .find(line.as_bytes())?
-                                                    .unwrap();
-                                                line_matches.push((
-                                                    lnum,
-                                                    (mymatch.start(), mymatch.end()),
-                                                    line.to_string(),
-                                                ));
-                                                Ok(true)
-                                            }),
-                                        );
-                                        if !line_matches.is_empty() {
-                                            matches
-                                                .insert(path.clone(), line_matches);
-                                        }
-                                    }
-                                }
-                            }
+                if only_if_exists && !buffer.path.exists() {
+                    self.respond(id, Err(anyhow!("file was deleted")));
+                } else {
+                    let resp = buffer.save(rev).map(|_r| json!({}));
+                    if resp.is_ok() && !buffer.large_file {
+                        if let Some(workspace) = self.workspace.lock().as_ref() {
+                            let _ = history::record_version(
+                                workspace,
+                                &buffer.path,
+                                &buffer.rope,
+                            );
                         }
-                        local_dispatcher
-                            .respond(id, Ok(serde_json::to_value(matches).unwrap()));
-                    });
+                    }
+                    self.lsp.lock().save_buffer(buffer);
+                    self.respond(id, resp);
                 }
             }
         }
@@ -598,47 +1036,6 @@ pub struct DiffHunk {
     pub header: String,
 }
 
-fn git_commit(
-    workspace_path: &Path,
-    message: &str,
-    diffs: Vec<FileDiff>,
-) -> Result<()> {
-    let repo = Repository::open(
-        workspace_path
-            .to_str()
-            .ok_or_else(|| anyhow!("workspace path can't changed to str"))?,
-    )?;
-    let mut index = repo.index()?;
-    for diff in diffs {
-        match diff {
-            FileDiff::Modified(p) | FileDiff::Added(p) => {
-                index.add_path(p.strip_prefix(workspace_path)?)?;
-            }
-            FileDiff::Renamed(a, d) => {
-                index.add_path(a.strip_prefix(workspace_path)?)?;
-                index.remove_path(d.strip_prefix(workspace_path)?)?;
-            }
-            FileDiff::Deleted(p) => {
-                index.remove_path(p.strip_prefix(workspace_path)?)?;
-            }
-        }
-    }
-    index.write()?;
-    let tree = index.write_tree()?;
-    let tree = repo.find_tree(tree)?;
-    let signature = repo.signature()?;
-    let parent = repo.head()?.peel_to_commit()?;
-    repo.commit(
-        Some("HEAD"),
-        &signature,
-        &signature,
-        message,
-        &tree,
-        &[&parent],
-    )?;
-    Ok(())
-}
-
 fn git_delta_format(
     workspace_path: &Path,
     delta: &git2::DiffDelta,
@@ -664,39 +1061,178 @@ fn git_delta_format(
 }
 
 fn git_diff_new(workspace_path: &Path) -> Option<DiffInfo> {
-    let repo = Repository::open(workspace_path.to_str()?).ok()?;
-    let head = repo.head().ok()?;
-    let name = head.shorthand()?.to_string();
+    let mut repo = Repository::open(workspace_path.to_str()?).ok()?;
+    let name = match repo.head() {
+        Ok(head) => head.shorthand()?.to_string(),
+        // Nothing was committed yet, HEAD names the branch the first commit
+        // creates.
+        Err(e) if e.code() == ErrorCode::UnbornBranch => repo
+            .find_reference("HEAD")
+            .ok()?
+            .symbolic_target()?
+            .trim_start_matches("refs/heads/")
+            .to_string(),
+        Err(_) => return None,
+    };
 
     let mut branches = Vec::new();
     for branch in repo.branches(None).ok()? {
         branches.push(branch.ok()?.0.name().ok()??.to_string());
     }
 
+    // Conflicted files are listed on their own rather than as changes.
+    let mut conflicts = Vec::new();
+    let index = repo.index().ok()?;
+    if index.has_conflicts() {
+        for conflict in index.conflicts().ok()? {
+            let conflict = conflict.ok()?;
+            let entry = conflict
+                .our
+                .as_ref()
+                .or(conflict.their.as_ref())
+                .or(conflict.ancestor.as_ref())?;
+            let path = workspace_path.join(std::str::from_utf8(&entry.path).ok()?);
+            conflicts.push(match conflict.ancestor {
+                Some(_) => FileDiff::Modified(path),
+                None => FileDiff::Added(path),
+            });
+        }
+    }
+    let conflict_paths: HashSet<&PathBuf> =
+        conflicts.iter().map(|diff| diff.path()).collect();
+
+    // The worktree diff lists the untracked and ignored files as well, so
+    // the worktree is only walked once.
     let mut deltas = Vec::new();
+    let mut untracked = Vec::new();
+    let mut ignored = Vec::new();
     let mut diff_options = DiffOptions::new();
     let diff = repo
-        .diff_index_to_workdir(None, Some(diff_options.include_untracked(true)))
+        .diff_index_to_workdir(
+            None,
+            Some(
+                diff_options
+                    .include_untracked(true)
+                    .recurse_untracked_dirs(true)
+                    .include_ignored(true)
+                    .recurse_ignored_dirs(false),
+            ),
+        )
         .ok()?;
     for delta in diff.deltas() {
+        if delta.status() == git2::Delta::Ignored {
+            if let Some(path) = delta.new_file().path() {
+                ignored.push(workspace_path.join(path));
+            }
+            continue;
+        }
+        let is_untracked = delta.status() == git2::Delta::Untracked;
         if let Some(delta) = git_delta_format(workspace_path, &delta) {
+            if conflict_paths.contains(&delta.2) {
+                continue;
+            }
+            if is_untracked {
+                // An untracked folder is a nested repository, listed on its
+                // own.
+                if delta.2.is_dir() {
+                    continue;
+                }
+                untracked.push(delta.2.clone());
+            }
             deltas.push(delta);
         }
     }
+    let mut staged_deltas = Vec::new();
+    let head_tree = repo
+        .revparse_single("HEAD^{tree}")
+        .ok()
+        .and_then(|tree| repo.find_tree(tree.id()).ok());
     let cached_diff = repo
-        .diff_tree_to_index(
-            repo.find_tree(repo.revparse_single("HEAD^{tree}").ok()?.id())
-                .ok()
-                .as_ref(),
-            None,
-            None,
-        )
+        .diff_tree_to_index(head_tree.as_ref(), None, None)
         .ok()?;
     for delta in cached_diff.deltas() {
         if let Some(delta) = git_delta_format(workspace_path, &delta) {
-            deltas.push(delta);
+            if !conflict_paths.contains(&delta.2) {
+                staged_deltas.push(delta);
+            }
         }
     }
+    let (ahead, behind) = git::ahead_behind(&repo).unwrap_or((0, 0));
+    let mut stashes = 0;
+    repo.stash_foreach(|_, _, _| {
+        stashes += 1;
+        true
+    })
+    .ok()?;
+    Some(DiffInfo {
+        path: workspace_path.to_path_buf(),
+        head: name,
+        branches,
+        diffs: git_file_diffs(deltas),
+        staged: git_file_diffs(staged_deltas),
+        conflicts,
+        untracked,
+        ignored,
+        stashes,
+        ahead,
+        behind,
+    })
+}
+
+/// Reports the progress of a fetch, pull or push to the core, and asks it
+/// for credentials.
+struct GitRemoteHandler {
+    dispatcher: Dispatcher,
+    token: NumberOrString,
+}
+
+impl GitRemoteHandler {
+    fn send(&self, progress: WorkDoneProgress) {
+        self.dispatcher.send_notification(
+            "work_done_progress",
+            json!({
+                "progress": ProgressParams {
+                    token: self.token.clone(),
+                    value: ProgressParamsValue::WorkDone(progress),
+                },
+            }),
+        );
+    }
+}
+
+impl git::RemoteHandler for GitRemoteHandler {
+    fn progress(&self, message: String, percentage: u32) {
+        self.send(WorkDoneProgress::Report(WorkDoneProgressReport {
+            cancellable: None,
+            message: Some(message),
+            percentage: Some(percentage),
+        }));
+    }
+
+    fn credentials(
+        &self,
+        url: &str,
+        username: Option<&str>,
+        ssh_key: Option<&Path>,
+    ) -> Option<GitCredentials> {
+        let resp = self
+            .dispatcher
+            .send_request(
+                "git_credentials",
+                json!({
+                    "url": url,
+                    "username": username,
+                    "ssh_key": ssh_key,
+                }),
+            )
+            .ok()?;
+        serde_json::from_value(resp).ok()?
+    }
+}
+
+/// Turns deltas into file diffs, pairing deletes and adds of the same
+/// content as renames.
+fn git_file_diffs(deltas: Vec<(git2::Delta, git2::Oid, PathBuf)>) -> Vec<FileDiff> {
     let mut renames = Vec::new();
     let mut renamed_deltas = HashSet::new();
 
@@ -738,11 +1274,7 @@ fn git_diff_new(workspace_path: &Path) -> Option<DiffInfo> {
         | FileDiff::Renamed(p, _)
         | FileDiff::Deleted(p) => p.clone(),
     });
-    Some(DiffInfo {
-        head: name,
-        branches,
-        diffs: file_diffs,
-    })
+    file_diffs
 }
 
 fn file_get_head(workspace_path: &Path, path: &Path) -> Result<(String, String)> {
@@ -0,0 +1,1810 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, Result};
+use directories::BaseDirs;
+use git2::build::CheckoutBuilder;
+use git2::{
+    Branch, BranchType, Commit, Cred, CredentialType, Delta, DiffOptions, ErrorCode,
+    FetchOptions, IndexEntry, IndexTime, Oid, Patch, PushOptions, RemoteCallbacks,
+    Repository, Sort, StashFlags, StatusOptions,
+};
+use lapce_rpc::source_control::{
+    BlameHunk, FileDiff, GitBranch, GitCommit, GitCommitFileResponse,
+    GitCredentials, GitHunk, GitRevision, GitRevisionKind, GitStash,
+};
+
+/// The worktrees of the repositories in the workspace: the workspace itself
+/// when it's one, its submodules and any repository nested in it. The other
+/// functions here take the worktree of the repository they work on.
+pub fn repositories(workspace_path: &Path) -> Vec<PathBuf> {
+    let mut repositories = Vec::new();
+    let walk = ignore::WalkBuilder::new(workspace_path)
+        .hidden(false)
+        .filter_entry(|entry| entry.file_name() != ".git")
+        .build();
+    for entry in walk.flatten() {
+        let is_dir = entry.file_type().map(|t| t.is_dir()).unwrap_or(false);
+        // Submodules have a `.git` file pointing at their git dir.
+        if is_dir
+            && entry.path().join(".git").exists()
+            && Repository::open(entry.path()).is_ok()
+        {
+            repositories.push(entry.path().to_path_buf());
+        }
+    }
+    repositories
+}
+
+/// All local and remote branches of the repository.
+pub fn branches(workspace_path: &Path) -> Result<Vec<GitBranch>> {
+    let repo = Repository::open(workspace_path)?;
+    let mut branches = Vec::new();
+    for branch in repo.branches(None)? {
+        let (branch, kind) = branch?;
+        if let Some(name) = branch.name()? {
+            // `origin/HEAD` only points at another remote branch.
+            if kind == BranchType::Remote && name.ends_with("/HEAD") {
+                continue;
+            }
+            branches.push(GitBranch {
+                name: name.to_string(),
+                remote: kind == BranchType::Remote,
+                head: branch.is_head(),
+            });
+        }
+    }
+    branches.sort_by(|a, b| a.remote.cmp(&b.remote).then(a.name.cmp(&b.name)));
+    Ok(branches)
+}
+
+/// Whether tracked files have changes, staged or not, that a checkout
+/// could overwrite.
+fn is_dirty(repo: &Repository) -> Result<bool> {
+    let mut options = StatusOptions::new();
+    options.include_untracked(false).include_ignored(false);
+    Ok(!repo.statuses(Some(&mut options))?.is_empty())
+}
+
+/// Switches to the branch `name`. A remote branch is checked out as a new
+/// local branch tracking it, or as the local branch of the same name when
+/// there is one, fast-forwarded when it's behind. Refuses to switch when the worktree is dirty,
+/// unless `force` is set, in which case local changes are thrown away.
+pub fn checkout(workspace_path: &Path, name: &str, force: bool) -> Result<()> {
+    let repo = Repository::open(workspace_path)?;
+    if !force && is_dirty(&repo)? {
+        return Err(anyhow!(
+            "the worktree has uncommitted changes, commit them or force the checkout"
+        ));
+    }
+
+    let branch = match repo.find_branch(name, BranchType::Local) {
+        Ok(branch) => branch,
+        Err(_) => {
+            let remote = repo.find_branch(name, BranchType::Remote)?;
+            let commit = remote.get().peel_to_commit()?;
+            let local_name = name.split_once('/').map(|(_, n)| n).unwrap_or(name);
+            match repo.find_branch(local_name, BranchType::Local) {
+                Ok(branch) => {
+                    let local = branch.get().peel_to_commit()?.id();
+                    if local != commit.id()
+                        && repo.graph_descendant_of(commit.id(), local)?
+                    {
+                        let reference = branch.into_reference().set_target(
+                            commit.id(),
+                            &format!("checkout: fast-forward to {name}"),
+                        )?;
+                        Branch::wrap(reference)
+                    } else {
+                        branch
+                    }
+                }
+                Err(_) => {
+                    let mut branch = repo.branch(local_name, &commit, false)?;
+                    branch.set_upstream(Some(name))?;
+                    branch
+                }
+            }
+        }
+    };
+    let reference = branch.into_reference();
+    let refname = reference
+        .name()
+        .ok_or_else(|| anyhow!("branch name is not utf-8"))?
+        .to_string();
+    let commit = reference.peel_to_commit()?;
+
+    let mut checkout = CheckoutBuilder::new();
+    if force {
+        checkout.force();
+    } else {
+        checkout.safe();
+    }
+    repo.checkout_tree(commit.as_object(), Some(&mut checkout))?;
+    repo.set_head(&refname)?;
+    Ok(())
+}
+
+/// Creates the local branch `name` at `start_point`, which can be any
+/// revision git understands, or HEAD when there's none.
+pub fn create_branch(
+    workspace_path: &Path,
+    name: &str,
+    start_point: Option<&str>,
+) -> Result<()> {
+    let repo = Repository::open(workspace_path)?;
+    let commit = repo
+        .revparse_single(start_point.unwrap_or("HEAD"))?
+        .peel_to_commit()?;
+    repo.branch(name, &commit, false)?;
+    Ok(())
+}
+
+/// Deletes the local branch `name`, which can't be the current branch.
+pub fn delete_branch(workspace_path: &Path, name: &str) -> Result<()> {
+    let repo = Repository::open(workspace_path)?;
+    let mut branch = repo.find_branch(name, BranchType::Local)?;
+    if branch.is_head() {
+        return Err(anyhow!("can't delete the current branch {name}"));
+    }
+    branch.delete()?;
+    Ok(())
+}
+
+/// Adds the files to the index, or removes them from it when they were
+/// deleted in the worktree.
+pub fn stage(workspace_path: &Path, paths: &[PathBuf]) -> Result<()> {
+    let repo = Repository::open(workspace_path)?;
+    let mut index = repo.index()?;
+    for path in paths {
+        let relative = path.strip_prefix(workspace_path)?;
+        if path.exists() {
+            index.add_path(relative)?;
+        } else {
+            index.remove_path(relative)?;
+        }
+    }
+    index.write()?;
+    Ok(())
+}
+
+/// Marks the conflicts of the file as resolved by staging it, which is
+/// refused while it still has conflict markers.
+pub fn resolve(workspace_path: &Path, path: &Path) -> Result<()> {
+    if path.exists() {
+        let content = fs::read_to_string(path)?;
+        if content
+            .lines()
+            .any(|line| line.starts_with("<<<<<<<") || line.starts_with(">>>>>>>"))
+        {
+            return Err(anyhow!("the file still has conflict markers"));
+        }
+    }
+    stage(workspace_path, &[path.to_path_buf()])
+}
+
+/// Resets the index entries of the files to HEAD, keeping the worktree.
+pub fn unstage(workspace_path: &Path, paths: &[PathBuf]) -> Result<()> {
+    let repo = Repository::open(workspace_path)?;
+    let relative = paths
+        .iter()
+        .map(|path| path.strip_prefix(workspace_path))
+        .collect::<Result<Vec<&Path>, _>>()?;
+    match repo.head().and_then(|head| head.peel_to_commit()) {
+        Ok(commit) => repo.reset_default(Some(commit.as_object()), relative)?,
+        // Nothing was committed yet, so unstaging empties the entries.
+        Err(_) => {
+            let mut index = repo.index()?;
+            for path in relative {
+                index.remove_path(path)?;
+            }
+            index.write()?;
+        }
+    }
+    Ok(())
+}
+
+/// Throws away the unstaged changes of the files: tracked files are checked
+/// out from the index and untracked files are deleted.
+pub fn discard(workspace_path: &Path, paths: &[PathBuf]) -> Result<()> {
+    let repo = Repository::open(workspace_path)?;
+    let mut index = repo.index()?;
+    let mut checkout = CheckoutBuilder::new();
+    checkout.force();
+    let mut tracked = false;
+    for path in paths {
+        let relative = path.strip_prefix(workspace_path)?;
+        if index.get_path(relative, 0).is_some() {
+            checkout.path(relative);
+            tracked = true;
+        } else if path.is_file() {
+            fs::remove_file(path)?;
+        }
+    }
+    if tracked {
+        repo.checkout_index(Some(&mut index), Some(&mut checkout))?;
+    }
+    Ok(())
+}
+
+/// Stages a single change of the file against HEAD, leaving the rest of the
+/// file's changes, staged or not, as they are.
+pub fn stage_hunk(workspace_path: &Path, path: &Path, hunk: &GitHunk) -> Result<()> {
+    let repo = Repository::open(workspace_path)?;
+    let relative = path.strip_prefix(workspace_path)?;
+    // A new file, or one in a repository without commits, changes an empty
+    // one.
+    let head = match repo.head() {
+        Ok(head) => match head.peel_to_tree()?.get_path(relative) {
+            Ok(entry) => blob_content(&repo, entry.id())?,
+            Err(e) if e.code() == ErrorCode::NotFound => String::new(),
+            Err(e) => return Err(e.into()),
+        },
+        Err(e) if e.code() == ErrorCode::UnbornBranch => String::new(),
+        Err(e) => return Err(e.into()),
+    };
+    let mut index = repo.index()?;
+    let (mut entry, staged) = match index.get_path(relative, 0) {
+        Some(entry) => {
+            let staged = blob_content(&repo, entry.id)?;
+            (entry, staged)
+        }
+        None => (new_index_entry(relative)?, String::new()),
+    };
+
+    let head_lines: Vec<&str> = head.split_inclusive('\n').collect();
+    let staged_lines: Vec<&str> = staged.split_inclusive('\n').collect();
+    let old_end = hunk.old_start + hunk.old_lines;
+    let old = head_lines
+        .get(hunk.old_start..old_end)
+        .ok_or_else(|| anyhow!("the change is out of date"))?;
+    let start = map_line(&head, &staged, hunk.old_start, hunk.old_lines)?;
+    if staged_lines.get(start..start + hunk.old_lines) != Some(old) {
+        return Err(anyhow!(
+            "the change overlaps changes that are already staged"
+        ));
+    }
+
+    let content = [
+        staged_lines[..start].concat(),
+        hunk.new_content.clone(),
+        staged_lines[start + hunk.old_lines..].concat(),
+    ]
+    .concat();
+    entry.file_size = content.len() as u32;
+    index.add_frombuffer(&entry, content.as_bytes())?;
+    index.write()?;
+    Ok(())
+}
+
+/// The index entry of a regular file that isn't in the index yet, whose
+/// content is added with it.
+fn new_index_entry(relative: &Path) -> Result<IndexEntry> {
+    let path = relative
+        .to_str()
+        .ok_or_else(|| anyhow!("the path is not utf-8"))?
+        .replace('\\', "/")
+        .into_bytes();
+    Ok(IndexEntry {
+        ctime: IndexTime::new(0, 0),
+        mtime: IndexTime::new(0, 0),
+        dev: 0,
+        ino: 0,
+        mode: 0o100644,
+        uid: 0,
+        gid: 0,
+        file_size: 0,
+        id: Oid::zero(),
+        flags: path.len().min(0xfff) as u16,
+        flags_extended: 0,
+        path,
+    })
+}
+
+/// Commits the index after staging `diffs`, running the `pre-commit` and
+/// `commit-msg` hooks like git does. Amending replaces HEAD's commit, keeping
+/// its message when `message` is empty, and a repository without commits
+/// gets its first one.
+pub fn commit(
+    workspace_path: &Path,
+    message: &str,
+    diffs: Vec<FileDiff>,
+    amend: bool,
+    sign_off: bool,
+) -> Result<()> {
+    let repo = Repository::open(workspace_path)?;
+    let mut index = repo.index()?;
+    for diff in diffs {
+        match diff {
+            FileDiff::Modified(p) | FileDiff::Added(p) => {
+                index.add_path(p.strip_prefix(workspace_path)?)?;
+            }
+            FileDiff::Renamed(a, d) => {
+                index.add_path(a.strip_prefix(workspace_path)?)?;
+                index.remove_path(d.strip_prefix(workspace_path)?)?;
+            }
+            FileDiff::Deleted(p) => {
+                index.remove_path(p.strip_prefix(workspace_path)?)?;
+            }
+        }
+    }
+    index.write()?;
+
+    let head = match repo.head() {
+        Ok(head) => Some(head.peel_to_commit()?),
+        Err(e) if e.code() == ErrorCode::UnbornBranch => None,
+        Err(e) => return Err(e.into()),
+    };
+    if amend && head.is_none() {
+        return Err(anyhow!("there is no commit to amend"));
+    }
+
+    run_hook(&repo, "pre-commit", &[])?;
+
+    let signature = repo.signature()?;
+    let mut message = match (&head, message.is_empty() && amend) {
+        (Some(head), true) => head.message().unwrap_or("").trim_end().to_string(),
+        _ => message.to_string(),
+    };
+    if sign_off {
+        let trailer = format!(
+            "Signed-off-by: {} <{}>",
+            signature.name().unwrap_or(""),
+            signature.email().unwrap_or("")
+        );
+        if !message.lines().any(|line| line == trailer) {
+            message = format!("{message}\n\n{trailer}");
+        }
+    }
+
+    // The hook may rewrite the message, which it gets as a file.
+    let message_path = repo.path().join("COMMIT_EDITMSG");
+    fs::write(&message_path, format!("{message}\n"))?;
+    run_hook(&repo, "commit-msg", &[&message_path])?;
+    let message = fs::read_to_string(&message_path)?;
+
+    // The pre-commit hook may have changed what is staged.
+    index.read(true)?;
+    let tree = repo.find_tree(index.write_tree()?)?;
+    match head {
+        Some(head) if amend => {
+            head.amend(
+                Some("HEAD"),
+                None,
+                Some(&signature),
+                None,
+                Some(&message),
+                Some(&tree),
+            )?;
+        }
+        Some(head) => {
+            repo.commit(
+                Some("HEAD"),
+                &signature,
+                &signature,
+                &message,
+                &tree,
+                &[&head],
+            )?;
+        }
+        None => {
+            repo.commit(Some("HEAD"), &signature, &signature, &message, &tree, &[])?;
+        }
+    }
+    Ok(())
+}
+
+/// Runs the repository's hook of that name if it has one, failing with the
+/// hook's output when it exits with an error.
+fn run_hook(repo: &Repository, name: &str, args: &[&Path]) -> Result<()> {
+    let workdir = repo.workdir().unwrap_or_else(|| repo.path());
+    let hooks = match repo.config()?.get_path("core.hooksPath") {
+        Ok(path) => workdir.join(path),
+        Err(_) => repo.path().join("hooks"),
+    };
+    let hook = hooks.join(name);
+    if !is_executable(&hook) {
+        return Ok(());
+    }
+
+    // Hooks are shell scripts, which Windows can't run by itself.
+    #[cfg(windows)]
+    let mut command = {
+        let mut command = Command::new("sh");
+        command.arg(&hook);
+        command
+    };
+    #[cfg(not(windows))]
+    let mut command = Command::new(&hook);
+    let output = command.args(args).current_dir(workdir).output()?;
+    if !output.status.success() {
+        let output = format!(
+            "{}{}",
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr)
+        );
+        return Err(anyhow!("the {name} hook failed\n{}", output.trim_end()));
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    fs::metadata(path)
+        .map(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+/// Stashes the changes of the worktree and the index, and those of untracked
+/// files if asked to. Without a message the stash is named after HEAD's
+/// commit, like git does.
+pub fn stash_save(
+    workspace_path: &Path,
+    message: &str,
+    include_untracked: bool,
+) -> Result<()> {
+    let mut repo = Repository::open(workspace_path)?;
+    let signature = repo.signature()?;
+    let message = if message.is_empty() {
+        let head = repo.head()?.peel_to_commit()?;
+        let id = head.id().to_string();
+        format!("{} {}", &id[..7], head.summary().unwrap_or(""))
+    } else {
+        message.to_string()
+    };
+    let mut flags = StashFlags::DEFAULT;
+    if include_untracked {
+        flags |= StashFlags::INCLUDE_UNTRACKED;
+    }
+    repo.stash_save(&signature, &message, Some(flags))?;
+    Ok(())
+}
+
+/// The stashes, latest first.
+pub fn stashes(workspace_path: &Path) -> Result<Vec<GitStash>> {
+    let mut repo = Repository::open(workspace_path)?;
+    let mut stashes = Vec::new();
+    repo.stash_foreach(|index, message, id| {
+        stashes.push(GitStash {
+            index,
+            id: id.to_string(),
+            message: message.to_string(),
+        });
+        true
+    })?;
+    Ok(stashes)
+}
+
+pub fn stash_apply(workspace_path: &Path, index: usize) -> Result<()> {
+    let mut repo = Repository::open(workspace_path)?;
+    repo.stash_apply(index, None)?;
+    Ok(())
+}
+
+pub fn stash_pop(workspace_path: &Path, index: usize) -> Result<()> {
+    let mut repo = Repository::open(workspace_path)?;
+    repo.stash_pop(index, None)?;
+    Ok(())
+}
+
+pub fn stash_drop(workspace_path: &Path, index: usize) -> Result<()> {
+    let mut repo = Repository::open(workspace_path)?;
+    repo.stash_drop(index)?;
+    Ok(())
+}
+
+/// How many times the user is asked for credentials before a fetch or push
+/// gives up.
+const MAX_CREDENTIAL_PROMPTS: usize = 3;
+
+/// Where a fetch or push reports its progress to and gets credentials from.
+pub trait RemoteHandler {
+    fn progress(&self, message: String, percentage: u32);
+
+    /// Asks the user for credentials to the remote at `url`, or only for the
+    /// passphrase of `ssh_key`. None when the user cancelled.
+    fn credentials(
+        &self,
+        url: &str,
+        username: Option<&str>,
+        ssh_key: Option<&Path>,
+    ) -> Option<GitCredentials>;
+}
+
+/// Fetches the remote the current branch tracks, or `origin`.
+pub fn fetch(workspace_path: &Path, handler: &dyn RemoteHandler) -> Result<()> {
+    let repo = Repository::open(workspace_path)?;
+    fetch_remote(&repo, handler)
+}
+
+fn fetch_remote(repo: &Repository, handler: &dyn RemoteHandler) -> Result<()> {
+    let remote_name = match head_branch(repo) {
+        Ok(branch) => branch_remote(repo, &branch)?,
+        Err(_) => "origin".to_string(),
+    };
+    let mut remote = repo.find_remote(&remote_name)?;
+    let mut options = FetchOptions::new();
+    options.remote_callbacks(remote_callbacks(repo, handler));
+    remote.fetch(&[] as &[&str], Some(&mut options), None)?;
+    Ok(())
+}
+
+/// Fetches and brings the current branch up to date with its upstream by
+/// fast-forwarding it. When the branches have diverged the local commits are
+/// rebased onto the upstream if `rebase` is set; a rebase that conflicts is
+/// aborted, leaving the branch as it was.
+pub fn pull(
+    workspace_path: &Path,
+    rebase: bool,
+    handler: &dyn RemoteHandler,
+) -> Result<()> {
+    let repo = Repository::open(workspace_path)?;
+    let branch_name = head_branch(&repo)?;
+    fetch_remote(&repo, handler)?;
+
+    let branch = repo.find_branch(&branch_name, BranchType::Local)?;
+    let upstream = branch
+        .upstream()
+        .map_err(|_| anyhow!("{branch_name} has no upstream branch to pull from"))?;
+    let upstream = repo.reference_to_annotated_commit(upstream.get())?;
+    let (analysis, _) = repo.merge_analysis(&[&upstream])?;
+    if analysis.is_up_to_date() {
+        return Ok(());
+    }
+    if analysis.is_fast_forward() {
+        let commit = repo.find_commit(upstream.id())?;
+        let mut checkout = CheckoutBuilder::new();
+        checkout.safe();
+        repo.checkout_tree(commit.as_object(), Some(&mut checkout))?;
+        branch
+            .into_reference()
+            .set_target(upstream.id(), "pull: Fast-forward")?;
+        return Ok(());
+    }
+
+    if !rebase {
+        return Err(anyhow!(
+            "{branch_name} and its upstream have diverged, pull with rebase instead"
+        ));
+    }
+    if is_dirty(&repo)? {
+        return Err(anyhow!(
+            "the worktree has uncommitted changes, commit or stash them before rebasing"
+        ));
+    }
+    let signature = repo.signature()?;
+    let mut rebase = repo.rebase(None, Some(&upstream), None, None)?;
+    while let Some(operation) = rebase.next() {
+        if let Err(e) = operation {
+            let _ = rebase.abort();
+            return Err(e.into());
+        }
+        if repo.index()?.has_conflicts() {
+            rebase.abort()?;
+            return Err(anyhow!(
+                "the rebase conflicts with the upstream, pull in a terminal to resolve the conflicts"
+            ));
+        }
+        match rebase.commit(None, &signature, None) {
+            Ok(_) => {}
+            // The upstream already has the commit's changes.
+            Err(e) if e.code() == ErrorCode::Applied => {}
+            Err(e) => {
+                let _ = rebase.abort();
+                return Err(e.into());
+            }
+        }
+    }
+    rebase.finish(Some(&signature))?;
+    Ok(())
+}
+
+/// Pushes the current branch to the branch it tracks. A branch that tracks
+/// none is pushed to a branch of the same name on `origin`, which it then
+/// tracks.
+pub fn push(workspace_path: &Path, handler: &dyn RemoteHandler) -> Result<()> {
+    let repo = Repository::open(workspace_path)?;
+    let branch_name = head_branch(&repo)?;
+    let refname = format!("refs/heads/{branch_name}");
+    let remote_name = branch_remote(&repo, &branch_name)?;
+    let remote_refname = repo
+        .config()?
+        .snapshot()?
+        .get_string(&format!("branch.{branch_name}.merge"))
+        .unwrap_or_else(|_| refname.clone());
+
+    let mut remote = repo.find_remote(&remote_name)?;
+    let mut callbacks = remote_callbacks(&repo, handler);
+    callbacks.push_update_reference(|refname, status| match status {
+        Some(status) => Err(git2::Error::from_str(&format!(
+            "the remote rejected {refname}: {status}"
+        ))),
+        None => Ok(()),
+    });
+    let mut options = PushOptions::new();
+    options.remote_callbacks(callbacks);
+    remote.push(&[format!("{refname}:{remote_refname}")], Some(&mut options))?;
+
+    let mut branch = repo.find_branch(&branch_name, BranchType::Local)?;
+    if branch.upstream().is_err() {
+        branch.set_upstream(Some(&format!("{remote_name}/{branch_name}")))?;
+    }
+    Ok(())
+}
+
+/// The name of the branch HEAD points at.
+fn head_branch(repo: &Repository) -> Result<String> {
+    let head = repo.head()?;
+    if !head.is_branch() {
+        return Err(anyhow!("HEAD is detached, check out a branch first"));
+    }
+    Ok(head
+        .shorthand()
+        .ok_or_else(|| anyhow!("branch name is not utf-8"))?
+        .to_string())
+}
+
+/// The remote the local branch tracks, or `origin` when it tracks none.
+fn branch_remote(repo: &Repository, branch: &str) -> Result<String> {
+    match repo.branch_upstream_remote(&format!("refs/heads/{branch}")) {
+        Ok(remote) => Ok(remote
+            .as_str()
+            .ok_or_else(|| anyhow!("remote name is not utf-8"))?
+            .to_string()),
+        Err(_) => Ok("origin".to_string()),
+    }
+}
+
+/// Callbacks reporting the transfer progress to the handler. Credentials
+/// come from the ssh agent or the credential helper first, and from the
+/// user when those don't work.
+fn remote_callbacks<'a>(
+    repo: &'a Repository,
+    handler: &'a dyn RemoteHandler,
+) -> RemoteCallbacks<'a> {
+    let mut callbacks = RemoteCallbacks::new();
+
+    let mut tried_defaults = false;
+    let mut prompts = 0;
+    callbacks.credentials(move |url, username, allowed| {
+        if allowed.contains(CredentialType::USERNAME) {
+            return Cred::username(username.unwrap_or("git"));
+        }
+        if !tried_defaults {
+            tried_defaults = true;
+            if allowed.contains(CredentialType::SSH_KEY) {
+                if let Ok(cred) = Cred::ssh_key_from_agent(username.unwrap_or("git"))
+                {
+                    return Ok(cred);
+                }
+            }
+            if allowed.contains(CredentialType::USER_PASS_PLAINTEXT) {
+                if let Ok(cred) = repo.config().and_then(|config| {
+                    Cred::credential_helper(&config, url, username)
+                }) {
+                    return Ok(cred);
+                }
+            }
+            if allowed.contains(CredentialType::DEFAULT) {
+                return Cred::default();
+            }
+        }
+
+        prompts += 1;
+        if prompts > MAX_CREDENTIAL_PROMPTS {
+            return Err(git2::Error::from_str("authentication failed"));
+        }
+        let cancelled = || git2::Error::from_str("authentication cancelled");
+        if allowed.contains(CredentialType::USER_PASS_PLAINTEXT) {
+            let credentials = handler
+                .credentials(url, username, None)
+                .ok_or_else(cancelled)?;
+            Cred::userpass_plaintext(&credentials.username, &credentials.password)
+        } else if allowed.contains(CredentialType::SSH_KEY) {
+            let key = default_ssh_key().ok_or_else(|| {
+                git2::Error::from_str("no ssh key found in ~/.ssh")
+            })?;
+            let credentials = handler
+                .credentials(url, username, Some(&key))
+                .ok_or_else(cancelled)?;
+            Cred::ssh_key(
+                &credentials.username,
+                None,
+                &key,
+                Some(&credentials.password),
+            )
+        } else {
+            Err(git2::Error::from_str(
+                "the remote asks for an unsupported kind of credentials",
+            ))
+        }
+    });
+
+    let mut received = None;
+    callbacks.transfer_progress(move |progress| {
+        let total = progress.total_objects();
+        if total > 0 {
+            let percentage = (progress.received_objects() * 100 / total) as u32;
+            if received != Some(percentage) {
+                received = Some(percentage);
+                handler.progress(
+                    format!("{}/{} objects", progress.received_objects(), total),
+                    percentage,
+                );
+            }
+        }
+        true
+    });
+    let mut sent = None;
+    callbacks.push_transfer_progress(move |current, total, _| {
+        if total > 0 {
+            let percentage = (current * 100 / total) as u32;
+            if sent != Some(percentage) {
+                sent = Some(percentage);
+                handler.progress(format!("{current}/{total} objects"), percentage);
+            }
+        }
+    });
+    callbacks
+}
+
+/// The user's default ssh private key.
+fn default_ssh_key() -> Option<PathBuf> {
+    let ssh = BaseDirs::new()?.home_dir().join(".ssh");
+    ["id_ed25519", "id_ecdsa", "id_rsa"]
+        .iter()
+        .map(|name| ssh.join(name))
+        .find(|path| path.exists())
+}
+
+/// How many commits HEAD and its upstream branch each have that the other
+/// doesn't, none when HEAD isn't a branch tracking another.
+pub fn ahead_behind(repo: &Repository) -> Option<(usize, usize)> {
+    let head = repo.head().ok()?;
+    let local = head.target()?;
+    if !head.is_branch() {
+        return None;
+    }
+    let upstream = Branch::wrap(head).upstream().ok()?.get().target()?;
+    repo.graph_ahead_behind(local, upstream).ok()
+}
+
+/// Who last changed each line of the file. `content` is the file as the
+/// editor has it, whose lines are mapped onto the blame of HEAD so that
+/// unsaved edits show up as uncommitted lines instead of throwing the rest
+/// of the blame off.
+pub fn blame(
+    workspace_path: &Path,
+    path: &Path,
+    content: Option<&str>,
+) -> Result<Vec<BlameHunk>> {
+    let repo = Repository::open(workspace_path)?;
+    let relative = path.strip_prefix(workspace_path)?;
+    let blame = repo.blame_file(relative, None)?;
+    let head = {
+        let tree = repo.head()?.peel_to_tree()?;
+        let entry = tree.get_path(relative)?;
+        blob_content(&repo, entry.id())?
+    };
+    let content = match content {
+        Some(content) => content.to_string(),
+        None => fs::read_to_string(path)?,
+    };
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0);
+    let mut commits: HashMap<Oid, String> = HashMap::new();
+    let mut hunks: Vec<BlameHunk> = Vec::new();
+    let mut last_id = None;
+    for (line, origin) in line_origins(&head, &content)?.into_iter().enumerate() {
+        let hunk = origin.and_then(|old| blame.get_line(old + 1));
+        let id = hunk.as_ref().map(|hunk| hunk.final_commit_id());
+        if let Some(last) = hunks.last_mut() {
+            if id == last_id {
+                last.lines += 1;
+                continue;
+            }
+        }
+        last_id = id;
+
+        let (id, hunk) = match (id, hunk) {
+            (Some(id), Some(hunk)) => (id, hunk),
+            _ => {
+                hunks.push(BlameHunk {
+                    start_line: line,
+                    lines: 1,
+                    commit_id: Oid::zero().to_string(),
+                    author: "Not Committed Yet".to_string(),
+                    time: now,
+                    summary: String::new(),
+                    uncommitted: true,
+                });
+                continue;
+            }
+        };
+        if !commits.contains_key(&id) {
+            let commit = repo.find_commit(id)?;
+            commits.insert(id, commit.summary().unwrap_or("").to_string());
+        }
+        let summary = commits[&id].clone();
+        let signature = hunk.final_signature();
+        hunks.push(BlameHunk {
+            start_line: line,
+            lines: 1,
+            commit_id: id.to_string(),
+            author: signature.name().unwrap_or("").to_string(),
+            time: signature.when().seconds(),
+            summary,
+            uncommitted: false,
+        });
+    }
+    Ok(hunks)
+}
+
+/// For each line of `new`, the line of `old` it was kept from, or none when
+/// it was added or changed.
+fn line_origins(old: &str, new: &str) -> Result<Vec<Option<usize>>> {
+    let mut options = DiffOptions::new();
+    options.context_lines(0);
+    let patch = Patch::from_buffers(
+        old.as_bytes(),
+        None,
+        new.as_bytes(),
+        None,
+        Some(&mut options),
+    )?;
+    let len = new.split_inclusive('\n').count();
+    let mut origins = Vec::with_capacity(len);
+    // How far the lines of `old` are ahead of the lines of `new`.
+    let mut shift = 0isize;
+    for i in 0..patch.num_hunks() {
+        let (hunk, _) = patch.hunk(i)?;
+        let new_lines = hunk.new_lines() as usize;
+        // libgit2 puts an empty range after the 1-based `new_start`.
+        let begin = if new_lines == 0 {
+            hunk.new_start() as usize
+        } else {
+            hunk.new_start() as usize - 1
+        };
+        while origins.len() < begin {
+            origins.push(Some((origins.len() as isize + shift) as usize));
+        }
+        origins.extend(std::iter::repeat(None).take(new_lines));
+        shift += hunk.old_lines() as isize - new_lines as isize;
+    }
+    while origins.len() < len {
+        origins.push(Some((origins.len() as isize + shift) as usize));
+    }
+    Ok(origins)
+}
+
+/// The content of the file at `revision`, which is empty when the file
+/// didn't exist yet.
+pub fn file_version(
+    workspace_path: &Path,
+    path: &Path,
+    revision: &str,
+) -> Result<String> {
+    let repo = Repository::open(workspace_path)?;
+    let relative = path.strip_prefix(workspace_path)?;
+    let tree = repo.revparse_single(revision)?.peel_to_tree()?;
+    match tree.get_path(relative) {
+        Ok(entry) => blob_content(&repo, entry.id()),
+        Err(e) if e.code() == ErrorCode::NotFound => Ok(String::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// The staged content of the file, which is empty when it isn't in the
+/// index.
+pub fn index_version(workspace_path: &Path, path: &Path) -> Result<String> {
+    let repo = Repository::open(workspace_path)?;
+    let relative = path.strip_prefix(workspace_path)?;
+    match repo.index()?.get_path(relative, 0) {
+        Some(entry) => blob_content(&repo, entry.id),
+        None => Ok(String::new()),
+    }
+}
+
+/// The local branches, remote branches, tags and the latest `commits`
+/// commits of HEAD, in that order.
+pub fn revisions(workspace_path: &Path, commits: usize) -> Result<Vec<GitRevision>> {
+    let repo = Repository::open(workspace_path)?;
+    let summary = |object: git2::Object| -> String {
+        object
+            .peel_to_commit()
+            .ok()
+            .and_then(|commit| commit.summary().map(|s| s.to_string()))
+            .unwrap_or_default()
+    };
+
+    let mut revisions = Vec::new();
+    for branch in branches(workspace_path)? {
+        let object = repo.revparse_single(&branch.name)?;
+        revisions.push(GitRevision {
+            kind: if branch.remote {
+                GitRevisionKind::RemoteBranch
+            } else {
+                GitRevisionKind::Branch
+            },
+            name: branch.name,
+            summary: summary(object),
+        });
+    }
+    for name in repo.tag_names(None)?.iter().flatten() {
+        let object = repo.revparse_single(&format!("refs/tags/{name}"))?;
+        revisions.push(GitRevision {
+            name: name.to_string(),
+            kind: GitRevisionKind::Tag,
+            summary: summary(object),
+        });
+    }
+    // A repository without commits has no log.
+    if repo.head().is_ok() {
+        for commit in log(workspace_path, None, 0, commits)? {
+            revisions.push(GitRevision {
+                name: commit.id,
+                kind: GitRevisionKind::Commit,
+                summary: commit.summary,
+            });
+        }
+    }
+    Ok(revisions)
+}
+
+/// A page of the commits reachable from HEAD, newest first. With a `path`,
+/// only the commits that changed that file or directory are listed.
+pub fn log(
+    workspace_path: &Path,
+    path: Option<&Path>,
+    skip: usize,
+    limit: usize,
+) -> Result<Vec<GitCommit>> {
+    let repo = Repository::open(workspace_path)?;
+    let relative = path.map(|p| p.strip_prefix(workspace_path)).transpose()?;
+    let mut revwalk = repo.revwalk()?;
+    revwalk.push_head()?;
+    revwalk.set_sorting(Sort::TIME)?;
+
+    let mut commits = Vec::new();
+    let mut matched = 0;
+    for id in revwalk {
+        let commit = repo.find_commit(id?)?;
+        if let Some(relative) = relative {
+            if !changes_path(&commit, relative)? {
+                continue;
+            }
+        }
+        matched += 1;
+        if matched <= skip {
+            continue;
+        }
+        let author = commit.author();
+        commits.push(GitCommit {
+            id: commit.id().to_string(),
+            author: author.name().unwrap_or("").to_string(),
+            time: author.when().seconds(),
+            summary: commit.summary().unwrap_or("").to_string(),
+        });
+        if commits.len() == limit {
+            break;
+        }
+    }
+    Ok(commits)
+}
+
+/// Whether `path` differs between the commit and each of its parents, the
+/// way `git log <path>` picks commits.
+fn changes_path(commit: &Commit, path: &Path) -> Result<bool> {
+    let entry_id = |tree: git2::Tree| tree.get_path(path).ok().map(|e| e.id());
+    let id = entry_id(commit.tree()?);
+    if commit.parent_count() == 0 {
+        return Ok(id.is_some());
+    }
+    for parent in commit.parents() {
+        if entry_id(parent.tree()?) == id {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// The files the commit changed compared to its first parent.
+pub fn commit_files(workspace_path: &Path, commit: &str) -> Result<Vec<FileDiff>> {
+    let repo = Repository::open(workspace_path)?;
+    let commit = repo.revparse_single(commit)?.peel_to_commit()?;
+    let parent_tree = match commit.parent(0) {
+        Ok(parent) => Some(parent.tree()?),
+        Err(_) => None,
+    };
+    let mut diff =
+        repo.diff_tree_to_tree(parent_tree.as_ref(), Some(&commit.tree()?), None)?;
+    diff.find_similar(None)?;
+
+    let mut files = Vec::new();
+    for delta in diff.deltas() {
+        let old = delta.old_file().path().map(|p| workspace_path.join(p));
+        let new = delta.new_file().path().map(|p| workspace_path.join(p));
+        let file = match (delta.status(), old, new) {
+            (Delta::Added, _, Some(new)) => FileDiff::Added(new),
+            (Delta::Deleted, Some(old), _) => FileDiff::Deleted(old),
+            (Delta::Modified, _, Some(new)) => FileDiff::Modified(new),
+            (Delta::Renamed, Some(old), Some(new)) => FileDiff::Renamed(new, old),
+            _ => continue,
+        };
+        files.push(file);
+    }
+    Ok(files)
+}
+
+/// Reads the file as of the commit, along with its content before the commit
+/// from `parent_path`, which differs from `path` for renames. Nothing is
+/// written: the snapshot path only names the file for the editor.
+pub fn commit_file(
+    workspace_path: &Path,
+    commit: &str,
+    path: &Path,
+    parent_path: &Path,
+) -> Result<(GitCommitFileResponse, String)> {
+    let repo = Repository::open(workspace_path)?;
+    let commit = repo.revparse_single(commit)?.peel_to_commit()?;
+    let content_at = |commit: &Commit, path: &Path| -> Result<String> {
+        let relative = path.strip_prefix(workspace_path)?;
+        match commit.tree()?.get_path(relative) {
+            Ok(entry) => blob_content(&repo, entry.id()),
+            Err(e) if e.code() == ErrorCode::NotFound => Ok(String::new()),
+            Err(e) => Err(e.into()),
+        }
+    };
+    let parent_content = match commit.parent(0) {
+        Ok(parent) => content_at(&parent, parent_path)?,
+        Err(_) => String::new(),
+    };
+
+    let content = content_at(&commit, path)?;
+    let snapshot = workspace_path
+        .join(".lapce")
+        .join("git")
+        .join(&commit.id().to_string()[..12])
+        .join(path.strip_prefix(workspace_path)?);
+    Ok((
+        GitCommitFileResponse {
+            snapshot,
+            parent_content,
+        },
+        content,
+    ))
+}
+
+fn blob_content(repo: &Repository, id: git2::Oid) -> Result<String> {
+    let blob = repo.find_blob(id)?;
+    Ok(std::str::from_utf8(blob.content())?.to_string())
+}
+
+/// Maps the 0-based `line` of `old` to the same line in `new`, failing when
+/// any of the `len` lines from there on differ between the two.
+fn map_line(old: &str, new: &str, line: usize, len: usize) -> Result<usize> {
+    let mut options = DiffOptions::new();
+    options.context_lines(0);
+    let patch = Patch::from_buffers(
+        old.as_bytes(),
+        None,
+        new.as_bytes(),
+        None,
+        Some(&mut options),
+    )?;
+    let mut shift = 0isize;
+    for i in 0..patch.num_hunks() {
+        let (hunk, _) = patch.hunk(i)?;
+        let old_lines = hunk.old_lines() as usize;
+        // libgit2 puts an empty range after the 1-based `old_start`.
+        let begin = if old_lines == 0 {
+            hunk.old_start() as usize
+        } else {
+            hunk.old_start() as usize - 1
+        };
+        let end = begin + old_lines;
+        if end <= line {
+            shift += hunk.new_lines() as isize - old_lines as isize;
+        } else if begin >= line + len {
+            break;
+        } else {
+            return Err(anyhow!(
+                "the change overlaps changes that are already staged"
+            ));
+        }
+    }
+    Ok((line as isize + shift) as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A new repository in a temporary directory, with an identity to
+    /// commit with.
+    fn repo() -> (tempfile::TempDir, Repository) {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        let mut config = repo.config().unwrap();
+        config.set_str("user.name", "Test").unwrap();
+        config.set_str("user.email", "test@example.com").unwrap();
+        (dir, repo)
+    }
+
+    /// Stages everything in the worktree and commits it, a second after its
+    /// parent so that the log order doesn't depend on the clock.
+    fn commit_all(repo: &Repository, message: &str) -> Oid {
+        let mut index = repo.index().unwrap();
+        index.read(true).unwrap();
+        index
+            .add_all(["*"].iter(), git2::IndexAddOption::DEFAULT, None)
+            .unwrap();
+        index.update_all(["*"].iter(), None).unwrap();
+        index.write().unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let parent = repo.head().ok().and_then(|head| head.peel_to_commit().ok());
+        let time = parent
+            .as_ref()
+            .map(|parent| parent.time().seconds() + 1)
+            .unwrap_or(1_600_000_000);
+        let signature = git2::Signature::new(
+            "Test",
+            "test@example.com",
+            &git2::Time::new(time, 0),
+        )
+        .unwrap();
+        let parents: Vec<&Commit> = parent.iter().collect();
+        repo.commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            message,
+            &tree,
+            &parents,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_line_origins() {
+        assert_eq!(
+            line_origins("a\nb\nc\n", "a\nx\nb\nc\n").unwrap(),
+            vec![Some(0), None, Some(1), Some(2)]
+        );
+        assert_eq!(
+            line_origins("a\nb\nc\n", "a\nc\n").unwrap(),
+            vec![Some(0), Some(2)]
+        );
+        assert_eq!(
+            line_origins("a\nb\nc\n", "a\nB\nc\n").unwrap(),
+            vec![Some(0), None, Some(2)]
+        );
+        assert_eq!(line_origins("a\n", "").unwrap(), vec![]);
+        assert_eq!(line_origins("", "a\n").unwrap(), vec![None]);
+    }
+
+    /// Ignores progress and never has credentials, which a remote on disk
+    /// doesn't ask for.
+    struct NoCredentials;
+
+    impl RemoteHandler for NoCredentials {
+        fn progress(&self, _message: String, _percentage: u32) {}
+
+        fn credentials(
+            &self,
+            _url: &str,
+            _username: Option<&str>,
+            _ssh_key: Option<&Path>,
+        ) -> Option<GitCredentials> {
+            None
+        }
+    }
+
+    /// A bare repository to push to, and a clone of it with a first commit
+    /// pushed.
+    fn remote() -> (tempfile::TempDir, tempfile::TempDir, Repository) {
+        let bare = tempfile::tempdir().unwrap();
+        Repository::init_bare(bare.path()).unwrap();
+        let (dir, repo) = repo();
+        repo.remote("origin", bare.path().to_str().unwrap())
+            .unwrap();
+        fs::write(dir.path().join("file.txt"), "a\n").unwrap();
+        commit_all(&repo, "first");
+        push(dir.path(), &NoCredentials).unwrap();
+        (bare, dir, repo)
+    }
+
+    /// Another clone of the bare repository.
+    fn clone(bare: &Path) -> (tempfile::TempDir, Repository) {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::clone(bare.to_str().unwrap(), dir.path()).unwrap();
+        let mut config = repo.config().unwrap();
+        config.set_str("user.name", "Other").unwrap();
+        config.set_str("user.email", "other@example.com").unwrap();
+        (dir, repo)
+    }
+
+    /// The staged content of the file at `path`, as the functions under test
+    /// left the index on disk.
+    fn staged(repo: &Repository, path: &str) -> Option<String> {
+        let mut index = repo.index().unwrap();
+        index.read(true).unwrap();
+        let entry = index.get_path(Path::new(path), 0)?;
+        Some(blob_content(repo, entry.id).unwrap())
+    }
+
+    #[test]
+    fn test_branches() {
+        let (dir, repo) = repo();
+        let path = dir.path().join("file.txt");
+        fs::write(&path, "a\n").unwrap();
+        let first = commit_all(&repo, "first");
+        let main = head_branch(&repo).unwrap();
+
+        create_branch(dir.path(), "feature", None).unwrap();
+        fs::write(&path, "b\n").unwrap();
+        commit_all(&repo, "second");
+        create_branch(dir.path(), "old", Some(&first.to_string())).unwrap();
+        assert!(create_branch(dir.path(), "old", None).is_err());
+
+        let names: Vec<(String, bool)> = branches(dir.path())
+            .unwrap()
+            .into_iter()
+            .map(|b| (b.name, b.head))
+            .collect();
+        let mut expected = vec![
+            ("feature".to_string(), false),
+            (main.clone(), true),
+            ("old".to_string(), false),
+        ];
+        expected.sort();
+        assert_eq!(names, expected);
+
+        // A dirty worktree is only thrown away when forced.
+        fs::write(&path, "dirty\n").unwrap();
+        assert!(checkout(dir.path(), "feature", false).is_err());
+        assert_eq!(head_branch(&repo).unwrap(), main);
+        checkout(dir.path(), "feature", true).unwrap();
+        assert_eq!(head_branch(&repo).unwrap(), "feature");
+        assert_eq!(fs::read_to_string(&path).unwrap(), "a\n");
+
+        assert!(delete_branch(dir.path(), "feature").is_err());
+        delete_branch(dir.path(), "old").unwrap();
+        assert!(repo.find_branch("old", BranchType::Local).is_err());
+    }
+
+    #[test]
+    fn test_checkout_remote_branch() {
+        let (bare, dir, repo) = remote();
+        let main = head_branch(&repo).unwrap();
+        let (other_dir, other) = clone(bare.path());
+        let other_file = other_dir.path().join("file.txt");
+        create_branch(other_dir.path(), "topic", None).unwrap();
+        checkout(other_dir.path(), "topic", false).unwrap();
+        fs::write(&other_file, "topic\n").unwrap();
+        let first = commit_all(&other, "topic");
+        push(other_dir.path(), &NoCredentials).unwrap();
+
+        // The remote branch makes a local one tracking it.
+        fetch(dir.path(), &NoCredentials).unwrap();
+        checkout(dir.path(), "origin/topic", false).unwrap();
+        assert_eq!(head_branch(&repo).unwrap(), "topic");
+        assert_eq!(repo.head().unwrap().target(), Some(first));
+        let topic = repo.find_branch("topic", BranchType::Local).unwrap();
+        assert_eq!(
+            topic.upstream().unwrap().name().unwrap(),
+            Some("origin/topic")
+        );
+
+        fs::write(&other_file, "topic 2\n").unwrap();
+        let second = commit_all(&other, "topic 2");
+        push(other_dir.path(), &NoCredentials).unwrap();
+        checkout(dir.path(), &main, false).unwrap();
+        fetch(dir.path(), &NoCredentials).unwrap();
+
+        // The local branch exists already, and is fast-forwarded.
+        checkout(dir.path(), "origin/topic", false).unwrap();
+        assert_eq!(head_branch(&repo).unwrap(), "topic");
+        assert_eq!(repo.head().unwrap().target(), Some(second));
+        assert_eq!(
+            fs::read_to_string(dir.path().join("file.txt")).unwrap(),
+            "topic 2\n"
+        );
+
+        // A local branch ahead of the remote one is checked out as it is.
+        fs::write(dir.path().join("file.txt"), "ahead\n").unwrap();
+        let ahead = commit_all(&repo, "ahead");
+        checkout(dir.path(), &main, false).unwrap();
+        checkout(dir.path(), "origin/topic", false).unwrap();
+        assert_eq!(repo.head().unwrap().target(), Some(ahead));
+    }
+
+    #[test]
+    fn test_stage_and_unstage() {
+        let (dir, repo) = repo();
+        let path = dir.path().join("file.txt");
+        fs::write(&path, "a\n").unwrap();
+        commit_all(&repo, "first");
+
+        fs::write(&path, "b\n").unwrap();
+        let new = dir.path().join("new.txt");
+        fs::write(&new, "new\n").unwrap();
+        stage(dir.path(), &[path.clone(), new.clone()]).unwrap();
+        assert_eq!(staged(&repo, "file.txt").as_deref(), Some("b\n"));
+        assert_eq!(staged(&repo, "new.txt").as_deref(), Some("new\n"));
+
+        unstage(dir.path(), &[path.clone(), new]).unwrap();
+        assert_eq!(staged(&repo, "file.txt").as_deref(), Some("a\n"));
+        assert_eq!(staged(&repo, "new.txt"), None);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "b\n");
+
+        fs::remove_file(&path).unwrap();
+        stage(dir.path(), &[path]).unwrap();
+        assert_eq!(staged(&repo, "file.txt"), None);
+    }
+
+    #[test]
+    fn test_discard() {
+        let (dir, repo) = repo();
+        let path = dir.path().join("file.txt");
+        fs::write(&path, "a\n").unwrap();
+        commit_all(&repo, "first");
+
+        fs::write(&path, "b\n").unwrap();
+        let untracked = dir.path().join("untracked.txt");
+        fs::write(&untracked, "new\n").unwrap();
+        discard(dir.path(), &[path.clone(), untracked.clone()]).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "a\n");
+        assert!(!untracked.exists());
+    }
+
+    #[test]
+    fn test_resolve() {
+        let (dir, repo) = repo();
+        let path = dir.path().join("file.txt");
+        fs::write(&path, "a\n").unwrap();
+        commit_all(&repo, "first");
+
+        fs::write(&path, "<<<<<<< HEAD\nb\n=======\nc\n>>>>>>> branch\n").unwrap();
+        assert!(resolve(dir.path(), &path).is_err());
+        assert_eq!(staged(&repo, "file.txt").as_deref(), Some("a\n"));
+
+        fs::write(&path, "b\nc\n").unwrap();
+        resolve(dir.path(), &path).unwrap();
+        assert_eq!(staged(&repo, "file.txt").as_deref(), Some("b\nc\n"));
+    }
+
+    #[test]
+    fn test_stage_hunk() {
+        let (dir, repo) = repo();
+        let path = dir.path().join("file.txt");
+        fs::write(&path, "a\nb\nc\nd\ne\n").unwrap();
+        commit_all(&repo, "first");
+
+        // A change further down is already staged.
+        fs::write(&path, "a\nb\nc\nd\nE\n").unwrap();
+        stage(dir.path(), &[path.clone()]).unwrap();
+        fs::write(&path, "A\nb\nc\nd\nE\n").unwrap();
+
+        let hunk = GitHunk {
+            old_start: 0,
+            old_lines: 1,
+            new_content: "A\nnew\n".to_string(),
+        };
+        stage_hunk(dir.path(), &path, &hunk).unwrap();
+        assert_eq!(
+            staged(&repo, "file.txt").as_deref(),
+            Some("A\nnew\nb\nc\nd\nE\n")
+        );
+
+        let hunk = GitHunk {
+            old_start: 4,
+            old_lines: 1,
+            new_content: "F\n".to_string(),
+        };
+        assert!(stage_hunk(dir.path(), &path, &hunk).is_err());
+    }
+
+    #[test]
+    fn test_stage_hunk_new_file() {
+        let (dir, repo) = repo();
+        let path = dir.path().join("new.txt");
+        fs::write(&path, "a\nb\n").unwrap();
+        let hunk = GitHunk {
+            old_start: 0,
+            old_lines: 0,
+            new_content: "a\n".to_string(),
+        };
+
+        // There are no commits yet.
+        stage_hunk(dir.path(), &path, &hunk).unwrap();
+        assert_eq!(staged(&repo, "new.txt").as_deref(), Some("a\n"));
+        commit_all(&repo, "first");
+
+        // The file isn't in HEAD nor in the index.
+        let path = dir.path().join("src").join("other.txt");
+        fs::create_dir(dir.path().join("src")).unwrap();
+        fs::write(&path, "x\ny\n").unwrap();
+        let hunk = GitHunk {
+            old_start: 0,
+            old_lines: 0,
+            new_content: "x\ny\n".to_string(),
+        };
+        stage_hunk(dir.path(), &path, &hunk).unwrap();
+        assert_eq!(staged(&repo, "src/other.txt").as_deref(), Some("x\ny\n"));
+    }
+
+    #[test]
+    fn test_map_line() {
+        let old = "a\nb\nc\nd\n";
+        assert_eq!(map_line(old, "x\na\nb\nc\nd\n", 2, 1).unwrap(), 3);
+        assert_eq!(map_line(old, "b\nc\nd\n", 2, 2).unwrap(), 1);
+        assert_eq!(map_line(old, "a\nb\nc\nD\n", 1, 2).unwrap(), 1);
+        assert!(map_line(old, "a\nB\nc\nd\n", 0, 2).is_err());
+    }
+
+    #[test]
+    fn test_commit() {
+        let (dir, repo) = repo();
+        let path = dir.path().join("file.txt");
+        fs::write(&path, "a\n").unwrap();
+        let deleted = dir.path().join("deleted.txt");
+        fs::write(&deleted, "deleted\n").unwrap();
+        commit(
+            dir.path(),
+            "first",
+            vec![
+                FileDiff::Added(path.clone()),
+                FileDiff::Added(deleted.clone()),
+            ],
+            false,
+            false,
+        )
+        .unwrap();
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        assert_eq!(head.message(), Some("first\n"));
+        assert_eq!(head.parent_count(), 0);
+
+        fs::write(&path, "b\n").unwrap();
+        fs::remove_file(&deleted).unwrap();
+        commit(
+            dir.path(),
+            "second",
+            vec![FileDiff::Modified(path.clone()), FileDiff::Deleted(deleted)],
+            false,
+            true,
+        )
+        .unwrap();
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        assert_eq!(
+            head.message(),
+            Some("second\n\nSigned-off-by: Test <test@example.com>\n")
+        );
+        assert_eq!(head.parent_count(), 1);
+        assert_eq!(staged(&repo, "file.txt").as_deref(), Some("b\n"));
+        assert_eq!(staged(&repo, "deleted.txt"), None);
+    }
+
+    #[test]
+    fn test_commit_amend() {
+        let (dir, repo) = repo();
+        let path = dir.path().join("file.txt");
+        assert!(commit(dir.path(), "", vec![], true, false).is_err());
+
+        fs::write(&path, "a\n").unwrap();
+        let first = commit_all(&repo, "first");
+        fs::write(&path, "b\n").unwrap();
+        let second = commit_all(&repo, "second");
+
+        // Amending without a message keeps the one of HEAD.
+        fs::write(&path, "c\n").unwrap();
+        stage(dir.path(), &[path.clone()]).unwrap();
+        commit(dir.path(), "", vec![], true, false).unwrap();
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        assert_ne!(head.id(), second);
+        assert_eq!(head.message(), Some("second\n"));
+        assert_eq!(head.parent_id(0).unwrap(), first);
+        let tree = head.tree().unwrap();
+        let entry = tree.get_path(Path::new("file.txt")).unwrap();
+        assert_eq!(blob_content(&repo, entry.id()).unwrap(), "c\n");
+
+        commit(dir.path(), "reworded", vec![], true, false).unwrap();
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        assert_eq!(head.message(), Some("reworded\n"));
+        assert_eq!(head.parent_id(0).unwrap(), first);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_commit_hooks() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let (dir, repo) = repo();
+        let path = dir.path().join("file.txt");
+        fs::write(&path, "a\n").unwrap();
+        let hooks = repo.path().join("hooks");
+        fs::create_dir_all(&hooks).unwrap();
+        let write_hook = |name: &str, script: &str| {
+            let hook = hooks.join(name);
+            fs::write(&hook, script).unwrap();
+            fs::set_permissions(&hook, fs::Permissions::from_mode(0o755)).unwrap();
+        };
+
+        write_hook("commit-msg", "#!/bin/sh\necho \"hooked\" > \"$1\"\n");
+        commit(
+            dir.path(),
+            "first",
+            vec![FileDiff::Added(path.clone())],
+            false,
+            false,
+        )
+        .unwrap();
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        assert_eq!(head.message(), Some("hooked\n"));
+
+        write_hook("pre-commit", "#!/bin/sh\necho refused\nexit 1\n");
+        fs::write(&path, "b\n").unwrap();
+        let err = commit(
+            dir.path(),
+            "second",
+            vec![FileDiff::Modified(path)],
+            false,
+            false,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("refused"));
+        assert_eq!(
+            repo.head().unwrap().peel_to_commit().unwrap().id(),
+            head.id()
+        );
+    }
+
+    #[test]
+    fn test_blame() {
+        let (dir, repo) = repo();
+        let path = dir.path().join("file.txt");
+        fs::write(&path, "a\nb\n").unwrap();
+        let first = commit_all(&repo, "first");
+        fs::write(&path, "a\nb\nc\n").unwrap();
+        let second = commit_all(&repo, "second");
+
+        let hunks = blame(dir.path(), &path, Some("a\nx\nb\nc\n")).unwrap();
+        let hunks: Vec<(usize, usize, String, bool)> = hunks
+            .into_iter()
+            .map(|h| (h.start_line, h.lines, h.commit_id, h.uncommitted))
+            .collect();
+        assert_eq!(
+            hunks,
+            vec![
+                (0, 1, first.to_string(), false),
+                (1, 1, Oid::zero().to_string(), true),
+                (2, 1, first.to_string(), false),
+                (3, 1, second.to_string(), false),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_stash() {
+        let (dir, repo) = repo();
+        let path = dir.path().join("file.txt");
+        fs::write(&path, "a\n").unwrap();
+        let head = commit_all(&repo, "first");
+
+        fs::write(&path, "b\n").unwrap();
+        stash_save(dir.path(), "", false).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "a\n");
+        let untracked = dir.path().join("untracked.txt");
+        fs::write(&untracked, "new\n").unwrap();
+        fs::write(&path, "c\n").unwrap();
+        stash_save(dir.path(), "with untracked", true).unwrap();
+        assert!(!untracked.exists());
+
+        let messages: Vec<String> = stashes(dir.path())
+            .unwrap()
+            .into_iter()
+            .map(|s| s.message)
+            .collect();
+        assert_eq!(messages.len(), 2);
+        assert!(messages[0].ends_with("with untracked"));
+        assert!(messages[1].ends_with(&format!("{} first", &head.to_string()[..7])));
+
+        stash_apply(dir.path(), 1).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "b\n");
+        assert_eq!(stashes(dir.path()).unwrap().len(), 2);
+
+        discard(dir.path(), &[path.clone()]).unwrap();
+        stash_pop(dir.path(), 0).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "c\n");
+        assert!(untracked.exists());
+        assert_eq!(stashes(dir.path()).unwrap().len(), 1);
+
+        stash_drop(dir.path(), 0).unwrap();
+        assert!(stashes(dir.path()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_push_and_fetch() {
+        let (bare, dir, repo) = remote();
+        let branch = head_branch(&repo).unwrap();
+        // The first push makes the branch track the remote one.
+        assert!(repo
+            .find_branch(&branch, BranchType::Local)
+            .unwrap()
+            .upstream()
+            .is_ok());
+        assert_eq!(ahead_behind(&repo), Some((0, 0)));
+
+        let (other_dir, other) = clone(bare.path());
+        fs::write(other_dir.path().join("file.txt"), "b\n").unwrap();
+        let pushed = commit_all(&other, "second");
+        push(other_dir.path(), &NoCredentials).unwrap();
+
+        fetch(dir.path(), &NoCredentials).unwrap();
+        assert_eq!(ahead_behind(&repo), Some((0, 1)));
+        let upstream = repo
+            .find_branch(&branch, BranchType::Local)
+            .unwrap()
+            .upstream()
+            .unwrap();
+        assert_eq!(upstream.get().target(), Some(pushed));
+
+        // The remote is ahead, so pushing an older branch is rejected.
+        fs::write(dir.path().join("file.txt"), "c\n").unwrap();
+        commit_all(&repo, "diverged");
+        assert!(push(dir.path(), &NoCredentials).is_err());
+    }
+
+    #[test]
+    fn test_pull_fast_forward() {
+        let (bare, dir, repo) = remote();
+        let (other_dir, other) = clone(bare.path());
+        fs::write(other_dir.path().join("file.txt"), "b\n").unwrap();
+        let pushed = commit_all(&other, "second");
+        push(other_dir.path(), &NoCredentials).unwrap();
+
+        pull(dir.path(), false, &NoCredentials).unwrap();
+        assert_eq!(repo.head().unwrap().target(), Some(pushed));
+        assert_eq!(
+            fs::read_to_string(dir.path().join("file.txt")).unwrap(),
+            "b\n"
+        );
+        assert_eq!(ahead_behind(&repo), Some((0, 0)));
+        // Nothing new to pull.
+        pull(dir.path(), false, &NoCredentials).unwrap();
+    }
+
+    #[test]
+    fn test_pull_rebase() {
+        let (bare, dir, repo) = remote();
+        let (other_dir, other) = clone(bare.path());
+        fs::write(other_dir.path().join("other.txt"), "other\n").unwrap();
+        let pushed = commit_all(&other, "other");
+        push(other_dir.path(), &NoCredentials).unwrap();
+        fs::write(dir.path().join("file.txt"), "b\n").unwrap();
+        commit_all(&repo, "local");
+
+        assert!(pull(dir.path(), false, &NoCredentials).is_err());
+        pull(dir.path(), true, &NoCredentials).unwrap();
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        assert_eq!(head.summary(), Some("local"));
+        assert_eq!(head.parent_id(0).unwrap(), pushed);
+        assert!(dir.path().join("other.txt").exists());
+        assert_eq!(ahead_behind(&repo), Some((1, 0)));
+    }
+
+    #[test]
+    fn test_pull_rebase_conflict() {
+        let (bare, dir, repo) = remote();
+        let (other_dir, other) = clone(bare.path());
+        fs::write(other_dir.path().join("file.txt"), "theirs\n").unwrap();
+        commit_all(&other, "theirs");
+        push(other_dir.path(), &NoCredentials).unwrap();
+        fs::write(dir.path().join("file.txt"), "ours\n").unwrap();
+        let local = commit_all(&repo, "ours");
+
+        assert!(pull(dir.path(), true, &NoCredentials).is_err());
+        // The rebase was aborted, leaving the branch as it was.
+        assert_eq!(repo.head().unwrap().target(), Some(local));
+        assert_eq!(
+            fs::read_to_string(dir.path().join("file.txt")).unwrap(),
+            "ours\n"
+        );
+    }
+
+    #[test]
+    fn test_log() {
+        let (dir, repo) = repo();
+        let a = dir.path().join("a.txt");
+        fs::write(&a, "a\n").unwrap();
+        let first = commit_all(&repo, "first");
+        fs::write(dir.path().join("b.txt"), "b\n").unwrap();
+        let second = commit_all(&repo, "second");
+        fs::write(&a, "A\n").unwrap();
+        let third = commit_all(&repo, "third");
+
+        let ids = |commits: Vec<GitCommit>| -> Vec<String> {
+            commits.into_iter().map(|c| c.id).collect()
+        };
+        assert_eq!(
+            ids(log(dir.path(), None, 0, 10).unwrap()),
+            vec![third.to_string(), second.to_string(), first.to_string()]
+        );
+        assert_eq!(
+            ids(log(dir.path(), Some(&a), 0, 10).unwrap()),
+            vec![third.to_string(), first.to_string()]
+        );
+        assert_eq!(
+            ids(log(dir.path(), None, 1, 1).unwrap()),
+            vec![second.to_string()]
+        );
+        let commits = log(dir.path(), None, 0, 1).unwrap();
+        assert_eq!(commits[0].summary, "third");
+        assert_eq!(commits[0].author, "Test");
+    }
+
+    #[test]
+    fn test_commit_file() {
+        let (dir, repo) = repo();
+        let path = dir.path().join("file.txt");
+        fs::write(&path, "old\n").unwrap();
+        commit_all(&repo, "first");
+        fs::write(&path, "new\n").unwrap();
+        let second = commit_all(&repo, "second").to_string();
+
+        assert_eq!(
+            commit_files(dir.path(), &second).unwrap(),
+            vec![FileDiff::Modified(path.clone())]
+        );
+        let (resp, content) =
+            commit_file(dir.path(), &second, &path, &path).unwrap();
+        assert_eq!(content, "new\n");
+        assert_eq!(resp.parent_content, "old\n");
+        assert_eq!(
+            resp.snapshot,
+            dir.path()
+                .join(".lapce")
+                .join("git")
+                .join(&second[..12])
+                .join("file.txt")
+        );
+        // Nothing is written to the worktree.
+        assert!(!dir.path().join(".lapce").exists());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new\n");
+    }
+
+    #[test]
+    fn test_commit_files_rename() {
+        let (dir, repo) = repo();
+        let old = dir.path().join("old.txt");
+        fs::write(&old, "content\n").unwrap();
+        let first = commit_all(&repo, "first").to_string();
+        let new = dir.path().join("new.txt");
+        fs::rename(&old, &new).unwrap();
+        let second = commit_all(&repo, "second").to_string();
+
+        assert_eq!(
+            commit_files(dir.path(), &first).unwrap(),
+            vec![FileDiff::Added(old.clone())]
+        );
+        assert_eq!(
+            commit_files(dir.path(), &second).unwrap(),
+            vec![FileDiff::Renamed(new.clone(), old.clone())]
+        );
+        let (resp, content) = commit_file(dir.path(), &second, &new, &old).unwrap();
+        assert_eq!(content, "content\n");
+        assert_eq!(resp.parent_content, "content\n");
+    }
+}
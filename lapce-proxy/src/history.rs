@@ -0,0 +1,188 @@
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, Result};
+use lapce_rpc::buffer::FileHistoryVersion;
+use sha2::{Digest, Sha256};
+use xi_rope::Rope;
+
+/// How many saved versions are kept for each file.
+const MAX_VERSIONS: usize = 50;
+
+fn history_root(workspace: &Path) -> PathBuf {
+    workspace.join(".lapce").join("history")
+}
+
+/// The directory holding the saved versions of `path`. Each version is a
+/// file named `<save time in ms>-<content hash>`. The names are on disk, so
+/// they come from a hash that doesn't change between Rust releases.
+fn history_dir(workspace: &Path, path: &Path) -> PathBuf {
+    let hash = Sha256::digest(path.to_string_lossy().as_bytes());
+    history_root(workspace).join(hex(&hash[..8]))
+}
+
+fn content_hash(rope: &Rope) -> String {
+    let mut hasher = Sha256::new();
+    for chunk in rope.iter_chunks(..) {
+        hasher.update(chunk.as_bytes());
+    }
+    hex(&hasher.finalize()[..8])
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn parse_version(name: &str) -> Option<FileHistoryVersion> {
+    let (time, hash) = name.split_once('-')?;
+    Some(FileHistoryVersion {
+        id: name.to_string(),
+        time: time.parse().ok()?,
+        hash: hash.to_string(),
+    })
+}
+
+/// All saved versions of `path`, newest first.
+pub fn list_versions(
+    workspace: &Path,
+    path: &Path,
+) -> Result<Vec<FileHistoryVersion>> {
+    let dir = history_dir(workspace, path);
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        // Nothing was saved yet.
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    let mut versions: Vec<FileHistoryVersion> = entries
+        .flatten()
+        .filter_map(|entry| parse_version(entry.file_name().to_str()?))
+        .collect();
+    versions.sort_by(|a, b| b.time.cmp(&a.time));
+    Ok(versions)
+}
+
+pub fn get_version(workspace: &Path, path: &Path, version: &str) -> Result<String> {
+    if parse_version(version).is_none() {
+        return Err(anyhow!("invalid version {}", version));
+    }
+    let content = fs::read_to_string(history_dir(workspace, path).join(version))?;
+    Ok(content)
+}
+
+/// Stores `content` as the newest version of `path`. If the same content was
+/// saved before, that version is moved to the top instead of being stored
+/// twice. The oldest versions are dropped past `MAX_VERSIONS`.
+pub fn record_version(workspace: &Path, path: &Path, content: &Rope) -> Result<()> {
+    let dir = history_dir(workspace, path);
+    fs::create_dir_all(&dir)?;
+    // Keep the versions out of the source control panel.
+    let ignore = history_root(workspace).join(".gitignore");
+    if !ignore.exists() {
+        fs::write(ignore, "*\n")?;
+    }
+
+    let hash = content_hash(content);
+    let versions = list_versions(workspace, path)?;
+    if versions.first().map(|v| v.hash == hash).unwrap_or(false) {
+        return Ok(());
+    }
+
+    let time = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis();
+    let new_path = dir.join(format!("{}-{}", time, hash));
+    match versions.iter().find(|v| v.hash == hash) {
+        Some(existing) => fs::rename(dir.join(&existing.id), &new_path)?,
+        None => fs::write(&new_path, content.to_string())?,
+    }
+
+    let kept = versions.iter().filter(|v| v.hash != hash);
+    for version in kept.skip(MAX_VERSIONS - 1) {
+        let _ = fs::remove_file(dir.join(&version.id));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+    use std::time::Duration;
+
+    use super::*;
+
+    fn record(workspace: &Path, path: &Path, content: &str) {
+        record_version(workspace, path, &Rope::from(content)).unwrap();
+        // The versions are ordered by their time in ms.
+        thread::sleep(Duration::from_millis(2));
+    }
+
+    #[test]
+    fn test_list_versions_without_history() {
+        let workspace = tempfile::tempdir().unwrap();
+        let path = workspace.path().join("main.rs");
+        assert!(list_versions(workspace.path(), &path).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_record_version() {
+        let workspace = tempfile::tempdir().unwrap();
+        let workspace = workspace.path();
+        let path = workspace.join("main.rs");
+
+        record(workspace, &path, "one");
+        record(workspace, &path, "one");
+        record(workspace, &path, "two");
+        let versions = list_versions(workspace, &path).unwrap();
+        assert_eq!(versions.len(), 2);
+        assert_eq!(
+            get_version(workspace, &path, &versions[0].id).unwrap(),
+            "two"
+        );
+        assert_eq!(
+            get_version(workspace, &path, &versions[1].id).unwrap(),
+            "one"
+        );
+
+        // Saving an older content again moves it to the top.
+        record(workspace, &path, "one");
+        let versions = list_versions(workspace, &path).unwrap();
+        assert_eq!(versions.len(), 2);
+        assert_eq!(
+            get_version(workspace, &path, &versions[0].id).unwrap(),
+            "one"
+        );
+
+        assert_eq!(
+            fs::read_to_string(workspace.join(".lapce/history/.gitignore")).unwrap(),
+            "*\n"
+        );
+    }
+
+    #[test]
+    fn test_record_version_drops_oldest() {
+        let workspace = tempfile::tempdir().unwrap();
+        let workspace = workspace.path();
+        let path = workspace.join("main.rs");
+        for i in 0..MAX_VERSIONS + 5 {
+            record(workspace, &path, &i.to_string());
+        }
+        let versions = list_versions(workspace, &path).unwrap();
+        assert_eq!(versions.len(), MAX_VERSIONS);
+        let newest = (MAX_VERSIONS + 4).to_string();
+        assert_eq!(
+            get_version(workspace, &path, &versions[0].id).unwrap(),
+            newest
+        );
+    }
+
+    #[test]
+    fn test_history_dir_is_stable() {
+        // The directory names are on disk, so they must never change.
+        assert_eq!(
+            history_dir(Path::new("/ws"), Path::new("/ws/main.rs")),
+            Path::new("/ws/.lapce/history/43662cf3472fba0b")
+        );
+        assert_eq!(content_hash(&Rope::from("hello")), "2cf24dba5fb0a30e");
+    }
+}
@@ -1,7 +1,10 @@
 pub mod buffer;
 pub mod dispatch;
+pub mod git;
+pub mod history;
 pub mod lsp;
 pub mod plugin;
+pub mod search;
 pub mod terminal;
 
 use dispatch::Dispatcher;
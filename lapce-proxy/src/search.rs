@@ -0,0 +1,386 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use crossbeam_channel::{unbounded, RecvTimeoutError};
+use grep_matcher::Matcher;
+use grep_regex::{RegexMatcher, RegexMatcherBuilder};
+use grep_searcher::sinks::UTF8;
+use grep_searcher::{Searcher, SearcherBuilder};
+use ignore::overrides::OverrideBuilder;
+use ignore::{WalkBuilder, WalkParallel, WalkState};
+use lapce_rpc::search::{SearchMatch, SearchOptions, SearchReplacement};
+use serde_json::json;
+
+use crate::dispatch::Dispatcher;
+
+/// How long matches are collected before they are sent as one batch.
+const BATCH_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Searches the workspace and streams the matches to the core in batches
+/// tagged with `search_id`. The search stops as soon as `current_search` no
+/// longer holds `search_id`, i.e. when a newer search was started or the
+/// search was cancelled.
+#[allow(clippy::too_many_arguments)]
+pub fn global_search(
+    dispatcher: &Dispatcher,
+    current_search: Arc<AtomicU64>,
+    search_id: u64,
+    workspace: &Path,
+    pattern: &str,
+    options: &SearchOptions,
+    max_file_matches: usize,
+    max_matches: usize,
+) {
+    let search = search_matcher(pattern, options)
+        .and_then(|matcher| Ok((matcher, search_walk(workspace, options)?)));
+    let (matcher, walk) = match search {
+        Ok(search) => search,
+        Err(e) => {
+            send_done(dispatcher, search_id, false, Some(e.to_string()));
+            return;
+        }
+    };
+
+    let total = Arc::new(AtomicUsize::new(0));
+    let limit_reached = Arc::new(AtomicBool::new(false));
+    let (tx, rx) = unbounded::<(PathBuf, Vec<SearchMatch>)>();
+    {
+        let current_search = current_search.clone();
+        let limit_reached = limit_reached.clone();
+        thread::spawn(move || {
+            walk.run(|| {
+                let matcher = matcher.clone();
+                let tx = tx.clone();
+                let current_search = current_search.clone();
+                let total = total.clone();
+                let limit_reached = limit_reached.clone();
+                let mut searcher = SearcherBuilder::new().build();
+                Box::new(move |entry| {
+                    if current_search.load(Ordering::SeqCst) != search_id {
+                        return WalkState::Quit;
+                    }
+                    let entry = match entry {
+                        Ok(entry) => entry,
+                        Err(_) => return WalkState::Continue,
+                    };
+                    if !entry.file_type().map(|t| t.is_file()).unwrap_or(false) {
+                        return WalkState::Continue;
+                    }
+
+                    let path = entry.into_path();
+                    let mut line_matches = search_file(
+                        &mut searcher,
+                        &matcher,
+                        &path,
+                        max_file_matches,
+                    );
+                    if line_matches.is_empty() {
+                        return WalkState::Continue;
+                    }
+
+                    let found =
+                        total.fetch_add(line_matches.len(), Ordering::SeqCst);
+                    if found >= max_matches {
+                        limit_reached.store(true, Ordering::SeqCst);
+                        return WalkState::Quit;
+                    }
+                    line_matches.truncate(max_matches - found);
+                    let quit = found + line_matches.len() >= max_matches;
+                    let _ = tx.send((path, line_matches));
+                    if quit {
+                        limit_reached.store(true, Ordering::SeqCst);
+                        return WalkState::Quit;
+                    }
+                    WalkState::Continue
+                })
+            });
+        });
+    }
+
+    let mut batch = HashMap::new();
+    let mut last_sent = Instant::now();
+    loop {
+        let disconnected = match rx.recv_timeout(BATCH_INTERVAL) {
+            Ok((path, line_matches)) => {
+                batch.insert(path, line_matches);
+                false
+            }
+            Err(RecvTimeoutError::Timeout) => false,
+            Err(RecvTimeoutError::Disconnected) => true,
+        };
+        if current_search.load(Ordering::SeqCst) != search_id {
+            return;
+        }
+        if !batch.is_empty()
+            && (disconnected || last_sent.elapsed() >= BATCH_INTERVAL)
+        {
+            dispatcher.send_notification(
+                "global_search_result",
+                json!({
+                    "search_id": search_id,
+                    "matches": batch,
+                }),
+            );
+            batch = HashMap::new();
+            last_sent = Instant::now();
+        }
+        if disconnected {
+            break;
+        }
+    }
+    send_done(
+        dispatcher,
+        search_id,
+        limit_reached.load(Ordering::SeqCst),
+        None,
+    );
+}
+
+/// Builds the matcher for `pattern`, which is taken literally unless the
+/// options ask for a regex.
+fn search_matcher(pattern: &str, options: &SearchOptions) -> Result<RegexMatcher> {
+    let pattern = if options.is_regex {
+        pattern.to_string()
+    } else {
+        regex::escape(pattern)
+    };
+    Ok(RegexMatcherBuilder::new()
+        .case_insensitive(!options.case_sensitive)
+        .word(options.whole_word)
+        .build(&pattern)?)
+}
+
+/// Every match in the file at `path`, up to `max_file_matches`. A line with
+/// several matches gives one match for each of them.
+fn search_file(
+    searcher: &mut Searcher,
+    matcher: &RegexMatcher,
+    path: &Path,
+    max_file_matches: usize,
+) -> Vec<SearchMatch> {
+    let mut matches = Vec::new();
+    let _ = searcher.search_path(
+        matcher,
+        path,
+        UTF8(|lnum, line| {
+            matcher.find_iter(line.as_bytes(), |m| {
+                matches.push((
+                    lnum as usize,
+                    (m.start(), m.end()),
+                    line.to_string(),
+                ));
+                matches.len() < max_file_matches
+            })?;
+            Ok(matches.len() < max_file_matches)
+        }),
+    );
+    matches
+}
+
+/// Ends the search, with the reason it couldn't run when the pattern or a
+/// glob is invalid.
+fn send_done(
+    dispatcher: &Dispatcher,
+    search_id: u64,
+    limit_reached: bool,
+    error: Option<String>,
+) {
+    dispatcher.send_notification(
+        "global_search_done",
+        json!({
+            "search_id": search_id,
+            "limit_reached": limit_reached,
+            "error": error,
+        }),
+    );
+}
+
+/// Walks the workspace honouring ignore files and the include and exclude
+/// globs of the search options.
+fn search_walk(workspace: &Path, options: &SearchOptions) -> Result<WalkParallel> {
+    let mut overrides = OverrideBuilder::new(workspace);
+    for glob in options.include.iter() {
+        overrides.add(glob)?;
+    }
+    for glob in options.exclude.iter() {
+        overrides.add(&format!("!{glob}"))?;
+    }
+    Ok(WalkBuilder::new(workspace)
+        .overrides(overrides.build()?)
+        .build_parallel())
+}
+
+/// Applies the replacements to files that aren't open in the editor and
+/// returns how many files were changed.
+pub fn replace_in_files(files: HashMap<PathBuf, Vec<SearchReplacement>>) -> usize {
+    let mut changed = 0;
+    for (path, mut replacements) in files {
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(_) => continue,
+        };
+        let mut lines: Vec<String> = content
+            .split_inclusive('\n')
+            .map(|l| l.to_string())
+            .collect();
+        let mut file_changed = false;
+        // Back to front, so earlier byte offsets on a line stay valid.
+        replacements.sort_by_key(|r| (r.line, r.start));
+        for replacement in replacements.into_iter().rev() {
+            if let Some(line) = replacement
+                .line
+                .checked_sub(1)
+                .and_then(|line| lines.get_mut(line))
+            {
+                if line.get(replacement.start..replacement.end)
+                    == Some(replacement.old.as_str())
+                {
+                    line.replace_range(
+                        replacement.start..replacement.end,
+                        &replacement.new,
+                    );
+                    file_changed = true;
+                }
+            }
+        }
+        if file_changed && fs::write(&path, lines.concat()).is_ok() {
+            changed += 1;
+        }
+    }
+    changed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn search(
+        content: &str,
+        pattern: &str,
+        options: &SearchOptions,
+    ) -> Vec<SearchMatch> {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("file.txt");
+        fs::write(&path, content).unwrap();
+        let matcher = search_matcher(pattern, options).unwrap();
+        let mut searcher = SearcherBuilder::new().build();
+        search_file(&mut searcher, &matcher, &path, 100)
+    }
+
+    fn replacement(
+        line: usize,
+        start: usize,
+        old: &str,
+        new: &str,
+    ) -> SearchReplacement {
+        SearchReplacement {
+            line,
+            start,
+            end: start + old.len(),
+            old: old.to_string(),
+            new: new.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_invalid_search() {
+        let regex = SearchOptions {
+            is_regex: true,
+            ..Default::default()
+        };
+        assert!(search_matcher("foo(", &regex).is_err());
+        assert!(search_matcher("foo(", &SearchOptions::default()).is_ok());
+
+        let dir = tempfile::tempdir().unwrap();
+        let options = SearchOptions {
+            exclude: vec!["src/{a,b".to_string()],
+            ..Default::default()
+        };
+        assert!(search_walk(dir.path(), &options).is_err());
+        assert!(search_walk(dir.path(), &SearchOptions::default()).is_ok());
+    }
+
+    #[test]
+    fn test_search_every_match_on_a_line() {
+        let matches = search("foo bar foo\nbar\n", "foo", &SearchOptions::default());
+        assert_eq!(
+            matches,
+            vec![
+                (1, (0, 3), "foo bar foo\n".to_string()),
+                (1, (8, 11), "foo bar foo\n".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_search_options() {
+        let options = SearchOptions {
+            whole_word: true,
+            ..Default::default()
+        };
+        let matches = search("Foo food foo\n", "foo", &options);
+        assert_eq!(
+            matches,
+            vec![
+                (1, (0, 3), "Foo food foo\n".to_string()),
+                (1, (9, 12), "Foo food foo\n".to_string()),
+            ]
+        );
+
+        let options = SearchOptions {
+            case_sensitive: true,
+            ..Default::default()
+        };
+        assert_eq!(search("Foo foo\n", "foo", &options).len(), 1);
+        assert_eq!(search("a.c abc\n", "a.c", &options).len(), 1);
+
+        let options = SearchOptions {
+            is_regex: true,
+            ..Default::default()
+        };
+        assert_eq!(search("a.c abc\n", "a.c", &options).len(), 2);
+    }
+
+    #[test]
+    fn test_search_max_file_matches() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("file.txt");
+        fs::write(&path, "a a a\na a\n").unwrap();
+        let matcher = search_matcher("a", &SearchOptions::default()).unwrap();
+        let mut searcher = SearcherBuilder::new().build();
+        assert_eq!(search_file(&mut searcher, &matcher, &path, 2).len(), 2);
+        assert_eq!(search_file(&mut searcher, &matcher, &path, 10).len(), 5);
+    }
+
+    #[test]
+    fn test_replace_in_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("file.txt");
+        fs::write(&path, "foo bar foo\nfoo\n").unwrap();
+        let unchanged = dir.path().join("unchanged.txt");
+        fs::write(&unchanged, "bar\n").unwrap();
+
+        let mut files = HashMap::new();
+        files.insert(
+            path.clone(),
+            vec![
+                replacement(1, 0, "foo", "a"),
+                replacement(1, 8, "foo", "bc"),
+                replacement(2, 0, "foo", ""),
+            ],
+        );
+        // The file doesn't have `foo` there any more, so it's left alone.
+        files.insert(unchanged.clone(), vec![replacement(1, 0, "foo", "a")]);
+        files.insert(dir.path().join("missing.txt"), vec![]);
+
+        assert_eq!(replace_in_files(files), 1);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "a bar bc\n\n");
+        assert_eq!(fs::read_to_string(&unchanged).unwrap(), "bar\n");
+    }
+}
@@ -2,7 +2,7 @@ use std::{
     borrow::Cow,
     collections::VecDeque,
     io::{self, ErrorKind, Read, Write},
-    path::PathBuf,
+    path::{Path, PathBuf},
 };
 
 use alacritty_terminal::{
@@ -14,7 +14,7 @@ use alacritty_terminal::{
     tty::{self, setup_env, EventedPty, EventedReadWrite},
 };
 use directories::BaseDirs;
-use lapce_rpc::terminal::TermId;
+use lapce_rpc::terminal::{TermId, TerminalProfile};
 #[cfg(not(windows))]
 use mio::unix::UnixReady;
 #[allow(deprecated)]
@@ -46,25 +46,30 @@ impl Terminal {
     pub fn new(
         term_id: TermId,
         cwd: Option<PathBuf>,
-        shell: String,
+        profile: TerminalProfile,
         width: usize,
         height: usize,
     ) -> Terminal {
         let poll = mio::Poll::new().unwrap();
         let mut config = TermConfig::default();
+        // A directory restored from the last session may be gone by now.
         config.pty_config.working_directory =
-            cwd.or_else(|| BaseDirs::new().map(|d| PathBuf::from(d.home_dir())));
-        let shell = shell.trim();
-        if !shell.is_empty() {
-            let mut parts = shell.split(' ');
-            let program = parts.next().unwrap();
-            if let Ok(p) = which::which(program) {
-                config.pty_config.shell = Some(Program::WithArgs {
-                    program: p.to_str().unwrap().to_string(),
-                    args: parts.map(|p| p.to_string()).collect::<Vec<String>>(),
-                })
+            working_directory(cwd, profile.cwd.as_deref())
+                .filter(|dir| dir.is_dir())
+                .or_else(|| BaseDirs::new().map(|d| PathBuf::from(d.home_dir())));
+        if profile.report_exit_code {
+            config.pty_config.shell =
+                Some(reporting_program(&profile.program, &profile.args));
+        } else if !profile.program.is_empty() {
+            if let Ok(p) = which::which(&profile.program) {
+                config.pty_config.shell =
+                    Some(shell_program(p.to_str().unwrap(), &profile.args));
             }
         }
+        config.pty_config.shell =
+            with_env(config.pty_config.shell.take(), &profile.env);
+        // Only TERM and COLORTERM are left for it to set, which are the same
+        // for every terminal.
         setup_env(&config);
 
         #[cfg(target_os = "macos")]
@@ -118,6 +123,14 @@ impl Terminal {
                         if let Some(tty::ChildEvent::Exited) =
                             self.pty.next_child_event()
                         {
+                            // What the program wrote right before it exited
+                            // may not have been read yet.
+                            while let Ok(n) = self.pty.reader().read(&mut buf) {
+                                if n == 0 {
+                                    break;
+                                }
+                                self.send_content(&dispatcher, &buf[..n]);
+                            }
                             dispatcher.send_notification(
                                 "close_terminal",
                                 json!({
@@ -140,13 +153,7 @@ impl Terminal {
                         if event.readiness().is_readable() {
                             match self.pty.reader().read(&mut buf) {
                                 Ok(n) => {
-                                    dispatcher.send_notification(
-                                        "update_terminal",
-                                        json!({
-                                            "term_id": self.term_id,
-                                            "content": base64::encode(&buf[..n]),
-                                        }),
-                                    );
+                                    self.send_content(&dispatcher, &buf[..n]);
                                 }
                                 Err(_e) => (),
                             }
@@ -174,6 +181,16 @@ impl Terminal {
         let _ = self.pty.deregister(&self.poll);
     }
 
+    fn send_content(&self, dispatcher: &Dispatcher, content: &[u8]) {
+        dispatcher.send_notification(
+            "update_terminal",
+            json!({
+                "term_id": self.term_id,
+                "content": base64::encode(content),
+            }),
+        );
+    }
+
     /// Drain the channel.
     ///
     /// Returns `false` when a shutdown message was received.
@@ -311,6 +328,169 @@ impl State {
     }
 }
 
+/// Where the shell starts: the profile's directory, relative to the
+/// workspace `cwd`, or `cwd`, or the home directory.
+fn working_directory(
+    cwd: Option<PathBuf>,
+    profile_cwd: Option<&Path>,
+) -> Option<PathBuf> {
+    let home = BaseDirs::new().map(|d| PathBuf::from(d.home_dir()));
+    let dir = match profile_cwd {
+        Some(dir) => match dir.strip_prefix("~") {
+            Ok(rest) => home?.join(rest),
+            Err(_) => match cwd.as_ref() {
+                Some(cwd) => cwd.join(dir),
+                None => dir.to_path_buf(),
+            },
+        },
+        None => return cwd.or(home),
+    };
+    Some(dir)
+}
+
+/// On Windows the program and args are joined with spaces into the command
+/// line of the shell, so they are quoted for it to split them back the same.
+fn shell_program(program: &str, args: &[String]) -> Program {
+    #[cfg(windows)]
+    let (program, args): (String, Vec<String>) = (
+        quote_arg(program),
+        args.iter().map(|arg| quote_arg(arg)).collect(),
+    );
+    #[cfg(not(windows))]
+    let (program, args) = (program.to_string(), args.to_vec());
+    Program::WithArgs { program, args }
+}
+
+/// The program and its arguments, the default shell when it's `None`.
+fn program_and_args(program: Option<Program>) -> (String, Vec<String>) {
+    match program {
+        Some(Program::Just(program)) => (program, Vec::new()),
+        Some(Program::WithArgs { program, args }) => (program, args),
+        #[cfg(not(windows))]
+        None => (
+            std::env::var("SHELL").unwrap_or_else(|_| "/bin/sh".to_string()),
+            Vec::new(),
+        ),
+        #[cfg(windows)]
+        None => ("powershell".to_string(), Vec::new()),
+    }
+}
+
+/// Runs the program through `env` with the environment variables of the
+/// profile. Setting them in the proxy instead would leak them into the
+/// terminals of other profiles, which inherit its environment.
+#[cfg(not(windows))]
+fn with_env(program: Option<Program>, env: &[(String, String)]) -> Option<Program> {
+    if env.is_empty() {
+        return program;
+    }
+    let (program, args) = program_and_args(program);
+    let mut env_args = vec!["--".to_string()];
+    env_args.extend(env.iter().map(|(key, value)| format!("{key}={value}")));
+    env_args.push(program);
+    env_args.extend(args);
+    Some(Program::WithArgs {
+        program: "env".to_string(),
+        args: env_args,
+    })
+}
+
+/// Runs the program through `cmd`, which sets the environment variables of
+/// the profile first. The program and args are quoted already.
+#[cfg(windows)]
+fn with_env(program: Option<Program>, env: &[(String, String)]) -> Option<Program> {
+    if env.is_empty() {
+        return program;
+    }
+    let (program, args) = program_and_args(program);
+    let mut cmd_args = vec!["/c".to_string()];
+    cmd_args.extend(
+        env.iter()
+            .map(|(key, value)| format!("set \"{key}={value}\"&&")),
+    );
+    cmd_args.push(program);
+    cmd_args.extend(args);
+    Some(Program::WithArgs {
+        program: "cmd".to_string(),
+        args: cmd_args,
+    })
+}
+
+/// Runs the program through a shell that writes OSC 133 sequences around
+/// it, the last one with its exit code. The arguments are passed on as they
+/// are, without going through the parsing of the shell.
+#[cfg(not(windows))]
+fn reporting_program(program: &str, args: &[String]) -> Program {
+    let script = r#"printf '\033]133;A\007\033]133;C\007'; "$@"; printf '\033]133;D;%d\007' "$?""#;
+    let mut shell_args = vec![
+        "-c".to_string(),
+        script.to_string(),
+        "sh".to_string(),
+        program.to_string(),
+    ];
+    shell_args.extend(args.iter().cloned());
+    Program::WithArgs {
+        program: "sh".to_string(),
+        args: shell_args,
+    }
+}
+
+/// Runs the program through PowerShell, which writes OSC 133 sequences
+/// around it, the last one with its exit code. Single quoted strings of
+/// PowerShell only need their quotes doubled.
+#[cfg(windows)]
+fn reporting_program(program: &str, args: &[String]) -> Program {
+    let quote = |arg: &str| format!("'{}'", arg.replace('\'', "''"));
+    let mut command = format!("& {}", quote(program));
+    for arg in args {
+        command.push(' ');
+        command.push_str(&quote(arg));
+    }
+    let osc = |params: &str| format!("[char]27 + ']133;{params}' + [char]7");
+    let script = format!(
+        "[Console]::Write({} + {}); {command}; $ok = $?; \
+         $code = if ($LASTEXITCODE -ne $null) {{ $LASTEXITCODE }} \
+         elseif ($ok) {{ 0 }} else {{ 1 }}; \
+         [Console]::Write({} + $code + [char]7)",
+        osc("A"),
+        osc("C"),
+        "[char]27 + ']133;D;'",
+    );
+    shell_program(
+        "powershell",
+        &["-NoProfile".to_string(), "-Command".to_string(), script],
+    )
+}
+
+/// Quotes an argument of a Windows command line the way the C runtime
+/// parses it, where backslashes only escape quotes.
+#[cfg(windows)]
+fn quote_arg(arg: &str) -> String {
+    if !arg.is_empty() && !arg.contains(&[' ', '\t', '"'][..]) {
+        return arg.to_string();
+    }
+    let mut quoted = String::from('"');
+    let mut backslashes = 0;
+    for c in arg.chars() {
+        if c == '\\' {
+            backslashes += 1;
+            continue;
+        }
+        let escapes = if c == '"' {
+            backslashes * 2 + 1
+        } else {
+            backslashes
+        };
+        quoted.extend(std::iter::repeat('\\').take(escapes));
+        quoted.push(c);
+        backslashes = 0;
+    }
+    // The closing quote mustn't be escaped by trailing backslashes.
+    quoted.extend(std::iter::repeat('\\').take(backslashes * 2));
+    quoted.push('"');
+    quoted
+}
+
 #[allow(dead_code)]
 fn set_locale_environment() {
     let locale = locale_config::Locale::global_default()
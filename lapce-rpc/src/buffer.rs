@@ -15,6 +15,26 @@ impl BufferId {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NewBufferResponse {
     pub content: String,
+    /// The file exceeded the large file threshold, so `content` is empty
+    /// and the text has to be fetched with `buffer_chunk` requests.
+    pub large_file: bool,
+    /// The file exists but can't be written to.
+    pub read_only: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BufferChunkResponse {
+    pub content: String,
+    pub done: bool,
+}
+
+/// A version of a file kept in the local file history.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileHistoryVersion {
+    pub id: String,
+    /// When the version was saved, in ms since the unix epoch.
+    pub time: u64,
+    pub hash: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -4,7 +4,8 @@ use std::{collections::HashMap, path::PathBuf};
 
 use crate::{
     buffer::BufferId, file::FileNodeItem, plugin::PluginDescription,
-    source_control::DiffInfo, style::LineStyle, terminal::TermId,
+    search::SearchMatch, source_control::DiffInfo, style::LineStyle,
+    terminal::TermId,
 };
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -42,8 +43,9 @@ pub enum CoreNotification {
     DiffFiles {
         files: Vec<PathBuf>,
     },
+    /// The diff info of every repository in the workspace.
     DiffInfo {
-        diff: DiffInfo,
+        repositories: Vec<DiffInfo>,
     },
     UpdateTerminal {
         term_id: TermId,
@@ -52,7 +54,28 @@ pub enum CoreNotification {
     CloseTerminal {
         term_id: TermId,
     },
+    GlobalSearchResult {
+        search_id: u64,
+        matches: HashMap<PathBuf, Vec<SearchMatch>>,
+    },
+    GlobalSearchDone {
+        search_id: u64,
+        limit_reached: bool,
+        /// Why the search couldn't run, like an invalid regex or glob.
+        error: Option<String>,
+    },
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub enum CoreRequest {}
+#[serde(rename_all = "snake_case")]
+#[serde(tag = "method", content = "params")]
+pub enum CoreRequest {
+    /// Asks the user for credentials to a git remote, responded to with
+    /// `Option<GitCredentials>`. With `ssh_key` only the key's passphrase is
+    /// asked for.
+    GitCredentials {
+        url: String,
+        username: Option<String>,
+        ssh_key: Option<PathBuf>,
+    },
+}
@@ -5,6 +5,7 @@ pub mod file;
 mod parse;
 pub mod plugin;
 pub mod proxy;
+pub mod search;
 pub mod source_control;
 mod stdio;
 pub mod style;
@@ -76,7 +77,9 @@ pub trait Handler {
     type Request: DeserializeOwned;
 
     fn handle_notification(&mut self, rpc: Self::Notification) -> ControlFlow;
-    fn handle_request(&mut self, rpc: Self::Request) -> Result<Value, Value>;
+    /// Handles the request `id`, which is answered with
+    /// [`RpcHandler::respond`], possibly later and from another thread.
+    fn handle_request(&mut self, id: u64, rpc: Self::Request);
 }
 
 #[derive(Clone)]
@@ -114,8 +117,7 @@ impl RpcHandler {
             } else {
                 match rpc.into_rpc::<H::Notification, H::Request>() {
                     Ok(Call::Request(id, request)) => {
-                        let result = handler.handle_request(request);
-                        self.respond(id, result);
+                        handler.handle_request(id, request);
                     }
                     Ok(Call::Notification(notification)) => {
                         if handler.handle_notification(notification)
@@ -189,7 +191,7 @@ impl RpcHandler {
         }
     }
 
-    fn respond(&self, id: u64, result: Result<Value, Value>) {
+    pub fn respond(&self, id: u64, result: Result<Value, Value>) {
         let mut response = json!({ "id": id });
         match result {
             Ok(result) => response["result"] = result,
@@ -1,12 +1,15 @@
-use std::path::PathBuf;
+use std::{collections::HashMap, path::PathBuf};
 
 use lsp_types::{CompletionItem, Position};
 use serde::{Deserialize, Serialize};
 use xi_rope::RopeDelta;
 
 use crate::{
-    buffer::BufferId, plugin::PluginDescription, source_control::FileDiff,
-    terminal::TermId,
+    buffer::BufferId,
+    plugin::PluginDescription,
+    search::{SearchOptions, SearchReplacement},
+    source_control::{DiffBase, FileDiff, GitHunk},
+    terminal::{TermId, TerminalProfile},
 };
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -17,6 +20,14 @@ pub enum ProxyNotification {
         workspace: PathBuf,
     },
     Shutdown {},
+    GlobalSearch {
+        search_id: u64,
+        pattern: String,
+        options: SearchOptions,
+        max_file_matches: usize,
+        max_matches: usize,
+    },
+    CancelGlobalSearch {},
     Update {
         buffer_id: BufferId,
         delta: RopeDelta,
@@ -25,15 +36,11 @@ pub enum ProxyNotification {
     NewTerminal {
         term_id: TermId,
         cwd: Option<PathBuf>,
-        shell: String,
+        profile: TerminalProfile,
     },
     InstallPlugin {
         plugin: PluginDescription,
     },
-    GitCommit {
-        message: String,
-        diffs: Vec<FileDiff>,
-    },
     TerminalWrite {
         term_id: TermId,
         content: String,
@@ -46,6 +53,9 @@ pub enum ProxyNotification {
     TerminalClose {
         term_id: TermId,
     },
+    GitCloseSnapshot {
+        path: PathBuf,
+    },
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -55,19 +65,129 @@ pub enum ProxyRequest {
     NewBuffer {
         buffer_id: BufferId,
         path: PathBuf,
+        large_file_size: u64,
+    },
+    BufferChunk {
+        buffer_id: BufferId,
+        offset: usize,
     },
+    /// The content of the file the buffer's changes are shown against.
     BufferHead {
         buffer_id: BufferId,
         path: PathBuf,
+        base: DiffBase,
+    },
+    FileHistory {
+        path: PathBuf,
+    },
+    FileHistoryVersion {
+        path: PathBuf,
+        version: String,
+    },
+    ReplaceInFiles {
+        files: HashMap<PathBuf, Vec<SearchReplacement>>,
+    },
+    GitBranches {
+        repository: PathBuf,
+    },
+    GitCheckout {
+        repository: PathBuf,
+        branch: String,
+        force: bool,
+    },
+    GitCreateBranch {
+        repository: PathBuf,
+        name: String,
+        start_point: Option<String>,
+    },
+    GitDeleteBranch {
+        repository: PathBuf,
+        name: String,
+    },
+    GitStage {
+        paths: Vec<PathBuf>,
+    },
+    GitUnstage {
+        paths: Vec<PathBuf>,
+    },
+    GitDiscard {
+        paths: Vec<PathBuf>,
+    },
+    GitResolve {
+        path: PathBuf,
+    },
+    GitStageHunk {
+        path: PathBuf,
+        hunk: GitHunk,
+    },
+    GitBlame {
+        path: PathBuf,
+    },
+    /// The branches, tags and recent commits a file can be compared with.
+    GitRevisions {
+        path: PathBuf,
+    },
+    /// The commits of `repository`, or of the repository of `path` changing
+    /// it when there is one.
+    GitLog {
+        repository: PathBuf,
+        path: Option<PathBuf>,
+        skip: usize,
+        limit: usize,
+    },
+    GitCommit {
+        repository: PathBuf,
+        message: String,
+        diffs: Vec<FileDiff>,
+        amend: bool,
+        sign_off: bool,
+    },
+    GitCommitFiles {
+        repository: PathBuf,
+        commit: String,
+    },
+    GitCommitFile {
+        commit: String,
+        path: PathBuf,
+        parent_path: PathBuf,
+    },
+    GitStashSave {
+        repository: PathBuf,
+        message: String,
+        include_untracked: bool,
+    },
+    GitStashes {
+        repository: PathBuf,
+    },
+    GitStashApply {
+        repository: PathBuf,
+        index: usize,
+    },
+    GitStashPop {
+        repository: PathBuf,
+        index: usize,
+    },
+    GitStashDrop {
+        repository: PathBuf,
+        index: usize,
+    },
+    GitFetch {
+        repository: PathBuf,
+    },
+    /// Fetches and brings the current branch up to date with its upstream,
+    /// fast-forwarding or, with `rebase`, rebasing the local commits.
+    GitPull {
+        repository: PathBuf,
+        rebase: bool,
+    },
+    GitPush {
+        repository: PathBuf,
     },
     GetCompletion {
         request_id: usize,
         buffer_id: BufferId,
         position: Position,
     },
-    GlobalSearch {
-        pattern: String,
-    },
     CompletionResolve {
         buffer_id: BufferId,
         completion_item: Box<CompletionItem>,
@@ -106,8 +226,15 @@ pub enum ProxyRequest {
     ReadDir {
         path: PathBuf,
     },
+    /// The content of a file that isn't opened in an editor, like the
+    /// workspace configuration.
+    ReadFile {
+        path: PathBuf,
+    },
     Save {
         rev: u64,
         buffer_id: BufferId,
+        /// Don't recreate the file if it was deleted on disk.
+        only_if_exists: bool,
     },
 }
@@ -0,0 +1,40 @@
+use serde::{Deserialize, Serialize};
+
+/// A match of a global search: the line number, the byte range of the
+/// match within the line, and the line itself.
+pub type SearchMatch = (usize, (usize, usize), String);
+
+/// Replaces `old` at the byte range `start..end` of the 1-based line `line`
+/// with `new`. The replacement is skipped if the file no longer has `old`
+/// there.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SearchReplacement {
+    pub line: usize,
+    pub start: usize,
+    pub end: usize,
+    pub old: String,
+    pub new: String,
+}
+
+/// Options shared by the global search and the in-editor find.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SearchOptions {
+    pub case_sensitive: bool,
+    pub whole_word: bool,
+    pub is_regex: bool,
+    /// Globs a file path has to match to be searched. Empty means all files.
+    pub include: Vec<String>,
+    /// Globs of file paths that are never searched.
+    pub exclude: Vec<String>,
+}
+
+impl SearchOptions {
+    /// Splits a comma separated list of globs, as typed in the search panel.
+    pub fn parse_globs(s: &str) -> Vec<String> {
+        s.split(',')
+            .map(|glob| glob.trim())
+            .filter(|glob| !glob.is_empty())
+            .map(|glob| glob.to_string())
+            .collect()
+    }
+}
@@ -4,9 +4,123 @@ use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
 pub struct DiffInfo {
+    /// The worktree of the repository.
+    pub path: PathBuf,
     pub head: String,
     pub branches: Vec<String>,
+    /// Changes in the worktree that aren't staged.
     pub diffs: Vec<FileDiff>,
+    /// Changes in the index.
+    pub staged: Vec<FileDiff>,
+    /// Files with unresolved merge conflicts, which are in neither `diffs`
+    /// nor `staged`.
+    pub conflicts: Vec<FileDiff>,
+    /// Files git doesn't track yet, which `diffs` lists as added.
+    pub untracked: Vec<PathBuf>,
+    /// Ignored files and folders. The content of an ignored folder isn't
+    /// listed on its own.
+    pub ignored: Vec<PathBuf>,
+    pub stashes: usize,
+    /// Commits HEAD has that its upstream branch doesn't.
+    pub ahead: usize,
+    /// Commits the upstream branch has that HEAD doesn't.
+    pub behind: usize,
+}
+
+/// What the editor shows the changes of a file against.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub enum DiffBase {
+    Head,
+    /// The staged content of the file.
+    Index,
+    /// A branch, tag or commit, anything git resolves to a commit.
+    Revision(String),
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum GitRevisionKind {
+    Branch,
+    RemoteBranch,
+    Tag,
+    Commit,
+}
+
+/// A branch, tag or commit a file can be compared with.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct GitRevision {
+    /// The name of the branch or tag, or the id of the commit.
+    pub name: String,
+    pub kind: GitRevisionKind,
+    /// The summary of the commit the revision points at.
+    pub summary: String,
+}
+
+/// A change of a file against HEAD: `old_lines` lines of HEAD starting at the
+/// 0-based line `old_start` were replaced with `new_content`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct GitHunk {
+    pub old_start: usize,
+    pub old_lines: usize,
+    pub new_content: String,
+}
+
+/// Consecutive lines of a file that were last changed by the same commit.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct BlameHunk {
+    /// The 0-based line the hunk starts at.
+    pub start_line: usize,
+    pub lines: usize,
+    pub commit_id: String,
+    pub author: String,
+    /// Seconds since the unix epoch.
+    pub time: i64,
+    pub summary: String,
+    /// The lines were changed in the worktree or the unsaved buffer.
+    pub uncommitted: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct GitCommit {
+    pub id: String,
+    pub author: String,
+    /// Seconds since the unix epoch.
+    pub time: i64,
+    pub summary: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct GitStash {
+    /// The position in the stash list, 0 being the latest.
+    pub index: usize,
+    /// The stash's commit, which can be diffed like any other.
+    pub id: String,
+    pub message: String,
+}
+
+/// What the user entered when a git remote asked for credentials.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct GitCredentials {
+    pub username: String,
+    /// The password, or the passphrase of the ssh key.
+    pub password: String,
+}
+
+/// A file as a commit left it, next to its content before the commit.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct GitCommitFileResponse {
+    /// The path the proxy opens the file as of the commit under, which only
+    /// exists in memory.
+    pub snapshot: PathBuf,
+    pub parent_content: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct GitBranch {
+    pub name: String,
+    /// A remote tracking branch such as `origin/main`.
+    pub remote: bool,
+    /// The branch HEAD points at.
+    pub head: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
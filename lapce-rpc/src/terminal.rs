@@ -1,3 +1,5 @@
+use std::path::PathBuf;
+
 use serde::{Deserialize, Serialize};
 
 use crate::counter::Counter;
@@ -11,3 +13,21 @@ impl TermId {
         Self(TERMINAL_ID_COUNTER.next())
     }
 }
+
+/// What a terminal runs and where.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct TerminalProfile {
+    /// The shell, the default one of the system when it's empty.
+    pub program: String,
+    /// Passed to the shell as they are, without any splitting or unquoting.
+    pub args: Vec<String>,
+    /// Environment variables set for the shell.
+    pub env: Vec<(String, String)>,
+    /// The working directory, relative to the workspace, where `~` is the
+    /// home directory. The workspace when it isn't set.
+    pub cwd: Option<PathBuf>,
+    /// Runs the program through a shell that marks its output and exit code
+    /// with OSC 133, like shell integration does for each command. For tasks,
+    /// whose program may not exist.
+    pub report_exit_code: bool,
+}
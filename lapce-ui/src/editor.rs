@@ -1,5 +1,5 @@
 use std::time::Duration;
-use std::{iter::Iterator, sync::Arc, time::Instant};
+use std::{iter::Iterator, ops::Range, sync::Arc, time::Instant};
 
 use druid::TimerToken;
 use druid::{
@@ -11,7 +11,10 @@ use druid::{
     Widget, WidgetId,
 };
 use lapce_data::{
-    buffer::{matching_pair_direction, BufferContent, DiffLines, LocalBufferKind},
+    buffer::{
+        conflict::{ConflictBlock, ConflictChoice},
+        matching_pair_direction, BufferContent, DiffLines, LocalBufferKind,
+    },
     command::{
         CommandTarget, LapceCommand, LapceCommandNew, LapceUICommand,
         LapceWorkbenchCommand, LAPCE_UI_COMMAND,
@@ -22,9 +25,10 @@ use lapce_data::{
     menu::MenuItem,
     movement::{ColPosition, CursorMode, Movement, Selection},
     panel::PanelPosition,
+    source_control::blame_annotation,
     state::{Mode, VisualMode},
 };
-use lapce_rpc::buffer::BufferId;
+use lapce_rpc::{buffer::BufferId, source_control::BlameHunk};
 use lsp_types::{DiagnosticSeverity, DocumentChanges, TextEdit, Url, WorkspaceEdit};
 use strum::EnumMessage;
 
@@ -110,6 +114,10 @@ pub struct LapceEditor {
     /// A timer for listening for when the user has hovered for long enough to trigger showing
     /// of hover info (if there is any)
     mouse_hover_timer: TimerToken,
+    /// Where the blame of the cursor line was last painted.
+    inline_blame: Option<(Rect, BlameHunk)>,
+    /// Where the actions resolving merge conflicts were last painted.
+    conflict_actions: Vec<(Rect, ConflictBlock, ConflictChoice)>,
 }
 
 impl LapceEditor {
@@ -121,6 +129,8 @@ impl LapceEditor {
             last_left_click: None,
             mouse_pos: Point::ZERO,
             mouse_hover_timer: TimerToken::INVALID,
+            inline_blame: None,
+            conflict_actions: Vec::new(),
         }
     }
 
@@ -284,6 +294,9 @@ impl LapceEditor {
             BufferContent::Local(kind) => match kind {
                 LocalBufferKind::FilePicker
                 | LocalBufferKind::Search
+                | LocalBufferKind::SearchReplace
+                | LocalBufferKind::SearchInclude
+                | LocalBufferKind::SearchExclude
                 | LocalBufferKind::Settings
                 | LocalBufferKind::Keymap => Size::new(
                     editor_size.width.max(width * data.buffer.len() as f64),
@@ -462,6 +475,7 @@ impl LapceEditor {
         {
             Self::paint_cursor(data, ctx, is_focused, placeholder, char_width, env);
             Self::paint_find(data, ctx, char_width, env);
+            Self::paint_conflicts(data, ctx, line_height);
         }
         let self_size = ctx.size();
         let rect = ctx.region().bounding_box();
@@ -747,6 +761,158 @@ impl LapceEditor {
         }
     }
 
+    /// Highlights the two sides of each merge conflict, and their markers a
+    /// shade darker.
+    fn paint_conflicts(
+        data: &LapceEditorBufferData,
+        ctx: &mut PaintCtx,
+        line_height: f64,
+    ) {
+        if !data.is_conflicted() {
+            return;
+        }
+        let width = ctx.size().width;
+        let rect = ctx.region().bounding_box();
+        let start_line = (rect.y0 / line_height).floor() as usize;
+        let end_line = (rect.y1 / line_height).ceil() as usize;
+        let current = data
+            .config
+            .get_color_unchecked(LapceTheme::SOURCE_CONTROL_CONFLICT_CURRENT);
+        let incoming = data
+            .config
+            .get_color_unchecked(LapceTheme::SOURCE_CONTROL_CONFLICT_INCOMING);
+        let fill_lines = |ctx: &mut PaintCtx, lines: Range<usize>, color: &Color| {
+            ctx.fill(
+                Rect::new(
+                    0.0,
+                    lines.start as f64 * line_height,
+                    width,
+                    lines.end as f64 * line_height,
+                ),
+                color,
+            );
+        };
+        for conflict in data.buffer.conflicts().iter() {
+            if conflict.end < start_line || conflict.start > end_line {
+                continue;
+            }
+            fill_lines(ctx, conflict.start..conflict.current().end, current);
+            fill_lines(ctx, conflict.start..conflict.start + 1, current);
+            fill_lines(ctx, conflict.incoming().start..conflict.end + 1, incoming);
+            fill_lines(ctx, conflict.end..conflict.end + 1, incoming);
+        }
+    }
+
+    /// Paints the actions resolving each merge conflict after its first
+    /// marker, and returns where so that they can be clicked.
+    fn paint_conflict_actions(
+        data: &LapceEditorBufferData,
+        ctx: &mut PaintCtx,
+    ) -> Vec<(Rect, ConflictBlock, ConflictChoice)> {
+        let mut actions = Vec::new();
+        if data.editor.compare.is_some()
+            || data.editor.code_lens
+            || !data.is_conflicted()
+        {
+            return actions;
+        }
+
+        let line_height = data.config.editor.line_height as f64;
+        let char_width = data.config.editor_char_width(ctx.text());
+        let rect = ctx.region().bounding_box();
+        let dim = data.config.get_color_unchecked(LapceTheme::EDITOR_DIM);
+        for conflict in data.buffer.conflicts().iter() {
+            let y = conflict.start as f64 * line_height;
+            if y + line_height < rect.y0 || y > rect.y1 {
+                continue;
+            }
+            let col = data.buffer.line_end_col(
+                conflict.start,
+                true,
+                data.config.editor.tab_width,
+            );
+            let mut x = (col + 4) as f64 * char_width;
+            let choices = [
+                ("Accept Current Change", ConflictChoice::Current),
+                ("Accept Incoming Change", ConflictChoice::Incoming),
+                ("Accept Both Changes", ConflictChoice::Both),
+            ];
+            for (i, (text, choice)) in choices.into_iter().enumerate() {
+                let text = if i == 0 {
+                    text.to_string()
+                } else {
+                    format!(" | {text}")
+                };
+                let text_layout = ctx
+                    .text()
+                    .new_text_layout(text)
+                    .font(
+                        data.config.editor.font_family(),
+                        data.config.editor.font_size as f64,
+                    )
+                    .text_color(dim.clone())
+                    .build()
+                    .unwrap();
+                let size = text_layout.size();
+                let origin = Point::new(x, y + (line_height - size.height) / 2.0);
+                ctx.draw_text(&text_layout, origin);
+                actions.push((
+                    Rect::from_origin_size(origin, size),
+                    conflict.clone(),
+                    choice,
+                ));
+                x += size.width;
+            }
+        }
+        actions
+    }
+
+    /// Paints who last changed the cursor line after the end of the line,
+    /// and returns where so that clicking it can open the commit.
+    fn paint_inline_blame(
+        data: &LapceEditorBufferData,
+        ctx: &mut PaintCtx,
+        is_focused: bool,
+    ) -> Option<(Rect, BlameHunk)> {
+        if !data.config.editor.inline_blame
+            || !is_focused
+            || data.editor.content.is_input()
+            || data.editor.compare.is_some()
+            || data.editor.code_lens
+        {
+            return None;
+        }
+        let line = data.buffer.line_of_offset(data.editor.cursor.offset());
+        let hunk = data.buffer.blame_at_line(line)?;
+
+        let line_height = data.config.editor.line_height as f64;
+        let char_width = data.config.editor_char_width(ctx.text());
+        let col = data
+            .buffer
+            .line_end_col(line, true, data.config.editor.tab_width);
+        let text_layout = ctx
+            .text()
+            .new_text_layout(blame_annotation(hunk))
+            .font(
+                data.config.editor.font_family(),
+                data.config.editor.font_size as f64,
+            )
+            .text_color(
+                data.config
+                    .get_color_unchecked(LapceTheme::EDITOR_DIM)
+                    .clone(),
+            )
+            .build()
+            .unwrap();
+        let size = text_layout.size();
+        let origin = Point::new(
+            (col + 4) as f64 * char_width,
+            line_height * line as f64 + (line_height - size.height) / 2.0,
+        );
+        ctx.draw_text(&text_layout, origin);
+        Some((Rect::from_origin_size(origin, size), hunk.clone()))
+    }
+
     #[allow(clippy::too_many_arguments)]
     fn paint_cursor_on_line(
         data: &LapceEditorBufferData,
@@ -1618,7 +1784,20 @@ impl Widget<LapceTabData> for LapceEditor {
     ) {
         match event {
             Event::MouseMove(mouse_event) => {
-                ctx.set_cursor(&druid::Cursor::IBeam);
+                if self
+                    .inline_blame
+                    .as_ref()
+                    .map(|(rect, _)| rect.contains(mouse_event.pos))
+                    .unwrap_or(false)
+                    || self
+                        .conflict_actions
+                        .iter()
+                        .any(|(rect, _, _)| rect.contains(mouse_event.pos))
+                {
+                    ctx.set_cursor(&druid::Cursor::Pointer);
+                } else {
+                    ctx.set_cursor(&druid::Cursor::IBeam);
+                }
                 if mouse_event.pos != self.mouse_pos {
                     self.mouse_pos = mouse_event.pos;
                     // Get a new hover timer, overwriting the old one that will just be ignored
@@ -1648,10 +1827,38 @@ impl Widget<LapceTabData> for LapceEditor {
                 ctx.set_active(false);
             }
             Event::MouseDown(mouse_event) => {
+                if let Some((rect, hunk)) = self.inline_blame.as_ref() {
+                    let editor = data.main_split.editors.get(&self.view_id).unwrap();
+                    if let BufferContent::File(path) = &editor.content {
+                        if mouse_event.button.is_left()
+                            && rect.contains(mouse_event.pos)
+                        {
+                            let path = path.clone();
+                            data.main_split.open_blame_diff(ctx, &path, hunk);
+                            ctx.set_handled();
+                            return;
+                        }
+                    }
+                }
                 let buffer = data.main_split.editor_buffer(self.view_id);
                 let editor =
                     data.main_split.editors.get(&self.view_id).unwrap().clone();
                 let mut editor_data = data.editor_view_content(self.view_id);
+                let action = self
+                    .conflict_actions
+                    .iter()
+                    .find(|(rect, _, _)| rect.contains(mouse_event.pos))
+                    .filter(|_| mouse_event.button.is_left());
+                if let Some((_, conflict, choice)) = action {
+                    editor_data.resolve_conflict(ctx, conflict, *choice);
+                    data.update_from_editor_buffer_data(
+                        editor_data,
+                        &editor,
+                        &buffer,
+                    );
+                    ctx.set_handled();
+                    return;
+                }
                 self.mouse_down(ctx, mouse_event, &mut editor_data, &data.config);
                 data.update_from_editor_buffer_data(editor_data, &editor, &buffer);
                 // match mouse_event.button {
@@ -1820,6 +2027,8 @@ impl Widget<LapceTabData> for LapceEditor {
         let is_focused = data.focus == self.view_id;
         let data = data.editor_view_content(self.view_id);
         Self::paint_content(&data, ctx, is_focused, self.placeholder.as_ref(), env);
+        self.inline_blame = Self::paint_inline_blame(&data, ctx, is_focused);
+        self.conflict_actions = Self::paint_conflict_actions(&data, ctx);
     }
 }
 
@@ -1,26 +1,41 @@
+use std::sync::Arc;
+
 use crate::svg::get_svg;
 use druid::{
     piet::{Text, TextLayout, TextLayoutBuilder},
-    BoxConstraints, Env, Event, EventCtx, LayoutCtx, LifeCycle, LifeCycleCtx,
-    PaintCtx, Point, Rect, RenderContext, Size, UpdateCtx, Widget, WidgetId,
+    BoxConstraints, Command, Env, Event, EventCtx, LayoutCtx, LifeCycle,
+    LifeCycleCtx, PaintCtx, Point, Rect, RenderContext, Size, Target, UpdateCtx,
+    Widget, WidgetId,
 };
 use lapce_data::{
     buffer::DiffLines,
+    command::{
+        CommandTarget, LapceCommand, LapceCommandNew, LapceUICommand,
+        LAPCE_UI_COMMAND,
+    },
     config::LapceTheme,
     data::LapceTabData,
     editor::{LapceEditorBufferData, Syntax},
+    menu::MenuItem,
+    source_control::relative_time,
 };
+use strum::EnumMessage;
 
 pub struct LapceEditorGutter {
     view_id: WidgetId,
     width: f64,
+    blame_width: f64,
 }
 
 impl LapceEditorGutter {
+    /// How many characters wide the blame column is.
+    const BLAME_COLUMNS: usize = 28;
+
     pub fn new(view_id: WidgetId) -> Self {
         Self {
             view_id,
             width: 0.0,
+            blame_width: 0.0,
         }
     }
 }
@@ -28,11 +43,59 @@ impl LapceEditorGutter {
 impl Widget<LapceTabData> for LapceEditorGutter {
     fn event(
         &mut self,
-        _ctx: &mut EventCtx,
-        _event: &Event,
-        _data: &mut LapceTabData,
+        ctx: &mut EventCtx,
+        event: &Event,
+        data: &mut LapceTabData,
         _env: &Env,
     ) {
+        if let Event::MouseDown(mouse_event) = event {
+            let editor_data = data.editor_view_content(self.view_id);
+            if editor_data.editor.compare.is_some() || editor_data.editor.code_lens {
+                return;
+            }
+            // Only the diff markers between the line numbers and the text.
+            let char_width = data.config.editor_char_width(ctx.text());
+            if mouse_event.pos.x < self.width
+                || mouse_event.pos.x > self.width + char_width * 2.0
+            {
+                return;
+            }
+            let line_height = data.config.editor.line_height as f64;
+            let line = ((mouse_event.pos.y + editor_data.editor.scroll_offset.y)
+                / line_height)
+                .floor() as usize;
+            if editor_data.buffer.diff_hunk_at_line("head", line).is_none() {
+                return;
+            }
+
+            ctx.submit_command(Command::new(
+                LAPCE_UI_COMMAND,
+                LapceUICommand::JumpToLine(Some(self.view_id), line + 1),
+                Target::Widget(data.id),
+            ));
+            let menu_items: Vec<MenuItem> =
+                [LapceCommand::StageHunk, LapceCommand::RevertHunk]
+                    .iter()
+                    .map(|command| MenuItem {
+                        text: command.get_message().unwrap().to_string(),
+                        command: LapceCommandNew {
+                            cmd: command.to_string(),
+                            palette_desc: None,
+                            data: None,
+                            target: CommandTarget::Focus,
+                        },
+                    })
+                    .collect();
+            ctx.submit_command(Command::new(
+                LAPCE_UI_COMMAND,
+                LapceUICommand::ShowMenu(
+                    mouse_event.window_pos.round(),
+                    Arc::new(menu_items),
+                ),
+                Target::Auto,
+            ));
+            ctx.set_handled();
+        }
     }
 
     fn lifecycle(
@@ -94,6 +157,16 @@ impl Widget<LapceTabData> for LapceEditorGutter {
         if data.editor.compare.is_some() {
             width += self.width + char_width * 2.0;
         }
+        self.blame_width = if data.config.editor.blame_gutter
+            && data.editor.compare.is_none()
+            && !data.editor.code_lens
+            && !data.editor.content.is_input()
+        {
+            char_width * (Self::BLAME_COLUMNS + 1) as f64
+        } else {
+            0.0
+        };
+        width += self.blame_width;
         Size::new(width.ceil(), bc.max().height)
     }
 
@@ -464,6 +537,67 @@ impl LapceEditorGutter {
         }
     }
 
+    /// Paints the author and date of the commit that last changed each line
+    /// on the first line of every blame hunk, right before the text.
+    fn paint_blame(&self, data: &LapceEditorBufferData, ctx: &mut PaintCtx) {
+        let hunks = match data.buffer.blame() {
+            Some(hunks) => hunks,
+            None => return,
+        };
+        let line_height = data.config.editor.line_height as f64;
+        let scroll_offset = data.editor.scroll_offset;
+        let start_line = (scroll_offset.y / line_height).floor() as usize;
+        let end_line =
+            ((scroll_offset.y + ctx.size().height) / line_height).ceil() as usize;
+        let x = ctx.size().width - self.blame_width;
+        let current = data
+            .buffer
+            .blame_at_line(data.editor.cursor.current_line(data.buffer.data()))
+            .map(|hunk| hunk.start_line);
+
+        let start =
+            hunks.partition_point(|hunk| hunk.start_line + hunk.lines <= start_line);
+        for hunk in hunks[start..].iter() {
+            if hunk.start_line > end_line {
+                break;
+            }
+            let text = if hunk.uncommitted {
+                "You".to_string()
+            } else {
+                let date = relative_time(hunk.time);
+                let author: String = hunk
+                    .author
+                    .chars()
+                    .take(Self::BLAME_COLUMNS.saturating_sub(date.len() + 1))
+                    .collect();
+                format!("{author} {date}")
+            };
+            let text_layout = ctx
+                .text()
+                .new_text_layout(text)
+                .font(
+                    data.config.editor.font_family(),
+                    data.config.editor.font_size as f64,
+                )
+                .text_color(
+                    data.config
+                        .get_color_unchecked(if current == Some(hunk.start_line) {
+                            LapceTheme::EDITOR_FOREGROUND
+                        } else {
+                            LapceTheme::EDITOR_DIM
+                        })
+                        .clone(),
+                )
+                .build()
+                .unwrap();
+            // Hunks that start above the view keep their text on the top line.
+            let line = hunk.start_line.max(start_line);
+            let y = line_height * line as f64 - scroll_offset.y
+                + (line_height - text_layout.size().height) / 2.0;
+            ctx.draw_text(&text_layout, Point::new(x, y));
+        }
+    }
+
     fn paint_gutter(&self, data: &LapceEditorBufferData, ctx: &mut PaintCtx) {
         let rect = ctx.size().to_rect();
         ctx.with_save(|ctx| {
@@ -622,6 +756,9 @@ impl LapceEditorGutter {
             if *data.main_split.active == Some(self.view_id) {
                 self.paint_code_actions_hint(data, ctx);
             }
+            if self.blame_width > 0.0 {
+                self.paint_blame(data, ctx);
+            }
         });
     }
 }
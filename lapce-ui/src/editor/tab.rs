@@ -6,6 +6,7 @@ use druid::{
     Rect, RenderContext, Size, Target, UpdateCtx, Widget, WidgetId, WidgetPod,
 };
 use lapce_data::{
+    buffer::BufferContent,
     command::{LapceUICommand, LAPCE_UI_COMMAND},
     config::LapceTheme,
     data::{
@@ -116,7 +117,11 @@ impl LapceEditorTab {
         if delete {
             match removed_child {
                 EditorTabChild::Editor(view_id, _) => {
-                    data.main_split.editors.remove(&view_id);
+                    if let Some(editor) = data.main_split.editors.remove(&view_id) {
+                        if let BufferContent::File(path) = &editor.content {
+                            data.main_split.close_git_snapshot(path);
+                        }
+                    }
                 }
             }
         }
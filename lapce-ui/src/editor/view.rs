@@ -120,8 +120,20 @@ impl LapceEditorView {
                 LocalBufferKind::FilePicker => {
                     data.focus_area = FocusArea::FilePicker;
                 }
-                LocalBufferKind::Search => {
-                    data.focus_area = FocusArea::Panel(PanelKind::Search);
+                LocalBufferKind::Search
+                | LocalBufferKind::SearchReplace
+                | LocalBufferKind::SearchInclude
+                | LocalBufferKind::SearchExclude => {
+                    // The find bar of a terminal keeps the focus in its panel.
+                    let in_terminal =
+                        data.terminal.terminals.values().any(|terminal| {
+                            editor.parent_view_id == Some(terminal.widget_id)
+                        });
+                    data.focus_area = if in_terminal {
+                        FocusArea::Panel(PanelKind::Terminal)
+                    } else {
+                        FocusArea::Panel(PanelKind::Search)
+                    };
                 }
                 LocalBufferKind::SourceControl => {
                     data.focus_area = FocusArea::Panel(PanelKind::SourceControl);
@@ -632,6 +644,9 @@ impl Widget<LapceTabData> for LapceEditorView {
         if editor_data.buffer.dirty() != old_editor_data.buffer.dirty() {
             ctx.request_paint();
         }
+        if !editor_data.buffer.blame_same(&old_editor_data.buffer) {
+            ctx.request_paint();
+        }
         if editor_data.editor.cursor != old_editor_data.editor.cursor {
             ctx.request_paint();
         }
@@ -13,6 +13,7 @@ use lapce_data::{
     command::LAPCE_UI_COMMAND,
     config::{Config, LapceTheme},
     data::LapceTabData,
+    source_control::{GitDecorations, GitFileStatus},
     split::SplitDirection,
 };
 use lapce_data::{data::PanelKind, explorer::FileExplorerData};
@@ -40,6 +41,7 @@ pub fn paint_file_node_item(
     active: usize,
     hovered: Option<usize>,
     config: &Config,
+    decorations: &GitDecorations,
     toggle_rects: &mut HashMap<usize, Rect>,
 ) -> usize {
     if current > max {
@@ -108,6 +110,15 @@ pub fn paint_file_node_item(
                 .with_origin(Point::new(1.0 + 16.0 + padding, svg_y));
             ctx.draw_svg(&svg, rect, None);
         }
+        let status = if item.is_dir {
+            decorations.folder_status(&item.path_buf)
+        } else {
+            decorations.file_status(&item.path_buf)
+        };
+        let color = match status {
+            Some(status) => config.get_color_unchecked(status.theme_key()),
+            None => config.get_color_unchecked(LapceTheme::EDITOR_FOREGROUND),
+        };
         let text_layout = ctx
             .text()
             .new_text_layout(
@@ -119,11 +130,7 @@ pub fn paint_file_node_item(
                     .to_string(),
             )
             .font(FontFamily::SYSTEM_UI, 13.0)
-            .text_color(
-                config
-                    .get_color_unchecked(LapceTheme::EDITOR_FOREGROUND)
-                    .clone(),
-            )
+            .text_color(color.clone())
             .build()
             .unwrap();
         ctx.draw_text(
@@ -133,6 +140,31 @@ pub fn paint_file_node_item(
                 y + (line_height - text_layout.size().height) / 2.0,
             ),
         );
+
+        // Files get the letter of their status, folders only a dot telling
+        // they contain changes.
+        let badge = match status {
+            Some(GitFileStatus::Ignored) | None => None,
+            Some(_) if item.is_dir => Some("\u{2022}"),
+            Some(status) => Some(status.badge()),
+        };
+        if let Some(badge) = badge {
+            let text_layout = ctx
+                .text()
+                .new_text_layout(badge)
+                .font(FontFamily::SYSTEM_UI, 13.0)
+                .text_color(color.clone())
+                .build()
+                .unwrap();
+            let text_size = text_layout.size();
+            ctx.draw_text(
+                &text_layout,
+                Point::new(
+                    width - text_size.width - 10.0,
+                    y + (line_height - text_size.height) / 2.0,
+                ),
+            );
+        }
     }
     let mut i = current;
     if item.open {
@@ -149,6 +181,7 @@ pub fn paint_file_node_item(
                 active,
                 hovered,
                 config,
+                decorations,
                 toggle_rects,
             );
             if i > max {
@@ -479,6 +512,12 @@ impl Widget<LapceTabData> for FileExplorerFileList {
         {
             ctx.request_layout();
         }
+        if !Arc::ptr_eq(
+            &data.source_control.decorations,
+            &old_data.source_control.decorations,
+        ) {
+            ctx.request_paint();
+        }
     }
 
     fn layout(
@@ -522,6 +561,7 @@ impl Widget<LapceTabData> for FileExplorerFileList {
                     index,
                     self.hovered,
                     &data.config,
+                    &data.source_control.decorations,
                     &mut HashMap::new(),
                 );
                 if i > max {
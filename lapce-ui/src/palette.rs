@@ -12,6 +12,7 @@ use druid::{
 };
 use lapce_data::config::Config;
 use lapce_data::palette::PaletteItemContent;
+use lapce_data::source_control::commit_hint;
 use lapce_data::state::LapceWorkspaceType;
 use lapce_data::{
     command::{LapceUICommand, LAPCE_UI_COMMAND},
@@ -489,31 +490,38 @@ impl Widget<PaletteViewData> for NewPaletteInput {
         let text = data.palette.input.clone();
         let cursor = data.palette.cursor;
 
-        let text_layout = if text.is_empty()
-            && data.palette.palette_type == PaletteType::SshHost
-        {
-            ctx.text()
-                .new_text_layout("Enter your SSH details, like user@host")
-                .font(FontFamily::SYSTEM_UI, 14.0)
-                .text_color(
-                    data.config
-                        .get_color_unchecked(LapceTheme::EDITOR_DIM)
-                        .clone(),
-                )
-                .build()
-                .unwrap()
-        } else {
-            ctx.text()
-                .new_text_layout(text)
-                .font(FontFamily::SYSTEM_UI, 14.0)
-                .text_color(
-                    data.config
-                        .get_color_unchecked(LapceTheme::EDITOR_FOREGROUND)
-                        .clone(),
-                )
-                .build()
-                .unwrap()
+        let placeholder = match &data.palette.palette_type {
+            PaletteType::SshHost => Some("Enter your SSH details, like user@host"),
+            PaletteType::GitBranchName(_) => Some("Enter the new branch name"),
+            PaletteType::GitStashMessage(_) => {
+                Some("Enter the stash message, or leave it empty")
+            }
+            _ => None,
         };
+        let text_layout =
+            if let Some(placeholder) = placeholder.filter(|_| text.is_empty()) {
+                ctx.text()
+                    .new_text_layout(placeholder)
+                    .font(FontFamily::SYSTEM_UI, 14.0)
+                    .text_color(
+                        data.config
+                            .get_color_unchecked(LapceTheme::EDITOR_DIM)
+                            .clone(),
+                    )
+                    .build()
+                    .unwrap()
+            } else {
+                ctx.text()
+                    .new_text_layout(text)
+                    .font(FontFamily::SYSTEM_UI, 14.0)
+                    .text_color(
+                        data.config
+                            .get_color_unchecked(LapceTheme::EDITOR_FOREGROUND)
+                            .clone(),
+                    )
+                    .build()
+                    .unwrap()
+            };
 
         let line = text_layout.cursor_line_for_text_position(cursor);
         ctx.stroke(
@@ -634,6 +642,73 @@ impl NewPaletteContent {
                 "".to_string(),
                 vec![],
             ),
+            PaletteItemContent::LocalHistory { time, .. } => (
+                None,
+                time.clone(),
+                indices.to_vec(),
+                "".to_string(),
+                vec![],
+            ),
+            PaletteItemContent::GitBranch(_, name) => (
+                None,
+                name.clone(),
+                indices.to_vec(),
+                "".to_string(),
+                vec![],
+            ),
+            PaletteItemContent::GitCommit(commit) => {
+                let text = commit.summary.clone();
+                let hint = commit_hint(commit);
+                let text_indices = indices
+                    .iter()
+                    .filter(|i| **i < text.len())
+                    .copied()
+                    .collect();
+                let hint_indices = indices
+                    .iter()
+                    .filter(|i| **i >= text.len())
+                    .map(|i| i - text.len())
+                    .collect();
+                (None, text, text_indices, hint, hint_indices)
+            }
+            PaletteItemContent::GitLogMore { .. } => (
+                None,
+                "Load More Commits…".to_string(),
+                vec![],
+                "".to_string(),
+                vec![],
+            ),
+            PaletteItemContent::GitCommitFile { path, .. } => {
+                Self::file_paint_items(path, indices)
+            }
+            PaletteItemContent::GitDiffBase { name, hint, .. } => {
+                let text_indices = indices
+                    .iter()
+                    .filter(|i| **i < name.len())
+                    .copied()
+                    .collect();
+                let hint_indices = indices
+                    .iter()
+                    .filter(|i| **i > name.len())
+                    .map(|i| i - name.len() - 1)
+                    .collect();
+                (None, name.clone(), text_indices, hint.clone(), hint_indices)
+            }
+            PaletteItemContent::TerminalProfile { name, hint }
+            | PaletteItemContent::Task { name, hint } => (
+                None,
+                name.clone(),
+                indices.to_vec(),
+                hint.clone(),
+                vec![],
+            ),
+            PaletteItemContent::GitStash(_, stash) => (
+                None,
+                stash.message.clone(),
+                indices.to_vec(),
+                format!("stash@{{{}}}", stash.index),
+                vec![],
+            ),
         };
 
         if let Some(svg) = svg.as_ref() {
@@ -488,11 +488,16 @@ impl Widget<LapceTabData> for PanelMainHeader {
 
     fn update(
         &mut self,
-        _ctx: &mut UpdateCtx,
-        _old_data: &LapceTabData,
-        _data: &LapceTabData,
+        ctx: &mut UpdateCtx,
+        old_data: &LapceTabData,
+        data: &LapceTabData,
         _env: &Env,
     ) {
+        if self.kind == PanelKind::SourceControl
+            && old_data.source_control.stashes != data.source_control.stashes
+        {
+            ctx.request_paint();
+        }
     }
 
     fn layout(
@@ -526,9 +531,16 @@ impl Widget<LapceTabData> for PanelMainHeader {
                     .get_color_unchecked(LapceTheme::EDITOR_BACKGROUND),
             );
 
+            let stashes = data.source_control.stashes;
+            let text = if self.kind == PanelKind::SourceControl && stashes > 0 {
+                let plural = if stashes == 1 { "" } else { "es" };
+                format!("{} ({stashes} stash{plural})", self.text)
+            } else {
+                self.text.clone()
+            };
             let text_layout = ctx
                 .text()
-                .new_text_layout(self.text.clone())
+                .new_text_layout(text)
                 .font(FontFamily::SYSTEM_UI, data.config.editor.font_size as f64)
                 .text_color(
                     data.config
@@ -695,6 +695,7 @@ impl Widget<LapceTabData> for FilePickerExplorer {
                     index,
                     None,
                     &data.config,
+                    &data.source_control.decorations,
                     &mut self.toggle_rects,
                 );
                 if i > max {
@@ -2,10 +2,14 @@ use druid::{
     piet::{Text, TextAttribute, TextLayout as PietTextLayout, TextLayoutBuilder},
     BoxConstraints, Command, Cursor, Data, Env, Event, EventCtx, FontFamily,
     FontWeight, LayoutCtx, LifeCycle, LifeCycleCtx, MouseEvent, PaintCtx, Point,
-    RenderContext, Size, Target, UpdateCtx, Widget, WidgetExt, WidgetId,
+    Rect, RenderContext, Size, Target, UpdateCtx, Widget, WidgetExt, WidgetId,
+    WidgetPod,
 };
 use lapce_data::{
-    command::{LapceUICommand, LAPCE_UI_COMMAND},
+    command::{
+        CommandTarget, LapceCommandNew, LapceUICommand, LapceWorkbenchCommand,
+        LAPCE_NEW_COMMAND, LAPCE_UI_COMMAND,
+    },
     config::LapceTheme,
     data::{LapceTabData, PanelKind},
     editor::EditorLocationNew,
@@ -19,7 +23,7 @@ use crate::{
     panel::{LapcePanel, PanelHeaderKind},
     scroll::LapceScrollNew,
     split::LapceSplitNew,
-    svg::file_svg_new,
+    svg::{file_svg_new, get_svg},
 };
 
 #[derive(Clone)]
@@ -41,9 +45,52 @@ pub fn new_search_panel(data: &LapceTabData) -> LapcePanel {
         .hide_header()
         .hide_gutter()
         .padding((15.0, 15.0));
+    let replace = LapceEditorView::new(data.search.replace_view_id, None)
+        .hide_header()
+        .hide_gutter()
+        .padding((15.0, 5.0));
+    let include = LapceEditorView::new(data.search.include_view_id, None)
+        .hide_header()
+        .hide_gutter()
+        .padding((15.0, 5.0));
+    let exclude = LapceEditorView::new(data.search.exclude_view_id, None)
+        .hide_header()
+        .hide_gutter()
+        .padding((15.0, 5.0));
     let split = LapceSplitNew::new(data.search.split_id)
         .horizontal()
-        .with_child(input.boxed(), None, 100.0)
+        .with_child(
+            SearchInput::new(
+                input.boxed(),
+                vec![
+                    ("Aa", LapceWorkbenchCommand::ToggleSearchCaseSensitive),
+                    ("ab", LapceWorkbenchCommand::ToggleSearchWholeWord),
+                    (".*", LapceWorkbenchCommand::ToggleSearchRegex),
+                ],
+            )
+            .boxed(),
+            None,
+            55.0,
+        )
+        .with_child(
+            SearchInput::new(
+                replace.boxed(),
+                vec![("All", LapceWorkbenchCommand::SearchReplaceAll)],
+            )
+            .boxed(),
+            None,
+            35.0,
+        )
+        .with_child(
+            SearchGlobInput::new("Include", include.boxed()).boxed(),
+            None,
+            35.0,
+        )
+        .with_child(
+            SearchGlobInput::new("Exclude", exclude.boxed()).boxed(),
+            None,
+            45.0,
+        )
         .with_flex_child(
             LapceScrollNew::new(SearchContent::new().boxed())
                 .vertical()
@@ -115,6 +162,268 @@ impl Default for SearchData {
     }
 }
 
+/// An input of the search panel, followed by buttons running workbench
+/// commands. Buttons for the search options show whether they're enabled.
+pub struct SearchInput {
+    input: WidgetPod<LapceTabData, Box<dyn Widget<LapceTabData>>>,
+    toggles: Vec<(&'static str, LapceWorkbenchCommand, Rect)>,
+    mouse_pos: Point,
+}
+
+impl SearchInput {
+    const TOGGLE_SIZE: f64 = 24.0;
+
+    pub fn new(
+        input: Box<dyn Widget<LapceTabData>>,
+        toggles: Vec<(&'static str, LapceWorkbenchCommand)>,
+    ) -> Self {
+        Self {
+            input: WidgetPod::new(input),
+            toggles: toggles
+                .into_iter()
+                .map(|(label, cmd)| (label, cmd, Rect::ZERO))
+                .collect(),
+            mouse_pos: Point::ZERO,
+        }
+    }
+
+    fn is_toggled(cmd: &LapceWorkbenchCommand, data: &LapceTabData) -> bool {
+        let options = &data.search.options;
+        match cmd {
+            LapceWorkbenchCommand::ToggleSearchCaseSensitive => {
+                options.case_sensitive
+            }
+            LapceWorkbenchCommand::ToggleSearchWholeWord => options.whole_word,
+            LapceWorkbenchCommand::ToggleSearchRegex => options.is_regex,
+            _ => false,
+        }
+    }
+
+    fn toggle_hit_test(&self, pos: Point) -> Option<&LapceWorkbenchCommand> {
+        self.toggles
+            .iter()
+            .find(|(_, _, rect)| rect.contains(pos))
+            .map(|(_, cmd, _)| cmd)
+    }
+}
+
+impl Widget<LapceTabData> for SearchInput {
+    fn event(
+        &mut self,
+        ctx: &mut EventCtx,
+        event: &Event,
+        data: &mut LapceTabData,
+        env: &Env,
+    ) {
+        match event {
+            Event::MouseMove(mouse_event) => {
+                self.mouse_pos = mouse_event.pos;
+                if self.toggle_hit_test(mouse_event.pos).is_some() {
+                    ctx.set_cursor(&Cursor::Pointer);
+                    ctx.request_paint();
+                    ctx.set_handled();
+                    return;
+                }
+                ctx.clear_cursor();
+                ctx.request_paint();
+            }
+            Event::MouseDown(mouse_event) => {
+                if let Some(cmd) = self.toggle_hit_test(mouse_event.pos) {
+                    ctx.submit_command(Command::new(
+                        LAPCE_NEW_COMMAND,
+                        LapceCommandNew {
+                            cmd: cmd.to_string(),
+                            data: None,
+                            palette_desc: None,
+                            target: CommandTarget::Workbench,
+                        },
+                        Target::Widget(data.id),
+                    ));
+                    ctx.set_handled();
+                    return;
+                }
+            }
+            _ => {}
+        }
+        self.input.event(ctx, event, data, env);
+    }
+
+    fn lifecycle(
+        &mut self,
+        ctx: &mut LifeCycleCtx,
+        event: &LifeCycle,
+        data: &LapceTabData,
+        env: &Env,
+    ) {
+        self.input.lifecycle(ctx, event, data, env);
+    }
+
+    fn update(
+        &mut self,
+        ctx: &mut UpdateCtx,
+        old_data: &LapceTabData,
+        data: &LapceTabData,
+        env: &Env,
+    ) {
+        if old_data.search.options != data.search.options {
+            ctx.request_paint();
+        }
+        self.input.update(ctx, data, env);
+    }
+
+    fn layout(
+        &mut self,
+        ctx: &mut LayoutCtx,
+        bc: &BoxConstraints,
+        data: &LapceTabData,
+        env: &Env,
+    ) -> Size {
+        let toggles_width = Self::TOGGLE_SIZE * self.toggles.len() as f64 + 15.0;
+        let input_bc = BoxConstraints::tight(Size::new(
+            (bc.max().width - toggles_width).max(0.0),
+            bc.max().height,
+        ));
+        let input_size = self.input.layout(ctx, &input_bc, data, env);
+        self.input.set_origin(ctx, data, env, Point::ZERO);
+
+        let y = (input_size.height - Self::TOGGLE_SIZE) / 2.0;
+        for (i, (_, _, rect)) in self.toggles.iter_mut().enumerate() {
+            *rect = Size::new(Self::TOGGLE_SIZE, Self::TOGGLE_SIZE)
+                .to_rect()
+                .with_origin(Point::new(
+                    input_size.width + i as f64 * Self::TOGGLE_SIZE,
+                    y,
+                ));
+        }
+
+        Size::new(bc.max().width, input_size.height)
+    }
+
+    fn paint(&mut self, ctx: &mut PaintCtx, data: &LapceTabData, env: &Env) {
+        self.input.paint(ctx, data, env);
+
+        for (label, cmd, rect) in self.toggles.iter() {
+            if Self::is_toggled(cmd, data) {
+                ctx.fill(
+                    rect,
+                    data.config
+                        .get_color_unchecked(LapceTheme::EDITOR_SELECTION),
+                );
+            } else if rect.contains(self.mouse_pos) {
+                ctx.fill(
+                    rect,
+                    data.config
+                        .get_color_unchecked(LapceTheme::EDITOR_CURRENT_LINE),
+                );
+            }
+            let text_layout = ctx
+                .text()
+                .new_text_layout(*label)
+                .font(FontFamily::SYSTEM_UI, 13.0)
+                .text_color(
+                    data.config
+                        .get_color_unchecked(LapceTheme::EDITOR_FOREGROUND)
+                        .clone(),
+                )
+                .build()
+                .unwrap();
+            let text_size = text_layout.size();
+            ctx.draw_text(
+                &text_layout,
+                Point::new(
+                    rect.x0 + (rect.width() - text_size.width) / 2.0,
+                    rect.y0 + (rect.height() - text_size.height) / 2.0,
+                ),
+            );
+        }
+    }
+}
+
+/// An input for a comma separated list of globs, with a label in front.
+pub struct SearchGlobInput {
+    label: &'static str,
+    label_width: f64,
+    input: WidgetPod<LapceTabData, Box<dyn Widget<LapceTabData>>>,
+}
+
+impl SearchGlobInput {
+    pub fn new(label: &'static str, input: Box<dyn Widget<LapceTabData>>) -> Self {
+        Self {
+            label,
+            label_width: 65.0,
+            input: WidgetPod::new(input),
+        }
+    }
+}
+
+impl Widget<LapceTabData> for SearchGlobInput {
+    fn event(
+        &mut self,
+        ctx: &mut EventCtx,
+        event: &Event,
+        data: &mut LapceTabData,
+        env: &Env,
+    ) {
+        self.input.event(ctx, event, data, env);
+    }
+
+    fn lifecycle(
+        &mut self,
+        ctx: &mut LifeCycleCtx,
+        event: &LifeCycle,
+        data: &LapceTabData,
+        env: &Env,
+    ) {
+        self.input.lifecycle(ctx, event, data, env);
+    }
+
+    fn update(
+        &mut self,
+        ctx: &mut UpdateCtx,
+        _old_data: &LapceTabData,
+        data: &LapceTabData,
+        env: &Env,
+    ) {
+        self.input.update(ctx, data, env);
+    }
+
+    fn layout(
+        &mut self,
+        ctx: &mut LayoutCtx,
+        bc: &BoxConstraints,
+        data: &LapceTabData,
+        env: &Env,
+    ) -> Size {
+        let input_bc = BoxConstraints::tight(Size::new(
+            (bc.max().width - self.label_width).max(0.0),
+            bc.max().height,
+        ));
+        let input_size = self.input.layout(ctx, &input_bc, data, env);
+        self.input
+            .set_origin(ctx, data, env, Point::new(self.label_width, 0.0));
+        Size::new(bc.max().width, input_size.height)
+    }
+
+    fn paint(&mut self, ctx: &mut PaintCtx, data: &LapceTabData, env: &Env) {
+        let text_layout = ctx
+            .text()
+            .new_text_layout(self.label)
+            .font(FontFamily::SYSTEM_UI, 13.0)
+            .text_color(
+                data.config
+                    .get_color_unchecked(LapceTheme::EDITOR_DIM)
+                    .clone(),
+            )
+            .build()
+            .unwrap();
+        ctx.draw_text(
+            &text_layout,
+            Point::new(15.0, (ctx.size().height - text_layout.size().height) / 2.0),
+        );
+        self.input.paint(ctx, data, env);
+    }
+}
+
 pub struct SearchContent {
     mouse_pos: Point,
     line_height: f64,
@@ -128,13 +437,16 @@ impl SearchContent {
         }
     }
 
+    /// Clicking the close icon at the end of a row excludes the file or the
+    /// match from "Replace All", clicking anywhere else on a match jumps to it.
     fn mouse_down(
         &self,
         ctx: &mut EventCtx,
         mouse_event: &MouseEvent,
-        data: &LapceTabData,
+        data: &mut LapceTabData,
     ) {
         let n = (mouse_event.pos.y / self.line_height).floor() as usize;
+        let exclude = mouse_event.pos.x >= ctx.size().width - self.line_height;
 
         let mut i = 0;
         for (path, matches) in data.search.matches.iter() {
@@ -143,9 +455,25 @@ impl SearchContent {
                 continue;
             }
 
-            for (line_number, (start, _end), _line) in matches {
+            if i == n {
+                if exclude {
+                    let path = path.clone();
+                    Arc::make_mut(&mut data.search).toggle_excluded(&path, None);
+                }
+                return;
+            }
+
+            for m in matches {
+                let (line_number, (start, _end), _line) = m;
                 i += 1;
                 if i == n {
+                    if exclude {
+                        let path = path.clone();
+                        let m = m.clone();
+                        Arc::make_mut(&mut data.search)
+                            .toggle_excluded(&path, Some(&m));
+                        return;
+                    }
                     ctx.submit_command(Command::new(
                         LAPCE_UI_COMMAND,
                         LapceUICommand::JumpToLocation(
@@ -213,9 +541,18 @@ impl Widget<LapceTabData> for SearchContent {
         data: &LapceTabData,
         _env: &Env,
     ) {
-        if !old_data.search.matches.same(&data.search.matches) {
+        if !old_data.search.matches.same(&data.search.matches)
+            || old_data.search.limit_reached != data.search.limit_reached
+            || old_data.search.error != data.search.error
+        {
             ctx.request_layout();
         }
+        if old_data.search.replace != data.search.replace
+            || old_data.search.excluded_files != data.search.excluded_files
+            || old_data.search.excluded_matches != data.search.excluded_matches
+        {
+            ctx.request_paint();
+        }
     }
 
     fn layout(
@@ -230,12 +567,20 @@ impl Widget<LapceTabData> for SearchContent {
             .matches
             .iter()
             .map(|(_, matches)| matches.len() + 1)
-            .sum::<usize>();
+            .sum::<usize>()
+            + data.search.limit_reached as usize
+            + data.search.error.is_some() as usize;
         let height = self.line_height * n as f64;
         Size::new(bc.max().width, height)
     }
 
     fn paint(&mut self, ctx: &mut PaintCtx, data: &LapceTabData, _env: &Env) {
+        let rows = data
+            .search
+            .matches
+            .iter()
+            .map(|(_, matches)| matches.len() + 1)
+            .sum::<usize>();
         if ctx.is_hot() {
             let size = ctx.size();
             let n = (self.mouse_pos.y / self.line_height).floor() as usize;
@@ -246,8 +591,34 @@ impl Widget<LapceTabData> for SearchContent {
                 data.config
                     .get_color_unchecked(LapceTheme::EDITOR_CURRENT_LINE),
             );
+            if n < rows {
+                let padding = (self.line_height - 10.0) / 2.0;
+                let rect = Size::new(self.line_height, self.line_height)
+                    .to_rect()
+                    .with_origin(Point::new(
+                        size.width - self.line_height,
+                        self.line_height * n as f64,
+                    ))
+                    .inflate(-padding, -padding);
+                if let Some(svg) = get_svg("close.svg") {
+                    ctx.draw_svg(
+                        &svg,
+                        rect,
+                        Some(
+                            data.config
+                                .get_color_unchecked(LapceTheme::EDITOR_FOREGROUND),
+                        ),
+                    );
+                }
+            }
         }
 
+        let replace_regex = if data.search.replace.is_empty() {
+            None
+        } else {
+            data.search.replace_regex()
+        };
+
         let rect = ctx.region().bounding_box();
         let min = (rect.y0 / self.line_height).floor() as usize;
         let max = (rect.y1 / self.line_height) as usize + 2;
@@ -261,6 +632,7 @@ impl Widget<LapceTabData> for SearchContent {
                 continue;
             }
 
+            let file_excluded = data.search.is_excluded(path, None);
             let svg = file_svg_new(path);
             let rect = Size::new(self.line_height, self.line_height)
                 .to_rect()
@@ -276,7 +648,11 @@ impl Widget<LapceTabData> for SearchContent {
                 .font(FontFamily::SYSTEM_UI, 13.0)
                 .text_color(
                     data.config
-                        .get_color_unchecked(LapceTheme::EDITOR_FOREGROUND)
+                        .get_color_unchecked(if file_excluded {
+                            LapceTheme::EDITOR_DIM
+                        } else {
+                            LapceTheme::EDITOR_FOREGROUND
+                        })
                         .clone(),
                 )
                 .build()
@@ -290,14 +666,14 @@ impl Widget<LapceTabData> for SearchContent {
                 ),
             );
 
-            let mut path = path.clone();
+            let mut relative = path.clone();
             if let Some(workspace_path) = data.workspace.path.as_ref() {
-                path = path
+                relative = relative
                     .strip_prefix(workspace_path)
-                    .unwrap_or(&path)
+                    .unwrap_or(&relative)
                     .to_path_buf();
             }
-            let folder = path
+            let folder = relative
                 .parent()
                 .and_then(|s| s.to_str())
                 .unwrap_or("")
@@ -326,31 +702,90 @@ impl Widget<LapceTabData> for SearchContent {
                 );
             }
 
-            for (line_number, (start, end), line) in matches {
+            for m in matches {
+                let (line_number, (start, end), line) = m;
                 i += 1;
                 if i > max {
                     return;
                 }
 
                 if i >= min {
-                    let mut text_layout = ctx
-                        .text()
-                        .new_text_layout(format!("{line_number}: {line}"))
-                        .font(FontFamily::SYSTEM_UI, 13.0)
-                        .text_color(
-                            data.config
-                                .get_color_unchecked(LapceTheme::EDITOR_FOREGROUND)
-                                .clone(),
-                        );
                     let prefix = line_number.to_string().len() + 2;
-                    text_layout = text_layout.range_attribute(
-                        *start + prefix..*end + prefix,
-                        TextAttribute::TextColor(focus_color.clone()),
-                    );
-                    text_layout = text_layout.range_attribute(
-                        *start + prefix..*end + prefix,
-                        TextAttribute::Weight(FontWeight::BOLD),
-                    );
+                    let excluded =
+                        file_excluded || data.search.is_excluded(path, Some(m));
+                    let text_layout = if excluded {
+                        ctx.text()
+                            .new_text_layout(format!("{line_number}: {line}"))
+                            .font(FontFamily::SYSTEM_UI, 13.0)
+                            .text_color(
+                                data.config
+                                    .get_color_unchecked(LapceTheme::EDITOR_DIM)
+                                    .clone(),
+                            )
+                    } else if let Some(regex) = replace_regex.as_ref() {
+                        // Preview the replacement right after the match.
+                        let new = data.search.replacement(regex, m);
+                        let text = format!(
+                            "{line_number}: {}{new}{}",
+                            &line[..*end],
+                            &line[*end..]
+                        );
+                        let new_start = *end + prefix;
+                        let new_end = new_start + new.len();
+                        ctx.text()
+                            .new_text_layout(text)
+                            .font(FontFamily::SYSTEM_UI, 13.0)
+                            .text_color(
+                                data.config
+                                    .get_color_unchecked(
+                                        LapceTheme::EDITOR_FOREGROUND,
+                                    )
+                                    .clone(),
+                            )
+                            .range_attribute(
+                                *start + prefix..*end + prefix,
+                                TextAttribute::TextColor(
+                                    data.config
+                                        .get_color_unchecked(
+                                            LapceTheme::SOURCE_CONTROL_REMOVED,
+                                        )
+                                        .clone(),
+                                ),
+                            )
+                            .range_attribute(
+                                new_start..new_end,
+                                TextAttribute::TextColor(
+                                    data.config
+                                        .get_color_unchecked(
+                                            LapceTheme::SOURCE_CONTROL_ADDED,
+                                        )
+                                        .clone(),
+                                ),
+                            )
+                            .range_attribute(
+                                new_start..new_end,
+                                TextAttribute::Weight(FontWeight::BOLD),
+                            )
+                    } else {
+                        ctx.text()
+                            .new_text_layout(format!("{line_number}: {line}"))
+                            .font(FontFamily::SYSTEM_UI, 13.0)
+                            .text_color(
+                                data.config
+                                    .get_color_unchecked(
+                                        LapceTheme::EDITOR_FOREGROUND,
+                                    )
+                                    .clone(),
+                            )
+                            .range_attribute(
+                                *start + prefix..*end + prefix,
+                                TextAttribute::TextColor(focus_color.clone()),
+                            )
+                            .range_attribute(
+                                *start + prefix..*end + prefix,
+                                TextAttribute::Weight(FontWeight::BOLD),
+                            )
+                    };
                     let text_layout = text_layout.build().unwrap();
                     ctx.draw_text(
                         &text_layout,
@@ -365,5 +800,52 @@ impl Widget<LapceTabData> for SearchContent {
             }
             i += 1;
         }
+
+        if let Some(error) = data.search.error.as_ref() {
+            let text_layout = ctx
+                .text()
+                .new_text_layout(error.clone())
+                .font(FontFamily::SYSTEM_UI, 13.0)
+                .text_color(
+                    data.config
+                        .get_color_unchecked(LapceTheme::LAPCE_ERROR)
+                        .clone(),
+                )
+                .build()
+                .unwrap();
+            ctx.draw_text(
+                &text_layout,
+                Point::new(
+                    10.0,
+                    self.line_height * i as f64
+                        + (self.line_height - text_layout.size().height) / 2.0,
+                ),
+            );
+            i += 1;
+        }
+
+        if data.search.limit_reached {
+            let text_layout = ctx
+                .text()
+                .new_text_layout(
+                    "The search stopped at the match limit, refine it to see all results",
+                )
+                .font(FontFamily::SYSTEM_UI, 13.0)
+                .text_color(
+                    data.config
+                        .get_color_unchecked(LapceTheme::EDITOR_DIM)
+                        .clone(),
+                )
+                .build()
+                .unwrap();
+            ctx.draw_text(
+                &text_layout,
+                Point::new(
+                    10.0,
+                    self.line_height * i as f64
+                        + (self.line_height - text_layout.size().height) / 2.0,
+                ),
+            );
+        }
     }
 }
@@ -1,14 +1,16 @@
-use std::sync::Arc;
+use std::{path::Path, sync::Arc};
 
 use druid::{
-    kurbo::BezPath,
     piet::{Text, TextLayout as PietTextLayout, TextLayoutBuilder},
-    BoxConstraints, Color, Command, Env, Event, EventCtx, FontFamily, LayoutCtx,
-    LifeCycle, LifeCycleCtx, Modifiers, PaintCtx, Point, RenderContext, Size,
-    Target, UpdateCtx, Widget, WidgetExt, WidgetId,
+    BoxConstraints, Command, Env, Event, EventCtx, FontFamily, LayoutCtx, LifeCycle,
+    LifeCycleCtx, Modifiers, PaintCtx, Point, Rect, RenderContext, Size, Target,
+    UpdateCtx, Widget, WidgetExt, WidgetId,
 };
 use lapce_data::{
-    command::{CommandExecuted, LapceCommand, LapceUICommand, LAPCE_UI_COMMAND},
+    command::{
+        CommandExecuted, CommandTarget, LapceCommand, LapceCommandNew,
+        LapceUICommand, LapceWorkbenchCommand, LAPCE_NEW_COMMAND, LAPCE_UI_COMMAND,
+    },
     config::LapceTheme,
     data::{FocusArea, LapceTabData, PanelKind},
     keypress::KeyPressFocus,
@@ -17,6 +19,7 @@ use lapce_data::{
     state::Mode,
 };
 use lapce_rpc::source_control::FileDiff;
+use serde_json::json;
 
 use crate::{
     editor::view::LapceEditorView,
@@ -52,7 +55,20 @@ pub fn new_source_control_panel(data: &LapceTabData) -> LapcePanel {
         .hide_gutter()
         .set_placeholder("Commit Message".to_string())
         .padding((15.0, 15.0));
-    let content = SourceControlFileList::new(data.source_control.file_list_id);
+    let repositories =
+        SourceControlRepositoryList::new(data.source_control.repository_list_id);
+    let merge = SourceControlFileList::new(
+        data.source_control.merge_list_id,
+        FileListKind::Merge,
+    );
+    let staged = SourceControlFileList::new(
+        data.source_control.staged_list_id,
+        FileListKind::Staged,
+    );
+    let content = SourceControlFileList::new(
+        data.source_control.file_list_id,
+        FileListKind::Unstaged,
+    );
     LapcePanel::new(
         PanelKind::SourceControl,
         data.source_control.widget_id,
@@ -60,12 +76,30 @@ pub fn new_source_control_panel(data: &LapceTabData) -> LapcePanel {
         data.source_control.split_direction,
         PanelHeaderKind::Simple("Source Control".to_string()),
         vec![
+            (
+                data.source_control.repository_list_id,
+                PanelHeaderKind::Simple("Repositories".to_string()),
+                repositories.boxed(),
+                Some(150.0),
+            ),
             (
                 editor_data.view_id,
                 PanelHeaderKind::None,
                 input.boxed(),
                 Some(300.0),
             ),
+            (
+                data.source_control.merge_list_id,
+                PanelHeaderKind::Simple("Merge Changes".to_string()),
+                merge.boxed(),
+                None,
+            ),
+            (
+                data.source_control.staged_list_id,
+                PanelHeaderKind::Simple("Staged Changes".to_string()),
+                staged.boxed(),
+                None,
+            ),
             (
                 data.source_control.file_list_id,
                 PanelHeaderKind::Simple("Changes".to_string()),
@@ -105,7 +139,8 @@ impl SourceControlData {
             .hide_gutter()
             .set_placeholder("Commit Message".to_string())
             .padding((15.0, 10.0));
-        let content = SourceControlFileList::new(self.file_list_id);
+        let content =
+            SourceControlFileList::new(self.file_list_id, FileListKind::Unstaged);
         LapcePanel::new(
             PanelKind::SourceControl,
             self.widget_id,
@@ -217,17 +252,216 @@ impl KeyPressFocus for SourceControlData {
     fn receive_char(&mut self, _ctx: &mut EventCtx, _c: &str) {}
 }
 
+/// The repositories in the workspace, clicking one shows its changes and
+/// makes commits and branch operations work on it.
+pub struct SourceControlRepositoryList {
+    widget_id: WidgetId,
+    mouse_pos: Option<Point>,
+    line_height: f64,
+}
+
+impl SourceControlRepositoryList {
+    pub fn new(widget_id: WidgetId) -> Self {
+        Self {
+            widget_id,
+            mouse_pos: None,
+            line_height: 25.0,
+        }
+    }
+}
+
+impl Widget<LapceTabData> for SourceControlRepositoryList {
+    fn id(&self) -> Option<WidgetId> {
+        Some(self.widget_id)
+    }
+
+    fn event(
+        &mut self,
+        ctx: &mut EventCtx,
+        event: &Event,
+        data: &mut LapceTabData,
+        _env: &Env,
+    ) {
+        match event {
+            Event::MouseMove(mouse_event) => {
+                self.mouse_pos = Some(mouse_event.pos);
+                ctx.set_cursor(&druid::Cursor::Pointer);
+                ctx.request_paint();
+                ctx.set_handled();
+            }
+            Event::MouseDown(mouse_event) => {
+                let line = (mouse_event.pos.y / self.line_height).floor() as usize;
+                if let Some(diff) = data.source_control.repositories.get(line) {
+                    ctx.submit_command(Command::new(
+                        LAPCE_UI_COMMAND,
+                        LapceUICommand::SelectRepository(diff.path.clone()),
+                        Target::Widget(data.id),
+                    ));
+                }
+                ctx.set_handled();
+            }
+            _ => (),
+        }
+    }
+
+    fn lifecycle(
+        &mut self,
+        ctx: &mut LifeCycleCtx,
+        event: &LifeCycle,
+        _data: &LapceTabData,
+        _env: &Env,
+    ) {
+        if let LifeCycle::HotChanged(false) = event {
+            self.mouse_pos = None;
+            ctx.request_paint();
+        }
+    }
+
+    fn update(
+        &mut self,
+        ctx: &mut UpdateCtx,
+        old_data: &LapceTabData,
+        data: &LapceTabData,
+        _env: &Env,
+    ) {
+        let repositories = &data.source_control.repositories;
+        let old_repositories = &old_data.source_control.repositories;
+        if repositories.len() != old_repositories.len() {
+            ctx.request_layout();
+        } else if repositories != old_repositories
+            || data.source_control.repository != old_data.source_control.repository
+        {
+            ctx.request_paint();
+        }
+    }
+
+    fn layout(
+        &mut self,
+        _ctx: &mut LayoutCtx,
+        bc: &BoxConstraints,
+        data: &LapceTabData,
+        _env: &Env,
+    ) -> Size {
+        let height =
+            self.line_height * data.source_control.repositories.len() as f64;
+        Size::new(bc.max().width, height)
+    }
+
+    fn paint(&mut self, ctx: &mut PaintCtx, data: &LapceTabData, _env: &Env) {
+        let width = ctx.size().width;
+        let hovered_line = self
+            .mouse_pos
+            .map(|pos| (pos.y / self.line_height).floor() as usize);
+
+        for (line, diff) in data.source_control.repositories.iter().enumerate() {
+            let y = self.line_height * line as f64;
+            let rect = Size::new(width, self.line_height)
+                .to_rect()
+                .with_origin(Point::new(0.0, y));
+            if diff.path == data.source_control.repository {
+                ctx.fill(
+                    rect,
+                    data.config.get_color_unchecked(LapceTheme::PANEL_CURRENT),
+                );
+            } else if hovered_line == Some(line) {
+                ctx.fill(
+                    rect,
+                    data.config.get_color_unchecked(LapceTheme::PANEL_HOVERED),
+                );
+            }
+
+            let svg = get_svg("default_folder.svg").unwrap();
+            let svg_size = 13.0;
+            let svg_rect =
+                Size::new(svg_size, svg_size)
+                    .to_rect()
+                    .with_origin(Point::new(
+                        (self.line_height - svg_size) / 2.0 + 5.0,
+                        (self.line_height - svg_size) / 2.0 + y,
+                    ));
+            ctx.draw_svg(&svg, svg_rect, None);
+
+            // Nested repositories go by their path in the workspace.
+            let name = data
+                .workspace
+                .path
+                .as_ref()
+                .and_then(|workspace| diff.path.strip_prefix(workspace).ok())
+                .filter(|path| !path.as_os_str().is_empty())
+                .or_else(|| diff.path.file_name().map(Path::new))
+                .and_then(|path| path.to_str())
+                .unwrap_or("")
+                .to_string();
+            let text_layout = ctx
+                .text()
+                .new_text_layout(name)
+                .font(FontFamily::SYSTEM_UI, 13.0)
+                .text_color(
+                    data.config
+                        .get_color_unchecked(LapceTheme::EDITOR_FOREGROUND)
+                        .clone(),
+                )
+                .build()
+                .unwrap();
+            ctx.draw_text(
+                &text_layout,
+                Point::new(
+                    self.line_height + 5.0,
+                    y + (self.line_height - text_layout.size().height) / 2.0,
+                ),
+            );
+
+            let changes =
+                diff.diffs.len() + diff.staged.len() + diff.conflicts.len();
+            let hint = if changes > 0 {
+                format!("{} \u{2022} {changes}", diff.head)
+            } else {
+                diff.head.clone()
+            };
+            let hint_layout = ctx
+                .text()
+                .new_text_layout(hint)
+                .font(FontFamily::SYSTEM_UI, 13.0)
+                .text_color(
+                    data.config
+                        .get_color_unchecked(LapceTheme::EDITOR_DIM)
+                        .clone(),
+                )
+                .build()
+                .unwrap();
+            ctx.draw_text(
+                &hint_layout,
+                Point::new(
+                    self.line_height + 5.0 + text_layout.size().width + 10.0,
+                    y + (self.line_height - hint_layout.size().height) / 2.0,
+                ),
+            );
+        }
+    }
+}
+
+/// Which changes a [`SourceControlFileList`] lists.
+#[derive(Clone, Copy, PartialEq)]
+pub enum FileListKind {
+    /// The files with merge conflicts left to resolve.
+    Merge,
+    Staged,
+    Unstaged,
+}
+
 pub struct SourceControlFileList {
     widget_id: WidgetId,
-    mouse_down: Option<usize>,
+    kind: FileListKind,
+    mouse_pos: Option<Point>,
     line_height: f64,
 }
 
 impl SourceControlFileList {
-    pub fn new(widget_id: WidgetId) -> Self {
+    pub fn new(widget_id: WidgetId, kind: FileListKind) -> Self {
         Self {
             widget_id,
-            mouse_down: None,
+            kind,
+            mouse_pos: None,
             line_height: 25.0,
         }
     }
@@ -239,6 +473,48 @@ impl SourceControlFileList {
         data.focus_area = FocusArea::Panel(PanelKind::SourceControl);
         data.focus = self.widget_id;
     }
+
+    fn diffs<'a>(&self, data: &'a LapceTabData) -> &'a [FileDiff] {
+        match self.kind {
+            FileListKind::Merge => &data.source_control.conflicts,
+            FileListKind::Staged => &data.source_control.staged_diffs,
+            FileListKind::Unstaged => &data.source_control.file_diffs,
+        }
+    }
+
+    fn list_index(&self, data: &LapceTabData) -> usize {
+        match self.kind {
+            FileListKind::Merge => data.source_control.merge_list_index,
+            FileListKind::Staged => data.source_control.staged_list_index,
+            FileListKind::Unstaged => data.source_control.file_list_index,
+        }
+    }
+
+    /// The buttons shown on a hovered row, from right to left.
+    fn actions(&self) -> Vec<(&'static str, LapceWorkbenchCommand)> {
+        match self.kind {
+            FileListKind::Merge => vec![(
+                "check.svg",
+                LapceWorkbenchCommand::SourceControlMarkResolved,
+            )],
+            FileListKind::Staged => {
+                vec![("remove.svg", LapceWorkbenchCommand::SourceControlUnstage)]
+            }
+            FileListKind::Unstaged => vec![
+                ("add.svg", LapceWorkbenchCommand::SourceControlStage),
+                ("discard.svg", LapceWorkbenchCommand::SourceControlDiscard),
+            ],
+        }
+    }
+
+    fn action_rect(&self, width: f64, line: usize, i: usize) -> Rect {
+        Size::new(self.line_height, self.line_height)
+            .to_rect()
+            .with_origin(Point::new(
+                width - 10.0 - 15.0 - 5.0 - (i + 1) as f64 * self.line_height,
+                line as f64 * self.line_height,
+            ))
+    }
 }
 
 impl Widget<LapceTabData> for SourceControlFileList {
@@ -254,49 +530,69 @@ impl Widget<LapceTabData> for SourceControlFileList {
         env: &Env,
     ) {
         match event {
-            Event::MouseMove(_mouse_event) => {
+            Event::MouseMove(mouse_event) => {
+                self.mouse_pos = Some(mouse_event.pos);
                 ctx.set_cursor(&druid::Cursor::Pointer);
+                ctx.request_paint();
                 ctx.set_handled();
             }
-            Event::MouseUp(mouse_event) => {
+            Event::MouseDown(mouse_event) => {
                 let y = mouse_event.pos.y;
                 if y > 0.0 {
                     let line = (y / self.line_height).floor() as usize;
-                    if line < data.source_control.file_diffs.len()
-                        && mouse_event.pos.x < self.line_height
-                    {
-                        if let Some(mouse_down) = self.mouse_down {
-                            if mouse_down == line {
-                                let source_control =
-                                    Arc::make_mut(&mut data.source_control);
-                                source_control.file_diffs[line].1 =
-                                    !source_control.file_diffs[line].1;
+                    if let Some(diff) = self.diffs(data).get(line).cloned() {
+                        let source_control = Arc::make_mut(&mut data.source_control);
+                        match self.kind {
+                            FileListKind::Merge => {
+                                source_control.merge_list_index = line;
+                            }
+                            FileListKind::Staged => {
+                                source_control.staged_list_index = line;
+                            }
+                            FileListKind::Unstaged => {
+                                source_control.file_list_index = line;
                             }
                         }
-                    }
-                }
-                self.mouse_down = None;
-                ctx.set_handled();
-            }
-            Event::MouseDown(mouse_event) => {
-                self.mouse_down = None;
-                let source_control = Arc::make_mut(&mut data.source_control);
-                let y = mouse_event.pos.y;
-                if y > 0.0 {
-                    let line = (y / self.line_height).floor() as usize;
-                    if line < source_control.file_diffs.len() {
-                        source_control.file_list_index = line;
-                        if mouse_event.pos.x < self.line_height {
-                            self.mouse_down = Some(line);
-                        } else {
-                            ctx.submit_command(Command::new(
-                                LAPCE_UI_COMMAND,
-                                LapceUICommand::OpenFileDiff(
-                                    source_control.file_diffs[line].0.path().clone(),
-                                    "head".to_string(),
-                                ),
-                                Target::Widget(data.id),
-                            ));
+                        let width = ctx.size().width;
+                        let action = self
+                            .actions()
+                            .into_iter()
+                            .enumerate()
+                            .find(|(i, _)| {
+                                self.action_rect(width, line, *i)
+                                    .contains(mouse_event.pos)
+                            })
+                            .map(|(_, (_, command))| command);
+                        match action {
+                            Some(command) => {
+                                ctx.submit_command(Command::new(
+                                    LAPCE_NEW_COMMAND,
+                                    LapceCommandNew {
+                                        cmd: command.to_string(),
+                                        data: Some(json!(diff.path())),
+                                        palette_desc: None,
+                                        target: CommandTarget::Workbench,
+                                    },
+                                    Target::Widget(data.id),
+                                ));
+                            }
+                            None if self.kind == FileListKind::Merge => {
+                                ctx.submit_command(Command::new(
+                                    LAPCE_UI_COMMAND,
+                                    LapceUICommand::OpenFile(diff.path().clone()),
+                                    Target::Widget(data.id),
+                                ));
+                            }
+                            None => {
+                                ctx.submit_command(Command::new(
+                                    LAPCE_UI_COMMAND,
+                                    LapceUICommand::OpenFileDiff(
+                                        diff.path().clone(),
+                                        "head".to_string(),
+                                    ),
+                                    Target::Widget(data.id),
+                                ));
+                            }
                         }
                     }
                 }
@@ -335,8 +631,15 @@ impl Widget<LapceTabData> for SourceControlFileList {
         _data: &LapceTabData,
         _env: &Env,
     ) {
-        if let LifeCycle::FocusChanged(_) = event {
-            ctx.request_paint();
+        match event {
+            LifeCycle::FocusChanged(_) => {
+                ctx.request_paint();
+            }
+            LifeCycle::HotChanged(false) => {
+                self.mouse_pos = None;
+                ctx.request_paint();
+            }
+            _ => (),
         }
     }
 
@@ -347,10 +650,19 @@ impl Widget<LapceTabData> for SourceControlFileList {
         data: &LapceTabData,
         _env: &Env,
     ) {
-        if data.source_control.file_diffs.len()
-            != old_data.source_control.file_diffs.len()
-        {
+        let diffs = self.diffs(data);
+        let old_diffs = self.diffs(old_data);
+        if diffs.len() != old_diffs.len() {
             ctx.request_layout();
+        } else if diffs != old_diffs
+            || data.source_control.file_list_index
+                != old_data.source_control.file_list_index
+            || data.source_control.staged_list_index
+                != old_data.source_control.staged_list_index
+            || data.source_control.merge_list_index
+                != old_data.source_control.merge_list_index
+        {
+            ctx.request_paint();
         }
     }
 
@@ -361,28 +673,30 @@ impl Widget<LapceTabData> for SourceControlFileList {
         data: &LapceTabData,
         _env: &Env,
     ) -> Size {
-        let height = self.line_height * data.source_control.file_diffs.len() as f64;
+        let height = self.line_height * self.diffs(data).len() as f64;
         Size::new(bc.max().width, height)
     }
 
     fn paint(&mut self, ctx: &mut PaintCtx, data: &LapceTabData, _env: &Env) {
         let self_size = ctx.size();
 
-        let diffs = &data.source_control.file_diffs;
+        let diffs = self.diffs(data);
+        let list_index = self.list_index(data);
 
         if ctx.is_focused() && !diffs.is_empty() {
             let rect = Size::new(ctx.size().width, self.line_height)
                 .to_rect()
-                .with_origin(Point::new(
-                    0.0,
-                    data.source_control.file_list_index as f64 * self.line_height,
-                ));
+                .with_origin(Point::new(0.0, list_index as f64 * self.line_height));
             ctx.fill(
                 rect,
                 data.config.get_color_unchecked(LapceTheme::PANEL_CURRENT),
             );
         }
 
+        let hovered_line = self
+            .mouse_pos
+            .map(|pos| (pos.y / self.line_height).floor() as usize);
+
         let rect = ctx.region().bounding_box();
         let start_line = (rect.y0 / self.line_height).floor() as usize;
         let end_line = (rect.y1 / self.line_height).ceil() as usize;
@@ -391,7 +705,7 @@ impl Widget<LapceTabData> for SourceControlFileList {
                 break;
             }
             let y = self.line_height * line as f64;
-            let (diff, checked) = diffs[line].clone();
+            let diff = diffs[line].clone();
             let mut path = diff.path().clone();
             if let Some(workspace_path) = data.workspace.path.as_ref() {
                 path = path
@@ -399,29 +713,11 @@ impl Widget<LapceTabData> for SourceControlFileList {
                     .unwrap_or(&path)
                     .to_path_buf();
             }
-            {
-                let width = 13.0;
-                let height = 13.0;
-                let origin = Point::new(
-                    (self.line_height - width) / 2.0 + 5.0,
-                    (self.line_height - height) / 2.0 + y,
-                );
-                let rect = Size::new(width, height).to_rect().with_origin(origin);
-                ctx.stroke(rect, &Color::rgb8(0, 0, 0), 1.0);
-
-                if checked {
-                    let mut path = BezPath::new();
-                    path.move_to((origin.x + 3.0, origin.y + 7.0));
-                    path.line_to((origin.x + 6.0, origin.y + 9.5));
-                    path.line_to((origin.x + 10.0, origin.y + 3.0));
-                    ctx.stroke(path, &Color::rgb8(0, 0, 0), 2.0);
-                }
-            }
             let svg = file_svg_new(&path);
             let width = 13.0;
             let height = 13.0;
             let rect = Size::new(width, height).to_rect().with_origin(Point::new(
-                (self.line_height - width) / 2.0 + self.line_height,
+                (self.line_height - width) / 2.0 + 5.0,
                 (self.line_height - height) / 2.0 + y,
             ));
             ctx.draw_svg(&svg, rect, None);
@@ -446,7 +742,7 @@ impl Widget<LapceTabData> for SourceControlFileList {
             ctx.draw_text(
                 &text_layout,
                 Point::new(
-                    self.line_height * 2.0,
+                    self.line_height + 5.0,
                     y + (self.line_height - text_layout.size().height) / 2.0,
                 ),
             );
@@ -472,13 +768,32 @@ impl Widget<LapceTabData> for SourceControlFileList {
                 ctx.draw_text(
                     &text_layout,
                     Point::new(
-                        self.line_height * 2.0 + x + 5.0,
+                        self.line_height + 5.0 + x + 5.0,
                         y + (self.line_height - text_layout.size().height) / 2.0,
                     ),
                 );
             }
 
+            if hovered_line == Some(line) {
+                for (i, (svg, _)) in self.actions().into_iter().enumerate() {
+                    let rect = self.action_rect(self_size.width, line, i);
+                    let svg = get_svg(svg).unwrap();
+                    ctx.draw_svg(
+                        &svg,
+                        rect.inflate(-5.0, -5.0),
+                        Some(
+                            data.config
+                                .get_color_unchecked(LapceTheme::EDITOR_FOREGROUND),
+                        ),
+                    );
+                }
+            }
+
             let (svg, color) = match diff {
+                _ if self.kind == FileListKind::Merge => (
+                    "warning.svg",
+                    data.config.get_color_unchecked(LapceTheme::LAPCE_WARN),
+                ),
                 FileDiff::Modified(_) => (
                     "diff-modified.svg",
                     data.config
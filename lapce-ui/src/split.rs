@@ -2,7 +2,7 @@ use crate::{
     editor::{tab::LapceEditorTab, view::LapceEditorView},
     terminal::LapceTerminalView,
 };
-use std::sync::Arc;
+use std::{path::PathBuf, sync::Arc};
 
 use druid::{
     kurbo::{Line, Rect},
@@ -18,13 +18,14 @@ use lapce_data::{
         CommandTarget, LapceCommandNew, LapceUICommand, LapceWorkbenchCommand,
         LAPCE_NEW_COMMAND, LAPCE_UI_COMMAND,
     },
-    config::{Config, LapceTheme},
+    config::{Config, LapceTheme, TerminalProfileConfig},
     data::{
         EditorTabChild, FocusArea, LapceEditorData, LapceTabData, PanelKind,
         SplitContent, SplitData,
     },
     keypress::{Alignment, DefaultKeyPressHandler, KeyMap, KeyPress},
     split::{SplitDirection, SplitMoveDirection},
+    task::TaskRun,
     terminal::LapceTerminalData,
 };
 use crate::svg::logo_svg;
@@ -495,7 +496,13 @@ impl LapceSplitNew {
             ctx.get_external_handle(),
             data.proxy.clone(),
             &data.config,
+            data.config.terminal_profile(None),
+            None,
+            None,
+            None,
         ));
+        data.main_split
+            .insert_terminal_find_editor(&terminal_data, &data.config);
         let terminal = LapceTerminalView::new(&terminal_data);
         Arc::make_mut(&mut data.terminal)
             .terminals
@@ -508,6 +515,140 @@ impl LapceSplitNew {
             1.0,
         );
         self.even_flex_children();
+        Arc::make_mut(&mut data.terminal).order = self.children_ids.clone();
+        ctx.children_changed();
+    }
+
+    /// Opens a terminal with the profile after the active one, which runs
+    /// the task if there is one.
+    pub fn new_terminal(
+        &mut self,
+        ctx: &mut EventCtx,
+        data: &mut LapceTabData,
+        profile: TerminalProfileConfig,
+        cwd: Option<PathBuf>,
+        task: Option<TaskRun>,
+    ) {
+        let index = self
+            .children_ids
+            .iter()
+            .position(|id| id == &data.terminal.active)
+            .map(|i| i + 1)
+            .unwrap_or(self.children_ids.len());
+
+        let terminal_data = Arc::new(LapceTerminalData::new(
+            data.workspace.clone(),
+            self.split_id,
+            ctx.get_external_handle(),
+            data.proxy.clone(),
+            &data.config,
+            profile,
+            cwd,
+            task,
+            None,
+        ));
+        data.main_split
+            .insert_terminal_find_editor(&terminal_data, &data.config);
+        let terminal = LapceTerminalView::new(&terminal_data);
+        self.insert_flex_child(
+            index,
+            terminal.boxed(),
+            Some(terminal_data.widget_id),
+            1.0,
+        );
+        self.even_flex_children();
+        ctx.submit_command(Command::new(
+            LAPCE_UI_COMMAND,
+            LapceUICommand::Focus,
+            Target::Widget(terminal_data.widget_id),
+        ));
+
+        let terminal_panel = Arc::make_mut(&mut data.terminal);
+        terminal_panel.active = terminal_data.widget_id;
+        terminal_panel.active_term_id = terminal_data.term_id;
+        terminal_panel.order = self.children_ids.clone();
+        terminal_panel
+            .terminals
+            .insert(terminal_data.term_id, terminal_data);
+        ctx.children_changed();
+    }
+
+    /// Opens the terminals of the last session, or a new one if there were
+    /// none. Terminals that are already open, like those of tasks started
+    /// before the panel was shown, stay open after the restored ones and
+    /// keep the focus.
+    fn init_terminal_panel(
+        &mut self,
+        ctx: &mut EventCtx,
+        data: &mut LapceTabData,
+        focus: bool,
+    ) {
+        let restore = Arc::make_mut(&mut data.terminal)
+            .restore
+            .take()
+            .unwrap_or_default();
+        let mut terminals: Vec<LapceTerminalData> = restore
+            .terminals
+            .iter()
+            .map(|info| {
+                LapceTerminalData::new(
+                    data.workspace.clone(),
+                    data.terminal.split_id,
+                    ctx.get_external_handle(),
+                    data.proxy.clone(),
+                    &data.config,
+                    data.config.terminal_profile(info.profile.as_deref()),
+                    info.cwd.clone(),
+                    None,
+                    info.scrollback.as_deref(),
+                )
+            })
+            .collect();
+        let opened = !data.terminal.terminals.is_empty();
+        if terminals.is_empty() && !opened {
+            terminals.push(LapceTerminalData::new(
+                data.workspace.clone(),
+                data.terminal.split_id,
+                ctx.get_external_handle(),
+                data.proxy.clone(),
+                &data.config,
+                data.config.terminal_profile(None),
+                None,
+                None,
+                None,
+            ));
+        }
+
+        let active = restore.active.min(terminals.len().saturating_sub(1));
+        for (i, terminal_data) in terminals.into_iter().enumerate() {
+            let terminal_data = Arc::new(terminal_data);
+            data.main_split
+                .insert_terminal_find_editor(&terminal_data, &data.config);
+            let terminal = LapceTerminalView::new(&terminal_data);
+            self.insert_flex_child(
+                i,
+                terminal.boxed(),
+                Some(terminal_data.widget_id),
+                1.0,
+            );
+            let terminal_panel = Arc::make_mut(&mut data.terminal);
+            if i == active && !opened {
+                if focus {
+                    ctx.submit_command(Command::new(
+                        LAPCE_UI_COMMAND,
+                        LapceUICommand::Focus,
+                        Target::Widget(terminal_data.widget_id),
+                    ));
+                }
+                terminal_panel.active = terminal_data.widget_id;
+                terminal_panel.active_term_id = terminal_data.term_id;
+            }
+            terminal_panel
+                .terminals
+                .insert(terminal_data.term_id, terminal_data);
+        }
+        self.even_flex_children();
+        Arc::make_mut(&mut data.terminal).order = self.children_ids.clone();
         ctx.children_changed();
     }
 
@@ -522,8 +663,13 @@ impl LapceSplitNew {
             return;
         }
 
+        if let Some(terminal) = data.terminal.terminals.get(&term_id) {
+            data.main_split.editors.remove(&terminal.find_view_id);
+        }
         if self.children.len() == 1 {
-            Arc::make_mut(&mut data.terminal).terminals.remove(&term_id);
+            let terminal_panel = Arc::make_mut(&mut data.terminal);
+            terminal_panel.terminals.remove(&term_id);
+            terminal_panel.order.clear();
             self.children.remove(0);
             self.children_ids.remove(0);
 
@@ -565,9 +711,11 @@ impl LapceSplitNew {
             Target::Widget(new_terminal_id),
         ));
 
-        Arc::make_mut(&mut data.terminal).terminals.remove(&term_id);
         self.children.remove(index);
         self.children_ids.remove(index);
+        let terminal_panel = Arc::make_mut(&mut data.terminal);
+        terminal_panel.terminals.remove(&term_id);
+        terminal_panel.order = self.children_ids.clone();
 
         self.even_flex_children();
         ctx.children_changed();
@@ -891,36 +1039,25 @@ impl Widget<LapceTabData> for LapceSplitNew {
                     LapceUICommand::SplitTerminalClose(term_id, widget_id) => {
                         self.split_terminal_close(ctx, data, *term_id, *widget_id);
                     }
+                    LapceUICommand::NewTerminal(profile, cwd) => {
+                        let profile =
+                            data.config.terminal_profile(profile.as_deref());
+                        self.new_terminal(ctx, data, profile, cwd.clone(), None);
+                    }
+                    LapceUICommand::NewTaskTerminal(run) => {
+                        self.new_terminal(
+                            ctx,
+                            data,
+                            run.task.profile(),
+                            None,
+                            Some(run.clone()),
+                        );
+                    }
                     LapceUICommand::InitTerminalPanel(focus) => {
-                        if data.terminal.terminals.is_empty() {
-                            let terminal_data = Arc::new(LapceTerminalData::new(
-                                data.workspace.clone(),
-                                data.terminal.split_id,
-                                ctx.get_external_handle(),
-                                data.proxy.clone(),
-                                &data.config,
-                            ));
-                            let terminal = LapceTerminalView::new(&terminal_data);
-                            self.insert_flex_child(
-                                0,
-                                terminal.boxed(),
-                                Some(terminal_data.widget_id),
-                                1.0,
-                            );
-                            if *focus {
-                                ctx.submit_command(Command::new(
-                                    LAPCE_UI_COMMAND,
-                                    LapceUICommand::Focus,
-                                    Target::Widget(terminal_data.widget_id),
-                                ));
-                            }
-                            let terminal_panel = Arc::make_mut(&mut data.terminal);
-                            terminal_panel.active = terminal_data.widget_id;
-                            terminal_panel.active_term_id = terminal_data.term_id;
-                            terminal_panel
-                                .terminals
-                                .insert(terminal_data.term_id, terminal_data);
-                            ctx.children_changed();
+                        if data.terminal.terminals.is_empty()
+                            || data.terminal.restore.is_some()
+                        {
+                            self.init_terminal_panel(ctx, data, *focus);
                         }
                     }
                     _ => (),
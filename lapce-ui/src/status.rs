@@ -166,7 +166,9 @@ impl Widget<LapceTabData> for LapceStatusNew {
             data.main_split.active_editor(),
         ) {
             (Some(old_data), Some(data)) => {
-                if old_data.cursor.get_mode() != data.cursor.get_mode() {
+                if old_data.cursor.get_mode() != data.cursor.get_mode()
+                    || old_data.content != data.content
+                {
                     ctx.request_paint();
                 }
             }
@@ -184,6 +186,15 @@ impl Widget<LapceTabData> for LapceStatusNew {
         if !old_data.progresses.ptr_eq(&data.progresses) {
             ctx.request_paint();
         }
+
+        if old_data.main_split.active_editor_large_file()
+            != data.main_split.active_editor_large_file()
+            || old_data.main_split.active_editor_read_only()
+                != data.main_split.active_editor_read_only()
+            || old_data.status_message != data.status_message
+        {
+            ctx.request_paint();
+        }
     }
 
     fn layout(
@@ -289,6 +300,54 @@ impl Widget<LapceTabData> for LapceStatusNew {
         ctx.draw_text(&text_layout, Point::new(left + 10.0, 4.0));
         left += 10.0 + text_layout.size().width;
 
+        if data.main_split.active_editor_large_file() {
+            let text_layout = ctx
+                .text()
+                .new_text_layout("Large File")
+                .font(FontFamily::SYSTEM_UI, 13.0)
+                .text_color(
+                    data.config
+                        .get_color_unchecked(LapceTheme::LAPCE_WARN)
+                        .clone(),
+                )
+                .build()
+                .unwrap();
+            ctx.draw_text(&text_layout, Point::new(left + 10.0, 4.0));
+            left += 10.0 + text_layout.size().width;
+        }
+
+        if data.main_split.active_editor_read_only() {
+            let text_layout = ctx
+                .text()
+                .new_text_layout("Read-Only")
+                .font(FontFamily::SYSTEM_UI, 13.0)
+                .text_color(
+                    data.config
+                        .get_color_unchecked(LapceTheme::LAPCE_WARN)
+                        .clone(),
+                )
+                .build()
+                .unwrap();
+            ctx.draw_text(&text_layout, Point::new(left + 10.0, 4.0));
+            left += 10.0 + text_layout.size().width;
+        }
+
+        if let Some(message) = data.status_message.as_ref() {
+            let text_layout = ctx
+                .text()
+                .new_text_layout(message.clone())
+                .font(FontFamily::SYSTEM_UI, 13.0)
+                .text_color(
+                    data.config
+                        .get_color_unchecked(LapceTheme::EDITOR_FOREGROUND)
+                        .clone(),
+                )
+                .build()
+                .unwrap();
+            ctx.draw_text(&text_layout, Point::new(left + 10.0, 4.0));
+            left += 10.0 + text_layout.size().width;
+        }
+
         for progress in data.progresses.iter() {
             let mut text = progress.title.clone();
             let message = progress.message.clone().unwrap_or_else(|| "".to_string());
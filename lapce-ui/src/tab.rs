@@ -1,22 +1,22 @@
-use std::{collections::HashMap, path::PathBuf, sync::Arc};
+use std::{collections::HashMap, sync::Arc, time::Duration};
 
 use druid::{
     kurbo::Line,
     piet::{PietTextLayout, Text, TextLayout, TextLayoutBuilder},
     BoxConstraints, Command, Cursor, Data, Env, Event, EventCtx, FontFamily,
     InternalLifeCycle, LayoutCtx, LifeCycle, LifeCycleCtx, PaintCtx, Point, Rect,
-    RenderContext, Size, Target, Widget, WidgetExt, WidgetId, WidgetPod,
-    WindowConfig,
+    RenderContext, Size, Target, TimerToken, UpdateCtx, Widget, WidgetExt, WidgetId,
+    WidgetPod, WindowConfig,
 };
 use itertools::Itertools;
 use lapce_data::{
-    buffer::LocalBufferKind,
+    buffer::{BufferContent, LocalBufferKind},
     command::{
         CommandTarget, LapceCommand, LapceCommandNew, LapceUICommand,
         LAPCE_NEW_COMMAND, LAPCE_UI_COMMAND,
     },
     completion::CompletionStatus,
-    config::{Config, LapceTheme},
+    config::{AutoSave, Config, LapceTheme},
     data::{
         DragContent, EditorDiagnostic, FocusArea, LapceTabData, PanelKind,
         WorkProgress,
@@ -29,8 +29,9 @@ use lapce_data::{
     panel::{PanelPosition, PanelResizePosition},
     proxy::path_from_url,
     state::LapceWorkspaceType,
+    task::TASK_SOURCE_PREFIX,
 };
-use lsp_types::DiagnosticSeverity;
+use lapce_rpc::search::SearchOptions;
 use serde::Deserialize;
 
 use crate::{
@@ -72,9 +73,21 @@ pub struct LapceTabNew {
     main_split_height: f64,
     status_height: f64,
     mouse_pos: Point,
+    hot_exit_timer: TimerToken,
+    auto_save_timer: TimerToken,
+    status_message_timer: TimerToken,
+    blame_timer: TimerToken,
+    /// Commits or checkouts may have changed the blame of unedited files.
+    blame_all: bool,
 }
 
 impl LapceTabNew {
+    /// How often unsaved buffers are snapshotted for hot exit.
+    const HOT_EXIT_INTERVAL: Duration = Duration::from_secs(30);
+    const STATUS_MESSAGE_DURATION: Duration = Duration::from_secs(5);
+    /// How long after the last edit the blame is brought up to date.
+    const BLAME_DELAY: Duration = Duration::from_millis(500);
+
     pub fn new(data: &LapceTabData) -> Self {
         let split_data = data
             .main_split
@@ -142,9 +155,120 @@ impl LapceTabNew {
             main_split_height: 0.0,
             status_height: 0.0,
             mouse_pos: Point::ZERO,
+            hot_exit_timer: TimerToken::INVALID,
+            auto_save_timer: TimerToken::INVALID,
+            status_message_timer: TimerToken::INVALID,
+            blame_timer: TimerToken::INVALID,
+            blame_all: false,
+        }
+    }
+
+    fn update_auto_save(
+        &mut self,
+        ctx: &mut UpdateCtx,
+        old_data: &LapceTabData,
+        data: &LapceTabData,
+    ) {
+        match data.config.editor.auto_save {
+            AutoSave::AfterDelay => {
+                let edited =
+                    data.main_split.open_files.iter().any(|(path, buffer)| {
+                        buffer.dirty()
+                            && old_data
+                                .main_split
+                                .open_files
+                                .get(path)
+                                .map(|old| old.rev() != buffer.rev())
+                                .unwrap_or(false)
+                    });
+                if edited {
+                    self.auto_save_timer = ctx.request_timer(Duration::from_millis(
+                        data.config.editor.auto_save_delay,
+                    ));
+                }
+            }
+            AutoSave::OnFocusChange => {
+                let focus_changed = old_data.main_split.active
+                    != data.main_split.active
+                    || (old_data.focus_area == FocusArea::Editor
+                        && data.focus_area != FocusArea::Editor);
+                if !focus_changed {
+                    return;
+                }
+                if let Some(BufferContent::File(path)) =
+                    old_data.main_split.active_editor().map(|e| &e.content)
+                {
+                    ctx.submit_command(Command::new(
+                        LAPCE_UI_COMMAND,
+                        LapceUICommand::AutoSave(path.clone()),
+                        Target::Widget(data.id),
+                    ));
+                }
+            }
+            AutoSave::Off | AutoSave::OnWindowChange => {}
         }
     }
 
+    fn update_blame(
+        &mut self,
+        ctx: &mut UpdateCtx,
+        old_data: &LapceTabData,
+        data: &LapceTabData,
+    ) {
+        let editor = &data.config.editor;
+        if !editor.inline_blame && !editor.blame_gutter {
+            return;
+        }
+        let toggled = old_data.config.editor.inline_blame != editor.inline_blame
+            || old_data.config.editor.blame_gutter != editor.blame_gutter;
+        let diff_changed =
+            !Arc::ptr_eq(&old_data.source_control, &data.source_control);
+        let edited = data.main_split.open_files.iter().any(|(path, buffer)| {
+            old_data
+                .main_split
+                .open_files
+                .get(path)
+                .map(|old| old.rev() != buffer.rev())
+                .unwrap_or(true)
+        });
+        if toggled || diff_changed {
+            self.blame_all = true;
+        }
+        if toggled
+            || diff_changed
+            || edited
+            || old_data.main_split.active != data.main_split.active
+        {
+            self.blame_timer = ctx.request_timer(Self::BLAME_DELAY);
+        }
+    }
+
+    /// Shows the repository of the file the user switched to in the source
+    /// control panel.
+    fn update_repository(
+        &mut self,
+        ctx: &mut UpdateCtx,
+        old_data: &LapceTabData,
+        data: &LapceTabData,
+    ) {
+        let path = match data.main_split.active_editor().map(|e| &e.content) {
+            Some(BufferContent::File(path)) => path,
+            _ => return,
+        };
+        if let Some(BufferContent::File(old_path)) =
+            old_data.main_split.active_editor().map(|e| &e.content)
+        {
+            if old_path == path {
+                return;
+            }
+        }
+        ctx.submit_command(Command::new(
+            LAPCE_UI_COMMAND,
+            LapceUICommand::SelectRepository(path.clone()),
+            Target::Widget(data.id),
+        ));
+    }
+
     fn update_split_point(&mut self, data: &mut LapceTabData, mouse_pos: Point) {
         if let Some(position) = self.current_bar_hover.as_ref() {
             match position {
@@ -265,6 +389,29 @@ impl Widget<LapceTabData> for LapceTabNew {
         env: &Env,
     ) {
         match event {
+            Event::Timer(token) if *token == self.hot_exit_timer => {
+                let _ = data.db.save_unsaved_buffers_async(data);
+                self.hot_exit_timer = ctx.request_timer(Self::HOT_EXIT_INTERVAL);
+                ctx.set_handled();
+            }
+            Event::Timer(token) if *token == self.status_message_timer => {
+                data.status_message = None;
+                ctx.set_handled();
+            }
+            Event::Timer(token) if *token == self.auto_save_timer => {
+                data.main_split.auto_save_all(ctx, &data.config);
+                ctx.set_handled();
+            }
+            Event::Timer(token) if *token == self.blame_timer => {
+                data.main_split.retrieve_blame(ctx, self.blame_all);
+                self.blame_all = false;
+                ctx.set_handled();
+            }
+            Event::WindowLostFocus => {
+                if data.config.editor.auto_save == AutoSave::OnWindowChange {
+                    data.main_split.auto_save_all(ctx, &data.config);
+                }
+            }
             Event::MouseDown(mouse) => {
                 if mouse.button.is_left() {
                     if let Some(position) = self.bar_hit_test(data, mouse.pos) {
@@ -319,11 +466,26 @@ impl Widget<LapceTabData> for LapceTabNew {
                     LapceUICommand::LoadBuffer {
                         path,
                         content,
+                        large_file,
+                        read_only,
                         locations,
                     } => {
                         let buffer =
                             data.main_split.open_files.get_mut(path).unwrap();
-                        Arc::make_mut(buffer).load_content(content);
+                        let buffer = Arc::make_mut(buffer);
+                        if *large_file {
+                            buffer.set_large_file();
+                        }
+                        buffer.set_read_only(*read_only);
+                        buffer.load_content(content);
+                        buffer.retrieve_file_head(
+                            data.id,
+                            data.proxy.clone(),
+                            ctx.get_external_handle(),
+                        );
+                        data.main_split.restore_unsaved_buffer(ctx, path);
+                        data.main_split
+                            .apply_pending_replacements(path, &data.config);
                         for (view_id, location) in locations {
                             data.main_split.go_to_location(
                                 ctx,
@@ -343,13 +505,13 @@ impl Widget<LapceTabData> for LapceTabNew {
                         if &buffer.rope().to_string() != pattern {
                             Arc::make_mut(buffer).load_content(pattern);
                         }
+                        Arc::make_mut(&mut data.search).reset(pattern);
                         if pattern.is_empty() {
                             Arc::make_mut(&mut data.find).unset();
-                            Arc::make_mut(&mut data.search).matches =
-                                Arc::new(HashMap::new());
+                            data.proxy.cancel_global_search();
                         } else {
                             let find = Arc::make_mut(&mut data.find);
-                            find.set_find(pattern, false, false, false);
+                            find.set_find(pattern, &data.search.options);
                             find.visual = true;
                             if data.focus_area == FocusArea::Panel(PanelKind::Search)
                             {
@@ -367,51 +529,76 @@ impl Widget<LapceTabData> for LapceTabNew {
                                     ));
                                 }
                             }
-                            let pattern = pattern.to_string();
-                            let event_sink = ctx.get_external_handle();
-                            let tab_id = data.id;
                             data.proxy.global_search(
-                                    pattern.clone(),
-                                    Box::new(move |result| {
-                                        if let Ok(matches) = result {
-                                            if let Ok(matches) =
-                                                serde_json::from_value::<
-                                                    HashMap<
-                                                        PathBuf,
-                                                        Vec<(
-                                                            usize,
-                                                            (usize, usize),
-                                                            String,
-                                                        )>,
-                                                    >,
-                                                >(
-                                                    matches
-                                                )
-                                            {
-                                                let _ = event_sink.submit_command(
-                                                    LAPCE_UI_COMMAND,
-                                                    LapceUICommand::GlobalSearchResult(
-                                                        pattern,
-                                                        Arc::new(matches),
-                                                    ),
-                                                    Target::Widget(tab_id),
-                                                );
-                                            }
-                                        }
-                                    }),
-                                )
+                                data.search.search_id,
+                                pattern,
+                                &data.search.options,
+                                data.config.lapce.search_max_file_matches,
+                                data.config.lapce.search_max_matches,
+                            );
                         }
                     }
-                    LapceUICommand::GlobalSearchResult(pattern, matches) => {
-                        let buffer = data
-                            .main_split
-                            .local_buffers
-                            .get(&LocalBufferKind::Search)
-                            .unwrap();
-                        if &buffer.rope().to_string() == pattern {
-                            Arc::make_mut(&mut data.search).matches =
-                                matches.clone();
+                    LapceUICommand::UpdateSearchReplace(replace) => {
+                        Arc::make_mut(&mut data.search).replace =
+                            replace.to_string();
+                        ctx.set_handled();
+                    }
+                    LapceUICommand::UpdateSearchInclude(include) => {
+                        Arc::make_mut(&mut data.search).options.include =
+                            SearchOptions::parse_globs(include);
+                        data.refresh_search(ctx);
+                        ctx.set_handled();
+                    }
+                    LapceUICommand::UpdateSearchExclude(exclude) => {
+                        Arc::make_mut(&mut data.search).options.exclude =
+                            SearchOptions::parse_globs(exclude);
+                        data.refresh_search(ctx);
+                        ctx.set_handled();
+                    }
+                    LapceUICommand::GlobalSearchResult(search_id, matches) => {
+                        if *search_id == data.search.search_id {
+                            let search = Arc::make_mut(&mut data.search);
+                            let all_matches = Arc::make_mut(&mut search.matches);
+                            for (path, file_matches) in matches.iter() {
+                                all_matches
+                                    .insert(path.clone(), file_matches.clone());
+                            }
+                        }
+                        ctx.set_handled();
+                    }
+                    LapceUICommand::GlobalSearchDone(
+                        search_id,
+                        limit_reached,
+                        error,
+                    ) => {
+                        if *search_id == data.search.search_id {
+                            let search = Arc::make_mut(&mut data.search);
+                            search.limit_reached = *limit_reached;
+                            search.error = error.clone();
+                        }
+                        ctx.set_handled();
+                    }
+                    LapceUICommand::SearchReplaceAll(search_id, replacements) => {
+                        data.search_replace_all(
+                            ctx,
+                            *search_id,
+                            replacements.clone(),
+                        );
+                        ctx.set_handled();
+                    }
+                    LapceUICommand::SetDiffBase(path, base) => {
+                        if let Some(buffer) =
+                            data.main_split.open_files.get_mut(path)
+                        {
+                            let buffer = Arc::make_mut(buffer);
+                            buffer.set_diff_base(base.clone());
+                            buffer.retrieve_file_head(
+                                data.id,
+                                data.proxy.clone(),
+                                ctx.get_external_handle(),
+                            );
                         }
+                        ctx.set_handled();
                     }
                     LapceUICommand::LoadBufferHead { path, id, content } => {
                         let buffer =
@@ -425,7 +612,9 @@ impl Widget<LapceTabData> for LapceTabNew {
                         if let Some(terminal) =
                             terminal_panel.terminals.get_mut(term_id)
                         {
-                            Arc::make_mut(terminal).title = title.to_string();
+                            if !terminal.fixed_title {
+                                Arc::make_mut(terminal).title = title.to_string();
+                            }
                         }
                     }
                     LapceUICommand::CancelFilePicker => {
@@ -441,6 +630,29 @@ impl Widget<LapceTabData> for LapceTabNew {
                         data.set_picker_pwd(path.clone());
                         ctx.set_handled();
                     }
+                    LapceUICommand::TerminalExited(id) => {
+                        let running_task = data
+                            .terminal
+                            .terminals
+                            .get(id)
+                            .and_then(|terminal| terminal.task.as_ref())
+                            .map_or(false, |task| !task.finished);
+                        if running_task {
+                            // The terminal of a task stays open for its output.
+                            data.task_finished(ctx, id);
+                        } else {
+                            ctx.submit_command(Command::new(
+                                LAPCE_UI_COMMAND,
+                                LapceUICommand::CloseTerminal(*id),
+                                Target::Widget(data.id),
+                            ));
+                        }
+                        ctx.set_handled();
+                    }
+                    LapceUICommand::RunTask(tasks, name) => {
+                        data.run_task(ctx, tasks.clone(), name);
+                        ctx.set_handled();
+                    }
                     LapceUICommand::CloseTerminal(id) => {
                         let terminal_panel = Arc::make_mut(&mut data.terminal);
                         if let Some(terminal) = terminal_panel.terminals.get_mut(id)
@@ -460,25 +672,11 @@ impl Widget<LapceTabData> for LapceTabNew {
                     LapceUICommand::UpdateInstalledPlugins(plugins) => {
                         data.installed_plugins = Arc::new(plugins.to_owned());
                     }
-                    LapceUICommand::UpdateDiffInfo(diff) => {
-                        let source_control = Arc::make_mut(&mut data.source_control);
-                        source_control.branch = diff.head.to_string();
-                        source_control.branches = diff.branches.clone();
-                        source_control.file_diffs = diff
-                            .diffs
-                            .iter()
-                            .map(|diff| {
-                                let mut checked = true;
-                                for (p, c) in source_control.file_diffs.iter() {
-                                    if p == diff {
-                                        checked = *c;
-                                        break;
-                                    }
-                                }
-                                (diff.clone(), checked)
-                            })
-                            .collect();
-
+                    LapceUICommand::UpdateDiffInfo(repositories) => {
+                        Arc::make_mut(&mut data.source_control).update_repositories(
+                            data.workspace.path.as_deref(),
+                            repositories.clone(),
+                        );
                         for (_path, buffer) in data.main_split.open_files.iter() {
                             buffer.retrieve_file_head(
                                 data.id,
@@ -488,6 +686,11 @@ impl Widget<LapceTabData> for LapceTabNew {
                         }
                         ctx.set_handled();
                     }
+                    LapceUICommand::SelectRepository(path) => {
+                        Arc::make_mut(&mut data.source_control)
+                            .select_repository(path);
+                        ctx.set_handled();
+                    }
                     LapceUICommand::WorkDoneProgress(params) => {
                         match &params.value {
                             lsp_types::ProgressParamsValue::WorkDone(progress) => {
@@ -525,6 +728,20 @@ impl Widget<LapceTabData> for LapceTabNew {
                     }
                     LapceUICommand::PublishDiagnostics(diagnostics) => {
                         let path = path_from_url(&diagnostics.uri);
+                        // The problems found by tasks stay until they run again.
+                        let tasks = data
+                            .main_split
+                            .diagnostics
+                            .get(&path)
+                            .into_iter()
+                            .flat_map(|d| d.iter())
+                            .filter(|d| {
+                                d.diagnositc.source.as_ref().map_or(false, |s| {
+                                    s.starts_with(TASK_SOURCE_PREFIX)
+                                })
+                            })
+                            .cloned()
+                            .collect::<Vec<_>>();
                         let diagnostics = diagnostics
                             .diagnostics
                             .iter()
@@ -532,41 +749,53 @@ impl Widget<LapceTabData> for LapceTabNew {
                                 range: None,
                                 diagnositc: d.clone(),
                             })
+                            .chain(tasks)
                             .collect();
                         data.main_split
                             .diagnostics
                             .insert(path, Arc::new(diagnostics));
-
-                        let mut errors = 0;
-                        let mut warnings = 0;
-                        for (_, diagnositics) in data.main_split.diagnostics.iter() {
-                            for diagnositic in diagnositics.iter() {
-                                if let Some(severity) =
-                                    diagnositic.diagnositc.severity
-                                {
-                                    match severity {
-                                        DiagnosticSeverity::Error => errors += 1,
-                                        DiagnosticSeverity::Warning => warnings += 1,
-                                        _ => (),
-                                    }
-                                }
-                            }
-                        }
-                        data.main_split.error_count = errors;
-                        data.main_split.warning_count = warnings;
+                        data.main_split.update_diagnostic_counts();
 
                         ctx.set_handled();
                     }
-                    LapceUICommand::DocumentFormatAndSave(path, rev, result) => {
+                    LapceUICommand::DocumentFormatAndSave(
+                        path,
+                        rev,
+                        result,
+                        auto_save,
+                    ) => {
                         data.main_split.document_format_and_save(
                             ctx,
                             path,
                             *rev,
                             result,
+                            *auto_save,
                             &data.config,
                         );
                         ctx.set_handled();
                     }
+                    LapceUICommand::ShowStatusMessage(message) => {
+                        data.status_message = Some(message.to_string());
+                        self.status_message_timer =
+                            ctx.request_timer(Self::STATUS_MESSAGE_DURATION);
+                        ctx.set_handled();
+                    }
+                    LapceUICommand::OpenLocalHistory(path, version) => {
+                        data.main_split.open_local_history(ctx, path, version);
+                        ctx.set_handled();
+                    }
+                    LapceUICommand::ClearCommitMessage => {
+                        data.clear_commit_message();
+                        ctx.set_handled();
+                    }
+                    LapceUICommand::OpenCommitFileDiff(commit, diff) => {
+                        data.main_split.open_commit_file_diff(ctx, commit, diff);
+                        ctx.set_handled();
+                    }
+                    LapceUICommand::AutoSave(path) => {
+                        data.main_split.auto_save(ctx, path, &data.config);
+                        ctx.set_handled();
+                    }
                     LapceUICommand::DocumentFormat(path, rev, result) => {
                         data.main_split.document_format(
                             path,
@@ -580,7 +809,9 @@ impl Widget<LapceTabData> for LapceTabNew {
                         let buffer =
                             data.main_split.open_files.get_mut(path).unwrap();
                         if buffer.rev() == *rev {
-                            Arc::make_mut(buffer).set_dirty(false);
+                            let buffer = Arc::make_mut(buffer);
+                            buffer.set_dirty(false);
+                            buffer.set_pristine();
                         }
                         ctx.set_handled();
                     }
@@ -607,6 +838,25 @@ impl Widget<LapceTabData> for LapceTabNew {
                             debug_assert!(update_result.is_some());
                         }
                     }
+                    LapceUICommand::OpenGitSnapshot {
+                        path,
+                        history,
+                        parent_content,
+                    } => {
+                        let editor_view_id = data.main_split.open_git_snapshot(
+                            ctx,
+                            path,
+                            history,
+                            parent_content,
+                            &data.config,
+                        );
+                        ctx.submit_command(Command::new(
+                            LAPCE_UI_COMMAND,
+                            LapceUICommand::Focus,
+                            Target::Widget(editor_view_id),
+                        ));
+                        ctx.set_handled();
+                    }
                     LapceUICommand::OpenFileDiff(path, history) => {
                         let editor_view_id = data.main_split.active.clone();
                         let editor_view_id = data.main_split.jump_to_location(
@@ -938,6 +1188,14 @@ impl Widget<LapceTabData> for LapceTabNew {
                             changes.clone(),
                         );
                     }
+                    LapceUICommand::UpdateBlame { path, rev, hunks } => {
+                        if let Some(buffer) =
+                            data.main_split.open_files.get_mut(path)
+                        {
+                            Arc::make_mut(buffer).set_blame(*rev, hunks.clone());
+                        }
+                        ctx.set_handled();
+                    }
                     #[allow(unused_variables)]
                     LapceUICommand::UpdateHistoryStyle {
                         id,
@@ -1036,6 +1294,9 @@ impl Widget<LapceTabData> for LapceTabNew {
         data: &LapceTabData,
         env: &Env,
     ) {
+        if let LifeCycle::WidgetAdded = event {
+            self.hot_exit_timer = ctx.request_timer(Self::HOT_EXIT_INTERVAL);
+        }
         if let LifeCycle::Internal(InternalLifeCycle::ParentWindowOrigin) = event {
             if ctx.window_origin() != data.window_origin {
                 ctx.submit_command(Command::new(
@@ -1095,6 +1356,10 @@ impl Widget<LapceTabData> for LapceTabNew {
             ctx.request_layout();
         }
 
+        self.update_auto_save(ctx, old_data, data);
+        self.update_blame(ctx, old_data, data);
+        self.update_repository(ctx, old_data, data);
+
         self.palette.update(ctx, data, env);
         self.activity.update(ctx, data, env);
         self.main_split.update(ctx, data, env);
@@ -1,14 +1,18 @@
-use std::sync::Arc;
+use std::{str::FromStr, sync::Arc};
 
 use alacritty_terminal::{
     ansi,
     event::EventListener,
     grid::Dimensions,
-    index::{Direction, Side},
-    term::{cell::Flags, search::RegexSearch, SizeInfo},
+    term::{
+        cell::Flags,
+        search::{Match, RegexSearch},
+        SizeInfo,
+    },
     Term,
 };
 use druid::{
+    kurbo::Line,
     piet::{Text, TextAttribute, TextLayout, TextLayoutBuilder},
     BoxConstraints, Command, Data, Env, Event, EventCtx, ExtEventSink, FontFamily,
     FontWeight, KbKey, LayoutCtx, LifeCycle, LifeCycleCtx, Modifiers, MouseEvent,
@@ -16,21 +20,33 @@ use druid::{
     WidgetExt, WidgetId, WidgetPod,
 };
 use lapce_data::{
-    command::{LapceUICommand, LAPCE_UI_COMMAND},
+    command::{
+        CommandTarget, LapceCommand, LapceCommandNew, LapceUICommand,
+        LapceWorkbenchCommand, LAPCE_NEW_COMMAND, LAPCE_UI_COMMAND,
+    },
     config::LapceTheme,
     data::{FocusArea, LapceTabData, PanelKind},
+    editor::EditorLocationNew,
+    find::Find,
     keypress::KeyPressFocus,
     proxy::LapceProxy,
     split::SplitDirection,
-    state::Mode,
-    terminal::{LapceTerminalData, LapceTerminalViewData},
+    state::{LapceWorkspaceType, Mode},
+    terminal::{
+        search_matches, terminal_search, LapceTerminalData, LapceTerminalViewData,
+        TerminalLink, TerminalLinkTarget, MAX_SEARCH_MATCHES,
+    },
 };
 use lapce_rpc::terminal::TermId;
+use lsp_types::Position;
 use unicode_width::UnicodeWidthChar;
+use xi_rope::find::CaseMatching;
 
 use crate::{
+    editor::view::LapceEditorView,
     panel::{LapcePanel, PanelHeaderKind},
     scroll::LapcePadding,
+    search::SearchInput,
     split::LapceSplitNew,
     svg::get_svg,
     tab::LapceIcon,
@@ -43,6 +59,9 @@ const CTRL_CHARS: &[char] = &[
 
 pub type TermConfig = alacritty_terminal::config::Config;
 
+/// The width of the marks of the shell commands, left of the terminal.
+const COMMAND_MARK_WIDTH: f64 = 3.0;
+
 pub struct RawTerminal {
     pub parser: ansi::Processor,
     pub term: Term<EventProxy>,
@@ -165,7 +184,9 @@ impl Widget<LapceTabData> for TerminalPanel {
         data: &LapceTabData,
         env: &Env,
     ) {
-        if data.terminal.terminals.is_empty() {
+        // A task can open its terminal before the panel was shown, the last
+        // session is still restored then.
+        if data.terminal.terminals.is_empty() || data.terminal.restore.is_some() {
             ctx.submit_command(Command::new(
                 LAPCE_UI_COMMAND,
                 LapceUICommand::InitTerminalPanel(true),
@@ -203,15 +224,18 @@ impl Widget<LapceTabData> for TerminalPanel {
 pub struct LapceTerminalView {
     header: WidgetPod<LapceTabData, LapceTerminalHeader>,
     terminal: WidgetPod<LapceTabData, Box<dyn Widget<LapceTabData>>>,
+    find: WidgetPod<LapceTabData, LapceTerminalFind>,
 }
 
 impl LapceTerminalView {
     pub fn new(data: &LapceTerminalData) -> Self {
         let header = LapceTerminalHeader::new(data);
         let terminal = LapcePadding::new(10.0, LapceTerminal::new(data));
+        let find = LapceTerminalFind::new(data);
         Self {
             header: WidgetPod::new(header),
             terminal: WidgetPod::new(terminal.boxed()),
+            find: WidgetPod::new(find),
         }
     }
 }
@@ -224,6 +248,23 @@ impl Widget<LapceTabData> for LapceTerminalView {
         data: &mut LapceTabData,
         env: &Env,
     ) {
+        // The find bar only takes the mouse while it's shown, but the
+        // commands to its editor go through it anyway.
+        let find_visible = self.find.widget().is_visible(data);
+        match event {
+            Event::Command(cmd)
+                if cmd.is(LAPCE_UI_COMMAND) || cmd.is(LAPCE_NEW_COMMAND) => {}
+            Event::MouseMove(_)
+            | Event::MouseDown(_)
+            | Event::MouseUp(_)
+            | Event::Wheel(_)
+                if !find_visible => {}
+            _ => self.find.event(ctx, event, data, env),
+        }
+        if ctx.is_handled() {
+            return;
+        }
+
         self.header.event(ctx, event, data, env);
         self.terminal.event(ctx, event, data, env);
     }
@@ -241,6 +282,7 @@ impl Widget<LapceTabData> for LapceTerminalView {
         }
         self.header.lifecycle(ctx, event, data, env);
         self.terminal.lifecycle(ctx, event, data, env);
+        self.find.lifecycle(ctx, event, data, env);
     }
 
     fn update(
@@ -252,6 +294,7 @@ impl Widget<LapceTabData> for LapceTerminalView {
     ) {
         self.header.update(ctx, data, env);
         self.terminal.update(ctx, data, env);
+        self.find.update(ctx, data, env);
     }
 
     fn layout(
@@ -278,6 +321,17 @@ impl Widget<LapceTabData> for LapceTerminalView {
             );
         }
 
+        let find_size = self.find.layout(ctx, bc, data, env);
+        self.find.set_origin(
+            ctx,
+            data,
+            env,
+            Point::new(
+                (self_size.width - find_size.width - 10.0).max(0.0),
+                header_size.height,
+            ),
+        );
+
         self_size
     }
 
@@ -302,6 +356,297 @@ impl Widget<LapceTabData> for LapceTerminalView {
 
         self.header.paint(ctx, data, env);
         self.terminal.paint(ctx, data, env);
+        if self.find.widget().is_visible(data) {
+            self.find.paint(ctx, data, env);
+        }
+    }
+}
+
+/// The find bar of a terminal, with the options of the search, the number
+/// of matches in the scrollback and the buttons to move between them.
+pub struct LapceTerminalFind {
+    term_id: TermId,
+    input_width: f64,
+    count_width: f64,
+    input: WidgetPod<LapceTabData, SearchInput>,
+    icons: Vec<LapceIcon>,
+    mouse_pos: Point,
+    /// The matches in the scrollback, searched again once the find or the
+    /// grid changed. `None` when there's nothing to search for.
+    matches: Option<(FindMatchesKey, Option<Vec<Match>>)>,
+}
+
+/// What the matches of the find bar were searched with.
+#[derive(PartialEq)]
+struct FindMatchesKey {
+    grid_version: u64,
+    search_string: Option<String>,
+    regex: bool,
+    case_sensitive: bool,
+}
+
+impl FindMatchesKey {
+    fn new(grid_version: u64, find: &Find) -> Self {
+        Self {
+            grid_version,
+            search_string: find.search_string.clone(),
+            regex: find.regex.is_some(),
+            case_sensitive: find.case_matching == CaseMatching::Exact,
+        }
+    }
+}
+
+impl LapceTerminalFind {
+    pub fn new(data: &LapceTerminalData) -> Self {
+        let input = LapceEditorView::new(data.find_view_id, None)
+            .hide_header()
+            .hide_gutter()
+            .padding((10.0, 5.0));
+        let input = SearchInput::new(
+            input.boxed(),
+            vec![
+                ("Aa", LapceWorkbenchCommand::ToggleSearchCaseSensitive),
+                (".*", LapceWorkbenchCommand::ToggleSearchRegex),
+            ],
+        );
+        let icons = [
+            ("arrow-up.svg", LapceCommand::SearchBackward),
+            ("arrow-down.svg", LapceCommand::SearchForward),
+            ("close.svg", LapceCommand::ClearSearch),
+        ]
+        .into_iter()
+        .map(|(icon, cmd)| LapceIcon {
+            icon: icon.to_string(),
+            rect: Rect::ZERO,
+            command: Command::new(
+                LAPCE_NEW_COMMAND,
+                LapceCommandNew {
+                    cmd: cmd.to_string(),
+                    data: None,
+                    palette_desc: None,
+                    target: CommandTarget::Focus,
+                },
+                Target::Widget(data.widget_id),
+            ),
+        })
+        .collect();
+        Self {
+            term_id: data.term_id,
+            input_width: 250.0,
+            count_width: 90.0,
+            input: WidgetPod::new(input),
+            icons,
+            mouse_pos: Point::ZERO,
+            matches: None,
+        }
+    }
+
+    fn is_visible(&self, data: &LapceTabData) -> bool {
+        data.find.visual
+            && data
+                .terminal
+                .terminals
+                .get(&self.term_id)
+                .map(|terminal| terminal.find_visual)
+                .unwrap_or(false)
+    }
+
+    /// The position of the current match among those in the scrollback,
+    /// or their number when the search hasn't moved to one.
+    fn match_count(&mut self, data: &LapceTabData) -> String {
+        let terminal = match data.terminal.terminals.get(&self.term_id) {
+            Some(terminal) => terminal,
+            None => return String::new(),
+        };
+        let raw = terminal.raw.lock();
+        let key = FindMatchesKey::new(raw.grid_version, &data.find);
+        if self.matches.as_ref().map(|(k, _)| k) != Some(&key) {
+            let term = &raw.term;
+            let matches = terminal_search(&data.find).map(|dfas| {
+                search_matches(
+                    term,
+                    &dfas,
+                    term.topmost_line(),
+                    term.bottommost_line(),
+                    MAX_SEARCH_MATCHES,
+                )
+            });
+            self.matches = Some((key, matches));
+        }
+        let matches = match self.matches.as_ref().and_then(|(_, m)| m.as_ref()) {
+            Some(matches) => matches,
+            None => return String::new(),
+        };
+        if matches.is_empty() {
+            return "No results".to_string();
+        }
+        let total = if matches.len() == MAX_SEARCH_MATCHES {
+            format!("{}+", matches.len())
+        } else {
+            matches.len().to_string()
+        };
+        match raw
+            .search_match
+            .as_ref()
+            .and_then(|current| matches.iter().position(|m| m == current))
+        {
+            Some(i) => format!("{} of {total}", i + 1),
+            None if matches.len() == 1 => "1 result".to_string(),
+            None => format!("{total} results"),
+        }
+    }
+}
+
+impl Widget<LapceTabData> for LapceTerminalFind {
+    fn event(
+        &mut self,
+        ctx: &mut EventCtx,
+        event: &Event,
+        data: &mut LapceTabData,
+        env: &Env,
+    ) {
+        self.input.event(ctx, event, data, env);
+        match event {
+            Event::MouseMove(mouse_event) => {
+                ctx.set_handled();
+                self.mouse_pos = mouse_event.pos;
+                if self
+                    .icons
+                    .iter()
+                    .any(|icon| icon.rect.contains(mouse_event.pos))
+                {
+                    ctx.set_cursor(&druid::Cursor::Pointer);
+                } else {
+                    ctx.clear_cursor();
+                }
+                ctx.request_paint();
+            }
+            Event::MouseDown(mouse_event) => {
+                ctx.set_handled();
+                for icon in self.icons.iter() {
+                    if icon.rect.contains(mouse_event.pos) {
+                        ctx.submit_command(icon.command.clone());
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn lifecycle(
+        &mut self,
+        ctx: &mut LifeCycleCtx,
+        event: &LifeCycle,
+        data: &LapceTabData,
+        env: &Env,
+    ) {
+        self.input.lifecycle(ctx, event, data, env);
+    }
+
+    fn update(
+        &mut self,
+        ctx: &mut UpdateCtx,
+        old_data: &LapceTabData,
+        data: &LapceTabData,
+        env: &Env,
+    ) {
+        if !old_data.find.same(&data.find) || !old_data.terminal.same(&data.terminal)
+        {
+            ctx.request_layout();
+        }
+        self.input.update(ctx, data, env);
+    }
+
+    fn layout(
+        &mut self,
+        ctx: &mut LayoutCtx,
+        bc: &BoxConstraints,
+        data: &LapceTabData,
+        env: &Env,
+    ) -> Size {
+        let input_bc =
+            BoxConstraints::tight(Size::new(self.input_width, bc.max().height));
+        let mut input_size = self.input.layout(ctx, &input_bc, data, env);
+        let height = input_size.height;
+        let mut width = input_size.width + self.count_width + height * 3.0;
+
+        if width + 20.0 > bc.max().width {
+            let input_bc = BoxConstraints::tight(Size::new(
+                (bc.max().width - self.count_width - height * 3.0 - 20.0).max(0.0),
+                bc.max().height,
+            ));
+            input_size = self.input.layout(ctx, &input_bc, data, env);
+            width = input_size.width + self.count_width + height * 3.0;
+        }
+        self.input.set_origin(ctx, data, env, Point::ZERO);
+
+        let icons_x = input_size.width + self.count_width;
+        for (i, icon) in self.icons.iter_mut().enumerate() {
+            icon.rect = Size::new(height, height)
+                .to_rect()
+                .with_origin(Point::new(icons_x + i as f64 * height, 0.0))
+                .inflate(-5.0, -5.0);
+        }
+
+        Size::new(width, height)
+    }
+
+    fn paint(&mut self, ctx: &mut PaintCtx, data: &LapceTabData, env: &Env) {
+        let rect = ctx.size().to_rect();
+        ctx.with_save(|ctx| {
+            ctx.clip(rect.inset((100.0, 0.0, 100.0, 100.0)));
+            let shadow_width = 5.0;
+            ctx.blurred_rect(
+                rect,
+                shadow_width,
+                data.config
+                    .get_color_unchecked(LapceTheme::LAPCE_DROPDOWN_SHADOW),
+            );
+        });
+        ctx.fill(
+            rect,
+            data.config
+                .get_color_unchecked(LapceTheme::EDITOR_BACKGROUND),
+        );
+        self.input.paint(ctx, data, env);
+
+        let input_width = self.input.layout_rect().width();
+        let text_layout = ctx
+            .text()
+            .new_text_layout(self.match_count(data))
+            .font(FontFamily::SYSTEM_UI, 13.0)
+            .text_color(
+                data.config
+                    .get_color_unchecked(LapceTheme::EDITOR_DIM)
+                    .clone(),
+            )
+            .build()
+            .unwrap();
+        let text_size = text_layout.size();
+        ctx.draw_text(
+            &text_layout,
+            Point::new(input_width + 10.0, (rect.height() - text_size.height) / 2.0),
+        );
+
+        for icon in self.icons.iter() {
+            if icon.rect.contains(self.mouse_pos) {
+                ctx.fill(
+                    &icon.rect,
+                    data.config
+                        .get_color_unchecked(LapceTheme::EDITOR_CURRENT_LINE),
+                );
+            }
+
+            let svg = get_svg(&icon.icon).unwrap();
+            ctx.draw_svg(
+                &svg,
+                icon.rect.inflate(-7.0, -7.0),
+                Some(
+                    data.config
+                        .get_color_unchecked(LapceTheme::EDITOR_FOREGROUND),
+                ),
+            );
+        }
     }
 }
 
@@ -512,6 +857,11 @@ pub struct LapceTerminal {
     widget_id: WidgetId,
     width: f64,
     height: f64,
+    /// The link under the mouse, which is underlined.
+    hover_link: Option<TerminalLink>,
+    /// The search the matches on screen are highlighted with, compiled again
+    /// once the find or the grid changed.
+    search: Option<(FindMatchesKey, Option<RegexSearch>)>,
 }
 
 impl LapceTerminal {
@@ -521,11 +871,83 @@ impl LapceTerminal {
             widget_id: data.widget_id,
             width: 0.0,
             height: 0.0,
+            hover_link: None,
+            search: None,
+        }
+    }
+
+    /// The link under the mouse. Files are checked to exist in local
+    /// workspaces, remote ones are trusted to the patterns.
+    fn link_at_mouse(
+        &self,
+        ctx: &mut EventCtx,
+        data: &LapceTabData,
+        mouse_event: &MouseEvent,
+    ) -> Option<TerminalLink> {
+        let char_width = data.config.editor_char_width(ctx.text());
+        let line_height = data.config.editor.line_height as f64;
+        let terminal = data.terminal.terminals.get(&self.term_id)?;
+        let raw = terminal.raw.lock();
+        let term = &raw.term;
+        let point = alacritty_terminal::index::Point::new(
+            alacritty_terminal::index::Line(
+                (mouse_event.pos.y / line_height).floor() as i32
+                    - term.grid().display_offset() as i32,
+            ),
+            alacritty_terminal::index::Column(
+                (mouse_event.pos.x / char_width).floor() as usize,
+            ),
+        );
+        let link = terminal.link_at(&raw, point, &data.config)?;
+        if let TerminalLinkTarget::File { path, .. } = &link.target {
+            if data.workspace.kind == LapceWorkspaceType::Local && !path.exists() {
+                return None;
+            }
+        }
+        Some(link)
+    }
+
+    fn open_link(
+        &self,
+        ctx: &mut EventCtx,
+        data: &LapceTabData,
+        link: &TerminalLink,
+    ) {
+        match &link.target {
+            TerminalLinkTarget::Url(url) => open_url(url),
+            TerminalLinkTarget::File { path, line, column } => {
+                ctx.submit_command(Command::new(
+                    LAPCE_UI_COMMAND,
+                    LapceUICommand::JumpToLocation(
+                        None,
+                        EditorLocationNew {
+                            path: path.clone(),
+                            position: Some(Position {
+                                line: line.saturating_sub(1),
+                                character: column.saturating_sub(1),
+                            }),
+                            scroll_offset: None,
+                            history: None,
+                        },
+                    ),
+                    Target::Widget(data.id),
+                ));
+            }
         }
     }
 
     pub fn request_focus(&self, ctx: &mut EventCtx, data: &mut LapceTabData) {
         ctx.request_focus();
+        // The find bar closed from its editor.
+        if !data.find.visual {
+            if let Some(terminal) = Arc::make_mut(&mut data.terminal)
+                .terminals
+                .get_mut(&self.term_id)
+                .filter(|terminal| terminal.find_visual)
+            {
+                Arc::make_mut(terminal).find_visual = false;
+            }
+        }
         Arc::make_mut(&mut data.terminal).active = self.widget_id;
         Arc::make_mut(&mut data.terminal).active_term_id = self.term_id;
         data.focus = self.widget_id;
@@ -560,8 +982,25 @@ impl Widget<LapceTabData> for LapceTerminal {
             find: data.find.clone(),
         };
         match event {
-            Event::MouseDown(_mouse_event) => {
+            Event::MouseMove(mouse_event) => {
+                let link = self.link_at_mouse(ctx, data, mouse_event);
+                if link.is_some() && mouse_event.mods.ctrl() {
+                    ctx.set_cursor(&druid::Cursor::Pointer);
+                } else {
+                    ctx.clear_cursor();
+                }
+                if link != self.hover_link {
+                    self.hover_link = link;
+                    ctx.request_paint();
+                }
+            }
+            Event::MouseDown(mouse_event) => {
                 self.request_focus(ctx, data);
+                if mouse_event.mods.ctrl() {
+                    if let Some(link) = self.link_at_mouse(ctx, data, mouse_event) {
+                        self.open_link(ctx, data, &link);
+                    }
+                }
             }
             Event::Wheel(wheel_event) => {
                 data.terminal
@@ -615,6 +1054,20 @@ impl Widget<LapceTabData> for LapceTerminal {
                 let command = cmd.get_unchecked(LAPCE_UI_COMMAND);
                 if let LapceUICommand::Focus = command {
                     self.request_focus(ctx, data);
+                    term_data.terminal =
+                        data.terminal.terminals.get(&self.term_id).unwrap().clone();
+                }
+            }
+            Event::Command(cmd) if cmd.is(LAPCE_NEW_COMMAND) => {
+                let command = cmd.get_unchecked(LAPCE_NEW_COMMAND);
+                if let Ok(command) = LapceCommand::from_str(&command.cmd) {
+                    term_data.run_command(
+                        ctx,
+                        &command,
+                        None,
+                        Modifiers::empty(),
+                        env,
+                    );
                 }
             }
             _ => (),
@@ -624,6 +1077,9 @@ impl Widget<LapceTabData> for LapceTerminal {
                 .terminals
                 .insert(term_data.terminal.term_id, term_data.terminal.clone());
         }
+        if !term_data.find.same(&data.find) {
+            data.find = term_data.find.clone();
+        }
     }
 
     fn lifecycle(
@@ -633,18 +1089,28 @@ impl Widget<LapceTabData> for LapceTerminal {
         _data: &LapceTabData,
         _env: &Env,
     ) {
-        if let LifeCycle::FocusChanged(_) = event {
-            ctx.request_paint();
+        match event {
+            LifeCycle::FocusChanged(_) => {
+                ctx.request_paint();
+            }
+            LifeCycle::HotChanged(false) if self.hover_link.is_some() => {
+                self.hover_link = None;
+                ctx.request_paint();
+            }
+            _ => (),
         }
     }
 
     fn update(
         &mut self,
-        _ctx: &mut UpdateCtx,
-        _old_data: &LapceTabData,
-        _data: &LapceTabData,
+        ctx: &mut UpdateCtx,
+        old_data: &LapceTabData,
+        data: &LapceTabData,
         _env: &Env,
     ) {
+        if !old_data.find.same(&data.find) {
+            ctx.request_paint();
+        }
     }
 
     fn layout(
@@ -655,6 +1121,7 @@ impl Widget<LapceTabData> for LapceTerminal {
         _env: &Env,
     ) -> Size {
         let size = bc.max();
+        ctx.set_paint_insets((COMMAND_MARK_WIDTH + 3.0, 0.0, 0.0, 0.0));
         if self.width != size.width || self.height != size.height {
             self.width = size.width;
             self.height = size.height;
@@ -823,46 +1290,85 @@ impl Widget<LapceTabData> for LapceTerminal {
                 ctx.draw_text(&text_layout, Point::new(x, y + y_shift));
             }
         }
-        if data.find.visual {
-            if let Some(search_string) = data.find.search_string.as_ref() {
-                if let Ok(dfas) = RegexSearch::new(search_string) {
-                    let mut start = alacritty_terminal::index::Point::new(
-                        alacritty_terminal::index::Line(
-                            -(content.display_offset as i32),
-                        ),
-                        alacritty_terminal::index::Column(0),
-                    );
-                    let end_line = (start.line + term.screen_lines())
-                        .min(term.bottommost_line());
-                    let mut max_lines = (end_line.0 - start.line.0) as usize;
-
-                    while let Some(m) = term.search_next(
-                        &dfas,
-                        start,
-                        Direction::Right,
-                        Side::Left,
-                        Some(max_lines),
-                    ) {
-                        let match_start = m.start();
-                        if match_start.line.0 < start.line.0
-                            || (match_start.line.0 == start.line.0
-                                && match_start.column.0 < start.column.0)
-                        {
-                            break;
-                        }
-                        let x = match_start.column.0 as f64 * char_width;
-                        let y = (match_start.line.0 as f64
-                            + content.display_offset as f64)
+        // Marks of the finished commands in the padding on the left, which
+        // stand out for the failed ones.
+        for command in &raw.shell.commands {
+            if command.exit_code.is_none() {
+                continue;
+            }
+            let line = raw.shell.grid_line(command.prompt).0
+                + content.display_offset as i32;
+            if line < 0 || line >= term.screen_lines() as i32 {
+                continue;
+            }
+            let y = line as f64 * line_height;
+            let color = if command.failed() {
+                data.config.get_color_unchecked(LapceTheme::LAPCE_ERROR)
+            } else {
+                data.config.get_color_unchecked(LapceTheme::EDITOR_DIM)
+            };
+            ctx.fill(
+                Rect::new(-COMMAND_MARK_WIDTH - 3.0, y, -3.0, y + line_height),
+                color,
+            );
+        }
+        if let Some(link) = self.hover_link.as_ref() {
+            let y = (link.line as f64 + content.display_offset as f64 + 1.0)
+                * line_height
+                - y_shift;
+            ctx.stroke(
+                Line::new(
+                    Point::new(link.start_column as f64 * char_width, y),
+                    Point::new(link.end_column as f64 * char_width, y),
+                ),
+                data.config
+                    .get_color_unchecked(LapceTheme::TERMINAL_FOREGROUND),
+                1.0,
+            );
+        }
+        if data.find.visual && terminal.find_visual {
+            let key = FindMatchesKey::new(raw.grid_version, &data.find);
+            if self.search.as_ref().map(|(k, _)| k) != Some(&key) {
+                self.search = Some((key, terminal_search(&data.find)));
+            }
+            if let Some(dfas) = self.search.as_ref().and_then(|(_, s)| s.as_ref()) {
+                let start_line = alacritty_terminal::index::Line(
+                    -(content.display_offset as i32),
+                );
+                let end_line = alacritty_terminal::index::Line(
+                    start_line.0 + term.screen_lines() as i32 - 1,
+                );
+                let matches =
+                    search_matches(term, dfas, start_line, end_line, usize::MAX);
+                for m in matches {
+                    let active = raw.search_match.as_ref() == Some(&m);
+                    for line in m.start().line.0..m.end().line.0 + 1 {
+                        let left_col = if line == m.start().line.0 {
+                            m.start().column.0
+                        } else {
+                            0
+                        };
+                        let right_col = if line == m.end().line.0 {
+                            m.end().column.0
+                                + term.grid()[*m.end()].c.width().unwrap_or(1)
+                        } else {
+                            term.columns()
+                        };
+                        let x0 = left_col as f64 * char_width;
+                        let x1 = right_col as f64 * char_width;
+                        let y0 = (line as f64 + content.display_offset as f64)
                             * line_height;
-                        let rect = Rect::ZERO
-                            .with_origin(Point::new(x, y))
-                            .with_size(Size::new(
-                                (m.end().column.0 - m.start().column.0
-                                    + term.grid()[*m.end()].c.width().unwrap_or(1))
-                                    as f64
-                                    * char_width,
-                                line_height,
-                            ));
+                        let rect = Rect::new(x0, y0, x1, y0 + line_height);
+                        if active {
+                            ctx.fill(
+                                rect,
+                                &data
+                                    .config
+                                    .get_color_unchecked(LapceTheme::EDITOR_CARET)
+                                    .clone()
+                                    .with_alpha(0.5),
+                            );
+                        }
                         ctx.stroke(
                             rect,
                             data.config.get_color_unchecked(
@@ -870,14 +1376,6 @@ impl Widget<LapceTabData> for LapceTerminal {
                             ),
                             1.0,
                         );
-                        start = *m.end();
-                        if start.column.0 < term.last_column() {
-                            start.column.0 += 1;
-                        } else if start.line.0 < term.bottommost_line() {
-                            start.column.0 = 0;
-                            start.line.0 += 1;
-                        }
-                        max_lines = (end_line.0 - start.line.0) as usize;
                     }
                 }
             }
@@ -885,6 +1383,24 @@ impl Widget<LapceTabData> for LapceTerminal {
     }
 }
 
+/// Opens the URL with the default application of the system.
+fn open_url(url: &str) {
+    #[cfg(target_os = "windows")]
+    let mut command = {
+        let mut command = std::process::Command::new("rundll32");
+        command.arg("url.dll,FileProtocolHandler");
+        command
+    };
+    #[cfg(target_os = "macos")]
+    let mut command = std::process::Command::new("open");
+    #[cfg(not(any(target_os = "windows", target_os = "macos")))]
+    let mut command = std::process::Command::new("xdg-open");
+
+    if let Err(e) = command.arg(url).spawn() {
+        log::error!("failed to open {url}: {e}");
+    }
+}
+
 #[derive(Clone)]
 pub struct EventProxy {
     term_id: TermId,
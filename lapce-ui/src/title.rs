@@ -381,9 +381,17 @@ impl Widget<LapceWindowData> for Title {
             x += size.height;
 
             let mut branch = tab.source_control.branch.clone();
-            if !tab.source_control.file_diffs.is_empty() {
+            if !tab.source_control.file_diffs.is_empty()
+                || !tab.source_control.staged_diffs.is_empty()
+            {
                 branch += "*";
             }
+            if tab.source_control.behind > 0 {
+                branch += &format!(" {}\u{2193}", tab.source_control.behind);
+            }
+            if tab.source_control.ahead > 0 {
+                branch += &format!(" {}\u{2191}", tab.source_control.ahead);
+            }
             let text_layout = ctx
                 .text()
                 .new_text_layout(branch)